@@ -1,15 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, Command};
+use tokio::process::Command;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
 use super::types::*;
+use crate::core::terminal::TerminalManager;
 
 #[derive(Debug, thiserror::Error)]
 pub enum AcpError {
@@ -33,36 +37,281 @@ pub enum AcpError {
 
     #[error("Not connected")]
     NotConnected,
+
+    #[error("Agent process exited: {0}")]
+    ProcessExited(String),
+}
+
+/// JSON-RPC error code ACP agents use when a request needs `authenticate` first
+pub const AUTH_REQUIRED_CODE: i32 = -32000;
+
+impl AcpError {
+    /// Whether the agent rejected the request until the client authenticates
+    pub fn is_auth_required(&self) -> bool {
+        match self {
+            AcpError::Rpc { code, message, .. } => {
+                *code == AUTH_REQUIRED_CODE || message.to_lowercase().contains("authentication required")
+            }
+            _ => false,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, AcpError>;
 
 type PendingRequest = oneshot::Sender<std::result::Result<serde_json::Value, AcpError>>;
+type PendingRequests = Arc<RwLock<HashMap<u64, PendingRequest>>>;
+type SharedWriter = Arc<RwLock<Option<mpsc::Sender<String>>>>;
+
+/// Lifecycle events of the agent process, forwarded to clients
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// The agent process exited or closed its output unexpectedly
+    Disconnected { reason: String },
+    /// A line the agent wrote to stderr
+    Stderr { line: String },
+    /// A freshly started agent answered `initialize`
+    Initialized { response: InitializeResponse },
+}
+
+/// Number of agent stderr lines kept for late-connecting clients
+pub const AGENT_LOG_LINES: usize = 200;
+
+/// Bounded buffer of recent agent stderr lines. Outlives the client so a
+/// failed startup can still be inspected.
+pub struct AgentLog {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl AgentLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Buffered lines, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().iter().cloned().collect()
+    }
+}
+
+impl Default for AgentLog {
+    fn default() -> Self {
+        Self::new(AGENT_LOG_LINES)
+    }
+}
+
+/// Default time to wait for a response to an ACP request
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default number of attempts to start the agent
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 3;
+
+/// Default delay before the second attempt to start the agent (doubled after each failure)
+pub const DEFAULT_CONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Run `attempt` (given the 1-based attempt number) up to `attempts` times,
+/// waiting `backoff` after the first failure and twice as long after each
+/// further one. Authentication errors are returned right away since retrying
+/// can't fix them; otherwise only the last error is returned.
+pub async fn retry_with_backoff<T, F, Fut>(attempts: u32, backoff: Duration, mut attempt: F) -> Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let attempts = attempts.max(1);
+    let mut delay = backoff;
+    let mut number = 0;
+    loop {
+        number += 1;
+        match attempt(number).await {
+            Ok(value) => return Ok(value),
+            Err(e) if number >= attempts || e.is_auth_required() => return Err(e),
+            Err(e) => {
+                warn!("Attempt {}/{} failed: {}; retrying in {:?}", number, attempts, e, delay);
+                tokio::time::sleep(delay).await;
+                delay = delay.saturating_mul(2);
+            }
+        }
+    }
+}
+
+/// How often the reader retries held-back chunks while the agent sends nothing
+const BACKLOG_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Text chunks of a session update, which can be merged with the next chunk of the same kind
+fn chunk_text(update: &mut SessionUpdate) -> Option<&mut String> {
+    match update {
+        SessionUpdate::UserMessageChunk { content: ContentBlock::Text { text } }
+        | SessionUpdate::AgentMessageChunk { content: ContentBlock::Text { text } }
+        | SessionUpdate::AgentThoughtChunk { content: ContentBlock::Text { text } } => Some(text),
+        _ => None,
+    }
+}
+
+/// Message chunks the reader couldn't queue because the notification channel was
+/// full. Blocking instead would stall the reader, and with it every response from
+/// the agent. Held-back chunks are merged per session and kind, so a burst of
+/// output takes one slot once the channel drains. Every other message (tool calls,
+/// plans, permission requests, responses) first waits for the backlog to be
+/// delivered, so ordering holds and nothing but chunk boundaries is lost.
+#[derive(Default)]
+struct ChunkBacklog {
+    updates: VecDeque<SessionNotification>,
+}
+
+impl ChunkBacklog {
+    fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    fn push(&mut self, mut notification: SessionNotification) {
+        if let Some(last) = self.updates.back_mut() {
+            let same_kind = last.session_id == notification.session_id
+                && std::mem::discriminant(&last.update) == std::mem::discriminant(&notification.update);
+            if let (true, Some(text), Some(more)) = (same_kind, chunk_text(&mut last.update), chunk_text(&mut notification.update)) {
+                text.push_str(more);
+                return;
+            }
+        }
+        self.updates.push_back(notification);
+    }
+
+    /// Queue as much of the backlog as fits without waiting
+    fn try_flush(&mut self, tx: &mpsc::Sender<SessionNotification>) {
+        while let Some(notification) = self.updates.pop_front() {
+            match tx.try_send(notification) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(notification)) => {
+                    self.updates.push_front(notification);
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    self.updates.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Deliver the whole backlog, waiting for room
+    async fn flush(&mut self, tx: &mpsc::Sender<SessionNotification>) {
+        while let Some(notification) = self.updates.pop_front() {
+            if tx.send(notification).await.is_err() {
+                self.updates.clear();
+                return;
+            }
+        }
+    }
+
+    /// Send a session update: text chunks never wait, anything else waits for
+    /// the backlog and then for room in the channel
+    async fn send(
+        &mut self,
+        tx: &mpsc::Sender<SessionNotification>,
+        mut notification: SessionNotification,
+    ) -> std::result::Result<(), mpsc::error::SendError<SessionNotification>> {
+        if chunk_text(&mut notification.update).is_none() {
+            self.flush(tx).await;
+            return tx.send(notification).await;
+        }
+
+        self.try_flush(tx);
+        if !self.is_empty() {
+            self.push(notification);
+            return Ok(());
+        }
+        match tx.try_send(notification) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(notification)) => {
+                self.push(notification);
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(notification)) => Err(mpsc::error::SendError(notification)),
+        }
+    }
+}
 
 pub struct AcpClient {
-    child: Option<Child>,
+    /// Signals the process monitor task to kill the agent
+    kill_tx: Option<oneshot::Sender<()>>,
     request_id: AtomicU64,
-    pending_requests: Arc<RwLock<HashMap<u64, PendingRequest>>>,
-    write_tx: Option<mpsc::Sender<String>>,
+    /// How long `send_request` waits for a response before failing with `AcpError::Timeout`
+    request_timeout: Duration,
+    pending_requests: PendingRequests,
+    /// In-flight `session/prompt` request ids per session, resolved early on cancel
+    prompt_requests: RwLock<HashMap<String, Vec<u64>>>,
+    /// Cleared when the agent exits so `is_connected()` reflects reality
+    write_tx: SharedWriter,
     notification_tx: mpsc::Sender<SessionNotification>,
     permission_tx: mpsc::Sender<PermissionRequest>,
+    event_tx: mpsc::Sender<AgentEvent>,
+    /// Terminals the agent can run commands in (enables the terminal capability)
+    terminal_manager: Option<Arc<TerminalManager>>,
+    /// Recent stderr lines of the agent process
+    agent_log: Option<Arc<AgentLog>>,
+    /// Directories put at the front of the agent's PATH
+    extra_path: Vec<String>,
 }
 
 impl AcpClient {
     pub fn new(
         notification_tx: mpsc::Sender<SessionNotification>,
         permission_tx: mpsc::Sender<PermissionRequest>,
+        event_tx: mpsc::Sender<AgentEvent>,
     ) -> Self {
         Self {
-            child: None,
+            kill_tx: None,
             request_id: AtomicU64::new(1),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
             pending_requests: Arc::new(RwLock::new(HashMap::new())),
-            write_tx: None,
+            prompt_requests: RwLock::new(HashMap::new()),
+            write_tx: Arc::new(RwLock::new(None)),
             notification_tx,
             permission_tx,
+            event_tx,
+            terminal_manager: None,
+            agent_log: None,
+            extra_path: Vec::new(),
         }
     }
 
+    /// Set the timeout applied to each request sent to the agent.
+    /// Long turns against slow providers (e.g. Ollama) may need more than the default.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Let the agent run commands in our PTY terminals via the ACP `terminal/*` methods
+    pub fn with_terminal_manager(mut self, terminal_manager: Arc<TerminalManager>) -> Self {
+        self.terminal_manager = Some(terminal_manager);
+        self
+    }
+
+    /// Keep the agent's stderr in a shared buffer (in addition to forwarding it as events)
+    pub fn with_agent_log(mut self, agent_log: Arc<AgentLog>) -> Self {
+        self.agent_log = Some(agent_log);
+        self
+    }
+
+    /// Put extra directories (e.g. a custom Node install) at the front of the agent's PATH
+    pub fn with_extra_path(mut self, extra_path: Vec<String>) -> Self {
+        self.extra_path = extra_path;
+        self
+    }
+
     pub async fn connect(
         &mut self,
         command: &str,
@@ -85,10 +334,11 @@ impl AcpClient {
             }
         }
 
-        // On macOS, when launched via .app bundle (double-click), the environment is minimal.
-        // We need to load the user's shell environment to find npx and other tools.
+        // When launched from a GUI (a macOS .app bundle or a Linux app menu), the
+        // environment is minimal and npx/node/bun can't be found.
+        // On macOS, load the user's shell environment to find npx and other tools.
         #[cfg(target_os = "macos")]
-        {
+        let shell_path = {
             // Try to get full shell environment by running a login shell
             let shell_env = get_shell_environment();
 
@@ -100,65 +350,25 @@ impl AcpClient {
                 }
             }
 
-            // Build comprehensive PATH
-            let shell_path = shell_env.get("PATH").map(|s| s.as_str()).unwrap_or("");
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let home = std::env::var("HOME").unwrap_or_default();
-
-            let mut additional_paths = vec![
-                "/usr/local/bin".to_string(),
-                "/opt/homebrew/bin".to_string(),
-                "/opt/local/bin".to_string(),
-                format!("{}/.local/bin", home),
-                format!("{}/Library/pnpm", home),
-                format!("{}/.bun/bin", home),
-                format!("{}/.cargo/bin", home),
-            ];
-
-            // Find nvm node versions
-            let nvm_versions_dir = format!("{}/.nvm/versions/node", home);
-            if let Ok(entries) = std::fs::read_dir(&nvm_versions_dir) {
-                for entry in entries.flatten() {
-                    let bin_path = entry.path().join("bin");
-                    if bin_path.exists() {
-                        additional_paths.push(bin_path.to_string_lossy().to_string());
-                    }
-                }
-            }
-
-            // Combine: additional paths + shell PATH + current PATH
-            let new_path = format!("{}:{}:{}", additional_paths.join(":"), shell_path, current_path);
-            cmd.env("PATH", new_path);
+            shell_env.get("PATH").cloned()
+        };
+        #[cfg(not(target_os = "macos"))]
+        let shell_path: Option<String> = None;
+
+        // The login shell's PATH goes before the one we were started with
+        let inherited_paths: Vec<String> = shell_path
+            .into_iter()
+            .chain(std::iter::once(std::env::var("PATH").unwrap_or_default()))
+            .collect();
+
+        let home = std::env::var("HOME").unwrap_or_default();
+        if let Some(path) = agent_path(&self.extra_path, &inherited_paths, &common_bin_dirs(&home), &home) {
+            debug!("Agent PATH: {}", path.to_string_lossy());
+            cmd.env("PATH", path);
         }
 
-        // On Linux, also ensure common paths are included
-        #[cfg(target_os = "linux")]
-        {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            let home = std::env::var("HOME").unwrap_or_default();
-
-            let additional_paths = vec![
-                "/usr/local/bin".to_string(),
-                format!("{}/.local/bin", home),
-                format!("{}/.bun/bin", home),
-                format!("{}/.cargo/bin", home),
-                format!("{}/.nvm/versions/node/*/bin", home), // Won't work as glob, but nvm sets PATH anyway
-            ];
-
-            // Find nvm node versions
-            let nvm_versions_dir = format!("{}/.nvm/versions/node", home);
-            if let Ok(entries) = std::fs::read_dir(&nvm_versions_dir) {
-                for entry in entries.flatten() {
-                    let bin_path = entry.path().join("bin");
-                    if bin_path.exists() {
-                        cmd.env("PATH", format!("{}:{}", bin_path.to_string_lossy(), current_path.clone()));
-                    }
-                }
-            }
-
-            let new_path = format!("{}:{}", additional_paths.join(":"), current_path);
-            cmd.env("PATH", new_path);
-        }
+        // Make sure the agent never outlives us, even if the monitor task is gone
+        cmd.kill_on_drop(true);
 
         let mut child = cmd.spawn()?;
 
@@ -194,15 +404,37 @@ impl AcpClient {
             }
         });
 
+        // Publish the writer before the reader starts so an early exit can clear it
+        *self.write_tx.write() = Some(write_tx);
+
         let pending_requests = self.pending_requests.clone();
         let notification_tx = self.notification_tx.clone();
         let permission_tx = self.permission_tx.clone();
+        let shared_writer = self.write_tx.clone();
+        let event_tx = self.event_tx.clone();
+        let terminal_manager = self.terminal_manager.clone();
 
         tokio::spawn(async move {
             let reader = BufReader::new(stdout);
             let mut lines = reader.lines();
+            let mut backlog = ChunkBacklog::default();
 
-            while let Ok(Some(line)) = lines.next_line().await {
+            loop {
+                let line = if backlog.is_empty() {
+                    lines.next_line().await
+                } else {
+                    // Keep retrying held-back chunks while the agent is quiet
+                    match tokio::time::timeout(BACKLOG_RETRY_INTERVAL, lines.next_line()).await {
+                        Ok(line) => line,
+                        Err(_) => {
+                            backlog.try_flush(&notification_tx);
+                            continue;
+                        }
+                    }
+                };
+                let Ok(Some(line)) = line else {
+                    break;
+                };
                 if line.trim().is_empty() {
                     continue;
                 }
@@ -221,6 +453,8 @@ impl AcpClient {
                 let has_error = msg.get("error").is_some();
 
                 if has_id && (has_result || has_error) {
+                    // Chunks streamed before a prompt's response must reach clients first
+                    backlog.flush(&notification_tx).await;
                     // This is a response to one of our requests
                     if let Ok(response) = serde_json::from_value::<JsonRpcResponse>(msg) {
                         if let Some(id) = response.id.as_u64() {
@@ -240,6 +474,7 @@ impl AcpClient {
                         }
                     }
                 } else if has_id && has_method {
+                    backlog.flush(&notification_tx).await;
                     // This is a request FROM the agent (we need to respond)
                     if let Ok(request) = serde_json::from_value::<JsonRpcRequest>(msg) {
                         match request.method.as_str() {
@@ -274,8 +509,44 @@ impl AcpClient {
                                     }
                                 }
                             }
+                            "fs/read_text_file" | "fs/write_text_file" => {
+                                // Run file IO off the reader loop so notifications keep flowing
+                                let writer = shared_writer.clone();
+                                tokio::spawn(async move {
+                                    let result = handle_fs_request(&request.method, request.params).await;
+                                    send_response(&writer, request.id, result).await;
+                                });
+                            }
+                            method if method.starts_with("terminal/") => {
+                                // wait_for_exit can block for a long time, never run it inline
+                                let writer = shared_writer.clone();
+                                let terminal_manager = terminal_manager.clone();
+                                tokio::spawn(async move {
+                                    let result = match terminal_manager {
+                                        Some(manager) => {
+                                            handle_terminal_request(&manager, &request.method, request.params).await
+                                        }
+                                        None => Err(JsonRpcError {
+                                            code: -32601,
+                                            message: "Terminal capability is not available".to_string(),
+                                            data: None,
+                                        }),
+                                    };
+                                    send_response(&writer, request.id, result).await;
+                                });
+                            }
                             _ => {
                                 warn!("Unhandled agent request: {}", request.method);
+                                send_response(
+                                    &shared_writer,
+                                    request.id,
+                                    Err(JsonRpcError {
+                                        code: -32601,
+                                        message: format!("Method not found: {}", request.method),
+                                        data: None,
+                                    }),
+                                )
+                                .await;
                             }
                         }
                     }
@@ -288,7 +559,7 @@ impl AcpClient {
                                     match serde_json::from_value::<SessionNotification>(params.clone()) {
                                         Ok(session_notification) => {
                                             debug!("Parsed session notification for session: {}", session_notification.session_id);
-                                            if let Err(e) = notification_tx.send(session_notification).await {
+                                            if let Err(e) = backlog.send(&notification_tx, session_notification).await {
                                                 error!("Failed to send notification through channel: {}", e);
                                             }
                                         }
@@ -307,34 +578,75 @@ impl AcpClient {
                     warn!("Unknown message type: {}", line);
                 }
             }
+            backlog.flush(&notification_tx).await;
+
+            handle_agent_exit(
+                &shared_writer,
+                &pending_requests,
+                &event_tx,
+                "Agent process closed its output".to_string(),
+            );
         });
 
+        let agent_log = self.agent_log.clone();
+        let stderr_event_tx = self.event_tx.clone();
         tokio::spawn(async move {
             let reader = BufReader::new(stderr);
             let mut lines = reader.lines();
             while let Ok(Some(line)) = lines.next_line().await {
                 warn!("Agent stderr: {}", line);
+                if let Some(log) = &agent_log {
+                    log.push(line.clone());
+                }
+                // Never block reading stderr on a slow consumer; the log keeps the line
+                let _ = stderr_event_tx.try_send(AgentEvent::Stderr { line });
             }
         });
 
-        self.child = Some(child);
-        self.write_tx = Some(write_tx);
+        // Monitor the child process: clean up when it exits, or kill it on request
+        let (kill_tx, kill_rx) = oneshot::channel::<()>();
+        let shared_writer = self.write_tx.clone();
+        let pending_requests = self.pending_requests.clone();
+        let event_tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = kill_rx => {
+                    let _ = child.kill().await;
+                    return;
+                }
+            };
+
+            let reason = match status {
+                Ok(status) => format!("Agent process exited ({})", status),
+                Err(e) => format!("Failed to wait for agent process: {}", e),
+            };
+            handle_agent_exit(&shared_writer, &pending_requests, &event_tx, reason);
+        });
+
+        self.kill_tx = Some(kill_tx);
 
         Ok(())
     }
 
     pub async fn disconnect(&mut self) -> Result<()> {
-        self.write_tx = None;
+        // Clear the writer first so the monitor does not report an unexpected exit
+        self.write_tx.write().take();
 
-        if let Some(mut child) = self.child.take() {
-            let _ = child.kill().await;
+        if let Some(kill_tx) = self.kill_tx.take() {
+            let _ = kill_tx.send(());
         }
 
         Ok(())
     }
 
     pub fn is_connected(&self) -> bool {
-        self.write_tx.is_some()
+        self.write_tx.read().is_some()
+    }
+
+    /// Get a handle to the stdin writer, failing if the agent is not running
+    fn writer(&self) -> Result<mpsc::Sender<String>> {
+        self.write_tx.read().clone().ok_or(AcpError::NotConnected)
     }
 
     async fn send_request<T: serde::de::DeserializeOwned>(
@@ -342,9 +654,18 @@ impl AcpClient {
         method: &str,
         params: Option<serde_json::Value>,
     ) -> Result<T> {
-        let write_tx = self.write_tx.as_ref().ok_or(AcpError::NotConnected)?;
-
         let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        self.send_request_with_id(id, method, params).await
+    }
+
+    async fn send_request_with_id<T: serde::de::DeserializeOwned>(
+        &self,
+        id: u64,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let write_tx = self.writer()?;
+
         let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             id: serde_json::Value::Number(id.into()),
@@ -362,22 +683,28 @@ impl AcpClient {
         let json = serde_json::to_string(&request)?;
         debug!("Sending: {}", json);
 
-        write_tx
-            .send(json)
-            .await
-            .map_err(|_| AcpError::ChannelClosed)?;
+        if write_tx.send(json).await.is_err() {
+            self.pending_requests.write().remove(&id);
+            return Err(AcpError::ChannelClosed);
+        }
 
-        let result = tokio::time::timeout(std::time::Duration::from_secs(300), response_rx)
-            .await
-            .map_err(|_| AcpError::Timeout)?
-            .map_err(|_| AcpError::ChannelClosed)??;
+        let result = match tokio::time::timeout(self.request_timeout, response_rx).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Drop the stale sender so a late response is not routed anywhere
+                self.pending_requests.write().remove(&id);
+                warn!("Request {} ({}) timed out after {:?}", id, method, self.request_timeout);
+                return Err(AcpError::Timeout);
+            }
+        };
+        let result = result.map_err(|_| AcpError::ChannelClosed)??;
 
         let value: T = serde_json::from_value(result)?;
         Ok(value)
     }
 
     async fn send_notification(&self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
-        let write_tx = self.write_tx.as_ref().ok_or(AcpError::NotConnected)?;
+        let write_tx = self.writer()?;
 
         let notification = JsonRpcNotification {
             jsonrpc: "2.0".to_string(),
@@ -405,8 +732,11 @@ impl AcpClient {
                 version: env!("CARGO_PKG_VERSION").to_string(),
             }),
             client_capabilities: Some(ClientCapabilities {
-                fs: None,      // Not implemented yet
-                terminal: None, // Not implemented yet
+                fs: Some(FileSystemCapability {
+                    read_text_file: Some(true),
+                    write_text_file: Some(true),
+                }),
+                terminal: Some(self.terminal_manager.is_some()),
             }),
         };
 
@@ -414,10 +744,10 @@ impl AcpClient {
             .await
     }
 
-    pub async fn create_session(&self, cwd: &str) -> Result<NewSessionResponse> {
+    pub async fn create_session(&self, cwd: &str, mcp_servers: Vec<serde_json::Value>) -> Result<NewSessionResponse> {
         let params = NewSessionRequest {
             cwd: cwd.to_string(),
-            mcp_servers: vec![],
+            mcp_servers,
         };
 
         self.send_request("session/new", Some(serde_json::to_value(params)?))
@@ -427,12 +757,18 @@ impl AcpClient {
     /// Resume an existing session (unstable API)
     ///
     /// This reattaches to an existing session without replaying history.
-    /// The session must exist in ~/.claude/projects/{path_key}/{session_id}.jsonl
-    pub async fn resume_session(&self, session_id: &str, cwd: &str) -> Result<NewSessionResponse> {
+    /// The session must exist in the agent's projects directory
+    /// (~/.claude/projects/{path_key}/{session_id}.jsonl by default)
+    pub async fn resume_session(
+        &self,
+        session_id: &str,
+        cwd: &str,
+        mcp_servers: Vec<serde_json::Value>,
+    ) -> Result<NewSessionResponse> {
         let params = ResumeSessionRequest {
             session_id: session_id.to_string(),
             cwd: cwd.to_string(),
-            mcp_servers: vec![],
+            mcp_servers,
         };
 
         info!("Resuming session {} in {}", session_id, cwd);
@@ -443,11 +779,16 @@ impl AcpClient {
     /// Fork an existing session (unstable API)
     ///
     /// This creates a new session based on an existing one with a new ID.
-    pub async fn fork_session(&self, session_id: &str, cwd: &str) -> Result<NewSessionResponse> {
+    pub async fn fork_session(
+        &self,
+        session_id: &str,
+        cwd: &str,
+        mcp_servers: Vec<serde_json::Value>,
+    ) -> Result<NewSessionResponse> {
         let params = ForkSessionRequest {
             session_id: session_id.to_string(),
             cwd: cwd.to_string(),
-            mcp_servers: vec![],
+            mcp_servers,
         };
 
         info!("Forking session {} in {}", session_id, cwd);
@@ -456,24 +797,88 @@ impl AcpClient {
     }
 
     pub async fn prompt(&self, session_id: &str, content: &str) -> Result<PromptResponse> {
-        let params = PromptRequest {
-            session_id: session_id.to_string(),
-            prompt: vec![ContentBlock::Text {
+        self.prompt_with_blocks(
+            session_id,
+            vec![ContentBlock::Text {
                 text: content.to_string(),
             }],
+        )
+        .await
+    }
+
+    /// Send a prompt made of arbitrary content blocks (text, images, resources)
+    pub async fn prompt_with_blocks(
+        &self,
+        session_id: &str,
+        blocks: Vec<ContentBlock>,
+    ) -> Result<PromptResponse> {
+        let params = PromptRequest {
+            session_id: session_id.to_string(),
+            prompt: blocks,
         };
 
-        self.send_request("session/prompt", Some(serde_json::to_value(params)?))
-            .await
+        // Track the request so cancel() can resolve it without waiting for the agent
+        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+        self.prompt_requests
+            .write()
+            .entry(session_id.to_string())
+            .or_default()
+            .push(id);
+
+        let result = self
+            .send_request_with_id(id, "session/prompt", Some(serde_json::to_value(params)?))
+            .await;
+
+        let mut prompts = self.prompt_requests.write();
+        if let Some(ids) = prompts.get_mut(session_id) {
+            ids.retain(|&pending_id| pending_id != id);
+            if ids.is_empty() {
+                prompts.remove(session_id);
+            }
+        }
+
+        result
     }
 
+    /// Cancel the session's current turn.
+    /// In-flight prompts for the session resolve immediately with `StopReason::Cancelled`
+    /// instead of waiting for the agent to wind down.
     pub async fn cancel(&self, session_id: &str) -> Result<()> {
         let params = CancelNotification {
             session_id: session_id.to_string(),
         };
 
-        self.send_notification("session/cancel", Some(serde_json::to_value(params)?))
-            .await
+        let result = self
+            .send_notification("session/cancel", Some(serde_json::to_value(params)?))
+            .await;
+
+        let ids = self.prompt_requests.write().remove(session_id).unwrap_or_default();
+        if !ids.is_empty() {
+            let cancelled = serde_json::to_value(PromptResponse {
+                stop_reason: StopReason::Cancelled,
+            })?;
+            let mut pending = self.pending_requests.write();
+            for id in ids {
+                if let Some(sender) = pending.remove(&id) {
+                    info!("Resolving prompt request {} for session {} as cancelled", id, session_id);
+                    let _ = sender.send(Ok(cancelled.clone()));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Authenticate with one of the `auth_methods` from the initialize response
+    pub async fn authenticate(&self, method_id: &str) -> Result<()> {
+        info!("Authenticating with method {}", method_id);
+        self.send_request::<serde_json::Value>(
+            "authenticate",
+            Some(serde_json::json!({ "methodId": method_id })),
+        )
+        .await?;
+
+        Ok(())
     }
 
     pub async fn set_session_mode(&self, session_id: &str, mode_id: &str) -> Result<()> {
@@ -488,12 +893,24 @@ impl AcpClient {
         Ok(())
     }
 
+    pub async fn set_session_model(&self, session_id: &str, model_id: &str) -> Result<()> {
+        let params = SetSessionModelRequest {
+            session_id: session_id.to_string(),
+            model_id: model_id.to_string(),
+        };
+
+        self.send_request::<serde_json::Value>("session/set_model", Some(serde_json::to_value(params)?))
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn respond_permission(
         &self,
         request_id: RequestId,
         outcome: PermissionOutcome,
     ) -> Result<()> {
-        let write_tx = self.write_tx.as_ref().ok_or(AcpError::NotConnected)?;
+        let write_tx = self.writer()?;
 
         // The result must be wrapped in RequestPermissionResult format
         // which has an "outcome" field containing the PermissionOutcome
@@ -526,10 +943,282 @@ impl AcpClient {
 
 impl Drop for AcpClient {
     fn drop(&mut self) {
-        if let Some(mut child) = self.child.take() {
-            let _ = child.start_kill();
+        self.write_tx.write().take();
+        if let Some(kill_tx) = self.kill_tx.take() {
+            let _ = kill_tx.send(());
+        }
+    }
+}
+
+/// Send a JSON-RPC response to a request the agent made to us
+async fn send_response(
+    write_tx: &SharedWriter,
+    id: RequestId,
+    result: std::result::Result<serde_json::Value, JsonRpcError>,
+) {
+    let Some(writer) = write_tx.read().clone() else {
+        warn!("Agent disconnected before response to {:?} could be sent", id);
+        return;
+    };
+
+    let (result, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result,
+        error,
+    };
+
+    match serde_json::to_string(&response) {
+        Ok(json) => {
+            debug!("Sending response: {}", json);
+            let _ = writer.send(json).await;
+        }
+        Err(e) => error!("Failed to serialize response: {}", e),
+    }
+}
+
+/// Handle `fs/read_text_file` and `fs/write_text_file` requests from the agent
+async fn handle_fs_request(
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> std::result::Result<serde_json::Value, JsonRpcError> {
+    let invalid_params = |e: serde_json::Error| JsonRpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    };
+    let internal_error = |message: String| JsonRpcError {
+        code: -32603,
+        message,
+        data: None,
+    };
+    let params = params.unwrap_or(serde_json::Value::Null);
+
+    match method {
+        "fs/read_text_file" => {
+            let request: ReadTextFileRequest =
+                serde_json::from_value(params).map_err(invalid_params)?;
+            info!("Agent reading file {} (session {})", request.path, request.session_id);
+
+            let content = crate::commands::file::read_file_impl(&request.path)
+                .await
+                .map_err(internal_error)?;
+            let content = slice_lines(&content, request.line, request.limit);
+
+            serde_json::to_value(ReadTextFileResponse { content })
+                .map_err(|e| internal_error(e.to_string()))
+        }
+        "fs/write_text_file" => {
+            let request: WriteTextFileRequest =
+                serde_json::from_value(params).map_err(invalid_params)?;
+            info!("Agent writing file {} (session {})", request.path, request.session_id);
+
+            crate::commands::file::write_file_impl(&request.path, &request.content)
+                .await
+                .map_err(internal_error)?;
+
+            Ok(serde_json::Value::Null)
+        }
+        _ => Err(JsonRpcError {
+            code: -32601,
+            message: format!("Method not found: {}", method),
+            data: None,
+        }),
+    }
+}
+
+/// Handle the `terminal/*` requests from the agent by driving our PTY terminals
+async fn handle_terminal_request(
+    terminal_manager: &TerminalManager,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> std::result::Result<serde_json::Value, JsonRpcError> {
+    let invalid_params = |e: serde_json::Error| JsonRpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", e),
+        data: None,
+    };
+    let internal_error = |message: String| JsonRpcError {
+        code: -32603,
+        message,
+        data: None,
+    };
+    let params = params.unwrap_or(serde_json::Value::Null);
+
+    if method == "terminal/create" {
+        let request: CreateTerminalRequest =
+            serde_json::from_value(params).map_err(invalid_params)?;
+        info!(
+            "Agent running command in terminal: {} {:?} (session {})",
+            request.command, request.args, request.session_id
+        );
+
+        let cwd = request.cwd.unwrap_or_else(|| {
+            std::env::var("HOME").unwrap_or_else(|_| "/".to_string())
+        });
+        let env: Vec<(String, String)> = request
+            .env
+            .into_iter()
+            .map(|var| (var.name, var.value))
+            .collect();
+
+        let terminal_id = terminal_manager
+            .create_command_terminal(
+                &request.command,
+                &request.args,
+                &env,
+                cwd,
+                request.output_byte_limit.map(|limit| limit as usize),
+            )
+            .map_err(internal_error)?;
+
+        return serde_json::to_value(CreateTerminalResponse { terminal_id })
+            .map_err(|e| internal_error(e.to_string()));
+    }
+
+    let request: TerminalRequest = serde_json::from_value(params).map_err(invalid_params)?;
+    let terminal_id = request.terminal_id.as_str();
+
+    match method {
+        "terminal/output" => {
+            let snapshot = terminal_manager.get_output(terminal_id).map_err(internal_error)?;
+            serde_json::to_value(snapshot).map_err(|e| internal_error(e.to_string()))
+        }
+        "terminal/wait_for_exit" => {
+            let status = terminal_manager
+                .wait_for_exit(terminal_id)
+                .await
+                .map_err(internal_error)?;
+            serde_json::to_value(status).map_err(|e| internal_error(e.to_string()))
+        }
+        "terminal/kill" => {
+            terminal_manager.kill_process(terminal_id).map_err(internal_error)?;
+            Ok(serde_json::Value::Null)
+        }
+        "terminal/release" => {
+            terminal_manager.kill_terminal(terminal_id).map_err(internal_error)?;
+            Ok(serde_json::Value::Null)
+        }
+        _ => Err(JsonRpcError {
+            code: -32601,
+            message: format!("Method not found: {}", method),
+            data: None,
+        }),
+    }
+}
+
+/// Select `limit` lines starting at 1-based `line`; returns the whole content when neither is set
+fn slice_lines(content: &str, line: Option<u32>, limit: Option<u32>) -> String {
+    if line.is_none() && limit.is_none() {
+        return content.to_string();
+    }
+
+    let start = line.unwrap_or(1).saturating_sub(1) as usize;
+    let lines = content.split_inclusive('\n').skip(start);
+    match limit {
+        Some(limit) => lines.take(limit as usize).collect(),
+        None => lines.collect(),
+    }
+}
+
+/// Clean up after the agent process went away unexpectedly.
+///
+/// Clears the writer, fails every pending request and emits `AgentEvent::Disconnected`.
+/// Runs at most once per connection: later calls find the writer already cleared.
+fn handle_agent_exit(
+    write_tx: &SharedWriter,
+    pending_requests: &PendingRequests,
+    event_tx: &mpsc::Sender<AgentEvent>,
+    reason: String,
+) {
+    if write_tx.write().take().is_none() {
+        return;
+    }
+
+    error!("{}", reason);
+
+    let pending: Vec<_> = pending_requests.write().drain().collect();
+    for (_, sender) in pending {
+        let _ = sender.send(Err(AcpError::ProcessExited(reason.clone())));
+    }
+
+    if let Err(e) = event_tx.try_send(AgentEvent::Disconnected { reason }) {
+        warn!("Failed to send agent disconnected event: {}", e);
+    }
+}
+
+/// PATH for the agent process: the `extra` entries first, then the inherited
+/// PATHs, then any `fallback` directory not already listed. Duplicates are
+/// dropped, so an already complete PATH keeps its order. A leading `~/` in an
+/// extra entry is expanded to `home`.
+fn agent_path(extra: &[String], inherited: &[String], fallback: &[PathBuf], home: &str) -> Option<OsString> {
+    let extra = expand_config_dirs(extra, home);
+    let inherited = inherited.iter().flat_map(std::env::split_paths);
+
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for entry in extra.into_iter().chain(inherited).chain(fallback.iter().cloned()) {
+        if !entry.as_os_str().is_empty() && !entries.contains(&entry) {
+            entries.push(entry);
         }
     }
+    std::env::join_paths(entries).ok()
+}
+
+/// Directories listed in the config (e.g. `agent.extraPath`), with a leading
+/// `~/` expanded to `home`
+pub(crate) fn expand_config_dirs(extra: &[String], home: &str) -> Vec<PathBuf> {
+    extra
+        .iter()
+        .map(|entry| match entry.strip_prefix("~/") {
+            Some(rest) if !home.is_empty() => Path::new(home).join(rest),
+            _ => PathBuf::from(entry),
+        })
+        .collect()
+}
+
+/// Existing directories where Node, Bun and package managers commonly install
+/// binaries, which a GUI-launched app's PATH usually lacks
+pub(crate) fn common_bin_dirs(home: &str) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if cfg!(unix) {
+        dirs.push(PathBuf::from("/usr/local/bin"));
+    }
+    if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/opt/homebrew/bin"));
+        dirs.push(PathBuf::from("/opt/local/bin"));
+    }
+    if cfg!(target_os = "linux") {
+        dirs.push(PathBuf::from("/home/linuxbrew/.linuxbrew/bin"));
+        dirs.push(PathBuf::from("/snap/bin"));
+    }
+
+    if cfg!(unix) && !home.is_empty() {
+        let home = Path::new(home);
+        for dir in [".local/bin", ".bun/bin", ".cargo/bin", ".volta/bin", ".npm-global/bin", ".yarn/bin", ".deno/bin"] {
+            dirs.push(home.join(dir));
+        }
+        if cfg!(target_os = "macos") {
+            dirs.push(home.join("Library/pnpm"));
+        } else {
+            dirs.push(home.join(".local/share/pnpm"));
+        }
+
+        // Every installed nvm node version
+        if let Ok(entries) = std::fs::read_dir(home.join(".nvm/versions/node")) {
+            let mut versions: Vec<PathBuf> = entries.flatten().map(|entry| entry.path().join("bin")).collect();
+            // Newest first, so it wins when no node is on PATH yet
+            versions.sort();
+            versions.reverse();
+            dirs.extend(versions);
+        }
+    }
+
+    dirs.retain(|dir| dir.is_dir());
+    dirs
 }
 
 /// Get environment variables from user's login shell.
@@ -569,3 +1258,112 @@ fn get_shell_environment() -> HashMap<String, String> {
 
     env_map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_log_is_bounded() {
+        let log = AgentLog::new(2);
+        log.push("one".to_string());
+        log.push("two".to_string());
+        log.push("three".to_string());
+        assert_eq!(log.lines(), vec!["two", "three"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_agent_path() {
+        let inherited = vec!["/usr/bin:/bin".to_string(), "/bin:/usr/local/bin".to_string()];
+        let fallback = vec![PathBuf::from("/usr/local/bin"), PathBuf::from("/home/me/.bun/bin")];
+        let extra = vec!["~/node/bin".to_string(), "/usr/bin".to_string()];
+        let path = agent_path(&extra, &inherited, &fallback, "/home/me").unwrap();
+        assert_eq!(
+            path,
+            OsString::from("/home/me/node/bin:/usr/bin:/bin:/usr/local/bin:/home/me/.bun/bin")
+        );
+
+        // A minimal PATH only gains the fallback directories
+        let path = agent_path(&[], &["/usr/bin".to_string()], &fallback, "/home/me").unwrap();
+        assert_eq!(path, OsString::from("/usr/bin:/usr/local/bin:/home/me/.bun/bin"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_retry_with_backoff_connects_on_second_attempt() {
+        let (notification_tx, _notification_rx) = mpsc::channel(1);
+        let (permission_tx, _permission_rx) = mpsc::channel(1);
+        let (event_tx, _event_rx) = mpsc::channel(16);
+        let mut commands = Vec::new();
+        let client = retry_with_backoff(3, Duration::from_millis(1), |attempt| {
+            // The first start fails like a transient npx error, the second succeeds
+            let command = if attempt == 1 { "/nonexistent/aerowork-agent" } else { "cat" };
+            commands.push(command);
+            let mut client = AcpClient::new(notification_tx.clone(), permission_tx.clone(), event_tx.clone());
+            async move {
+                client.connect(command, &[], None).await?;
+                Ok(client)
+            }
+        })
+        .await
+        .unwrap();
+        assert!(client.is_connected());
+        assert_eq!(commands, vec!["/nonexistent/aerowork-agent", "cat"]);
+
+        // Only the last error is returned once the attempts run out
+        let mut calls = 0;
+        let result: Result<()> = retry_with_backoff(2, Duration::from_millis(1), |_| {
+            calls += 1;
+            async { Err(AcpError::Timeout) }
+        })
+        .await;
+        assert!(matches!(result, Err(AcpError::Timeout)));
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_backlog_never_blocks_on_chunks() {
+        let chunk = |session: &str, text: &str| SessionNotification {
+            session_id: session.to_string(),
+            update: SessionUpdate::AgentMessageChunk { content: ContentBlock::Text { text: text.to_string() } },
+        };
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut backlog = ChunkBacklog::default();
+
+        // The channel holds one update; the rest are merged per session
+        for notification in [chunk("s1", "a"), chunk("s1", "b"), chunk("s1", "c"), chunk("s2", "x"), chunk("s1", "d")] {
+            backlog.send(&tx, notification).await.unwrap();
+        }
+        assert_eq!(backlog.updates.len(), 3);
+
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            backlog.try_flush(&tx);
+            let notification = rx.recv().await.unwrap();
+            let mut update = notification.update;
+            received.push(format!("{}:{}", notification.session_id, chunk_text(&mut update).unwrap()));
+        }
+        assert_eq!(received, vec!["s1:a", "s1:bc", "s2:x", "s1:d"]);
+        assert!(backlog.is_empty());
+    }
+
+    #[test]
+    fn test_is_auth_required() {
+        let rpc = |code, message: &str| AcpError::Rpc { code, message: message.to_string(), data: None };
+        assert!(rpc(AUTH_REQUIRED_CODE, "Unauthorized").is_auth_required());
+        assert!(rpc(-32603, "Authentication required").is_auth_required());
+        assert!(!rpc(-32603, "Internal error").is_auth_required());
+        assert!(!AcpError::Timeout.is_auth_required());
+    }
+
+    #[test]
+    fn test_slice_lines() {
+        let content = "one\ntwo\nthree\nfour\n";
+        assert_eq!(slice_lines(content, None, None), content);
+        assert_eq!(slice_lines(content, Some(2), Some(2)), "two\nthree\n");
+        assert_eq!(slice_lines(content, Some(4), None), "four\n");
+        assert_eq!(slice_lines(content, None, Some(1)), "one\n");
+        assert_eq!(slice_lines(content, Some(10), None), "");
+    }
+}
@@ -0,0 +1,245 @@
+//! Mock ACP agent for integration tests (`mock-agent` feature)
+//!
+//! Speaks newline-delimited JSON-RPC like a real agent, with a fixed script:
+//! - `session/prompt` replies with the chunks `"Mock "`, `"reply: "` and the
+//!   prompt text, then ends the turn
+//! - a prompt containing `"tool"` first reports a tool call, asks for
+//!   permission and completes (or fails) the tool call with the outcome
+//! - new sessions offer the models `mock-default` and `mock-fast` and the modes
+//!   `default`, `plan` and `locked`; switching to `locked` is rejected
+//!
+//! Run it as a process with the `aero-mock-agent` binary, or in-process with
+//! [`run`] over any reader and writer.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+
+/// Shared by the request handlers: outgoing lines and agent-to-client requests
+/// waiting for their response
+#[derive(Clone)]
+struct MockAgent {
+    out_tx: mpsc::UnboundedSender<String>,
+    pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl MockAgent {
+    fn send(&self, message: Value) {
+        let _ = self.out_tx.send(message.to_string());
+    }
+
+    fn respond(&self, id: Value, result: Result<Value, (i32, String)>) {
+        match result {
+            Ok(result) => self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+            Err((code, message)) => self.send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            })),
+        }
+    }
+
+    fn update(&self, session_id: &str, update: Value) {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": session_id, "update": update },
+        }));
+    }
+
+    fn chunk(&self, session_id: &str, text: &str) {
+        self.update(session_id, json!({
+            "sessionUpdate": "agent_message_chunk",
+            "content": { "type": "text", "text": text },
+        }));
+    }
+
+    /// Send a request to the client and wait for its result
+    async fn request(&self, method: &str, params: Value) -> Option<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().insert(id, tx);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        rx.await.ok()
+    }
+
+    async fn handle(&self, method: &str, params: Value) -> Result<Value, (i32, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": 1,
+                "agentInfo": { "name": "mock-agent", "version": env!("CARGO_PKG_VERSION") },
+                "agentCapabilities": {
+                    "loadSession": false,
+                    "promptCapabilities": { "image": true, "embeddedContext": true },
+                },
+                "authMethods": [],
+            })),
+            "session/set_mode" if params["modeId"] == "locked" => Err((-32602, "Mode is locked".to_string())),
+            "authenticate" | "session/set_mode" | "session/set_model" => Ok(json!({})),
+            "session/new" => Ok(json!({
+                "sessionId": format!("mock-{}", uuid::Uuid::new_v4()),
+                "modes": {
+                    "currentModeId": "default",
+                    "availableModes": [
+                        { "id": "default", "name": "Default" },
+                        { "id": "plan", "name": "Plan" },
+                        { "id": "locked", "name": "Locked" },
+                    ],
+                },
+                "models": {
+                    "currentModelId": "mock-default",
+                    "availableModels": [
+                        { "modelId": "mock-default", "name": "Mock" },
+                        { "modelId": "mock-fast", "name": "Mock Fast" },
+                    ],
+                },
+            })),
+            "session/resume" | "session/load" => Ok(json!({ "sessionId": params["sessionId"] })),
+            "session/fork" => Ok(json!({ "sessionId": format!("mock-{}", uuid::Uuid::new_v4()) })),
+            "session/prompt" => Ok(self.prompt(params).await),
+            _ => Err((-32601, format!("Method not found: {}", method))),
+        }
+    }
+
+    async fn prompt(&self, params: Value) -> Value {
+        let session_id = params["sessionId"].as_str().unwrap_or_default().to_string();
+        let text: String = params["prompt"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if text.contains("tool") {
+            let tool_call_id = "mock-tool-1";
+            self.update(&session_id, json!({
+                "sessionUpdate": "tool_call",
+                "toolCallId": tool_call_id,
+                "title": "Run mock tool",
+                "kind": "execute",
+                "status": "pending",
+                "rawInput": { "command": "echo mock" },
+            }));
+            let response = self
+                .request("session/request_permission", json!({
+                    "sessionId": session_id,
+                    "toolCall": { "toolCallId": tool_call_id, "title": "Run mock tool", "kind": "execute" },
+                    "options": [
+                        { "optionId": "allow", "name": "Allow", "kind": "allow_once" },
+                        { "optionId": "reject", "name": "Reject", "kind": "reject_once" },
+                    ],
+                }))
+                .await
+                .unwrap_or(Value::Null);
+            let allowed = response["outcome"]["optionId"] == "allow";
+            self.update(&session_id, json!({
+                "sessionUpdate": "tool_call_update",
+                "toolCallId": tool_call_id,
+                "status": if allowed { "completed" } else { "failed" },
+                "rawOutput": { "stdout": if allowed { "mock\n" } else { "" } },
+            }));
+        }
+
+        for chunk in ["Mock ", "reply: ", text.as_str()] {
+            self.chunk(&session_id, chunk);
+        }
+        json!({ "stopReason": "end_turn" })
+    }
+}
+
+/// Serve the mock agent until `input` closes
+pub async fn run<R, W>(input: R, mut output: W) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            output.write_all(line.as_bytes()).await?;
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let agent = MockAgent {
+        out_tx,
+        pending: Arc::new(Mutex::new(HashMap::new())),
+        next_id: Arc::new(AtomicU64::new(1)),
+    };
+
+    let mut lines = BufReader::new(input).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let id = message.get("id").cloned();
+        match (id, message["method"].as_str()) {
+            // A request from the client; prompts wait on permission responses,
+            // so every request runs in its own task
+            (Some(id), Some(method)) => {
+                let agent = agent.clone();
+                let method = method.to_string();
+                let params = message["params"].clone();
+                tokio::spawn(async move {
+                    let result = agent.handle(&method, params).await;
+                    agent.respond(id, result);
+                });
+            }
+            // A response to one of our requests
+            (Some(id), None) => {
+                if let Some(tx) = id.as_u64().and_then(|id| agent.pending.lock().remove(&id)) {
+                    let _ = tx.send(message["result"].clone());
+                }
+            }
+            // Notifications (session/cancel) need no reply
+            (None, _) => {}
+        }
+    }
+
+    drop(agent);
+    writer.await.map_err(std::io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_agent_prompt() {
+        let (client, agent) = tokio::io::duplex(64 * 1024);
+        let (agent_read, agent_write) = tokio::io::split(agent);
+        tokio::spawn(run(agent_read, agent_write));
+
+        let (client_read, mut client_write) = tokio::io::split(client);
+        let mut lines = BufReader::new(client_read).lines();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "session/prompt",
+            "params": { "sessionId": "s1", "prompt": [{ "type": "text", "text": "hi" }] },
+        });
+        client_write.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+
+        let mut text = String::new();
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            let message: Value = serde_json::from_str(&line).unwrap();
+            if message["id"] == 1 {
+                assert_eq!(message["result"]["stopReason"], "end_turn");
+                break;
+            }
+            assert_eq!(message["params"]["update"]["sessionUpdate"], "agent_message_chunk");
+            text.push_str(message["params"]["update"]["content"]["text"].as_str().unwrap());
+        }
+        assert_eq!(text, "Mock reply: hi");
+    }
+}
@@ -1,5 +1,10 @@
 pub mod types;
 pub mod client;
+#[cfg(feature = "mock-agent")]
+pub mod mock_agent;
 
 pub use types::*;
-pub use client::{AcpClient, AcpError};
+pub use client::{
+    retry_with_backoff, AcpClient, AcpError, AgentEvent, AgentLog, DEFAULT_CONNECT_ATTEMPTS,
+    DEFAULT_CONNECT_BACKOFF, DEFAULT_REQUEST_TIMEOUT,
+};
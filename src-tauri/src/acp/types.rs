@@ -195,6 +195,7 @@ pub enum StopReason {
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentBlock {
     Text { text: String },
+    #[serde(rename_all = "camelCase")]
     Image { data: String, mime_type: String },
     ResourceLink { uri: String, name: String },
     Resource { resource: ResourceContents },
@@ -203,8 +204,20 @@ pub enum ContentBlock {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ResourceContents {
-    Text { uri: String, text: String },
-    Blob { uri: String, blob: String },
+    #[serde(rename_all = "camelCase")]
+    Text {
+        uri: String,
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Blob {
+        uri: String,
+        blob: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -220,6 +233,13 @@ pub struct SetSessionModeRequest {
     pub mode_id: SessionModeId,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSessionModelRequest {
+    pub session_id: SessionId,
+    pub model_id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ToolCallStatus {
@@ -373,6 +393,71 @@ pub enum PermissionOutcome {
     },
 }
 
+/// Agent request to read a text file through the client (`fs/read_text_file`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadTextFileRequest {
+    pub session_id: SessionId,
+    pub path: String,
+    /// 1-based line to start reading from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Maximum number of lines to read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadTextFileResponse {
+    pub content: String,
+}
+
+/// Agent request to write a text file through the client (`fs/write_text_file`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteTextFileRequest {
+    pub session_id: SessionId,
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// Agent request to run a command in a client terminal (`terminal/create`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTerminalRequest {
+    pub session_id: SessionId,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<EnvVariable>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_byte_limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTerminalResponse {
+    pub terminal_id: String,
+}
+
+/// Agent request targeting an existing terminal
+/// (`terminal/output`, `terminal/wait_for_exit`, `terminal/kill`, `terminal/release`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalRequest {
+    pub session_id: SessionId,
+    pub terminal_id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AvailableCommand {
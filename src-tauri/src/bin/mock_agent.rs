@@ -0,0 +1,9 @@
+//! Scripted ACP agent for integration tests
+//!
+//! Run with: cargo run --features mock-agent --bin aero-mock-agent
+//! Point the app at it with `set_agent_command` or `agent.defaultCommand`.
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    aero_work_lib::acp::mock_agent::run(tokio::io::stdin(), tokio::io::stdout()).await
+}
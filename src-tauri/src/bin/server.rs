@@ -1,24 +1,18 @@
 //! Standalone WebSocket server for web mode
 //!
 //! Run with: cargo run --bin server
-//! Or: cargo run --bin server -- --port 9527
+//! Or: cargo run --bin server -- --port 9527 --host 0.0.0.0
 
 use std::sync::Arc;
 
 use aero_work_lib::core::AppState;
-use aero_work_lib::server::WebSocketServer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use aero_work_lib::core::logging::init_tracing;
+use aero_work_lib::server::{graceful_shutdown, resolve_bind_host_arg, resolve_tls, WebSocketServer};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Initialize logging
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "aero_work=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Initialize logging (stdout, plus a rotating log file with --log-file/AERO_LOG_FILE)
+    let _log_guard = init_tracing("aero_work=debug,tower_http=debug");
 
     // Parse port from args or env
     let port: u16 = std::env::args()
@@ -27,46 +21,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .and_then(|p| p.parse().ok())
         .or_else(|| std::env::var("AERO_WS_PORT").ok().and_then(|p| p.parse().ok()))
         .unwrap_or(9527);
+    // Bind host from args, AERO_BIND_HOST or `server.bindHost` (default 127.0.0.1)
+    let host = resolve_bind_host_arg();
 
     tracing::info!("Starting standalone WebSocket server on preferred port {}", port);
 
     // Create app state
     let state = Arc::new(AppState::new());
 
-    // Start notification forwarding tasks (these would normally be handled by Tauri events)
-    // For standalone mode, we just need to drain the channels
-    let notification_rx = state.notification_rx.write().take();
-    if let Some(mut rx) = notification_rx {
-        tokio::spawn(async move {
-            while rx.recv().await.is_some() {
-                // Notifications are forwarded via WebSocket broadcast in the server
-            }
-        });
-    }
+    // Start WebSocket server (it consumes the notification channels and
+    // forwards them to clients)
+    let server = WebSocketServer::new(state.clone()).with_tls(resolve_tls()).with_host(host.clone());
+    let event_tx = server.event_sender();
+    let actual_port = server.spawn(port).await?;
 
-    let permission_rx = state.permission_rx.write().take();
-    if let Some(mut rx) = permission_rx {
-        tokio::spawn(async move {
-            while rx.recv().await.is_some() {
-                // Permissions are forwarded via WebSocket broadcast in the server
-            }
-        });
-    }
+    tracing::info!("WebSocket server is running on {}:{}", host, actual_port);
 
-    let terminal_rx = state.terminal_output_rx.write().take();
-    if let Some(mut rx) = terminal_rx {
-        tokio::spawn(async move {
-            while rx.recv().await.is_some() {
-                // Terminal output is forwarded via WebSocket broadcast in the server
-            }
-        });
-    }
-
-    // Start WebSocket server
-    let server = WebSocketServer::new(state);
-    let actual_port = server.start(port).await?;
-
-    tracing::info!("WebSocket server is running on port {}", actual_port);
+    tokio::signal::ctrl_c().await.ok();
+    tracing::info!("Shutting down...");
+    graceful_shutdown(&state, &event_tx).await;
 
     Ok(())
 }
@@ -1,9 +1,27 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use tauri::command;
 
+/// Default and maximum depth for directory tree listings
+const DEFAULT_TREE_DEPTH: usize = 3;
+const MAX_TREE_DEPTH: usize = 10;
+/// Maximum number of entries returned by a directory tree listing
+const MAX_TREE_ENTRIES: usize = 5000;
+/// Default cap on content search matches
+const DEFAULT_SEARCH_RESULTS: usize = 1000;
+/// Files larger than this are skipped by content search
+const MAX_SEARCH_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Matching lines are cut to this many characters
+const MAX_SEARCH_LINE_CHARS: usize = 500;
+/// A delete preview stops counting after this many entries
+const MAX_PREVIEW_ENTRIES: usize = 100_000;
+/// Bytes read to classify a file whose extension says nothing
+const SNIFF_BYTES: u64 = 512;
+
 /// Expand ~ to home directory
 fn expand_tilde(path: &str) -> PathBuf {
     if path.starts_with("~/") {
@@ -27,11 +45,106 @@ pub struct FileEntry {
     pub is_hidden: bool,
     pub size: Option<u64>,
     pub modified: Option<u64>,
+    /// The entry itself is a symlink (`is_dir`, `size` and `kind` describe the link)
+    #[serde(default)]
+    pub is_symlink: bool,
+    /// Coarse type for picking an icon and a read path
+    pub kind: FileKind,
+    /// Matched the ignore rules but was listed because ignored entries were requested
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub force_included: bool,
+}
+
+/// Coarse type of a directory entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Directory,
+    Symlink,
+    Text,
+    Image,
+    Binary,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "ico", "svg", "tif", "tiff", "avif", "heic"];
+const BINARY_EXTENSIONS: &[&str] = &[
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "tar", "jar", "pdf", "exe", "dll", "so", "dylib", "a", "o",
+    "class", "wasm", "pyc", "bin", "db", "sqlite", "mp3", "mp4", "mov", "avi", "mkv", "webm", "wav", "flac", "ogg",
+    "woff", "woff2", "ttf", "otf", "eot", "psd", "dmg", "iso",
+];
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rs", "ts", "tsx", "js", "jsx", "mjs", "cjs", "json", "jsonl", "toml", "yaml", "yml",
+    "html", "htm", "css", "scss", "less", "py", "go", "java", "kt", "swift", "c", "h", "cc", "cpp", "hpp", "cs",
+    "rb", "php", "sh", "bash", "zsh", "fish", "sql", "xml", "csv", "ini", "cfg", "conf", "env", "log", "lock",
+    "vue", "svelte", "gradle", "properties", "dockerfile",
+];
+
+/// Classify a file from its extension, falling back to sniffing its first
+/// bytes when the extension is unknown or missing
+fn file_kind(path: &Path, size: u64) -> FileKind {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    if let Some(extension) = extension.as_deref() {
+        if IMAGE_EXTENSIONS.contains(&extension) {
+            return FileKind::Image;
+        }
+        if BINARY_EXTENSIONS.contains(&extension) {
+            return FileKind::Binary;
+        }
+        if TEXT_EXTENSIONS.contains(&extension) {
+            return FileKind::Text;
+        }
+    }
+    if size == 0 {
+        return FileKind::Text;
+    }
+    match read_head(path, SNIFF_BYTES) {
+        Ok((bytes, truncated)) => sniff_kind(&bytes, truncated),
+        Err(_) => FileKind::Binary,
+    }
+}
+
+/// Classify the start of a file: known image signatures, then text if it is
+/// UTF-8 without null bytes
+fn sniff_kind(bytes: &[u8], truncated: bool) -> FileKind {
+    const IMAGE_SIGNATURES: &[&[u8]] = &[b"\x89PNG", b"\xFF\xD8\xFF", b"GIF8", b"BM", b"\x00\x00\x01\x00"];
+    if IMAGE_SIGNATURES.iter().any(|sig| bytes.starts_with(sig))
+        || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP"))
+    {
+        return FileKind::Image;
+    }
+    if bytes.contains(&0) {
+        return FileKind::Binary;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => FileKind::Text,
+        // A multi-byte character may be cut off at the end of the sample
+        Err(e) if truncated && e.error_len().is_none() => FileKind::Text,
+        Err(_) => FileKind::Binary,
+    }
 }
 
 // Re-export for WebSocket server
 pub type DirEntry = FileEntry;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTreeEntry {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    /// Directory contents (None for files and directories below the depth limit)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<FileTreeEntry>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTree {
+    pub path: String,
+    pub entries: Vec<FileTreeEntry>,
+    /// Whether the entry cap was hit and some entries were left out
+    pub truncated: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileContent {
@@ -57,6 +170,110 @@ pub struct BinaryFileContent {
     pub content: String, // base64 encoded
     pub size: u64,
     pub modified: Option<u64>,
+    /// Whether `content` holds only the start of the file
+    pub truncated: bool,
+}
+
+/// Text file content that may have been cut at a size limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialFileContent {
+    pub path: String,
+    pub content: String,
+    /// Full size of the file in bytes
+    pub size: u64,
+    /// Whether `content` holds only the start of the file
+    pub truncated: bool,
+}
+
+/// What deleting a path would remove
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletePreview {
+    pub path: String,
+    pub is_dir: bool,
+    /// Files (and symlinks) that would be removed
+    pub files: u64,
+    /// Directories that would be removed, including `path` itself
+    pub directories: u64,
+    /// Total size of the files in bytes
+    pub total_bytes: u64,
+    /// Whether counting stopped at the entry cap, so the totals are a lower bound
+    pub truncated: bool,
+}
+
+/// Whether a rename would succeed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamePreview {
+    pub from: String,
+    pub to: String,
+    /// Something already exists at `to` (renames never overwrite)
+    pub collision: bool,
+    /// What exists at `to` is a directory
+    pub target_is_dir: bool,
+    /// `to` is inside the directory being renamed
+    pub into_itself: bool,
+    /// Why the rename would fail, if it would
+    pub error: Option<String>,
+}
+
+/// A changed region of a file, with 1-based line numbers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+}
+
+/// What a write changed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileWriteDiff {
+    /// Unified diff of the old and new content (None when the old content was binary)
+    pub diff: Option<String>,
+    pub hunks: Vec<DiffHunk>,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    /// The file didn't exist before the write
+    pub created: bool,
+    /// The old content wasn't text, so no diff was computed
+    pub binary: bool,
+}
+
+/// Options for searching file contents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    /// Treat the query as a regular expression instead of literal text
+    pub regex: bool,
+    /// Only search files matching one of these globs (relative to the root)
+    pub include: Vec<String>,
+    /// Skip files matching any of these globs (relative to the root)
+    pub exclude: Vec<String>,
+    pub max_results: Option<usize>,
+    pub show_hidden: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub path: String,
+    /// 1-based line number
+    pub line_number: usize,
+    pub line: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSummary {
+    pub matches: usize,
+    pub files_searched: usize,
+    /// Whether the search stopped at max_results
+    pub truncated: bool,
 }
 
 fn detect_language(path: &str) -> Option<String> {
@@ -98,89 +315,97 @@ fn is_hidden(name: &str) -> bool {
     name.starts_with('.')
 }
 
+/// Build artifacts and VCS metadata skipped when copying directories
 fn should_ignore(name: &str) -> bool {
-    matches!(
-        name,
-        "node_modules"
-            | "target"
-            | "dist"
-            | "build"
-            | ".git"
-            | ".svn"
-            | "__pycache__"
-            | ".DS_Store"
-            | "Thumbs.db"
-    )
+    crate::core::config::DEFAULT_IGNORE.contains(&name)
 }
 
-#[command]
-pub async fn list_directory(path: String, show_hidden: bool) -> Result<Vec<FileEntry>, String> {
-    let dir_path = PathBuf::from(&path);
+/// Which entries directory listings skip: `files.ignore` globs matched against
+/// entry names and, with `files.respectGitignore`, the .gitignore files from the
+/// repository root down to the listed directory
+pub struct IgnoreRules {
+    names: globset::GlobSet,
+    gitignore: bool,
+    /// List ignored entries anyway, marked `forceIncluded`
+    include_ignored: bool,
+    /// Parsed .gitignore per directory (None when it has none)
+    gitignores: std::cell::RefCell<std::collections::HashMap<PathBuf, Option<ignore::gitignore::Gitignore>>>,
+}
 
-    if !dir_path.exists() {
-        return Err(format!("Directory does not exist: {}", path));
+impl IgnoreRules {
+    /// Rules from the config, with the overrides of the project containing `dir`
+    pub fn from_config(dir: &Path, include_ignored: bool) -> Self {
+        let files = crate::core::project_config::effective_config(dir).files;
+        Self::new(&files.ignore, files.respect_gitignore, include_ignored)
     }
 
-    if !dir_path.is_dir() {
-        return Err(format!("Path is not a directory: {}", path));
+    pub fn new(patterns: &[String], gitignore: bool, include_ignored: bool) -> Self {
+        let names = build_globset(patterns).ok().flatten().unwrap_or_else(globset::GlobSet::empty);
+        Self {
+            names,
+            gitignore,
+            include_ignored,
+            gitignores: Default::default(),
+        }
     }
 
-    let mut entries = Vec::new();
-
-    let read_dir = fs::read_dir(&dir_path).map_err(|e| format!("Failed to read directory: {}", e))?;
-
-    for entry in read_dir {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        let name = entry.file_name().to_string_lossy().to_string();
-
-        // Skip ignored directories
-        if should_ignore(&name) {
-            continue;
+    fn is_ignored(&self, path: &Path, name: &str, is_dir: bool) -> bool {
+        if self.names.is_match(name) {
+            return true;
         }
-
-        // Skip hidden files unless requested
-        let hidden = is_hidden(&name);
-        if hidden && !show_hidden {
-            continue;
+        if !self.gitignore {
+            return false;
         }
+        // Deeper .gitignore files take precedence, and a whitelist (`!pattern`) wins
+        for dir in path.ancestors().skip(1) {
+            let ignored = self.with_gitignore(dir, |gitignore| {
+                let matched = gitignore.matched(path, is_dir);
+                (!matched.is_none()).then(|| matched.is_ignore())
+            });
+            if let Some(ignored) = ignored.flatten() {
+                return ignored;
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        false
+    }
 
-        let metadata = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
-
-        let is_dir = metadata.is_dir();
-        let size = if is_dir { None } else { Some(metadata.len()) };
-        let modified = metadata
-            .modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs());
-
-        entries.push(FileEntry {
-            name,
-            path: entry.path().to_string_lossy().to_string(),
-            is_dir,
-            is_hidden: hidden,
-            size,
-            modified,
+    fn with_gitignore<T>(&self, dir: &Path, f: impl FnOnce(&ignore::gitignore::Gitignore) -> T) -> Option<T> {
+        let mut cache = self.gitignores.borrow_mut();
+        let gitignore = cache.entry(dir.to_path_buf()).or_insert_with(|| {
+            let file = dir.join(".gitignore");
+            if !file.is_file() {
+                return None;
+            }
+            let (gitignore, error) = ignore::gitignore::Gitignore::new(&file);
+            if let Some(e) = error {
+                tracing::debug!("Problem parsing {}: {}", file.display(), e);
+            }
+            Some(gitignore)
         });
+        gitignore.as_ref().map(f)
     }
+}
 
-    // Sort: directories first, then alphabetically
-    entries.sort_by(|a, b| {
-        match (a.is_dir, b.is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-        }
-    });
+#[command]
+pub async fn list_directory(
+    path: String,
+    show_hidden: bool,
+    include_ignored: Option<bool>,
+) -> Result<Vec<FileEntry>, String> {
+    list_directory_impl(&path, show_hidden, include_ignored.unwrap_or(false)).await
+}
 
-    Ok(entries)
+#[command]
+pub async fn list_directory_tree(
+    path: String,
+    max_depth: Option<usize>,
+    show_hidden: bool,
+    include_ignored: Option<bool>,
+) -> Result<FileTree, String> {
+    list_directory_tree_impl(&path, max_depth, show_hidden, include_ignored.unwrap_or(false)).await
 }
 
 #[command]
@@ -195,10 +420,10 @@ pub async fn read_file(path: String) -> Result<FileContent, String> {
         return Err(format!("Path is not a file: {}", path));
     }
 
-    // Check file size (limit to 10MB)
     let metadata = fs::metadata(&file_path).map_err(|e| format!("Failed to read metadata: {}", e))?;
-    if metadata.len() > 10 * 1024 * 1024 {
-        return Err("File is too large (max 10MB)".to_string());
+    let max_bytes = crate::core::ConfigManager::new().config().files.max_text_bytes;
+    if metadata.len() > max_bytes {
+        return Err(too_large_error(max_bytes));
     }
 
     let content = fs::read_to_string(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
@@ -223,7 +448,8 @@ pub async fn write_file(path: String, content: String) -> Result<(), String> {
         }
     }
 
-    fs::write(&file_path, &content).map_err(|e| format!("Failed to write file: {}", e))?;
+    write_atomic(&file_path, |file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write file: {}", e))?;
 
     Ok(())
 }
@@ -261,21 +487,11 @@ pub async fn create_directory(path: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Delete a file or directory, moving it to the OS trash unless `to_trash` is false.
+/// Returns whether it went to the trash.
 #[command]
-pub async fn delete_path(path: String) -> Result<(), String> {
-    let target_path = PathBuf::from(&path);
-
-    if !target_path.exists() {
-        return Err(format!("Path does not exist: {}", path));
-    }
-
-    if target_path.is_dir() {
-        fs::remove_dir_all(&target_path).map_err(|e| format!("Failed to delete directory: {}", e))?;
-    } else {
-        fs::remove_file(&target_path).map_err(|e| format!("Failed to delete file: {}", e))?;
-    }
-
-    Ok(())
+pub async fn delete_path(path: String, to_trash: Option<bool>) -> Result<bool, String> {
+    delete_path_impl(&path, to_trash.unwrap_or(true)).await
 }
 
 #[command]
@@ -283,9 +499,131 @@ pub async fn rename_path(old_path: String, new_path: String) -> Result<(), Strin
     rename_path_impl(&old_path, &new_path).await
 }
 
+#[command]
+pub async fn copy_path(
+    from: String,
+    to: String,
+    overwrite: Option<bool>,
+    include_ignored: Option<bool>,
+) -> Result<(), String> {
+    copy_path_impl(&from, &to, overwrite.unwrap_or(false), include_ignored.unwrap_or(false)).await
+}
+
 // Implementation functions for reuse by WebSocket server
 
-pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<FileEntry>, String> {
+fn build_globset(patterns: &[String]) -> Result<Option<globset::GlobSet>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = globset::Glob::new(pattern).map_err(|e| format!("Invalid glob {}: {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map(Some).map_err(|e| format!("Invalid globs: {}", e))
+}
+
+/// Search file contents under `root`, calling `on_match` for each matching line.
+/// Ignored directories, binary files (null bytes) and files over 5MB are skipped;
+/// symlinked directories are not followed. Stops early when `on_match` returns false.
+/// Blocking: run it on a blocking thread.
+pub fn search_files_impl(
+    root: &str,
+    query: &str,
+    options: &SearchOptions,
+    mut on_match: impl FnMut(SearchMatch) -> bool,
+) -> Result<SearchSummary, String> {
+    let root_path = expand_tilde(root);
+    if !root_path.is_dir() {
+        return Err(format!("Path is not a directory: {}", root));
+    }
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+
+    let pattern = if options.regex { query.to_string() } else { regex::escape(query) };
+    let matcher = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e))?;
+    let include = build_globset(&options.include)?;
+    let exclude = build_globset(&options.exclude)?;
+    let max_results = options.max_results.unwrap_or(DEFAULT_SEARCH_RESULTS);
+    let rules = IgnoreRules::from_config(&root_path, false);
+
+    let mut summary = SearchSummary { matches: 0, files_searched: 0, truncated: false };
+    let mut pending_dirs = vec![root_path.clone()];
+
+    while let Some(dir) = pending_dirs.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = read_dir.filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_hidden(&name) && !options.show_hidden {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if rules.is_ignored(&path, &name, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending_dirs.push(path);
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+
+            let relative = path.strip_prefix(&root_path).unwrap_or(&path);
+            if include.as_ref().is_some_and(|set| !set.is_match(relative))
+                || exclude.as_ref().is_some_and(|set| set.is_match(relative))
+            {
+                continue;
+            }
+            if fs::metadata(&path).map(|m| m.len() > MAX_SEARCH_FILE_SIZE).unwrap_or(true) {
+                continue;
+            }
+            let Ok(bytes) = fs::read(&path) else {
+                continue;
+            };
+            if bytes[..bytes.len().min(8192)].contains(&0) {
+                continue;
+            }
+
+            summary.files_searched += 1;
+            let content = String::from_utf8_lossy(&bytes);
+            for (index, line) in content.lines().enumerate() {
+                if !matcher.is_match(line) {
+                    continue;
+                }
+                if summary.matches >= max_results {
+                    summary.truncated = true;
+                    return Ok(summary);
+                }
+                summary.matches += 1;
+                let keep_going = on_match(SearchMatch {
+                    path: path.to_string_lossy().to_string(),
+                    line_number: index + 1,
+                    line: line.chars().take(MAX_SEARCH_LINE_CHARS).collect(),
+                });
+                if !keep_going {
+                    return Ok(summary);
+                }
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+pub async fn list_directory_impl(path: &str, show_hidden: bool, include_ignored: bool) -> Result<Vec<FileEntry>, String> {
     let dir_path = PathBuf::from(path);
 
     if !dir_path.exists() {
@@ -296,9 +634,88 @@ pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<Fi
         return Err(format!("Path is not a directory: {}", path));
     }
 
+    read_dir_entries(&dir_path, show_hidden, &IgnoreRules::from_config(&dir_path, include_ignored))
+}
+
+/// List a directory and its subdirectories down to `max_depth` levels
+/// (1 = the directory itself, like list_directory)
+pub async fn list_directory_tree_impl(
+    path: &str,
+    max_depth: Option<usize>,
+    show_hidden: bool,
+    include_ignored: bool,
+) -> Result<FileTree, String> {
+    let dir_path = expand_tilde(path);
+
+    if !dir_path.exists() {
+        return Err(format!("Directory does not exist: {}", path));
+    }
+
+    if !dir_path.is_dir() {
+        return Err(format!("Path is not a directory: {}", path));
+    }
+
+    let max_depth = max_depth.unwrap_or(DEFAULT_TREE_DEPTH).clamp(1, MAX_TREE_DEPTH);
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = dir_path.canonicalize() {
+        visited.insert(canonical);
+    }
+    let mut remaining = MAX_TREE_ENTRIES;
+    let mut truncated = false;
+
+    let rules = IgnoreRules::from_config(&dir_path, include_ignored);
+    let entries = build_tree(&dir_path, max_depth, show_hidden, &rules, &mut visited, &mut remaining, &mut truncated)?;
+
+    Ok(FileTree {
+        path: dir_path.to_string_lossy().to_string(),
+        entries,
+        truncated,
+    })
+}
+
+fn build_tree(
+    dir_path: &Path,
+    depth: usize,
+    show_hidden: bool,
+    rules: &IgnoreRules,
+    visited: &mut HashSet<PathBuf>,
+    remaining: &mut usize,
+    truncated: &mut bool,
+) -> Result<Vec<FileTreeEntry>, String> {
+    let mut entries = read_dir_entries(dir_path, show_hidden, rules)?;
+    if entries.len() > *remaining {
+        entries.truncate(*remaining);
+        *truncated = true;
+    }
+    *remaining -= entries.len();
+
+    let mut tree = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let mut children = None;
+        if entry.is_dir && depth > 1 && *remaining > 0 {
+            // Symlinked directories can point back up the tree
+            let child_path = PathBuf::from(&entry.path);
+            let first_visit = child_path
+                .canonicalize()
+                .map(|canonical| visited.insert(canonical))
+                .unwrap_or(false);
+            if first_visit {
+                // Unreadable subdirectories are shown without children
+                children = build_tree(&child_path, depth - 1, show_hidden, rules, visited, remaining, truncated).ok();
+            }
+        }
+        tree.push(FileTreeEntry { entry, children });
+    }
+
+    Ok(tree)
+}
+
+/// Read one directory level, skipping ignored (unless requested) and optionally
+/// hidden entries. Directories come first, then files, each sorted by name.
+fn read_dir_entries(dir_path: &Path, show_hidden: bool, rules: &IgnoreRules) -> Result<Vec<FileEntry>, String> {
     let mut entries = Vec::new();
 
-    let read_dir = fs::read_dir(&dir_path).map_err(|e| format!("Failed to read directory: {}", e))?;
+    let read_dir = fs::read_dir(dir_path).map_err(|e| format!("Failed to read directory: {}", e))?;
 
     for entry in read_dir {
         let entry = match entry {
@@ -308,11 +725,6 @@ pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<Fi
 
         let name = entry.file_name().to_string_lossy().to_string();
 
-        // Skip ignored directories
-        if should_ignore(&name) {
-            continue;
-        }
-
         // Skip hidden files unless show_hidden is true
         let hidden = is_hidden(&name);
         if hidden && !show_hidden {
@@ -325,12 +737,26 @@ pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<Fi
         };
 
         let is_dir = metadata.is_dir();
+        let is_symlink = metadata.file_type().is_symlink();
+
+        // Skip ignored entries unless they were asked for
+        let ignored = rules.is_ignored(&entry.path(), &name, is_dir);
+        if ignored && !rules.include_ignored {
+            continue;
+        }
         let size = if is_dir { None } else { Some(metadata.len()) };
         let modified = metadata
             .modified()
             .ok()
             .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
             .map(|d| d.as_secs());
+        let kind = if is_dir {
+            FileKind::Directory
+        } else if is_symlink {
+            FileKind::Symlink
+        } else {
+            file_kind(&entry.path(), metadata.len())
+        };
 
         entries.push(FileEntry {
             name,
@@ -339,6 +765,9 @@ pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<Fi
             is_hidden: hidden,
             size,
             modified,
+            is_symlink,
+            kind,
+            force_included: ignored,
         });
     }
 
@@ -354,7 +783,15 @@ pub async fn list_directory_impl(path: &str, show_hidden: bool) -> Result<Vec<Fi
     Ok(entries)
 }
 
+/// Read a text file, rejecting files over the configured `files.maxTextBytes`
 pub async fn read_file_impl(path: &str) -> Result<String, String> {
+    let max_bytes = crate::core::ConfigManager::new().config().files.max_text_bytes;
+    read_file_partial_impl(path, max_bytes, false).await.map(|file| file.content)
+}
+
+/// Read a text file of at most `max_bytes`. Larger files are an error unless
+/// `truncate` is set, in which case the first `max_bytes` are returned.
+pub async fn read_file_partial_impl(path: &str, max_bytes: u64, truncate: bool) -> Result<PartialFileContent, String> {
     let file_path = expand_tilde(path);
 
     if !file_path.exists() {
@@ -365,13 +802,55 @@ pub async fn read_file_impl(path: &str) -> Result<String, String> {
         return Err(format!("Path is not a file: {}", path));
     }
 
-    // Check file size (limit to 10MB)
     let metadata = fs::metadata(&file_path).map_err(|e| format!("Failed to read metadata: {}", e))?;
-    if metadata.len() > 10 * 1024 * 1024 {
-        return Err("File is too large (max 10MB)".to_string());
+    let size = metadata.len();
+    if size > max_bytes && !truncate {
+        return Err(too_large_error(max_bytes));
     }
 
-    fs::read_to_string(&file_path).map_err(|e| format!("Failed to read file: {}", e))
+    let (mut bytes, truncated) = read_head(&file_path, max_bytes)?;
+    if truncated {
+        // Drop a multi-byte character cut in half at the limit
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            if e.error_len().is_none() {
+                bytes.truncate(e.valid_up_to());
+            }
+        }
+    }
+    let content = String::from_utf8(bytes)
+        .map_err(|_| "Failed to read file: stream did not contain valid UTF-8".to_string())?;
+
+    Ok(PartialFileContent {
+        path: path.to_string(),
+        content,
+        size,
+        truncated,
+    })
+}
+
+/// Read up to `max_bytes` from the start of a file; the flag is set when more remained
+fn read_head(path: &Path, max_bytes: u64) -> Result<(Vec<u8>, bool), String> {
+    use std::io::Read;
+
+    let file = fs::File::open(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let mut bytes = Vec::new();
+    // One extra byte tells whether the file goes on past the limit
+    file.take(max_bytes.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let truncated = bytes.len() as u64 > max_bytes;
+    if truncated {
+        bytes.truncate(max_bytes as usize);
+    }
+    Ok((bytes, truncated))
+}
+
+fn too_large_error(max_bytes: u64) -> String {
+    if max_bytes.is_multiple_of(1024 * 1024) {
+        format!("File is too large (max {}MB)", max_bytes / (1024 * 1024))
+    } else {
+        format!("File is too large (max {} bytes)", max_bytes)
+    }
 }
 
 pub async fn write_file_impl(path: &str, content: &str) -> Result<(), String> {
@@ -384,11 +863,113 @@ pub async fn write_file_impl(path: &str, content: &str) -> Result<(), String> {
         }
     }
 
-    fs::write(&file_path, content).map_err(|e| format!("Failed to write file: {}", e))?;
+    write_atomic(&file_path, |file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write file: {}", e))?;
 
     Ok(())
 }
 
+/// Write a file through a temporary file in the same directory that is renamed
+/// over the target, so a crash or full disk mid-write never leaves a truncated
+/// file. The temp file is fsynced first when `files.syncWrites` is set. An
+/// existing file keeps its permissions, and a symlink is followed rather than
+/// replaced.
+pub fn write_atomic(path: &Path, write: impl FnOnce(&mut fs::File) -> std::io::Result<()>) -> std::io::Result<()> {
+    let sync = crate::core::ConfigManager::new().config().files.sync_writes;
+    write_atomic_with(path, sync, write)
+}
+
+fn write_atomic_with(
+    path: &Path,
+    sync: bool,
+    write: impl FnOnce(&mut fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let target = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = dir.join(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4().simple()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        write(&mut file)?;
+        if sync {
+            file.sync_all()?;
+        }
+        if let Ok(meta) = fs::metadata(&target) {
+            fs::set_permissions(&temp, meta.permissions())?;
+        }
+        fs::rename(&temp, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Diff `old` against `new` line by line, with 3 lines of context per hunk
+pub fn diff_text(path: &str, old: &str, new: &str) -> FileWriteDiff {
+    use similar::{ChangeTag, TextDiff};
+
+    let diff = TextDiff::from_lines(old, new);
+    let mut result = FileWriteDiff::default();
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => result.lines_added += 1,
+            ChangeTag::Delete => result.lines_removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    for group in diff.grouped_ops(3) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        result.hunks.push(DiffHunk {
+            old_start: old_range.start + 1,
+            old_lines: old_range.len(),
+            new_start: new_range.start + 1,
+            new_lines: new_range.len(),
+        });
+    }
+    if !result.hunks.is_empty() {
+        let unified = diff
+            .unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string();
+        result.diff = Some(unified);
+    }
+    result
+}
+
+/// Write a text file and report what changed
+pub async fn write_file_with_diff_impl(path: &str, content: &str) -> Result<FileWriteDiff, String> {
+    let file_path = expand_tilde(path);
+    let old = match fs::read(&file_path) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read file: {}", e)),
+    };
+
+    let result = match &old {
+        None => FileWriteDiff { created: true, ..diff_text(path, "", content) },
+        Some(bytes) => match std::str::from_utf8(bytes) {
+            Ok(text) if !text.contains('\0') => diff_text(path, text, content),
+            _ => FileWriteDiff { binary: true, ..Default::default() },
+        },
+    };
+
+    write_file_impl(path, content).await?;
+    Ok(result)
+}
+
 // Write binary file from base64 encoded content
 pub async fn write_file_binary_impl(path: &str, content: &str) -> Result<(), String> {
     let file_path = PathBuf::from(path);
@@ -403,7 +984,8 @@ pub async fn write_file_binary_impl(path: &str, content: &str) -> Result<(), Str
     // Decode base64 content
     let bytes = BASE64.decode(content).map_err(|e| format!("Failed to decode base64: {}", e))?;
 
-    fs::write(&file_path, bytes).map_err(|e| format!("Failed to write file: {}", e))?;
+    write_atomic(&file_path, |file| file.write_all(&bytes))
+        .map_err(|e| format!("Failed to write file: {}", e))?;
 
     Ok(())
 }
@@ -439,20 +1021,36 @@ pub async fn create_directory_impl(path: &str) -> Result<(), String> {
     Ok(())
 }
 
-pub async fn delete_path_impl(path: &str) -> Result<(), String> {
+/// Delete a path, moving it to the OS trash when `to_trash` is set. Where the
+/// platform has no trash (see `has_trash`) the path is deleted permanently; a
+/// trash that fails is an error, so nothing is lost by surprise. Returns
+/// whether it went to the trash.
+pub async fn delete_path_impl(path: &str, to_trash: bool) -> Result<bool, String> {
     let target_path = PathBuf::from(path);
 
     if !target_path.exists() {
         return Err(format!("Path does not exist: {}", path));
     }
 
+    if to_trash && has_trash() {
+        trash::delete(&target_path).map_err(|e| format!("Failed to move {} to trash: {}", path, e))?;
+        return Ok(true);
+    }
+
     if target_path.is_dir() {
         fs::remove_dir_all(&target_path).map_err(|e| format!("Failed to delete directory: {}", e))?;
     } else {
         fs::remove_file(&target_path).map_err(|e| format!("Failed to delete file: {}", e))?;
     }
 
-    Ok(())
+    Ok(false)
+}
+
+/// Whether this platform has a trash to move deleted files to. Windows and macOS
+/// always do; elsewhere the freedesktop trash needs a data directory
+/// (`$XDG_DATA_HOME` or `~/.local/share`) to live in.
+fn has_trash() -> bool {
+    cfg!(any(windows, target_os = "macos")) || dirs::data_dir().is_some_and(|dir| dir.is_dir())
 }
 
 pub async fn rename_path_impl(old_path: &str, new_path: &str) -> Result<(), String> {
@@ -472,6 +1070,154 @@ pub async fn rename_path_impl(old_path: &str, new_path: &str) -> Result<(), Stri
     Ok(())
 }
 
+/// Count what `delete_path_impl` would remove, without following symlinks
+pub fn preview_delete_impl(path: &str) -> Result<DeletePreview, String> {
+    preview_delete_limited(path, MAX_PREVIEW_ENTRIES)
+}
+
+fn preview_delete_limited(path: &str, max_entries: usize) -> Result<DeletePreview, String> {
+    let root = PathBuf::from(path);
+    let metadata = fs::symlink_metadata(&root).map_err(|_| format!("Path does not exist: {}", path))?;
+
+    let mut preview = DeletePreview {
+        path: path.to_string(),
+        is_dir: metadata.is_dir(),
+        files: 0,
+        directories: 0,
+        total_bytes: 0,
+        truncated: false,
+    };
+    if !metadata.is_dir() {
+        preview.files = 1;
+        preview.total_bytes = metadata.len();
+        return Ok(preview);
+    }
+
+    preview.directories = 1;
+    let mut visited = 1;
+    let mut pending = vec![root];
+    'walk: while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if visited >= max_entries {
+                preview.truncated = true;
+                break 'walk;
+            }
+            visited += 1;
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                preview.directories += 1;
+                pending.push(entry.path());
+            } else {
+                preview.files += 1;
+                preview.total_bytes += metadata.len();
+            }
+        }
+    }
+
+    Ok(preview)
+}
+
+/// Check what `rename_path_impl` would do, without touching anything
+pub fn preview_rename_impl(old_path: &str, new_path: &str) -> Result<RenamePreview, String> {
+    let old = PathBuf::from(old_path);
+    let new = PathBuf::from(new_path);
+
+    if !old.exists() {
+        return Err(format!("Path does not exist: {}", old_path));
+    }
+
+    let collision = new.exists();
+    let into_itself = old.is_dir()
+        && match (new.parent(), old.canonicalize()) {
+            (Some(parent), Ok(old_real)) => parent.canonicalize().is_ok_and(|p| p.starts_with(&old_real)),
+            _ => false,
+        };
+    let error = if collision {
+        Some(format!("Target path already exists: {}", new_path))
+    } else if into_itself {
+        Some(format!("Cannot move a directory into itself: {}", new_path))
+    } else if !new.parent().is_some_and(|parent| parent.as_os_str().is_empty() || parent.is_dir()) {
+        Some(format!("Target directory does not exist: {}", new_path))
+    } else {
+        None
+    };
+
+    Ok(RenamePreview {
+        from: old_path.to_string(),
+        to: new_path.to_string(),
+        collision,
+        target_is_dir: new.is_dir(),
+        into_itself,
+        error,
+    })
+}
+
+/// Copy a file, or a directory recursively. Directory copies skip ignored entries
+/// (`node_modules`, `.git`, ...) unless `include_ignored` is set, and merge into
+/// an existing target when `overwrite` is set.
+pub async fn copy_path_impl(from: &str, to: &str, overwrite: bool, include_ignored: bool) -> Result<(), String> {
+    let source = PathBuf::from(from);
+    let target = PathBuf::from(to);
+
+    if !source.exists() {
+        return Err(format!("Path does not exist: {}", from));
+    }
+
+    if target.exists() && !overwrite {
+        return Err(format!("Target path already exists: {}", to));
+    }
+
+    if source.is_dir() {
+        let source_real = source.canonicalize().map_err(|e| format!("Failed to resolve {}: {}", from, e))?;
+        let target_real = match (target.parent(), target.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize().ok().map(|p| p.join(name)),
+            _ => None,
+        };
+        if target_real.is_some_and(|t| t.starts_with(&source_real)) {
+            return Err(format!("Cannot copy a directory into itself: {}", to));
+        }
+        copy_dir(&source, &target, include_ignored)
+    } else {
+        fs::copy(&source, &target).map_err(|e| format!("Failed to copy file: {}", e))?;
+        Ok(())
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path, include_ignored: bool) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    let entries = fs::read_dir(from).map_err(|e| format!("Failed to read directory: {}", e))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if !include_ignored && should_ignore(&name.to_string_lossy()) {
+            continue;
+        }
+        let source = entry.path();
+        let target = to.join(&name);
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_symlink() {
+            // Symlinks are not copied: following one could pull in files from
+            // outside the sandbox
+            continue;
+        } else if file_type.is_dir() {
+            copy_dir(&source, &target, include_ignored)?;
+        } else {
+            fs::copy(&source, &target)
+                .map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+        }
+    }
+
+    Ok(())
+}
+
 // Get file info without reading content
 pub async fn get_file_info_impl(path: &str) -> Result<FileInfo, String> {
     let file_path = PathBuf::from(path);
@@ -502,8 +1248,8 @@ pub async fn get_file_info_impl(path: &str) -> Result<FileInfo, String> {
     })
 }
 
-// Read file as binary (base64 encoded)
-pub async fn read_file_binary_impl(path: &str) -> Result<BinaryFileContent, String> {
+// Read file as binary (base64 encoded), at most `max_bytes` (see `read_file_partial_impl`)
+pub async fn read_file_binary_impl(path: &str, max_bytes: u64, truncate: bool) -> Result<BinaryFileContent, String> {
     let file_path = PathBuf::from(path);
 
     if !file_path.exists() {
@@ -516,12 +1262,11 @@ pub async fn read_file_binary_impl(path: &str) -> Result<BinaryFileContent, Stri
 
     let metadata = fs::metadata(&file_path).map_err(|e| format!("Failed to read metadata: {}", e))?;
 
-    // Limit to 50MB for binary files
-    if metadata.len() > 50 * 1024 * 1024 {
-        return Err("File is too large (max 50MB)".to_string());
+    if metadata.len() > max_bytes && !truncate {
+        return Err(too_large_error(max_bytes));
     }
 
-    let bytes = fs::read(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let (bytes, truncated) = read_head(&file_path, max_bytes)?;
     let content = BASE64.encode(&bytes);
 
     let modified = metadata
@@ -535,5 +1280,212 @@ pub async fn read_file_binary_impl(path: &str) -> Result<BinaryFileContent, Stri
         content,
         size: metadata.len(),
         modified,
+        truncated,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_path_buf();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_file_kind() {
+        let (_tmp, root) = temp_dir();
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("logo.png"), "not really a png").unwrap();
+        fs::write(root.join("Makefile"), "all:\n\techo ok\n").unwrap();
+        fs::write(root.join("picture"), b"\x89PNG\r\n\x1a\n").unwrap();
+        fs::write(root.join("blob"), [0u8, 1, 2, 3]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("main.rs"), root.join("link.rs")).unwrap();
+
+        let entries = futures::executor::block_on(list_directory_impl(&root.to_string_lossy(), true, true)).unwrap();
+        let kind = |name: &str| entries.iter().find(|e| e.name == name).map(|e| e.kind);
+        assert_eq!(kind("dir"), Some(FileKind::Directory));
+        assert_eq!(kind("main.rs"), Some(FileKind::Text));
+        assert_eq!(kind("logo.png"), Some(FileKind::Image));
+        assert_eq!(kind("Makefile"), Some(FileKind::Text));
+        assert_eq!(kind("picture"), Some(FileKind::Image));
+        assert_eq!(kind("blob"), Some(FileKind::Binary));
+        #[cfg(unix)]
+        {
+            let link = entries.iter().find(|e| e.name == "link.rs").unwrap();
+            assert!(link.is_symlink);
+            assert_eq!(link.kind, FileKind::Symlink);
+        }
+
+        // A multi-byte character cut off by the sample is still text
+        assert_eq!(sniff_kind("é".as_bytes().get(..1).unwrap(), true), FileKind::Text);
+        assert_eq!(sniff_kind("é".as_bytes().get(..1).unwrap(), false), FileKind::Binary);
+    }
+
+    #[test]
+    fn test_copy_nested_directory() {
+        let (_tmp, root) = temp_dir();
+        let source = root.join("src");
+        fs::create_dir_all(source.join("a/b")).unwrap();
+        fs::create_dir_all(source.join("node_modules/pkg")).unwrap();
+        fs::write(source.join("top.txt"), "top").unwrap();
+        fs::write(source.join("a/b/deep.txt"), "deep").unwrap();
+        fs::write(source.join("node_modules/pkg/index.js"), "js").unwrap();
+
+        let target = root.join("copy");
+        let (from, to) = (source.to_string_lossy(), target.to_string_lossy());
+        futures::executor::block_on(copy_path_impl(&from, &to, false, false)).unwrap();
+        assert_eq!(fs::read_to_string(target.join("top.txt")).unwrap(), "top");
+        assert_eq!(fs::read_to_string(target.join("a/b/deep.txt")).unwrap(), "deep");
+        assert!(!target.join("node_modules").exists());
+
+        // Existing targets need overwrite; include_ignored copies ignored entries
+        assert!(futures::executor::block_on(copy_path_impl(&from, &to, false, true)).is_err());
+        futures::executor::block_on(copy_path_impl(&from, &to, true, true)).unwrap();
+        assert!(target.join("node_modules/pkg/index.js").exists());
+    }
+
+    #[test]
+    fn test_preview_delete_and_rename() {
+        let (_tmp, root) = temp_dir();
+        let dir = root.join("dir");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("top.txt"), "12345").unwrap();
+        fs::write(dir.join("a/b/deep.txt"), "123").unwrap();
+
+        let preview = preview_delete_impl(&dir.to_string_lossy()).unwrap();
+        assert_eq!((preview.files, preview.directories, preview.total_bytes), (2, 3, 8));
+        assert!(preview.is_dir && !preview.truncated);
+        assert!(preview_delete_limited(&dir.to_string_lossy(), 2).unwrap().truncated);
+        assert!(preview_delete_impl(&root.join("missing").to_string_lossy()).is_err());
+
+        let from = dir.join("top.txt").to_string_lossy().to_string();
+        let ok = preview_rename_impl(&from, &dir.join("renamed.txt").to_string_lossy()).unwrap();
+        assert!(!ok.collision && ok.error.is_none());
+        let taken = preview_rename_impl(&from, &dir.join("a").to_string_lossy()).unwrap();
+        assert!(taken.collision && taken.target_is_dir && taken.error.is_some());
+        let nested = preview_rename_impl(&dir.to_string_lossy(), &dir.join("a/moved").to_string_lossy()).unwrap();
+        assert!(nested.into_itself);
+
+        // Nothing was touched
+        assert!(dir.join("top.txt").exists());
+    }
+
+    #[test]
+    fn test_write_file_with_diff() {
+        let (_tmp, root) = temp_dir();
+        let file = root.join("notes.txt");
+        let path = file.to_string_lossy().to_string();
+
+        let created = futures::executor::block_on(write_file_with_diff_impl(&path, "one\ntwo\n")).unwrap();
+        assert!(created.created);
+        assert_eq!((created.lines_added, created.lines_removed), (2, 0));
+
+        let changed = futures::executor::block_on(write_file_with_diff_impl(&path, "one\n2\nthree\n")).unwrap();
+        assert!(!changed.created && !changed.binary);
+        assert_eq!((changed.lines_added, changed.lines_removed), (2, 1));
+        assert_eq!(changed.hunks, vec![DiffHunk { old_start: 1, old_lines: 2, new_start: 1, new_lines: 3 }]);
+        let diff = changed.diff.unwrap();
+        assert!(diff.contains("-two\n") && diff.contains("+2\n") && diff.contains("+three\n"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n2\nthree\n");
+
+        let unchanged = futures::executor::block_on(write_file_with_diff_impl(&path, "one\n2\nthree\n")).unwrap();
+        assert!(unchanged.diff.is_none() && unchanged.hunks.is_empty());
+
+        fs::write(&file, [0x89, 0x50, 0x00, 0xff]).unwrap();
+        let binary = futures::executor::block_on(write_file_with_diff_impl(&path, "text\n")).unwrap();
+        assert!(binary.binary && binary.diff.is_none());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "text\n");
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_original_on_failure() {
+        let (_tmp, root) = temp_dir();
+        let file = root.join("main.rs");
+        fs::write(&file, "fn main() {}\n").unwrap();
+
+        // A write that fails halfway (e.g. disk full) leaves the original untouched
+        let failed = write_atomic_with(&file, false, |f| {
+            f.write_all(b"fn ma")?;
+            Err(std::io::Error::new(std::io::ErrorKind::StorageFull, "disk full"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn main() {}\n");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1, "temp file was not cleaned up");
+
+        write_atomic_with(&file, true, |f| f.write_all(b"fn main() { run() }\n")).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn main() { run() }\n");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+            let link = root.join("link.rs");
+            symlink(&file, &link).unwrap();
+            write_atomic_with(&link, false, |f| f.write_all(b"linked\n")).unwrap();
+            assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+            assert_eq!(fs::read_to_string(&file).unwrap(), "linked\n");
+            assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let (_tmp, root) = temp_dir();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("dist")).unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("app.log"), "").unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+        let names = |entries: Vec<FileEntry>| entries.into_iter().map(|e| e.name).collect::<Vec<_>>();
+
+        let patterns = vec!["out".to_string(), "*.tmp".to_string()];
+        let rules = IgnoreRules::new(&patterns, false, false);
+        assert_eq!(names(read_dir_entries(&root, false, &rules).unwrap()), vec!["dist", "app.log", "main.rs"]);
+
+        let rules = IgnoreRules::new(&patterns, true, false);
+        assert_eq!(names(read_dir_entries(&root, false, &rules).unwrap()), vec!["dist", "main.rs"]);
+
+        // Ignored entries can be listed on demand, marked as such
+        let rules = IgnoreRules::new(&patterns, true, true);
+        let entries = read_dir_entries(&root, false, &rules).unwrap();
+        let forced: Vec<_> = entries.iter().filter(|e| e.force_included).map(|e| e.name.as_str()).collect();
+        assert_eq!(forced, vec!["out", "app.log"]);
+    }
+
+    #[test]
+    fn test_copy_directory_into_itself() {
+        let (_tmp, root) = temp_dir();
+        fs::write(root.join("file.txt"), "x").unwrap();
+        let from = root.to_string_lossy();
+        let to = root.join("inner").to_string_lossy().to_string();
+        assert!(futures::executor::block_on(copy_path_impl(&from, &to, false, false)).is_err());
+        assert!(!root.join("inner").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_directory_skips_symlinks() {
+        let (_tmp, root) = temp_dir();
+        let (_outside_tmp, outside) = temp_dir();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        let source = root.join("src");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), "x").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), source.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&outside, source.join("link-dir")).unwrap();
+
+        let target = root.join("copy");
+        let (from, to) = (source.to_string_lossy(), target.to_string_lossy());
+        futures::executor::block_on(copy_path_impl(&from, &to, false, false)).unwrap();
+        assert!(target.join("file.txt").exists());
+        assert!(target.join("link.txt").symlink_metadata().is_err());
+        assert!(target.join("link-dir").symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use tauri::command;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFileStatus {
+    pub path: String,
+    /// Status letter from `git status` (M, A, D, R, C, U, T)
+    pub status: String,
+    /// Previous path of a renamed or copied file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orig_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GitStatus {
+    /// Current branch (None when HEAD is detached)
+    pub branch: Option<String>,
+    /// Changes in the index
+    pub staged: Vec<GitFileStatus>,
+    /// Changes in the working tree that are not staged
+    pub unstaged: Vec<GitFileStatus>,
+    pub untracked: Vec<String>,
+}
+
+#[command]
+pub async fn git_status(cwd: String) -> Result<GitStatus, String> {
+    git_status_impl(&cwd).await
+}
+
+#[command]
+pub async fn git_diff(cwd: String, path: Option<String>, staged: Option<bool>) -> Result<String, String> {
+    git_diff_impl(&cwd, path.as_deref(), staged.unwrap_or(false)).await
+}
+
+#[command]
+pub async fn read_file_at_revision(path: String, revision: Option<String>) -> Result<String, String> {
+    read_file_at_revision_impl(&path, revision.as_deref().unwrap_or("HEAD")).await
+}
+
+// ============================================================================
+// Implementation functions (used by both Tauri commands and WebSocket server)
+// ============================================================================
+
+/// Staged, unstaged and untracked files of the repository containing `cwd`
+pub async fn git_status_impl(cwd: &str) -> Result<GitStatus, String> {
+    let output = run_git(cwd, &["status", "--porcelain=v1", "--branch", "-z"]).await?;
+    Ok(parse_status(&output))
+}
+
+/// Unified diff of the working tree against the index, or of the index against
+/// HEAD with `staged`. Limited to `path` when given.
+pub async fn git_diff_impl(cwd: &str, path: Option<&str>, staged: bool) -> Result<String, String> {
+    let mut args = vec!["diff", "--no-color", "--no-ext-diff"];
+    if staged {
+        args.push("--cached");
+    }
+    if let Some(path) = path {
+        args.extend(["--", path]);
+    }
+    run_git(cwd, &args).await
+}
+
+/// Content of the file at `path` as of `revision` (e.g. `HEAD`, a branch or a
+/// commit) in the repository containing it. The file may since have been
+/// deleted from the working tree.
+pub async fn read_file_at_revision_impl(path: &str, revision: &str) -> Result<String, String> {
+    if revision.is_empty() || revision.starts_with('-') || revision.contains(':') {
+        return Err(format!("Invalid revision: {}", revision));
+    }
+    let (dir, relative) = split_existing_dir(Path::new(path))?;
+    let dir = dir.to_string_lossy();
+    // `<rev>:./<path>` is resolved against the directory git runs in
+    let spec = format!("{}:./{}", revision, relative);
+    run_git(&dir, &["show", &spec]).await.map_err(|e| {
+        if e.contains("does not exist in") || e.contains("exists on disk, but not in") {
+            format!("File is not tracked at {}: {}", revision, path)
+        } else {
+            e
+        }
+    })
+}
+
+/// The deepest existing directory above `path` and the rest of the path
+/// relative to it, with `/` separators
+fn split_existing_dir(path: &Path) -> Result<(std::path::PathBuf, String), String> {
+    let dir = path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| format!("No directory found for {}", path.display()))?;
+    let relative = path
+        .strip_prefix(dir)
+        .map_err(|_| format!("Invalid path: {}", path.display()))?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if relative.is_empty() {
+        return Err(format!("Not a file path: {}", path.display()));
+    }
+    Ok((dir.to_path_buf(), relative))
+}
+
+/// Run git in `cwd` and return its stdout
+async fn run_git(cwd: &str, args: &[&str]) -> Result<String, String> {
+    if !Path::new(cwd).is_dir() {
+        return Err(format!("Path is not a directory: {}", cwd));
+    }
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(cwd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute git: {}", e))?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        if error_msg.contains("not a git repository") {
+            return Err(format!("Not a git repository: {}", cwd));
+        }
+        return Err(format!("Git {} failed: {}", args[0], error_msg.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `git status --porcelain=v1 --branch -z` output
+fn parse_status(output: &str) -> GitStatus {
+    let mut status = GitStatus::default();
+    let mut entries = output.split('\0').filter(|e| !e.is_empty());
+
+    while let Some(entry) = entries.next() {
+        if let Some(branch) = entry.strip_prefix("## ") {
+            let branch = branch.strip_prefix("No commits yet on ").unwrap_or(branch);
+            let branch = branch.split("...").next().unwrap_or(branch);
+            if !branch.starts_with("HEAD (no branch)") {
+                status.branch = Some(branch.to_string());
+            }
+            continue;
+        }
+
+        let (Some(codes), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        let mut codes = codes.chars();
+        let (index, worktree) = (codes.next().unwrap_or(' '), codes.next().unwrap_or(' '));
+        match index {
+            '?' => {
+                status.untracked.push(path.to_string());
+                continue;
+            }
+            '!' => continue,
+            _ => {}
+        }
+
+        // The source path of a rename or copy follows as its own entry
+        let orig_path = if matches!(index, 'R' | 'C') || matches!(worktree, 'R' | 'C') {
+            entries.next().map(|p| p.to_string())
+        } else {
+            None
+        };
+        let file = |code: char| GitFileStatus {
+            path: path.to_string(),
+            status: code.to_string(),
+            orig_path: orig_path.clone(),
+        };
+        if index != ' ' {
+            status.staged.push(file(index));
+        }
+        if worktree != ' ' {
+            status.unstaged.push(file(worktree));
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let output = "## main...origin/main [ahead 1]\0M  staged.rs\0 M edited.rs\0MM both.rs\0R  new.rs\0old.rs\0?? notes.txt\0";
+        let status = parse_status(output);
+
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert_eq!(
+            status.staged.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+            vec!["staged.rs", "both.rs", "new.rs"]
+        );
+        assert_eq!(
+            status.unstaged.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+            vec!["edited.rs", "both.rs"]
+        );
+        assert_eq!(status.staged[2].status, "R");
+        assert_eq!(status.staged[2].orig_path.as_deref(), Some("old.rs"));
+        assert_eq!(status.untracked, vec!["notes.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_file_at_revision() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let dir = root.join("src");
+        std::fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(root)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+        if !git(&["init", "-q"]) {
+            // No git on this machine
+            return;
+        }
+        let file = dir.join("main.rs");
+        std::fs::write(&file, "committed\n").unwrap();
+        assert!(git(&["add", "."]) && git(&["commit", "-qm", "init"]));
+        std::fs::write(&file, "edited\n").unwrap();
+
+        let path = file.to_string_lossy();
+        assert_eq!(read_file_at_revision_impl(&path, "HEAD").await.unwrap(), "committed\n");
+        // Deleted files and their deleted directories still resolve
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read_file_at_revision_impl(&path, "HEAD").await.unwrap(), "committed\n");
+
+        let untracked = root.join("notes.txt");
+        let err = read_file_at_revision_impl(&untracked.to_string_lossy(), "HEAD").await.unwrap_err();
+        assert!(err.contains("not tracked"), "{}", err);
+        assert!(read_file_at_revision_impl(&path, "--output=x").await.unwrap_err().contains("Invalid revision"));
+    }
+
+    #[test]
+    fn test_parse_status_without_branch() {
+        assert_eq!(parse_status("## No commits yet on dev\0").branch.as_deref(), Some("dev"));
+        assert_eq!(parse_status("## HEAD (no branch)\0").branch, None);
+    }
+}
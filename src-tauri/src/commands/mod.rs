@@ -1,9 +1,15 @@
 pub mod agent;
 pub mod file;
+pub mod git;
+pub mod notification;
+pub mod opener;
 pub mod session;
 pub mod terminal;
 
 pub use agent::*;
 pub use file::*;
+pub use git::*;
+pub use notification::*;
+pub use opener::*;
 pub use session::*;
 pub use terminal::*;
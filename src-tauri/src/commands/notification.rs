@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_notification::NotificationExt;
+use tracing::warn;
+
+use crate::core::{AppState, DesktopNotifier};
+
+/// OS notifications through tauri-plugin-notification
+pub struct TauriNotifier {
+    app: AppHandle,
+}
+
+impl TauriNotifier {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+impl DesktopNotifier for TauriNotifier {
+    fn is_focused(&self) -> bool {
+        self.app
+            .get_webview_window("main")
+            .and_then(|window| window.is_focused().ok())
+            .unwrap_or(false)
+    }
+
+    fn notify(&self, title: &str, body: &str) {
+        if let Err(e) = self.app.notification().builder().title(title).body(body).show() {
+            warn!("Failed to show notification: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn set_notifications_enabled(
+    state: State<'_, Arc<AppState>>,
+    enabled: bool,
+) -> Result<(), String> {
+    state.set_notifications_enabled(enabled)
+}
@@ -0,0 +1,43 @@
+use std::path::Path;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::core::{parse_open_target, AppState, DesktopOpener, OpenTarget};
+
+/// Opens files and links through tauri-plugin-opener
+pub struct TauriOpener {
+    app: AppHandle,
+}
+
+impl TauriOpener {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+impl DesktopOpener for TauriOpener {
+    fn open_path(&self, path: &Path) -> Result<(), String> {
+        self.app
+            .opener()
+            .open_path(path.to_string_lossy(), None::<&str>)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+    }
+
+    fn open_url(&self, url: &str) -> Result<(), String> {
+        self.app
+            .opener()
+            .open_url(url, None::<&str>)
+            .map_err(|e| format!("Failed to open {}: {}", url, e))
+    }
+}
+
+/// Open a project file or an http(s)/mailto link with the OS default app
+#[tauri::command]
+pub async fn open_path(state: State<'_, Arc<AppState>>, path: String) -> Result<(), String> {
+    let target = match parse_open_target(&path)? {
+        OpenTarget::Path(path) => OpenTarget::Path(state.check_path(&path.to_string_lossy())?),
+        url => url,
+    };
+    state.open_external(&target)
+}
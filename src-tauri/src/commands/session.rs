@@ -12,7 +12,7 @@ pub async fn create_session(
 ) -> Result<NewSessionResponse, String> {
     info!("Creating new session in {}", cwd);
 
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(state.client.clone()).with_mcp_servers(state.mcp_servers());
 
     let response = manager.create_session(&cwd).await.map_err(|e: AcpError| {
         error!("Failed to create session: {}", e);
@@ -40,7 +40,7 @@ pub async fn resume_session(
 ) -> Result<NewSessionResponse, String> {
     info!("Resuming session {} in {}", session_id, cwd);
 
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(state.client.clone()).with_mcp_servers(state.mcp_servers());
 
     let response = manager
         .resume_session(&session_id, &cwd)
@@ -57,6 +57,7 @@ pub async fn resume_session(
         response.modes.clone(),
         response.models.clone(),
     );
+    state.session_registry.link_resumed_session(&session_id, &response.session_id);
 
     info!("Resumed session: {}", response.session_id);
     Ok(response)
@@ -71,7 +72,7 @@ pub async fn fork_session(
 ) -> Result<NewSessionResponse, String> {
     info!("Forking session {} in {}", session_id, cwd);
 
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(state.client.clone()).with_mcp_servers(state.mcp_servers());
 
     let response = manager
         .fork_session(&session_id, &cwd)
@@ -100,13 +101,23 @@ pub async fn list_sessions(
     cwd: Option<String>,
     limit: Option<usize>,
     offset: Option<usize>,
+    cursor: Option<String>,
+    pinned_only: Option<bool>,
 ) -> Result<ListSessionsResponse, String> {
     let limit = limit.unwrap_or(20);
     let offset = offset.unwrap_or(0);
+    let pinned_only = pinned_only.unwrap_or(false);
 
-    info!("Listing sessions (cwd={:?}, limit={}, offset={})", cwd, limit, offset);
+    info!(
+        "Listing sessions (cwd={:?}, limit={}, offset={}, cursor={:?}, pinned_only={})",
+        cwd, limit, offset, cursor, pinned_only
+    );
 
-    let response = state.session_registry.list_sessions(cwd.as_deref(), limit, offset);
+    // The cursor takes precedence; offset is kept for older clients
+    let response = match cursor.as_deref() {
+        Some(cursor) => state.session_registry.list_sessions_after(cwd.as_deref(), limit, cursor, pinned_only)?,
+        None => state.session_registry.list_sessions(cwd.as_deref(), limit, offset, pinned_only),
+    };
 
     info!("Found {} sessions (total: {})", response.sessions.len(), response.total);
     Ok(response)
@@ -184,3 +195,12 @@ pub async fn set_session_mode(
     info!("Session {} mode set to {}", session_id, mode_id);
     Ok(())
 }
+
+/// Allow client file commands under a project the user picked in the desktop
+/// app. Only reachable over Tauri IPC, never from WebSocket clients.
+#[tauri::command]
+pub async fn add_project_root(state: State<'_, Arc<AppState>>, path: String) -> Result<(), String> {
+    info!("Allowing project root {}", path);
+    state.allow_project_root(&path);
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use tauri::{command, State};
 
@@ -9,8 +10,16 @@ pub fn create_terminal(
     working_dir: String,
     cols: u16,
     rows: u16,
+    shell: Option<String>,
+    env: Option<HashMap<String, String>>,
 ) -> Result<String, String> {
-    state.terminal_manager.create_terminal(working_dir, cols, rows)
+    state.terminal_manager.create_terminal(
+        working_dir,
+        cols,
+        rows,
+        shell.as_deref(),
+        &env.unwrap_or_default(),
+    )
 }
 
 #[command]
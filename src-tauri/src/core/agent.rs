@@ -3,17 +3,28 @@ use tokio::sync::{mpsc, RwLock};
 use tracing::info;
 
 use crate::acp::{
-    AcpClient, AcpError, InitializeResponse, NewSessionResponse, PermissionOutcome,
+    AcpClient, AcpError, ContentBlock, InitializeResponse, NewSessionResponse, PermissionOutcome,
     PermissionRequest, PromptResponse, SessionNotification,
 };
 
 pub struct AgentManager {
     client: Arc<RwLock<Option<AcpClient>>>,
+    /// MCP servers (ACP format) attached to sessions created, resumed or forked
+    mcp_servers: Vec<serde_json::Value>,
 }
 
 impl AgentManager {
     pub fn new(client: Arc<RwLock<Option<AcpClient>>>) -> Self {
-        Self { client }
+        Self {
+            client,
+            mcp_servers: Vec::new(),
+        }
+    }
+
+    /// Attach MCP servers to the sessions this manager creates, resumes or forks
+    pub fn with_mcp_servers(mut self, mcp_servers: Vec<serde_json::Value>) -> Self {
+        self.mcp_servers = mcp_servers;
+        self
     }
 
     /// Connect is now a no-op - ACP agent is started lazily when creating/resuming sessions
@@ -51,10 +62,16 @@ impl AgentManager {
         client.initialize().await
     }
 
+    pub async fn authenticate(&self, method_id: &str) -> Result<(), AcpError> {
+        let guard = self.client.read().await;
+        let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
+        client.authenticate(method_id).await
+    }
+
     pub async fn create_session(&self, cwd: &str) -> Result<NewSessionResponse, AcpError> {
         let guard = self.client.read().await;
         let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
-        client.create_session(cwd).await
+        client.create_session(cwd, self.mcp_servers.clone()).await
     }
 
     /// Resume an existing session
@@ -65,7 +82,7 @@ impl AgentManager {
     ) -> Result<NewSessionResponse, AcpError> {
         let guard = self.client.read().await;
         let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
-        client.resume_session(session_id, cwd).await
+        client.resume_session(session_id, cwd, self.mcp_servers.clone()).await
     }
 
     /// Fork an existing session
@@ -76,7 +93,7 @@ impl AgentManager {
     ) -> Result<NewSessionResponse, AcpError> {
         let guard = self.client.read().await;
         let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
-        client.fork_session(session_id, cwd).await
+        client.fork_session(session_id, cwd, self.mcp_servers.clone()).await
     }
 
     pub async fn prompt(&self, session_id: &str, content: &str) -> Result<PromptResponse, AcpError> {
@@ -85,6 +102,16 @@ impl AgentManager {
         client.prompt(session_id, content).await
     }
 
+    pub async fn prompt_with_blocks(
+        &self,
+        session_id: &str,
+        blocks: Vec<ContentBlock>,
+    ) -> Result<PromptResponse, AcpError> {
+        let guard = self.client.read().await;
+        let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
+        client.prompt_with_blocks(session_id, blocks).await
+    }
+
     pub async fn cancel(&self, session_id: &str) -> Result<(), AcpError> {
         let guard = self.client.read().await;
         let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
@@ -97,6 +124,12 @@ impl AgentManager {
         client.set_session_mode(session_id, mode_id).await
     }
 
+    pub async fn set_session_model(&self, session_id: &str, model_id: &str) -> Result<(), AcpError> {
+        let guard = self.client.read().await;
+        let client = guard.as_ref().ok_or(AcpError::NotConnected)?;
+        client.set_session_model(session_id, model_id).await
+    }
+
     pub async fn respond_permission(
         &self,
         request_id: serde_json::Value,
@@ -0,0 +1,162 @@
+//! Agent Command
+//!
+//! A user-supplied command line for the ACP agent, e.g.
+//! `gemini --experimental-acp` or `ANTHROPIC_LOG=debug /opt/agent/bin/acp`.
+//! Leading `KEY=VALUE` words are environment variables for the agent process.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::acp::client::{common_bin_dirs, expand_config_dirs};
+use crate::core::terminal::find_program;
+use crate::core::ConfigManager;
+
+/// A parsed agent command line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCommand {
+    pub command: String,
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<(String, String)>,
+}
+
+impl AgentCommand {
+    /// Parse a command line. Words are split on whitespace; single and double
+    /// quotes group words and a backslash escapes the next character.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = split_words(line)?.into_iter().peekable();
+        let mut env = Vec::new();
+        while let Some(word) = words.peek() {
+            match word.split_once('=') {
+                Some((key, value)) if is_env_name(key) => {
+                    env.push((key.to_string(), value.to_string()));
+                    words.next();
+                }
+                _ => break,
+            }
+        }
+        let command = words.next().ok_or("Agent command is empty")?;
+        Ok(Self { command, args: words.collect(), env })
+    }
+
+    /// Check that the program exists, returning its full path. Bare names are
+    /// looked up like the agent's PATH does: `agent.extraPath`, then PATH, then
+    /// the common install directories.
+    pub fn resolve(&self) -> Result<PathBuf, String> {
+        let extra_path = ConfigManager::new().config().agent.extra_path.clone();
+        self.resolve_with(&extra_path)
+    }
+
+    fn resolve_with(&self, extra_path: &[String]) -> Result<PathBuf, String> {
+        if Path::new(&self.command).components().count() > 1 {
+            return find_program(&self.command)
+                .ok_or_else(|| format!("Agent command not found: {} (not a file)", self.command));
+        }
+        let home = std::env::var("HOME").unwrap_or_default();
+        let find_in = |dirs: Vec<PathBuf>| dirs.into_iter().map(|dir| dir.join(&self.command)).find(|p| p.is_file());
+        find_in(expand_config_dirs(extra_path, &home))
+            .or_else(|| find_program(&self.command))
+            .or_else(|| find_in(common_bin_dirs(&home)))
+            .ok_or_else(|| format!("Agent command not found: {} (not on PATH)", self.command))
+    }
+
+    /// Environment variables in the form `AcpClient::connect` takes
+    pub fn env_vars(&self) -> Option<Vec<(String, String)>> {
+        if self.env.is_empty() {
+            None
+        } else {
+            Some(self.env.clone())
+        }
+    }
+}
+
+fn is_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                word.push(chars.next().ok_or("Agent command ends with a backslash")?);
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("Agent command has an unterminated quote".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_agent_command() {
+        let cmd = AgentCommand::parse("gemini --experimental-acp").unwrap();
+        assert_eq!(cmd.command, "gemini");
+        assert_eq!(cmd.args, vec!["--experimental-acp"]);
+        assert!(cmd.env_vars().is_none());
+
+        let cmd = AgentCommand::parse(r#"LOG=debug API_KEY="a b" '/opt/my agent/acp' --name "x \"y\"" it\'s"#).unwrap();
+        assert_eq!(cmd.env, vec![("LOG".to_string(), "debug".to_string()), ("API_KEY".to_string(), "a b".to_string())]);
+        assert_eq!(cmd.command, "/opt/my agent/acp");
+        assert_eq!(cmd.args, vec!["--name", "x \"y\"", "it's"]);
+
+        // Only leading assignments are environment variables
+        let cmd = AgentCommand::parse("agent --flag=1 X=2").unwrap();
+        assert_eq!(cmd.args, vec!["--flag=1", "X=2"]);
+
+        assert!(AgentCommand::parse("   ").is_err());
+        assert!(AgentCommand::parse("FOO=bar").is_err());
+        assert!(AgentCommand::parse("agent 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_resolve_agent_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("my-agent");
+        std::fs::write(&program, "#!/bin/sh\n").unwrap();
+
+        let cmd = AgentCommand::parse(&program.to_string_lossy()).unwrap();
+        assert_eq!(cmd.resolve().unwrap(), program);
+        let missing = AgentCommand::parse(&dir.path().join("missing").to_string_lossy()).unwrap();
+        assert!(missing.resolve().unwrap_err().contains("not found"));
+        assert!(AgentCommand::parse("aerowork-no-such-agent").unwrap().resolve().is_err());
+
+        // Bare names are found in `agent.extraPath`
+        let bare = AgentCommand::parse("my-agent").unwrap();
+        let extra = [dir.path().to_string_lossy().to_string()];
+        assert_eq!(bare.resolve_with(&extra).unwrap(), program);
+    }
+}
@@ -0,0 +1,143 @@
+//! Command Runner Module
+//!
+//! Runs one-shot, non-interactive commands (e.g. `npm test`) and captures their
+//! output. Interactive shells live in `TerminalManager` instead.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Default and maximum time a command may run before it is killed
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+pub const MAX_COMMAND_TIMEOUT: Duration = Duration::from_secs(600);
+/// Bytes of stdout and of stderr kept per command (the end of the output is kept)
+const MAX_CAPTURED_BYTES: usize = 1024 * 1024;
+/// How long to keep reading output after the command exited or was killed.
+/// Background processes it started may hold the pipes open indefinitely.
+const OUTPUT_DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// Result of a finished command
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Exit code (None if the command was killed or terminated by a signal)
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the command (Unix only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// Whether the command was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// Whether the start of stdout or stderr was dropped to stay within 1MB
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+/// Run `program` with `args` in `cwd`, killing it after `timeout`
+pub async fn run_command(
+    cwd: &str,
+    program: &str,
+    args: &[String],
+    timeout: Duration,
+) -> Result<CommandOutput, String> {
+    if !Path::new(cwd).is_dir() {
+        return Err(format!("Path is not a directory: {}", cwd));
+    }
+
+    info!("Running command in {}: {} {:?}", cwd, program, args);
+    let started = Instant::now();
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    let stdout = tokio::spawn(read_capped(child.stdout.take()));
+    let stderr = tokio::spawn(read_capped(child.stderr.take()));
+
+    let (status, timed_out) = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => (Some(status.map_err(|e| format!("Failed to wait for {}: {}", program, e))?), false),
+        Err(_) => {
+            warn!("Command {} timed out after {:?}, killing it", program, timeout);
+            let _ = child.kill().await;
+            (None, true)
+        }
+    };
+
+    let (stdout, stdout_truncated) = tokio::time::timeout(OUTPUT_DRAIN_GRACE, stdout)
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+        .unwrap_or_default();
+    let (stderr, stderr_truncated) = tokio::time::timeout(OUTPUT_DRAIN_GRACE, stderr)
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+        .unwrap_or_default();
+
+    #[cfg(unix)]
+    let signal = status.and_then(|s| std::os::unix::process::ExitStatusExt::signal(&s));
+    #[cfg(not(unix))]
+    let signal = None;
+
+    Ok(CommandOutput {
+        stdout,
+        stderr,
+        exit_code: status.and_then(|s| s.code()),
+        signal,
+        timed_out,
+        truncated: stdout_truncated || stderr_truncated,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Read a pipe to the end, keeping its last `MAX_CAPTURED_BYTES`
+async fn read_capped(reader: Option<impl AsyncRead + Unpin>) -> (String, bool) {
+    let Some(mut reader) = reader else {
+        return (String::new(), false);
+    };
+    let mut captured = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        truncated |= push_capped(&mut captured, &buf[..n], MAX_CAPTURED_BYTES);
+    }
+    (String::from_utf8_lossy(&captured).into_owned(), truncated)
+}
+
+/// Append `chunk`, dropping bytes from the front beyond `limit`. Returns whether any were dropped.
+fn push_capped(captured: &mut Vec<u8>, chunk: &[u8], limit: usize) -> bool {
+    captured.extend_from_slice(chunk);
+    if captured.len() <= limit {
+        return false;
+    }
+    let excess = captured.len() - limit;
+    captured.drain(..excess);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_capped_keeps_the_end() {
+        let mut captured = Vec::new();
+        assert!(!push_capped(&mut captured, b"hello ", 8));
+        assert!(push_capped(&mut captured, b"world", 8));
+        assert_eq!(captured, b"lo world");
+    }
+}
@@ -6,7 +6,8 @@
 //! - Data: ~/.local/share/aerowork/ (or $XDG_DATA_HOME/aerowork/)
 //! - Cache: ~/.cache/aerowork/ (or $XDG_CACHE_HOME/aerowork/)
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
@@ -25,6 +26,53 @@ pub fn config_dir() -> PathBuf {
         .join(APP_NAME)
 }
 
+/// Files older builds wrote to a hard-coded ~/.config/aerowork/, which differs
+/// from `config_dir()` on macOS and Windows (models.json always used `config_dir()`)
+const LEGACY_CONFIG_FILES: &[&str] = &["recent-projects.json"];
+
+/// Move config files from the legacy ~/.config/aerowork/ into `config_dir()`.
+/// Files that already exist in `config_dir()` are left alone, so this is safe
+/// to run on every start.
+pub fn migrate_legacy_config() {
+    let Some(legacy_dir) = dirs::home_dir().map(|home| home.join(".config").join(APP_NAME)) else {
+        return;
+    };
+    for file in migrate_config_files(&legacy_dir, &config_dir(), LEGACY_CONFIG_FILES) {
+        info!("Moved {} from {:?} to {:?}", file, legacy_dir, config_dir());
+    }
+}
+
+/// Move `files` from `from` to `to` unless the target already has them.
+/// Returns the names of the files moved.
+fn migrate_config_files(from: &Path, to: &Path, files: &[&str]) -> Vec<String> {
+    if from == to {
+        return Vec::new();
+    }
+
+    let mut moved = Vec::new();
+    for file in files {
+        let source = from.join(file);
+        let target = to.join(file);
+        if !source.is_file() || target.exists() {
+            continue;
+        }
+        if let Err(e) = std::fs::create_dir_all(to) {
+            warn!("Failed to create config directory {:?}: {}", to, e);
+            return moved;
+        }
+        // rename fails across filesystems; copy and remove instead
+        let result = std::fs::rename(&source, &target).or_else(|_| {
+            std::fs::copy(&source, &target)?;
+            std::fs::remove_file(&source)
+        });
+        match result {
+            Ok(()) => moved.push(file.to_string()),
+            Err(e) => warn!("Failed to move {:?} to {:?}: {}", source, target, e),
+        }
+    }
+    moved
+}
+
 /// Get the data directory path
 /// Returns ~/.local/share/aerowork/ or $XDG_DATA_HOME/aerowork/
 pub fn data_dir() -> PathBuf {
@@ -64,6 +112,397 @@ pub struct Config {
     /// Model configuration
     #[serde(default)]
     pub model: ModelConfig,
+
+    /// Desktop notification configuration
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// File access configuration
+    #[serde(default)]
+    pub files: FilesConfig,
+
+    /// Terminal configuration
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+
+    /// Log file configuration
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// Messages hidden from session previews and history
+    #[serde(default)]
+    pub system_messages: SystemMessagesConfig,
+
+    /// Tool permission policies keyed by project path
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub permissions: HashMap<String, PermissionPolicy>,
+
+    /// MCP servers attached to new, resumed and forked sessions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mcp_servers: Vec<McpServerConfig>,
+}
+
+/// How the agent connects to an MCP server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum McpTransport {
+    /// Local process started by the agent
+    #[default]
+    Stdio,
+    Http,
+    Sse,
+}
+
+/// MCP server configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerConfig {
+    pub name: String,
+
+    #[serde(default, rename = "type")]
+    pub transport: McpTransport,
+
+    /// Command to start a stdio server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+
+    /// URL of an http or sse server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl McpServerConfig {
+    /// Check that the fields needed by the transport are present
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("MCP server name is empty".to_string());
+        }
+        match self.transport {
+            McpTransport::Stdio if self.command.as_deref().unwrap_or_default().is_empty() => {
+                Err(format!("MCP server {} needs a command", self.name))
+            }
+            McpTransport::Http | McpTransport::Sse if self.url.as_deref().unwrap_or_default().is_empty() => {
+                Err(format!("MCP server {} needs a url", self.name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The server in ACP's `McpServer` format, as sent with `session/new`
+    pub fn to_acp(&self) -> serde_json::Value {
+        let pairs = |map: &HashMap<String, String>| {
+            let mut pairs: Vec<_> = map
+                .iter()
+                .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+                .collect();
+            pairs.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+            pairs
+        };
+        match self.transport {
+            McpTransport::Stdio => serde_json::json!({
+                "name": self.name,
+                "command": self.command,
+                "args": self.args,
+                "env": pairs(&self.env),
+            }),
+            McpTransport::Http | McpTransport::Sse => serde_json::json!({
+                "type": self.transport,
+                "name": self.name,
+                "url": self.url,
+                "headers": pairs(&self.headers),
+            }),
+        }
+    }
+}
+
+/// What to do with a permission request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionAction {
+    /// Approve without asking
+    Allow,
+    /// Ask the user
+    Prompt,
+    /// Reject without asking
+    Deny,
+}
+
+/// Permission rules keyed by ACP tool kind (`read`, `edit`, `execute`, ...)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionPolicy {
+    /// Action per tool kind
+    #[serde(default)]
+    pub tools: HashMap<String, PermissionAction>,
+
+    /// Action for tool kinds without a rule
+    #[serde(default = "default_permission_action")]
+    pub default: PermissionAction,
+
+    /// Answer prompts nobody responded to after this many seconds (off when
+    /// unset), for unattended sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// Answer given when `timeout_secs` runs out (`prompt` counts as `deny`)
+    #[serde(default = "default_timeout_action")]
+    pub timeout_action: PermissionAction,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        Self {
+            tools: HashMap::new(),
+            default: default_permission_action(),
+            timeout_secs: None,
+            timeout_action: default_timeout_action(),
+        }
+    }
+}
+
+impl PermissionPolicy {
+    /// Policy that approves every tool, equivalent to dangerous mode
+    pub fn allow_all() -> Self {
+        Self {
+            default: PermissionAction::Allow,
+            ..Self::default()
+        }
+    }
+
+    /// Action for a request of the given tool kind
+    pub fn action_for(&self, kind: Option<&str>) -> PermissionAction {
+        kind.and_then(|kind| self.tools.get(kind))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// How long a prompt may wait and the action then taken, if the timeout is on
+    pub fn timeout(&self) -> Option<(std::time::Duration, PermissionAction)> {
+        let action = match self.timeout_action {
+            PermissionAction::Allow => PermissionAction::Allow,
+            PermissionAction::Prompt | PermissionAction::Deny => PermissionAction::Deny,
+        };
+        self.timeout_secs.map(|secs| (std::time::Duration::from_secs(secs), action))
+    }
+}
+
+fn default_permission_action() -> PermissionAction {
+    PermissionAction::Prompt
+}
+
+fn default_timeout_action() -> PermissionAction {
+    PermissionAction::Deny
+}
+
+/// File access configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesConfig {
+    /// Largest text file `read_file` returns in full
+    #[serde(default = "default_max_text_bytes")]
+    pub max_text_bytes: u64,
+
+    /// Largest file `read_file_binary` returns in full
+    #[serde(default = "default_max_binary_bytes")]
+    pub max_binary_bytes: u64,
+
+    /// Globs for entry names that directory listings and search skip
+    #[serde(default = "default_ignore")]
+    pub ignore: Vec<String>,
+
+    /// Also skip entries matched by the project's .gitignore files
+    #[serde(default)]
+    pub respect_gitignore: bool,
+
+    /// fsync written files before they replace the original (slower, survives power loss)
+    #[serde(default)]
+    pub sync_writes: bool,
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        Self {
+            max_text_bytes: default_max_text_bytes(),
+            max_binary_bytes: default_max_binary_bytes(),
+            ignore: default_ignore(),
+            respect_gitignore: false,
+            sync_writes: false,
+        }
+    }
+}
+
+/// Terminal configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalConfig {
+    /// Most terminals (user shells and agent commands) running at once; 0 disables the limit
+    #[serde(default = "default_max_terminals")]
+    pub max_terminals: usize,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            max_terminals: default_max_terminals(),
+        }
+    }
+}
+
+fn default_max_terminals() -> usize {
+    20
+}
+
+/// When the log file is rotated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// A new file every day
+    #[default]
+    Daily,
+    /// A new file every hour
+    Hourly,
+    /// A new file once the current one reaches `maxSizeMb`
+    Size,
+    /// A single file that is never rotated
+    Never,
+}
+
+/// Log file configuration (stdout logging is always on)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggingConfig {
+    /// Also write logs to files in ~/.cache/aerowork/logs/ (overridden by
+    /// AERO_LOG_FILE and `--log-file`)
+    #[serde(default)]
+    pub file: bool,
+
+    /// Rotation policy for the log file
+    #[serde(default)]
+    pub rotation: LogRotation,
+
+    /// Size at which the log file is rotated with `rotation: "size"`
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Log files to keep, including the current one; older ones are deleted
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: false,
+            rotation: LogRotation::default(),
+            max_size_mb: default_log_max_size_mb(),
+            max_files: default_log_max_files(),
+        }
+    }
+}
+
+fn default_log_max_size_mb() -> u64 {
+    10
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+/// Handling of a prompt sent while its session is running another one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BusyPromptPolicy {
+    /// Wait for the running prompt to finish, then send
+    #[default]
+    Queue,
+    /// Fail with a "session busy" error
+    Reject,
+}
+
+/// How a system message pattern is matched against message text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternMatch {
+    /// The message starts with the pattern
+    #[default]
+    Prefix,
+    /// The pattern appears anywhere in the message
+    Contains,
+}
+
+/// A pattern marking a message as a system message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemMessagePattern {
+    pub pattern: String,
+    #[serde(default, rename = "match")]
+    pub match_type: PatternMatch,
+}
+
+/// System message filtering. User patterns are added to the built-in ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemMessagesConfig {
+    /// Extra patterns to hide
+    #[serde(default)]
+    pub patterns: Vec<SystemMessagePattern>,
+
+    /// Built-in patterns to stop hiding (e.g. "Warmup" for a user who types it)
+    #[serde(default)]
+    pub disabled_defaults: Vec<String>,
+}
+
+/// Entry names skipped by listings and search unless `files.ignore` is configured,
+/// and always skipped by directory copies that don't include ignored entries
+pub const DEFAULT_IGNORE: &[&str] = &[
+    "node_modules",
+    "target",
+    "dist",
+    "build",
+    ".git",
+    ".svn",
+    "__pycache__",
+    ".DS_Store",
+    "Thumbs.db",
+];
+
+fn default_ignore() -> Vec<String> {
+    DEFAULT_IGNORE.iter().map(|name| name.to_string()).collect()
+}
+
+fn default_max_text_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_binary_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
+/// Desktop notification configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationsConfig {
+    /// Notify when a turn finishes or a permission is requested while the window is unfocused
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
 }
 
 /// Server-related configuration
@@ -74,16 +513,86 @@ pub struct ServerConfig {
     #[serde(default = "default_port")]
     pub port: u16,
 
-    /// Host to bind to
-    #[serde(default = "default_host")]
-    pub host: String,
+    /// Host to bind the WebSocket and web client servers to (overridden by
+    /// AERO_BIND_HOST). Local-only when unset; "0.0.0.0" serves every interface.
+    /// Older builds saved an unused `host: "0.0.0.0"`, which is ignored and
+    /// dropped on the next save.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_host: Option<String>,
+
+    /// Shared token required to open a WebSocket connection (overridden by AERO_WS_TOKEN)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+
+    /// Restrict client file commands to project directories (disable for unrestricted local use)
+    #[serde(default = "default_true")]
+    pub sandbox_files: bool,
+
+    /// Extra directories client file commands may use, besides the directory the
+    /// server was started in and projects picked in the desktop app
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub project_roots: Vec<String>,
+
+    /// Reject every method that changes sessions, files, terminals or settings,
+    /// leaving clients able to watch and browse only (overridden by AERO_READ_ONLY)
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// PEM certificate chain for serving HTTPS/WSS in headless mode (overridden by AERO_TLS_CERT)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key for `tls_cert` (overridden by AERO_TLS_KEY)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_key: Option<String>,
+
+    /// Requests one WebSocket connection may have in flight (0 = unlimited)
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+
+    /// Sustained requests per second allowed per connection (0 = unlimited)
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: u32,
+
+    /// Requests a connection may send at once above the sustained rate
+    #[serde(default = "default_request_burst")]
+    pub request_burst: u32,
+
+    /// Window in milliseconds for combining streamed message chunks into one update (0 = off)
+    #[serde(default = "default_chunk_coalesce_ms")]
+    pub chunk_coalesce_ms: u64,
+
+    /// Seconds between WebSocket ping frames sent to each client (0 = off)
+    #[serde(default = "default_ping_interval_secs")]
+    pub ping_interval_secs: u64,
+
+    /// Unanswered pings after which a client's connection is closed
+    #[serde(default = "default_max_missed_pongs")]
+    pub max_missed_pongs: u32,
+
+    /// Capacity of the internal agent notification, permission and terminal output queues
+    #[serde(default = "default_event_buffer_size")]
+    pub event_buffer_size: usize,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             port: default_port(),
-            host: default_host(),
+            bind_host: None,
+            auth_token: None,
+            sandbox_files: true,
+            project_roots: Vec::new(),
+            read_only: false,
+            tls_cert: None,
+            tls_key: None,
+            max_concurrent_requests: default_max_concurrent_requests(),
+            requests_per_second: default_requests_per_second(),
+            request_burst: default_request_burst(),
+            chunk_coalesce_ms: default_chunk_coalesce_ms(),
+            ping_interval_secs: default_ping_interval_secs(),
+            max_missed_pongs: default_max_missed_pongs(),
+            event_buffer_size: default_event_buffer_size(),
         }
     }
 }
@@ -92,8 +601,43 @@ fn default_port() -> u16 {
     9888
 }
 
-fn default_host() -> String {
-    "0.0.0.0".to_string()
+impl ServerConfig {
+    /// The configured bind host, or 127.0.0.1
+    pub fn bind_host(&self) -> String {
+        self.bind_host.clone().unwrap_or_else(|| "127.0.0.1".to_string())
+    }
+}
+
+fn default_max_concurrent_requests() -> usize {
+    8
+}
+
+fn default_requests_per_second() -> u32 {
+    20
+}
+
+fn default_request_burst() -> u32 {
+    40
+}
+
+fn default_chunk_coalesce_ms() -> u64 {
+    40
+}
+
+fn default_ping_interval_secs() -> u64 {
+    25
+}
+
+fn default_max_missed_pongs() -> u32 {
+    2
+}
+
+fn default_event_buffer_size() -> usize {
+    1024
+}
+
+fn default_true() -> bool {
+    true
 }
 
 /// Agent-related configuration
@@ -104,6 +648,47 @@ pub struct AgentConfig {
     #[serde(default)]
     pub default_command: Option<String>,
 
+    /// Timeout for a single ACP request in milliseconds (defaults to 300000)
+    #[serde(default)]
+    pub request_timeout_ms: Option<u64>,
+
+    /// Attempts to start the agent before giving up (defaults to 3)
+    #[serde(default)]
+    pub connect_attempts: Option<u32>,
+
+    /// Delay before retrying a failed agent start in milliseconds, doubled
+    /// after each further failure (defaults to 500)
+    #[serde(default)]
+    pub connect_backoff_ms: Option<u64>,
+
+    /// Chat items loaded from history when resuming a session (defaults to 200)
+    #[serde(default)]
+    pub history_limit: Option<usize>,
+
+    /// Directories put at the front of the agent's PATH (`~/` is expanded)
+    #[serde(default)]
+    pub extra_path: Vec<String>,
+
+    /// Project roots whose `.aerowork.json` may set `agentCommand` (`~/` is
+    /// expanded). Elsewhere it is ignored, so a cloned repository can't run a
+    /// program just by being opened.
+    #[serde(default)]
+    pub trusted_projects: Vec<String>,
+
+    /// Where the agent stores session files, if not ~/.claude/projects
+    /// (`~/` is expanded; overridden by AERO_PROJECTS_DIR)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projects_dir: Option<String>,
+
+    /// What a prompt sent while the session is still running one does
+    #[serde(default)]
+    pub busy_prompts: BusyPromptPolicy,
+
+    /// Create sessions as placeholders and start the agent on the first prompt
+    /// (`create_session` can override this with `lazy`)
+    #[serde(default)]
+    pub lazy_sessions: bool,
+
     /// Agent-specific settings (placeholder for future use)
     #[serde(default)]
     pub settings: serde_json::Value,
@@ -128,12 +713,51 @@ pub struct ConfigManager {
     config_path: PathBuf,
 }
 
+/// The last config read or saved, with the file's stamp at that time
+struct CachedConfig {
+    path: PathBuf,
+    stamp: Option<FileStamp>,
+    config: Config,
+}
+
+/// Modification time and size of the config file, to tell when it changed
+type FileStamp = (std::time::SystemTime, u64);
+
+fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Shared by every `ConfigManager`, so hot paths don't re-read and re-parse
+/// config.json. `save` refreshes it; edits made outside the app are picked up
+/// when the file's modification time or size changes.
+static CACHE: std::sync::Mutex<Option<CachedConfig>> = std::sync::Mutex::new(None);
+
+/// Bumped whenever the cached config is replaced, for values derived from it
+static GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Changes whenever a new or changed config is loaded or saved, so values
+/// built from the config can tell they are stale without comparing it
+pub fn config_generation() -> u64 {
+    GENERATION.load(std::sync::atomic::Ordering::SeqCst)
+}
+
 impl ConfigManager {
     /// Create a new config manager and load existing config
     pub fn new() -> Self {
-        let config_path = config_dir().join(CONFIG_FILE);
-        let config = Self::load_from_path(&config_path).unwrap_or_default();
+        Self::with_path(config_dir().join(CONFIG_FILE))
+    }
 
+    fn with_path(config_path: PathBuf) -> Self {
+        let stamp = file_stamp(&config_path);
+        let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = cache.as_ref().filter(|c| c.path == config_path && c.stamp == stamp) {
+            return Self { config: cached.config.clone(), config_path };
+        }
+
+        let config = Self::load_from_path(&config_path).unwrap_or_default();
+        *cache = Some(CachedConfig { path: config_path.clone(), stamp, config: config.clone() });
+        GENERATION.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         Self { config, config_path }
     }
 
@@ -184,6 +808,12 @@ impl ConfigManager {
 
         std::fs::write(&self.config_path, content)
             .map_err(|e| format!("Failed to write config file: {}", e))?;
+        *CACHE.lock().unwrap_or_else(|e| e.into_inner()) = Some(CachedConfig {
+            path: self.config_path.clone(),
+            stamp: file_stamp(&self.config_path),
+            config: self.config.clone(),
+        });
+        GENERATION.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
         info!("Saved config to {:?}", self.config_path);
         Ok(())
@@ -214,11 +844,72 @@ pub fn ensure_directories() -> Result<(), String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_migrate_config_files() {
+        let root = tempfile::tempdir().unwrap();
+        let (legacy, current) = (root.path().join("legacy"), root.path().join("current"));
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("models.json"), "old models").unwrap();
+        std::fs::write(legacy.join("recent-projects.json"), "old projects").unwrap();
+        std::fs::create_dir_all(&current).unwrap();
+        std::fs::write(current.join("recent-projects.json"), "new projects").unwrap();
+
+        let files = ["models.json", "recent-projects.json"];
+        let moved = migrate_config_files(&legacy, &current, &files);
+        assert_eq!(moved, vec!["models.json"]);
+        assert_eq!(std::fs::read_to_string(current.join("models.json")).unwrap(), "old models");
+        assert!(!legacy.join("models.json").exists());
+        // Existing files are never overwritten
+        assert_eq!(std::fs::read_to_string(current.join("recent-projects.json")).unwrap(), "new projects");
+        assert!(migrate_config_files(&current, &current, &files).is_empty());
+    }
+
+    #[test]
+    fn test_config_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join(CONFIG_FILE);
+        std::fs::write(&path, r#"{ "server": { "port": 1000 } }"#).unwrap();
+        assert_eq!(ConfigManager::with_path(path.clone()).config().server.port, 1000);
+
+        // Saving refreshes the cache
+        let mut manager = ConfigManager::with_path(path.clone());
+        manager.config_mut().server.port = 2000;
+        manager.save().unwrap();
+        assert_eq!(ConfigManager::with_path(path.clone()).config().server.port, 2000);
+
+        // So does editing the file outside the app
+        std::fs::write(&path, r#"{ "server": { "port": 30000 } }"#).unwrap();
+        assert_eq!(ConfigManager::with_path(path.clone()).config().server.port, 30000);
+    }
+
     #[test]
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.server.port, 9888);
-        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.bind_host(), "127.0.0.1");
+        assert!(config.server.sandbox_files);
+        assert!(config.notifications.enabled);
+        assert_eq!(config.files.max_text_bytes, 10 * 1024 * 1024);
+        assert_eq!(config.files.max_binary_bytes, 50 * 1024 * 1024);
+        assert_eq!(config.terminal.max_terminals, 20);
+    }
+
+    #[test]
+    fn test_legacy_host_is_ignored() {
+        // Older builds saved the unused `host` default on every save
+        let config: Config = serde_json::from_str(r#"{ "server": { "host": "0.0.0.0" } }"#).unwrap();
+        assert_eq!(config.server.bind_host(), "127.0.0.1");
+        let saved = serde_json::to_value(&config).unwrap();
+        assert!(saved["server"].get("host").is_none());
+        assert!(saved["server"].get("bindHost").is_none());
+    }
+
+    #[test]
+    fn test_files_limits() {
+        let config: Config =
+            serde_json::from_str(r#"{"files": {"maxTextBytes": 1048576}}"#).unwrap();
+        assert_eq!(config.files.max_text_bytes, 1024 * 1024);
+        assert_eq!(config.files.max_binary_bytes, 50 * 1024 * 1024);
     }
 
     #[test]
@@ -228,4 +919,77 @@ mod tests {
         let parsed: Config = serde_json::from_str(&json).unwrap();
         assert_eq!(parsed.server.port, config.server.port);
     }
+
+    #[test]
+    fn test_agent_request_timeout() {
+        let config: Config =
+            serde_json::from_str(r#"{"agent": {"requestTimeoutMs": 900000}}"#).unwrap();
+        assert_eq!(config.agent.request_timeout_ms, Some(900000));
+        assert_eq!(Config::default().agent.request_timeout_ms, None);
+    }
+
+    #[test]
+    fn test_server_auth_token() {
+        let config: Config =
+            serde_json::from_str(r#"{"server": {"authToken": "secret"}}"#).unwrap();
+        assert_eq!(config.server.auth_token.as_deref(), Some("secret"));
+        assert_eq!(config.server.port, 9888);
+
+        let json = serde_json::to_string(&Config::default()).unwrap();
+        assert!(!json.contains("authToken"));
+    }
+
+    #[test]
+    fn test_mcp_servers() {
+        let config: Config = serde_json::from_str(
+            r#"{"mcpServers": [
+                {"name": "fs", "command": "npx", "args": ["-y", "mcp-fs"], "env": {"ROOT": "/tmp"}},
+                {"name": "remote", "type": "http", "url": "https://example.com/mcp"},
+                {"name": "broken", "type": "sse", "enabled": false}
+            ]}"#,
+        )
+        .unwrap();
+        let [fs, remote, broken] = &config.mcp_servers[..] else {
+            panic!("expected three servers");
+        };
+        assert_eq!(fs.transport, McpTransport::Stdio);
+        assert!(fs.enabled && fs.validate().is_ok());
+        assert_eq!(
+            fs.to_acp(),
+            serde_json::json!({
+                "name": "fs",
+                "command": "npx",
+                "args": ["-y", "mcp-fs"],
+                "env": [{"name": "ROOT", "value": "/tmp"}],
+            })
+        );
+        assert_eq!(remote.to_acp()["type"], "http");
+        assert_eq!(remote.to_acp()["headers"], serde_json::json!([]));
+        assert!(!broken.enabled);
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn test_permission_policy() {
+        let config: Config = serde_json::from_str(
+            r#"{"permissions": {"/proj": {"tools": {"read": "allow", "execute": "deny"}}}}"#,
+        )
+        .unwrap();
+        let policy = &config.permissions["/proj"];
+        assert_eq!(policy.action_for(Some("read")), PermissionAction::Allow);
+        assert_eq!(policy.action_for(Some("execute")), PermissionAction::Deny);
+        assert_eq!(policy.action_for(Some("edit")), PermissionAction::Prompt);
+        assert_eq!(policy.action_for(None), PermissionAction::Prompt);
+        assert_eq!(PermissionPolicy::allow_all().action_for(Some("delete")), PermissionAction::Allow);
+
+        // Unanswered prompts wait forever unless a timeout is set; it rejects by default
+        assert_eq!(policy.timeout(), None);
+        let timed: PermissionPolicy = serde_json::from_str(r#"{"timeoutSecs": 30}"#).unwrap();
+        assert_eq!(timed.timeout(), Some((std::time::Duration::from_secs(30), PermissionAction::Deny)));
+        let timed: PermissionPolicy = serde_json::from_str(r#"{"timeoutSecs": 5, "timeoutAction": "allow"}"#).unwrap();
+        assert_eq!(timed.timeout(), Some((std::time::Duration::from_secs(5), PermissionAction::Allow)));
+
+        let json = serde_json::to_string(&Config::default()).unwrap();
+        assert!(!json.contains("permissions"));
+    }
 }
@@ -0,0 +1,129 @@
+//! File Watcher
+//!
+//! Watches files open in a client's editor so edits made on disk (usually by
+//! the agent) reach the viewer. Like the session watcher, the parent directory
+//! is watched so files that are replaced on save keep being tracked.
+
+use std::path::{Path, PathBuf};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::debug;
+
+/// Text files up to this size have their new content included in change events
+pub const MAX_PUSHED_CONTENT_BYTES: u64 = 256 * 1024;
+
+/// A watched file changed on disk
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChange {
+    /// The path as the client asked to watch it
+    pub path: String,
+    pub exists: bool,
+    pub size: Option<u64>,
+    /// Modification time in milliseconds since the epoch
+    pub modified: Option<u64>,
+    /// New content, for small UTF-8 files when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl FileChange {
+    /// Current state of a file on disk
+    pub fn read(path: String, file: &Path, include_content: bool) -> Self {
+        let metadata = std::fs::metadata(file).ok().filter(|m| m.is_file());
+        let size = metadata.as_ref().map(|m| m.len());
+        let modified = metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64);
+        let content = if include_content && size.is_some_and(|size| size <= MAX_PUSHED_CONTENT_BYTES) {
+            std::fs::read(file).ok().and_then(|bytes| String::from_utf8(bytes).ok())
+        } else {
+            None
+        };
+
+        Self {
+            path,
+            exists: metadata.is_some(),
+            size,
+            modified,
+            content,
+        }
+    }
+}
+
+/// Watches a single file until dropped
+pub struct FileWatch {
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatch {
+    /// Call `on_change` whenever `file` is written, replaced or removed.
+    /// Events that leave the size and mtime unchanged are not reported.
+    pub fn new(
+        path: String,
+        file: PathBuf,
+        include_content: bool,
+        on_change: impl Fn(FileChange) + Send + 'static,
+    ) -> notify::Result<Self> {
+        let dir = file
+            .parent()
+            .map(|p| p.to_path_buf())
+            .ok_or_else(|| notify::Error::generic("File has no parent directory"))?;
+        let last = Mutex::new(FileChange::read(path.clone(), &file, false));
+
+        let watched = file.clone();
+        let handler = move |result: notify::Result<notify::Event>| match result {
+            Ok(event) => {
+                if matches!(event.kind, EventKind::Access(_)) || !event.paths.contains(&watched) {
+                    return;
+                }
+                let change = FileChange::read(path.clone(), &watched, include_content);
+                {
+                    let mut last = last.lock();
+                    if (last.exists, last.size, last.modified) == (change.exists, change.size, change.modified) {
+                        return;
+                    }
+                    *last = FileChange { content: None, ..change.clone() };
+                }
+                on_change(change);
+            }
+            Err(e) => debug!("File watch error: {}", e),
+        };
+
+        let mut watcher = notify::recommended_watcher(handler)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_file_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("main.rs");
+        std::fs::write(&file, "fn main() {}").unwrap();
+
+        let change = FileChange::read("main.rs".to_string(), &file, true);
+        assert!(change.exists);
+        assert_eq!(change.size, Some(12));
+        assert!(change.modified.is_some());
+        assert_eq!(change.content.as_deref(), Some("fn main() {}"));
+        assert!(FileChange::read("main.rs".to_string(), &file, false).content.is_none());
+
+        // Binary content is never pushed
+        std::fs::write(&file, [0xff, 0xfe, 0x00]).unwrap();
+        assert!(FileChange::read("main.rs".to_string(), &file, true).content.is_none());
+
+        std::fs::remove_file(&file).unwrap();
+        let removed = FileChange::read("main.rs".to_string(), &file, true);
+        assert!(!removed.exists);
+        assert_eq!(removed.size, None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Logging Setup
+//!
+//! Logs always go to stdout. A rotating log file is added with `--log-file [DIR]`,
+//! AERO_LOG_FILE or `logging.file` in config, so long-running servers keep logs
+//! that can be attached to bug reports.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use super::config::{cache_dir, ConfigManager, LogRotation, LoggingConfig};
+
+/// Environment variable enabling the log file: "1"/"true" for the default
+/// directory, "0"/"false" to disable it, anything else is the directory
+pub const LOG_FILE_ENV: &str = "AERO_LOG_FILE";
+
+/// Log files are named `aero-work.log` (size rotation) or `aero-work.<date>.log`
+const LOG_FILE_PREFIX: &str = "aero-work";
+
+/// Default log directory: ~/.cache/aerowork/logs/
+pub fn logs_dir() -> PathBuf {
+    cache_dir().join("logs")
+}
+
+/// Directory to write log files to, if file logging is enabled. `--log-file`
+/// (optionally followed by a directory) wins over AERO_LOG_FILE, which wins
+/// over `logging.file`.
+pub fn resolve_log_dir(config: &LoggingConfig) -> Option<PathBuf> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--log-file");
+    if args.next().is_some() {
+        let dir = args.next().filter(|arg| !arg.starts_with("--"));
+        return Some(dir.map(PathBuf::from).unwrap_or_else(logs_dir));
+    }
+
+    match std::env::var(LOG_FILE_ENV) {
+        Ok(value) if !value.is_empty() => match value.to_lowercase().as_str() {
+            "1" | "true" => Some(logs_dir()),
+            "0" | "false" => None,
+            _ => Some(PathBuf::from(value)),
+        },
+        _ => config.file.then(logs_dir),
+    }
+}
+
+/// Install the global tracing subscriber: stdout, plus the log file when enabled.
+/// `RUST_LOG` overrides `default_filter`. Keep the returned guard alive until
+/// exit, or buffered lines are lost.
+pub fn init_tracing(default_filter: &str) -> Option<WorkerGuard> {
+    let config = ConfigManager::new().config().logging.clone();
+    let file = match resolve_log_dir(&config) {
+        Some(dir) => match file_writer(&dir, &config) {
+            Ok(writer) => Some((writer, dir)),
+            Err(e) => {
+                eprintln!("Failed to open log file in {}: {}", dir.display(), e);
+                None
+            }
+        },
+        None => None,
+    };
+    let (file_layer, guard, dir) = match file {
+        Some(((writer, guard), dir)) => (
+            Some(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(writer)),
+            Some(guard),
+            Some(dir),
+        ),
+        None => (None, None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| default_filter.into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .init();
+
+    if let Some(dir) = dir {
+        tracing::info!("Logging to {} ({:?} rotation, keeping {} files)", dir.display(), config.rotation, config.max_files);
+    }
+    guard
+}
+
+/// Background writer for the configured rotation policy
+fn file_writer(dir: &Path, config: &LoggingConfig) -> io::Result<(NonBlocking, WorkerGuard)> {
+    use tracing_appender::rolling::{Builder, Rotation};
+
+    std::fs::create_dir_all(dir)?;
+    let max_files = config.max_files.max(1);
+    let rotation = match config.rotation {
+        LogRotation::Size => {
+            let writer = SizeRotatingWriter::open(dir, config.max_size_mb.max(1) * 1024 * 1024, max_files)?;
+            return Ok(tracing_appender::non_blocking(writer));
+        }
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let appender = Builder::new()
+        .rotation(rotation)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(max_files)
+        .build(dir)
+        .map_err(io::Error::other)?;
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// Writes `aero-work.log` and, once it would grow past `max_bytes`, shifts it to
+/// `aero-work.log.1` (and older ones up by one), keeping `max_files` files in all
+pub struct SizeRotatingWriter {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl SizeRotatingWriter {
+    pub fn open(dir: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let path = dir.join(format!("{}.log", LOG_FILE_PREFIX));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { path, file, written, max_bytes, max_files: max_files.max(1) })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let keep = self.max_files - 1;
+        if keep > 0 {
+            let _ = std::fs::remove_file(self.rotated(keep));
+            for n in (1..keep).rev() {
+                let _ = std::fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_rotating_writer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        let mut writer = SizeRotatingWriter::open(dir, 10, 3).unwrap();
+        for line in ["line one\n", "line two\n", "line three\n", "line four\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("aero-work.log"), "line four\n");
+        assert_eq!(read("aero-work.log.1"), "line three\n");
+        assert_eq!(read("aero-work.log.2"), "line two\n");
+        assert!(!dir.join("aero-work.log.3").exists());
+
+        // Reopening appends to the current file
+        let mut writer = SizeRotatingWriter::open(dir, 100, 3).unwrap();
+        writer.write_all(b"line five\n").unwrap();
+        assert_eq!(read("aero-work.log"), "line four\nline five\n");
+    }
+}
@@ -1,43 +1,81 @@
 pub mod config;
+pub mod project_config;
 pub mod state;
 
-pub use config::{Config, ConfigManager, config_dir, data_dir, cache_dir};
+pub use config::{
+    BusyPromptPolicy, Config, ConfigManager, FilesConfig, LogRotation, LoggingConfig, McpServerConfig, McpTransport, NotificationsConfig,
+    PatternMatch, PermissionAction, PermissionPolicy, SystemMessagePattern, SystemMessagesConfig,
+    TerminalConfig, config_dir, data_dir, cache_dir,
+};
+pub use project_config::{LoadedProjectConfig, ProjectConfig};
 pub use state::AppState;
 
 // Desktop-only: session_state depends on acp types
 #[cfg(not(target_os = "android"))]
 pub mod session_state;
 #[cfg(not(target_os = "android"))]
-pub use session_state::{ChatItem, Message, MessageRole, SessionState, SessionStateUpdate};
+pub use session_state::{ChatItem, Message, MessageRole, SessionState, SessionStateUpdate, TokenUsage};
 
 // Desktop-only modules (require pty, websocket server, etc.)
 #[cfg(not(target_os = "android"))]
 pub mod agent;
 #[cfg(not(target_os = "android"))]
+pub mod agent_command;
+#[cfg(not(target_os = "android"))]
+pub mod command_runner;
+#[cfg(not(target_os = "android"))]
+pub mod file_watcher;
+#[cfg(not(target_os = "android"))]
+pub mod logging;
+#[cfg(not(target_os = "android"))]
 pub mod model_config;
 #[cfg(not(target_os = "android"))]
+pub mod notifier;
+#[cfg(not(target_os = "android"))]
+pub mod opener;
+#[cfg(not(target_os = "android"))]
 pub mod plugins;
 #[cfg(not(target_os = "android"))]
+pub mod sandbox;
+#[cfg(not(target_os = "android"))]
+pub mod session_export;
+#[cfg(not(target_os = "android"))]
 pub mod session_registry;
 #[cfg(not(target_os = "android"))]
 pub mod session_state_manager;
 #[cfg(not(target_os = "android"))]
+pub mod session_titles;
+#[cfg(not(target_os = "android"))]
+pub mod session_watcher;
+#[cfg(not(target_os = "android"))]
 pub mod terminal;
 
 #[cfg(not(target_os = "android"))]
 pub use agent::AgentManager;
 #[cfg(not(target_os = "android"))]
+pub use agent_command::AgentCommand;
+#[cfg(not(target_os = "android"))]
+pub use command_runner::CommandOutput;
+#[cfg(not(target_os = "android"))]
 pub use model_config::ModelConfig;
 #[cfg(not(target_os = "android"))]
+pub use notifier::DesktopNotifier;
+#[cfg(not(target_os = "android"))]
+pub use opener::{parse_open_target, DesktopOpener, OpenTarget};
+#[cfg(not(target_os = "android"))]
 pub use plugins::{
     AddMarketplaceRequest, InstallPluginRequest, InstallPluginResponse,
     ListPluginsResponse, MarketplaceResponse, PluginManager, UninstallPluginResponse,
 };
 #[cfg(not(target_os = "android"))]
-pub use session_registry::{ListSessionsResponse, SessionInfo, SessionRegistry, SessionStatus};
+pub use sandbox::PathSandbox;
+#[cfg(not(target_os = "android"))]
+pub use session_registry::{ListSessionsResponse, ProjectStats, SessionInfo, SessionRegistry, SessionSearchResult, SessionStatus};
+#[cfg(not(target_os = "android"))]
+pub use session_state_manager::{ClientId, ModeChange, SequencedUpdate, SessionStateManager, SharedSessionStateManager};
 #[cfg(not(target_os = "android"))]
-pub use session_state_manager::{ClientId, SessionStateManager, SharedSessionStateManager};
+pub use session_watcher::{SessionFileChange, SessionFileWatcher};
 #[cfg(not(target_os = "android"))]
-pub use state::SessionActivated;
+pub use state::{AgentKey, SessionActivated};
 #[cfg(not(target_os = "android"))]
-pub use terminal::{TerminalInfo, TerminalManager, TerminalOutput};
+pub use terminal::{TerminalExit, TerminalExitStatus, TerminalInfo, TerminalManager, TerminalOutput, TerminalOutputSnapshot};
@@ -1,6 +1,6 @@
 //! Model Provider Configuration
 //!
-//! Manages model provider settings stored in ~/.config/aerowork/models.json
+//! Manages model provider settings stored in models.json in `config_dir()`
 //! and syncs environment variables to ~/.claude/settings.json for Claude Code.
 
 use serde::{Deserialize, Serialize};
@@ -12,6 +12,75 @@ use super::config::config_dir;
 
 const CONFIG_FILE: &str = "models.json";
 
+/// Base URL used when a provider doesn't set ANTHROPIC_BASE_URL
+const DEFAULT_ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
+/// A built-in provider: ID (its key in `providers`), display name and the
+/// settings (camelCase keys) it can't work without
+struct BuiltinProvider {
+    id: &'static str,
+    name: &'static str,
+    required: &'static [&'static str],
+}
+
+/// Built-in providers, in the order settings forms list them
+const BUILTIN_PROVIDERS: &[BuiltinProvider] = &[
+    BuiltinProvider { id: "default", name: "Default", required: &[] },
+    BuiltinProvider { id: "anthropic", name: "Anthropic", required: &[] },
+    BuiltinProvider { id: "bedrock", name: "Amazon Bedrock", required: &["region"] },
+    BuiltinProvider { id: "bigmodel", name: "BigModel / Zhipu", required: &["authToken"] },
+    BuiltinProvider { id: "minimax", name: "MiniMax", required: &["authToken"] },
+    BuiltinProvider { id: "moonshot", name: "Moonshot AI / Kimi", required: &["authToken"] },
+    BuiltinProvider { id: "ollama", name: "Ollama", required: &["baseUrl"] },
+    BuiltinProvider { id: "openrouter", name: "OpenRouter", required: &["authToken", "model"] },
+    BuiltinProvider { id: "azure", name: "Azure", required: &["endpoint", "apiKey"] },
+];
+
+/// Settings that hold credentials
+const SECRET_FIELDS: &[&str] = &["apiKey", "authToken", "bearerToken"];
+
+/// Settings of a custom provider that must be filled in
+const CUSTOM_PROVIDER_REQUIRED: &[&str] = &["name", "baseUrl"];
+
+/// One setting of a provider, for building its settings form
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderField {
+    /// Key in the provider's object in models.json
+    pub key: String,
+    pub required: bool,
+    /// Holds a credential and should be masked
+    pub secret: bool,
+    /// Value in a fresh config, if not empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// Settings form description of a provider
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSchema {
+    pub id: String,
+    pub name: String,
+    /// Sorted by key
+    pub fields: Vec<ProviderField>,
+    /// ANTHROPIC_BASE_URL the provider gets with default settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_base_url: Option<String>,
+    /// ANTHROPIC_MODEL the provider gets with default settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+}
+
+/// HTTP request used to check that a provider is reachable
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderProbe {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// Whether credentials were configured (401/403 then counts as failure)
+    pub has_credentials: bool,
+}
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -35,6 +104,8 @@ pub struct Providers {
     pub ollama: OllamaProvider,
     #[serde(default)]
     pub openrouter: OpenRouterProvider,
+    #[serde(default)]
+    pub azure: AzureProvider,
 }
 
 /// Default provider - no additional environment variables
@@ -174,6 +245,59 @@ impl Default for OpenRouterProvider {
     }
 }
 
+/// Azure (Microsoft Foundry) provider configuration. Models are addressed by
+/// deployment name rather than model ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AzureProvider {
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    pub enabled: bool,
+    /// Resource endpoint, e.g. https://my-resource.services.ai.azure.com
+    #[serde(default)]
+    pub endpoint: String,
+    /// Sent as the `api-version` header when set
+    #[serde(default)]
+    pub api_version: String,
+    #[serde(default)]
+    pub opus_deployment: String,
+    #[serde(default)]
+    pub sonnet_deployment: String,
+    #[serde(default)]
+    pub haiku_deployment: String,
+    #[serde(default)]
+    pub api_key: String,
+}
+
+impl Default for AzureProvider {
+    fn default() -> Self {
+        Self {
+            provider_type: "azure".to_string(),
+            enabled: true,
+            endpoint: String::new(),
+            api_version: String::new(),
+            opus_deployment: "claude-opus-4-5".to_string(),
+            sonnet_deployment: "claude-sonnet-4-5".to_string(),
+            haiku_deployment: "claude-haiku-4-5".to_string(),
+            api_key: String::new(),
+        }
+    }
+}
+
+impl AzureProvider {
+    /// Anthropic-compatible base URL of the endpoint (`<endpoint>/anthropic`)
+    fn base_url(&self) -> Option<String> {
+        let endpoint = self.endpoint.trim().trim_end_matches('/');
+        if endpoint.is_empty() {
+            None
+        } else if endpoint.ends_with("/anthropic") {
+            Some(endpoint.to_string())
+        } else {
+            Some(format!("{}/anthropic", endpoint))
+        }
+    }
+}
+
 /// Custom provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -248,6 +372,7 @@ impl Default for ModelConfig {
                 },
                 ollama: OllamaProvider::default(),
                 openrouter: OpenRouterProvider::default(),
+                azure: AzureProvider::default(),
             },
             custom_providers: vec![],
         }
@@ -360,6 +485,14 @@ impl ModelConfig {
                     None // OpenRouter requires user to specify model
                 }
             }
+            "azure" => {
+                let p = &self.providers.azure;
+                if !p.sonnet_deployment.is_empty() {
+                    Some(p.sonnet_deployment.clone())
+                } else {
+                    None // Deployment names are chosen by the user
+                }
+            }
             custom_id => {
                 // Custom provider
                 if let Some(p) = self.custom_providers.iter().find(|p| p.id == custom_id) {
@@ -601,6 +734,38 @@ impl ModelConfig {
                     env.insert("CLAUDE_CODE_SUBAGENT_MODEL".to_string(), p.model.clone());
                 }
             }
+            "azure" => {
+                let p = &self.providers.azure;
+                if let Some(base_url) = p.base_url() {
+                    env.insert("ANTHROPIC_BASE_URL".to_string(), base_url);
+                }
+                // Deployment names stand in for model IDs
+                if !p.sonnet_deployment.is_empty() {
+                    env.insert("ANTHROPIC_MODEL".to_string(), p.sonnet_deployment.clone());
+                    env.insert("ANTHROPIC_DEFAULT_SONNET_MODEL".to_string(), p.sonnet_deployment.clone());
+                    env.insert("CLAUDE_CODE_SUBAGENT_MODEL".to_string(), p.sonnet_deployment.clone());
+                }
+                if !p.opus_deployment.is_empty() {
+                    env.insert("ANTHROPIC_DEFAULT_OPUS_MODEL".to_string(), p.opus_deployment.clone());
+                }
+                if !p.haiku_deployment.is_empty() {
+                    env.insert("ANTHROPIC_DEFAULT_HAIKU_MODEL".to_string(), p.haiku_deployment.clone());
+                }
+                if !p.api_key.is_empty() {
+                    env.insert("ANTHROPIC_API_KEY".to_string(), p.api_key.clone());
+                }
+                // Azure authenticates with `api-key`; ANTHROPIC_API_KEY is sent as `x-api-key`
+                let mut headers = Vec::new();
+                if !p.api_key.is_empty() {
+                    headers.push(format!("api-key: {}", p.api_key));
+                }
+                if !p.api_version.is_empty() {
+                    headers.push(format!("api-version: {}", p.api_version));
+                }
+                if !headers.is_empty() {
+                    env.insert("ANTHROPIC_CUSTOM_HEADERS".to_string(), headers.join("\n"));
+                }
+            }
             custom_id => {
                 // Look for custom provider
                 if let Some(p) = self.custom_providers.iter().find(|p| p.id == custom_id) {
@@ -636,6 +801,125 @@ impl ModelConfig {
 
         env
     }
+
+    /// Environment variables a provider would get as the active provider
+    pub fn provider_env_vars(&self, provider_id: &str) -> Result<HashMap<String, String>, String> {
+        if !BUILTIN_PROVIDERS.iter().any(|p| p.id == provider_id)
+            && !self.custom_providers.iter().any(|p| p.id == provider_id)
+        {
+            return Err(format!("Unknown provider: {}", provider_id));
+        }
+
+        let mut config = self.clone();
+        config.active_provider = provider_id.to_string();
+        Ok(config.get_env_vars())
+    }
+
+    /// Build a reachability check for a provider from the env vars it would
+    /// get as the active provider. Anthropic-compatible providers list models;
+    /// Bedrock only checks the regional runtime endpoint.
+    pub fn provider_probe(&self, provider_id: &str) -> Result<ProviderProbe, String> {
+        let env = self.provider_env_vars(provider_id)?;
+        let non_empty = |key: &str| env.get(key).filter(|v| !v.is_empty()).cloned();
+
+        if provider_id == "bedrock" {
+            let region = non_empty("AWS_REGION").unwrap_or_else(|| "us-east-1".to_string());
+            let mut headers = Vec::new();
+            let token = non_empty("AWS_BEARER_TOKEN_BEDROCK");
+            if let Some(token) = &token {
+                headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+            }
+            return Ok(ProviderProbe {
+                url: format!("https://bedrock-runtime.{}.amazonaws.com/", region),
+                headers,
+                has_credentials: token.is_some(),
+            });
+        }
+
+        let base_url = non_empty("ANTHROPIC_BASE_URL")
+            .unwrap_or_else(|| DEFAULT_ANTHROPIC_BASE_URL.to_string());
+        let mut headers = vec![("anthropic-version".to_string(), "2023-06-01".to_string())];
+        let api_key = non_empty("ANTHROPIC_API_KEY");
+        let auth_token = non_empty("ANTHROPIC_AUTH_TOKEN");
+        if let Some(key) = &api_key {
+            headers.push(("x-api-key".to_string(), key.clone()));
+        }
+        if let Some(token) = &auth_token {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+
+        Ok(ProviderProbe {
+            url: format!("{}/v1/models", base_url.trim_end_matches('/')),
+            headers,
+            has_credentials: api_key.is_some() || auth_token.is_some(),
+        })
+    }
+}
+
+/// Settings form descriptions of the built-in providers. Fields and defaults
+/// come from `ModelConfig::default()`, base URL and model from the env vars
+/// `get_env_vars` would produce with those defaults.
+pub fn provider_schemas() -> Vec<ProviderSchema> {
+    let defaults = ModelConfig::default();
+    let providers = serde_json::to_value(&defaults.providers).unwrap_or_default();
+    BUILTIN_PROVIDERS
+        .iter()
+        .map(|provider| {
+            let env = defaults.provider_env_vars(provider.id).unwrap_or_default();
+            ProviderSchema {
+                id: provider.id.to_string(),
+                name: provider.name.to_string(),
+                fields: schema_fields(&providers[provider.id], provider.required),
+                default_base_url: env.get("ANTHROPIC_BASE_URL").cloned(),
+                default_model: env.get("ANTHROPIC_MODEL").cloned(),
+            }
+        })
+        .collect()
+}
+
+/// Settings form description shared by all custom providers
+pub fn custom_provider_schema() -> ProviderSchema {
+    let template = CustomProvider {
+        id: String::new(),
+        name: String::new(),
+        model: String::new(),
+        opus_model: String::new(),
+        sonnet_model: String::new(),
+        haiku_model: String::new(),
+        subagent_model: String::new(),
+        api_key: String::new(),
+        auth_token: String::new(),
+        base_url: String::new(),
+    };
+    let value = serde_json::to_value(&template).unwrap_or_default();
+    ProviderSchema {
+        id: "custom".to_string(),
+        name: "Custom".to_string(),
+        // The ID is generated when the provider is added
+        fields: schema_fields(&value, CUSTOM_PROVIDER_REQUIRED)
+            .into_iter()
+            .filter(|field| field.key != "id")
+            .collect(),
+        default_base_url: None,
+        default_model: None,
+    }
+}
+
+/// Form fields of a provider's serialized settings (`type`/`enabled` are not settings)
+fn schema_fields(settings: &serde_json::Value, required: &[&str]) -> Vec<ProviderField> {
+    let Some(object) = settings.as_object() else {
+        return Vec::new();
+    };
+    object
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "type" | "enabled"))
+        .map(|(key, value)| ProviderField {
+            key: key.clone(),
+            required: required.contains(&key.as_str()),
+            secret: SECRET_FIELDS.contains(&key.as_str()),
+            default: value.as_str().filter(|v| !v.is_empty()).map(str::to_string),
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -686,6 +970,35 @@ mod tests {
         assert_eq!(env.get("AWS_REGION"), Some(&"us-west-2".to_string()));
     }
 
+    #[test]
+    fn test_env_vars_azure() {
+        let mut config = ModelConfig::default();
+        config.active_provider = "azure".to_string();
+        config.providers.azure.endpoint = "https://my-resource.services.ai.azure.com/".to_string();
+        config.providers.azure.api_version = "2025-01-01".to_string();
+        config.providers.azure.sonnet_deployment = "team-sonnet".to_string();
+        config.providers.azure.api_key = "azure-key".to_string();
+
+        let env = config.get_env_vars();
+        assert_eq!(
+            env.get("ANTHROPIC_BASE_URL"),
+            Some(&"https://my-resource.services.ai.azure.com/anthropic".to_string())
+        );
+        assert_eq!(env.get("ANTHROPIC_MODEL"), Some(&"team-sonnet".to_string()));
+        assert_eq!(env.get("ANTHROPIC_DEFAULT_OPUS_MODEL"), Some(&"claude-opus-4-5".to_string()));
+        assert_eq!(
+            env.get("ANTHROPIC_CUSTOM_HEADERS"),
+            Some(&"api-key: azure-key\napi-version: 2025-01-01".to_string())
+        );
+        assert_eq!(config.get_claude_model(), Some("team-sonnet".to_string()));
+
+        // Configs saved before Azure existed still load
+        let mut value = serde_json::to_value(ModelConfig::default()).unwrap();
+        value["providers"].as_object_mut().unwrap().remove("azure");
+        let loaded: ModelConfig = serde_json::from_value(value).unwrap();
+        assert_eq!(loaded.providers.azure.provider_type, "azure");
+    }
+
     #[test]
     fn test_env_vars_minimax() {
         let mut config = ModelConfig::default();
@@ -698,4 +1011,66 @@ mod tests {
         );
         assert_eq!(env.get("ANTHROPIC_MODEL"), Some(&"MiniMax-M2.1".to_string()));
     }
+
+    #[test]
+    fn test_provider_probe() {
+        let mut config = ModelConfig::default();
+        config.providers.anthropic.base_url = "https://proxy.example.com/".to_string();
+        config.providers.anthropic.api_key = "test-key".to_string();
+
+        // Probing doesn't depend on the active provider
+        let probe = config.provider_probe("anthropic").unwrap();
+        assert_eq!(probe.url, "https://proxy.example.com/v1/models");
+        assert!(probe.has_credentials);
+        assert!(probe
+            .headers
+            .contains(&("x-api-key".to_string(), "test-key".to_string())));
+
+        let probe = config.provider_probe("default").unwrap();
+        assert_eq!(probe.url, "https://api.anthropic.com/v1/models");
+        assert!(!probe.has_credentials);
+
+        config.providers.bedrock.region = "eu-west-1".to_string();
+        let probe = config.provider_probe("bedrock").unwrap();
+        assert_eq!(probe.url, "https://bedrock-runtime.eu-west-1.amazonaws.com/");
+
+        assert!(config.provider_probe("no-such-provider").is_err());
+    }
+
+    #[test]
+    fn test_provider_schemas() {
+        let schemas = provider_schemas();
+        assert_eq!(schemas.len(), BUILTIN_PROVIDERS.len());
+
+        let field = |schema: &ProviderSchema, key: &str| {
+            schema.fields.iter().find(|f| f.key == key).cloned().unwrap()
+        };
+
+        let default = &schemas[0];
+        assert_eq!(default.id, "default");
+        assert!(default.fields.is_empty());
+        assert_eq!(default.default_base_url, None);
+
+        let moonshot = schemas.iter().find(|s| s.id == "moonshot").unwrap();
+        assert_eq!(moonshot.default_base_url.as_deref(), Some("https://api.moonshot.ai/anthropic"));
+        assert_eq!(moonshot.default_model.as_deref(), Some("kimi-k2-thinking-turbo"));
+        let token = field(moonshot, "authToken");
+        assert!(token.required && token.secret);
+
+        let bedrock = schemas.iter().find(|s| s.id == "bedrock").unwrap();
+        assert_eq!(field(bedrock, "region").default.as_deref(), Some("us-east-1"));
+        assert!(field(bedrock, "bearerToken").secret);
+
+        // Every required field exists in the provider's settings
+        for (schema, provider) in schemas.iter().zip(BUILTIN_PROVIDERS) {
+            for key in provider.required {
+                assert!(schema.fields.iter().any(|f| f.key == *key), "{} has no {}", schema.id, key);
+            }
+        }
+
+        let custom = custom_provider_schema();
+        assert!(custom.fields.iter().all(|f| f.key != "id"));
+        assert!(field(&custom, "baseUrl").required);
+        assert!(field(&custom, "apiKey").secret);
+    }
 }
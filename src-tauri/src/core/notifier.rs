@@ -0,0 +1,14 @@
+//! Desktop Notifications
+//!
+//! Backend-agnostic hook for OS notifications. The Tauri desktop build installs
+//! a notifier at startup; headless and standalone server modes leave it unset,
+//! which makes notifications a no-op.
+
+/// Shows OS notifications for the desktop window
+pub trait DesktopNotifier: Send + Sync {
+    /// Whether the main window currently has focus (notifications are skipped if so)
+    fn is_focused(&self) -> bool;
+
+    /// Show a notification
+    fn notify(&self, title: &str, body: &str);
+}
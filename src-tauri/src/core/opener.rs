@@ -0,0 +1,77 @@
+//! Opening Files and Links
+//!
+//! Backend-agnostic hook for handing a file or URL to the OS default app. The
+//! Tauri desktop build installs an opener at startup; headless and standalone
+//! server modes leave it unset, so opening fails there.
+
+use std::path::{Path, PathBuf};
+
+/// URL schemes that may be opened; anything else (file:, javascript:, custom
+/// app handlers) is rejected
+pub const OPEN_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Opens files and URLs with the OS default handler
+pub trait DesktopOpener: Send + Sync {
+    /// Open a local file or directory
+    fn open_path(&self, path: &Path) -> Result<(), String>;
+
+    /// Open a URL (already checked against `OPEN_URL_SCHEMES`)
+    fn open_url(&self, url: &str) -> Result<(), String>;
+}
+
+/// What `open_path` was asked to open
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenTarget {
+    Path(PathBuf),
+    Url(String),
+}
+
+/// Tell a URL from a local path and check its scheme. A scheme needs at least
+/// two characters, so Windows drive letters (`C:\...`) stay paths.
+pub fn parse_open_target(target: &str) -> Result<OpenTarget, String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("Nothing to open".to_string());
+    }
+
+    let scheme = target.split_once(':').map(|(scheme, _)| scheme).filter(|scheme| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+    match scheme {
+        Some(scheme) if OPEN_URL_SCHEMES.contains(&scheme.to_lowercase().as_str()) => {
+            Ok(OpenTarget::Url(target.to_string()))
+        }
+        Some(scheme) => Err(format!("Opening {}: links is not allowed", scheme)),
+        None => Ok(OpenTarget::Path(PathBuf::from(target))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_open_target() {
+        assert_eq!(
+            parse_open_target("https://example.com/a?b=c"),
+            Ok(OpenTarget::Url("https://example.com/a?b=c".to_string()))
+        );
+        assert_eq!(
+            parse_open_target("MAILTO:someone@example.com"),
+            Ok(OpenTarget::Url("MAILTO:someone@example.com".to_string()))
+        );
+        assert_eq!(
+            parse_open_target("/home/user/doc.pdf"),
+            Ok(OpenTarget::Path(PathBuf::from("/home/user/doc.pdf")))
+        );
+        assert_eq!(
+            parse_open_target("C:\\Users\\doc.pdf"),
+            Ok(OpenTarget::Path(PathBuf::from("C:\\Users\\doc.pdf")))
+        );
+        assert!(parse_open_target("file:///etc/passwd").is_err());
+        assert!(parse_open_target("javascript:alert(1)").is_err());
+        assert!(parse_open_target("  ").is_err());
+    }
+}
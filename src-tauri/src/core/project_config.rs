@@ -0,0 +1,221 @@
+//! Project Config
+//!
+//! Per-project settings from a `.aerowork.json` that teams can commit to their
+//! repository. It is looked up from a session's cwd up to the repository root
+//! and overlays the global `Config` for that project: project values win.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::config::{Config, ConfigManager};
+use crate::acp::client::expand_config_dirs;
+
+/// File name of the project config
+pub const PROJECT_CONFIG_FILE: &str = ".aerowork.json";
+
+/// Settings a project can override. Unknown keys are rejected so typos don't
+/// go unnoticed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Provider for sessions started in the project (unless the client picks one)
+    #[serde(default)]
+    pub provider: Option<String>,
+
+    /// Agent command line for the project's sessions, replacing the server-wide
+    /// one (same syntax as `agent.defaultCommand`). They run in their own agent process.
+    /// Only honoured for projects listed in `agent.trustedProjects`.
+    #[serde(default)]
+    pub agent_command: Option<String>,
+
+    /// File access overrides
+    #[serde(default)]
+    pub files: ProjectFilesConfig,
+
+    /// Extra directories file commands may access, relative to the directory
+    /// holding `.aerowork.json`. They must stay inside it.
+    #[serde(default)]
+    pub sandbox_roots: Vec<String>,
+}
+
+/// File access overrides of a project
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProjectFilesConfig {
+    /// Globs skipped in addition to the global `files.ignore`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+
+    /// Replaces the global `files.respectGitignore`
+    #[serde(default)]
+    pub respect_gitignore: Option<bool>,
+}
+
+/// A project config and where it was found
+#[derive(Debug, Clone)]
+pub struct LoadedProjectConfig {
+    pub path: PathBuf,
+    pub config: ProjectConfig,
+}
+
+impl LoadedProjectConfig {
+    /// Directory holding the config file
+    pub fn root(&self) -> &Path {
+        self.path.parent().unwrap_or(&self.path)
+    }
+
+    /// Whether `root()` is one of the `trusted` directories
+    fn is_trusted(&self, trusted: &[String]) -> bool {
+        let Ok(root) = self.root().canonicalize() else {
+            return false;
+        };
+        let home = std::env::var("HOME").unwrap_or_default();
+        expand_config_dirs(trusted, &home).iter().any(|dir| dir.canonicalize().is_ok_and(|dir| dir == root))
+    }
+
+    /// The configured sandbox roots resolved against `root()`. Roots that don't
+    /// exist or escape the project are errors.
+    pub fn sandbox_roots(&self) -> Result<Vec<PathBuf>, String> {
+        let root = self.root().canonicalize().map_err(|e| format!("Failed to resolve {:?}: {}", self.root(), e))?;
+        self.config
+            .sandbox_roots
+            .iter()
+            .map(|entry| {
+                let resolved = root
+                    .join(entry)
+                    .canonicalize()
+                    .map_err(|e| format!("Invalid sandbox root {:?} in {:?}: {}", entry, self.path, e))?;
+                if !resolved.starts_with(&root) {
+                    return Err(format!("Sandbox root {:?} in {:?} is outside the project", entry, self.path));
+                }
+                Ok(resolved)
+            })
+            .collect()
+    }
+}
+
+impl ProjectConfig {
+    /// Find and parse the `.aerowork.json` for `cwd`. Directories are searched
+    /// from `cwd` up to the enclosing repository root (the first one containing
+    /// `.git`); outside a repository only `cwd` itself is checked. The nearest
+    /// file wins. Parse errors, including unknown keys, are returned.
+    /// `agentCommand` is dropped unless the project is in `agent.trustedProjects`.
+    pub fn load(cwd: &Path) -> Result<Option<LoadedProjectConfig>, String> {
+        let trusted = ConfigManager::new().config().agent.trusted_projects.clone();
+        Self::load_trusting(cwd, &trusted)
+    }
+
+    fn load_trusting(cwd: &Path, trusted: &[String]) -> Result<Option<LoadedProjectConfig>, String> {
+        let Some(path) = find_project_config(cwd) else {
+            return Ok(None);
+        };
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let config = serde_json::from_str(&content).map_err(|e| format!("Invalid {:?}: {}", path, e))?;
+        let mut loaded = LoadedProjectConfig { path, config };
+        if loaded.config.agent_command.is_some() && !loaded.is_trusted(trusted) {
+            warn!(
+                "Ignoring agentCommand in {:?}: {:?} is not in agent.trustedProjects",
+                loaded.path,
+                loaded.root()
+            );
+            loaded.config.agent_command = None;
+        }
+        Ok(Some(loaded))
+    }
+
+    /// `global` with this project's overrides applied
+    pub fn overlay(&self, global: &Config) -> Config {
+        let mut config = global.clone();
+        for pattern in &self.files.ignore {
+            if !config.files.ignore.contains(pattern) {
+                config.files.ignore.push(pattern.clone());
+            }
+        }
+        if let Some(respect_gitignore) = self.files.respect_gitignore {
+            config.files.respect_gitignore = respect_gitignore;
+        }
+        if let Some(agent_command) = &self.agent_command {
+            config.agent.default_command = Some(agent_command.clone());
+        }
+        config
+    }
+}
+
+/// The global config with the overrides of the project containing `path`.
+/// An invalid project config is logged and ignored here; sessions report it.
+pub fn effective_config(path: &Path) -> Config {
+    let global = ConfigManager::new().config().clone();
+    match ProjectConfig::load(path) {
+        Ok(Some(project)) => project.config.overlay(&global),
+        Ok(None) => global,
+        Err(e) => {
+            warn!("{}", e);
+            global
+        }
+    }
+}
+
+fn find_project_config(cwd: &Path) -> Option<PathBuf> {
+    let repo_root = cwd.ancestors().find(|dir| dir.join(".git").exists());
+    let search_end = repo_root.unwrap_or(cwd);
+    for dir in cwd.ancestors() {
+        let candidate = dir.join(PROJECT_CONFIG_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if dir == search_end {
+            break;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_project_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let repo = root.join("repo");
+        let sub = repo.join("packages/app");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("vendor")).unwrap();
+        std::fs::create_dir_all(&sub).unwrap();
+        // Above the repository root, so never used
+        std::fs::write(root.join(PROJECT_CONFIG_FILE), r#"{"provider": "outside"}"#).unwrap();
+        assert!(ProjectConfig::load(&sub).unwrap().is_none());
+
+        std::fs::write(
+            repo.join(PROJECT_CONFIG_FILE),
+            r#"{"provider": "bedrock", "agentCommand": "gemini --experimental-acp", "files": {"ignore": ["vendor"]}, "sandboxRoots": ["vendor"]}"#,
+        )
+        .unwrap();
+        let trusted = [repo.to_string_lossy().to_string()];
+        let loaded = ProjectConfig::load_trusting(&sub, &trusted).unwrap().unwrap();
+        assert_eq!(loaded.root(), repo.as_path());
+        assert_eq!(loaded.config.provider.as_deref(), Some("bedrock"));
+        assert_eq!(loaded.sandbox_roots().unwrap(), vec![repo.join("vendor").canonicalize().unwrap()]);
+
+        // Project values overlay the global config
+        let config = loaded.config.overlay(&Config::default());
+        assert!(config.files.ignore.contains(&"vendor".to_string()));
+        assert!(config.files.ignore.contains(&"node_modules".to_string()));
+        assert!(!config.files.respect_gitignore);
+        assert_eq!(config.agent.default_command.as_deref(), Some("gemini --experimental-acp"));
+
+        // The agent command of a project that isn't trusted is ignored
+        let untrusted = ProjectConfig::load_trusting(&sub, &[]).unwrap().unwrap();
+        assert_eq!(untrusted.config.agent_command, None);
+        assert_eq!(untrusted.config.provider.as_deref(), Some("bedrock"));
+
+        // Unknown keys and roots outside the project are rejected
+        std::fs::write(repo.join(PROJECT_CONFIG_FILE), r#"{"provder": "bedrock"}"#).unwrap();
+        assert!(ProjectConfig::load(&sub).unwrap_err().contains("provder"));
+        std::fs::write(repo.join(PROJECT_CONFIG_FILE), r#"{"sandboxRoots": [".."]}"#).unwrap();
+        assert!(ProjectConfig::load(&sub).unwrap().unwrap().sandbox_roots().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Path Sandbox
+//!
+//! Restricts file commands coming from remote clients to a set of allowed roots
+//! (project directories). Paths are canonicalized before the check so `../`
+//! traversal and symlinks cannot escape a root.
+
+use std::path::{Component, Path, PathBuf};
+
+use parking_lot::RwLock;
+use tracing::debug;
+
+/// Allowed filesystem roots for client file commands
+pub struct PathSandbox {
+    enabled: bool,
+    roots: RwLock<Vec<PathBuf>>,
+}
+
+impl PathSandbox {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            roots: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Add a root directory. Non-existent paths and the filesystem root are ignored.
+    pub fn allow_root(&self, root: impl AsRef<Path>) {
+        let Ok(root) = expand_tilde(root.as_ref()).canonicalize() else {
+            return;
+        };
+        if !root.is_dir() || root.parent().is_none() {
+            return;
+        }
+
+        let mut roots = self.roots.write();
+        if !roots.contains(&root) {
+            debug!("Sandbox: allowing root {:?}", root);
+            roots.push(root);
+        }
+    }
+
+    /// Currently allowed roots
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.read().clone()
+    }
+
+    /// Resolve a client-supplied path and ensure it stays inside an allowed root.
+    /// The path does not need to exist (e.g. for writes); its nearest existing
+    /// ancestor is canonicalized instead.
+    pub fn check(&self, path: &str) -> Result<PathBuf, String> {
+        let requested = expand_tilde(Path::new(path));
+        if !self.enabled {
+            return Ok(requested);
+        }
+
+        let resolved = resolve_path(&requested)
+            .ok_or_else(|| format!("Invalid path: {}", path))?;
+
+        if self.roots.read().iter().any(|root| resolved.starts_with(root)) {
+            Ok(resolved)
+        } else {
+            Err(format!("Access denied: {} is outside the allowed project directories", path))
+        }
+    }
+}
+
+/// Canonicalize a path whose tail may not exist yet.
+/// Returns None if the non-existent tail contains `..` or the path is relative.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+
+    let mut existing = path.to_path_buf();
+    let mut tail = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        tail.push(existing.file_name()?.to_os_string());
+        existing = existing.parent()?.to_path_buf();
+    }
+
+    let mut resolved = existing.canonicalize().ok()?;
+    for part in tail.into_iter().rev() {
+        match Path::new(&part).components().next() {
+            Some(Component::Normal(_)) => resolved.push(part),
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+fn expand_tilde(path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().canonicalize().unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_allows_paths_inside_root() {
+        let (_tmp, base) = temp_dir();
+        let project = base.join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("src/main.rs"), "").unwrap();
+
+        let sandbox = PathSandbox::new(true);
+        sandbox.allow_root(&project);
+
+        let existing = project.join("src/main.rs");
+        assert_eq!(sandbox.check(existing.to_str().unwrap()).unwrap(), existing);
+        // Not-yet-existing files (writes) resolve against their existing parent
+        let new_file = project.join("src/new/lib.rs");
+        assert_eq!(sandbox.check(new_file.to_str().unwrap()).unwrap(), new_file);
+    }
+
+    #[test]
+    fn test_blocks_traversal() {
+        let (_tmp, base) = temp_dir();
+        let project = base.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+
+        let sandbox = PathSandbox::new(true);
+        sandbox.allow_root(&project);
+
+        let escaped = format!("{}/../secret.txt", project.display());
+        assert!(sandbox.check(&escaped).is_err());
+        let escaped_new = format!("{}/missing/../../secret.txt", project.display());
+        assert!(sandbox.check(&escaped_new).is_err());
+        assert!(sandbox.check("/etc/passwd").is_err());
+        assert!(sandbox.check("relative/path").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_blocks_symlink_escape() {
+        let (_tmp, base) = temp_dir();
+        let project = base.join("project");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, project.join("link")).unwrap();
+
+        let sandbox = PathSandbox::new(true);
+        sandbox.allow_root(&project);
+
+        let via_link = project.join("link/secret.txt");
+        assert!(sandbox.check(via_link.to_str().unwrap()).is_err());
+        let new_via_link = project.join("link/new.txt");
+        assert!(sandbox.check(new_via_link.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_disabled_sandbox_allows_everything() {
+        let sandbox = PathSandbox::new(false);
+        assert!(sandbox.check("/etc/passwd").is_ok());
+    }
+
+    #[test]
+    fn test_filesystem_root_is_never_allowed() {
+        let sandbox = PathSandbox::new(true);
+        sandbox.allow_root("/");
+        assert!(sandbox.roots().is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! Session Export Module
+//!
+//! Renders a session as a Markdown transcript for sharing or archiving.
+//! JSON exports are the serialized `SessionState`.
+
+use crate::acp::{ContentBlock, ResourceContents, ToolCall, ToolCallContent};
+
+use super::session_registry::is_system_message;
+use super::session_state::{ChatItem, MessageRole, SessionState};
+
+/// Render a session as Markdown: messages, then tool calls with their input, diffs and output
+pub fn to_markdown(state: &SessionState, title: &str) -> String {
+    let mut out = format!("# {}\n\n", title);
+    out.push_str(&format!("- Session: `{}`\n", state.id));
+    out.push_str(&format!("- Project: `{}`\n", state.cwd));
+    out.push_str(&format!("- Exported: {}\n", chrono::Utc::now().to_rfc3339()));
+
+    for item in &state.chat_items {
+        match item {
+            ChatItem::Message { message } => {
+                if message.content.trim().is_empty() || is_system_message(&message.content) {
+                    continue;
+                }
+                let heading = match message.role {
+                    MessageRole::User => "User",
+                    MessageRole::Assistant => "Assistant",
+                };
+                out.push_str(&format!("\n## {}\n\n{}\n", heading, message.content.trim_end()));
+            }
+            ChatItem::ToolCall { tool_call } => push_tool_call(&mut out, tool_call),
+        }
+    }
+
+    out
+}
+
+fn push_tool_call(out: &mut String, tool_call: &ToolCall) {
+    out.push_str(&format!("\n### Tool: {}", tool_call.title));
+    if let Some(status) = tool_call.status.as_ref().and_then(|s| serde_json::to_value(s).ok()) {
+        if let Some(status) = status.as_str() {
+            out.push_str(&format!(" ({})", status));
+        }
+    }
+    out.push('\n');
+
+    if let Some(input) = &tool_call.raw_input {
+        let input = serde_json::to_string_pretty(input).unwrap_or_default();
+        out.push_str(&format!("\n**Input**\n\n{}", fence(&input, "json")));
+    }
+
+    let mut output = Vec::new();
+    for content in tool_call.content.iter().flatten() {
+        match content {
+            ToolCallContent::Diff { path, old_text, new_text } => {
+                let mut diff = String::new();
+                for line in old_text.as_deref().unwrap_or_default().lines() {
+                    diff.push_str(&format!("-{}\n", line));
+                }
+                for line in new_text.lines() {
+                    diff.push_str(&format!("+{}\n", line));
+                }
+                out.push_str(&format!("\n**Diff** `{}`\n\n{}", path, fence(&diff, "diff")));
+            }
+            ToolCallContent::Content { content } => match content {
+                ContentBlock::Text { text } => output.push(text.clone()),
+                ContentBlock::Resource { resource: ResourceContents::Text { text, .. } } => output.push(text.clone()),
+                ContentBlock::ResourceLink { uri, .. } => output.push(uri.clone()),
+                ContentBlock::Image { .. } | ContentBlock::Resource { .. } => {}
+            },
+            ToolCallContent::Terminal { .. } => {}
+        }
+    }
+    if output.is_empty() {
+        if let Some(raw_output) = &tool_call.raw_output {
+            output.push(match raw_output.as_str() {
+                Some(text) => text.to_string(),
+                None => serde_json::to_string_pretty(raw_output).unwrap_or_default(),
+            });
+        }
+    }
+    if !output.is_empty() {
+        out.push_str(&format!("\n**Output**\n\n{}", fence(&output.join("\n"), "")));
+    }
+}
+
+/// Fence a code block with more backticks than any run inside it
+fn fence(content: &str, lang: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let ticks = "`".repeat(longest_run.max(2) + 1);
+    format!("{}{}\n{}\n{}\n", ticks, lang, content.trim_end_matches('\n'), ticks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_export() {
+        let mut state = SessionState::new("s1".to_string(), "/proj".to_string());
+        state.add_user_message("Fix the bug".to_string(), None);
+        state.add_user_message("<system-reminder>hidden".to_string(), None);
+        let tool_call: ToolCall = serde_json::from_value(serde_json::json!({
+            "toolCallId": "t1",
+            "title": "Edit main.rs",
+            "status": "completed",
+            "rawInput": { "file_path": "main.rs" },
+            "content": [{ "type": "diff", "path": "main.rs", "oldText": "a", "newText": "b" }],
+        }))
+        .unwrap();
+        state.chat_items.push(ChatItem::ToolCall { tool_call });
+
+        let markdown = to_markdown(&state, "Bug fix");
+        assert!(markdown.starts_with("# Bug fix\n"));
+        assert!(markdown.contains("## User\n\nFix the bug\n"));
+        assert!(!markdown.contains("hidden"));
+        assert!(markdown.contains("### Tool: Edit main.rs (completed)"));
+        assert!(markdown.contains("```diff\n-a\n+b\n```"));
+    }
+
+    #[test]
+    fn test_fence_outlasts_content_backticks() {
+        assert_eq!(fence("let x = 1;", "rust"), "```rust\nlet x = 1;\n```\n");
+        assert!(fence("````\ncode\n````", "").starts_with("`````\n"));
+    }
+}
@@ -3,7 +3,7 @@
 //! Stores complete session state including messages, tool calls, and plan.
 //! This is the single source of truth for session data.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
@@ -14,6 +14,8 @@ use crate::acp::{
     SessionModeState, SessionModelState, SessionUpdate, ToolCall, ToolCallId, ToolCallUpdate,
 };
 
+use super::config::PermissionPolicy;
+
 /// Message role
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -41,6 +43,45 @@ pub enum ChatItem {
     ToolCall { tool_call: ToolCall },
 }
 
+impl ChatItem {
+    /// Message ID or tool call ID
+    pub fn id(&self) -> &str {
+        match self {
+            ChatItem::Message { message } => &message.id,
+            ChatItem::ToolCall { tool_call } => &tool_call.tool_call_id,
+        }
+    }
+}
+
+/// Token usage summed over a session's assistant messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    pub input: u64,
+    pub output: u64,
+    pub cache_read: u64,
+}
+
+impl TokenUsage {
+    /// Read the `usage` object of an Anthropic API message
+    pub fn from_api_usage(usage: &serde_json::Value) -> Self {
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        Self {
+            input: field("input_tokens"),
+            output: field("output_tokens"),
+            cache_read: field("cache_read_input_tokens"),
+        }
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.input += other.input;
+        self.output += other.output;
+        self.cache_read += other.cache_read;
+    }
+}
+
 /// Full session state stored in backend
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -59,6 +100,21 @@ pub struct SessionState {
     /// Dangerous mode - auto-approve all tool calls for this session
     #[serde(default)]
     pub dangerous_mode: bool,
+    /// Per-tool permission policy (None falls back to the project's policy)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_policy: Option<PermissionPolicy>,
+    /// Tool calls the user chose "always allow" for (see `permission_signature`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub always_allowed: Vec<String>,
+    /// Model provider override (None uses the global active provider)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    /// Tokens used so far, as recorded in the session file
+    #[serde(default)]
+    pub usage: TokenUsage,
+    /// Sequence number of the last delta applied to this state
+    #[serde(default)]
+    pub seq: u64,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -77,6 +133,11 @@ impl SessionState {
             available_commands: None,
             pending_permission: None,
             dangerous_mode: false,
+            permission_policy: None,
+            always_allowed: Vec::new(),
+            provider_id: None,
+            usage: TokenUsage::default(),
+            seq: 0,
             created_at: now,
             updated_at: now,
         }
@@ -93,6 +154,18 @@ impl SessionState {
         self.dangerous_mode
     }
 
+    /// Set the permission policy for this session
+    pub fn set_permission_policy(&mut self, policy: Option<PermissionPolicy>) {
+        self.permission_policy = policy;
+        self.updated_at = Utc::now().timestamp_millis();
+    }
+
+    /// Set the model provider override for this session
+    pub fn set_provider(&mut self, provider_id: Option<String>) {
+        self.provider_id = provider_id;
+        self.updated_at = Utc::now().timestamp_millis();
+    }
+
     /// Set pending permission request for this session
     pub fn set_pending_permission(&mut self, request: Option<PermissionRequest>) {
         self.pending_permission = request;
@@ -104,6 +177,30 @@ impl SessionState {
         self.pending_permission.as_ref()
     }
 
+    /// Remember an "always allow" decision. Returns false if it was already known.
+    pub fn allow_always(&mut self, signature: String) -> bool {
+        if self.always_allowed.contains(&signature) {
+            return false;
+        }
+        self.always_allowed.push(signature);
+        self.updated_at = Utc::now().timestamp_millis();
+        true
+    }
+
+    /// Forget one "always allow" decision, or all of them with None
+    pub fn revoke_always_allowed(&mut self, signature: Option<&str>) -> bool {
+        let before = self.always_allowed.len();
+        match signature {
+            Some(signature) => self.always_allowed.retain(|s| s != signature),
+            None => self.always_allowed.clear(),
+        }
+        let changed = self.always_allowed.len() != before;
+        if changed {
+            self.updated_at = Utc::now().timestamp_millis();
+        }
+        changed
+    }
+
     /// Check if there's a pending permission request
     pub fn has_pending_permission(&self) -> bool {
         self.pending_permission.is_some()
@@ -121,18 +218,139 @@ impl SessionState {
         self.updated_at = Utc::now().timestamp_millis();
     }
 
+    /// Check that a model is in the session's available models
+    pub fn check_model(&self, model_id: &str) -> Result<(), String> {
+        let models = self.models.as_ref().ok_or("Session does not support selecting a model")?;
+        if !models.available_models.iter().any(|m| m.model_id == model_id) {
+            let available: Vec<&str> = models.available_models.iter().map(|m| m.model_id.as_str()).collect();
+            return Err(format!("Unknown model: {} (available: {})", model_id, available.join(", ")));
+        }
+        Ok(())
+    }
+
+    /// Prompt text invoking one of the session's available slash commands, as
+    /// the agent expects it: `/name`, followed by `argument` if the command
+    /// takes input. `name` may include the leading slash. Before the agent has
+    /// advertised any commands (e.g. a placeholder session) nothing is checked.
+    pub fn command_prompt(&self, name: &str, argument: Option<&str>) -> Result<String, String> {
+        let name = name.strip_prefix('/').unwrap_or(name);
+        let argument = argument.map(str::trim).filter(|a| !a.is_empty());
+        let commands = self.available_commands.as_deref().unwrap_or_default();
+        if commands.is_empty() {
+            return Ok(match argument {
+                Some(argument) => format!("/{} {}", name, argument),
+                None => format!("/{}", name),
+            });
+        }
+        let command = commands
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("Unknown command: /{}", name))?;
+        match (argument, &command.input) {
+            (Some(argument), Some(_)) => Ok(format!("/{} {}", name, argument)),
+            (Some(_), None) => Err(format!("Command /{} does not take an argument", name)),
+            (None, _) => Ok(format!("/{}", name)),
+        }
+    }
+
+    /// Switch the current model, which must pass `check_model`
+    pub fn set_current_model(&mut self, model_id: &str) -> Result<(), String> {
+        self.check_model(model_id)?;
+        if let Some(ref mut models) = self.models {
+            models.current_model_id = model_id.to_string();
+        }
+        self.updated_at = Utc::now().timestamp_millis();
+        Ok(())
+    }
+
+    /// Switch the current mode, which must be one of the available modes.
+    /// Returns the previous mode.
+    pub fn set_current_mode(&mut self, mode_id: &str) -> Result<SessionModeId, String> {
+        let modes = self.modes.as_mut().ok_or("Session does not support modes")?;
+        if !modes.available_modes.iter().any(|m| m.id == mode_id) {
+            let available: Vec<&str> = modes.available_modes.iter().map(|m| m.id.as_str()).collect();
+            return Err(format!("Unknown mode: {} (available: {})", mode_id, available.join(", ")));
+        }
+        let previous = std::mem::replace(&mut modes.current_mode_id, mode_id.to_string());
+        self.updated_at = Utc::now().timestamp_millis();
+        Ok(previous)
+    }
+
     /// Load historical chat items (for resuming sessions)
     pub fn load_history(&mut self, chat_items: Vec<ChatItem>) {
-        // Build tool_calls_map index for any tool calls in history
-        for (idx, item) in chat_items.iter().enumerate() {
-            if let ChatItem::ToolCall { tool_call } = item {
-                self.tool_calls_map.insert(tool_call.tool_call_id.clone(), idx);
-            }
-        }
         self.chat_items = chat_items;
+        self.rebuild_index();
         self.updated_at = Utc::now().timestamp_millis();
     }
 
+    /// Merge chat items re-read from the session file and return the deltas.
+    /// When the known items line up with the file, new items are appended and
+    /// changed tool calls updated; otherwise (e.g. the file was truncated or
+    /// rewritten) the history is replaced and a full state is returned.
+    pub fn merge_history(&mut self, chat_items: Vec<ChatItem>) -> Vec<SessionStateUpdate> {
+        let start = match chat_items.first() {
+            _ if self.chat_items.is_empty() => Some(0),
+            Some(first) => self.chat_items.iter().position(|item| item.id() == first.id()),
+            None => None,
+        };
+        let aligned = start.filter(|&start| {
+            let overlap = self.chat_items.len() - start;
+            overlap <= chat_items.len()
+                && self.chat_items[start..]
+                    .iter()
+                    .zip(&chat_items)
+                    .all(|(known, new)| known.id() == new.id())
+        });
+
+        let Some(start) = aligned else {
+            self.load_history(chat_items);
+            return vec![SessionStateUpdate::FullState {
+                state: Box::new(self.clone()),
+            }];
+        };
+
+        let overlap = self.chat_items.len() - start;
+        let mut updates = Vec::new();
+        let mut new_items = chat_items.into_iter();
+
+        // Tool calls may have gained results since they were loaded
+        for (known, new) in self.chat_items[start..].iter_mut().zip(new_items.by_ref().take(overlap)) {
+            if let (ChatItem::ToolCall { tool_call: old }, ChatItem::ToolCall { tool_call }) = (&*known, &new) {
+                if serde_json::to_value(old).ok() != serde_json::to_value(tool_call).ok() {
+                    updates.push(SessionStateUpdate::ToolCallUpdated { tool_call: tool_call.clone() });
+                    *known = new;
+                }
+            }
+        }
+
+        for item in new_items {
+            updates.push(match &item {
+                ChatItem::Message { message } => SessionStateUpdate::MessageAdded { message: message.clone() },
+                ChatItem::ToolCall { tool_call } => SessionStateUpdate::ToolCallAdded { tool_call: tool_call.clone() },
+            });
+            self.chat_items.push(item);
+        }
+
+        if !updates.is_empty() {
+            self.rebuild_index();
+            self.updated_at = Utc::now().timestamp_millis();
+        }
+        updates
+    }
+
+    /// Rebuild the tool_calls_map index from chat_items (it is not serialized)
+    pub fn rebuild_index(&mut self) {
+        self.tool_calls_map = self
+            .chat_items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| match item {
+                ChatItem::ToolCall { tool_call } => Some((tool_call.tool_call_id.clone(), idx)),
+                _ => None,
+            })
+            .collect();
+    }
+
     /// Add a user message
     /// If message_id is provided, use it; otherwise generate a new UUID
     pub fn add_user_message(&mut self, content: String, message_id: Option<String>) -> SessionStateUpdate {
@@ -149,6 +367,113 @@ impl SessionState {
         SessionStateUpdate::MessageAdded { message }
     }
 
+    /// Replace a message's content and drop every item after it, so the edited
+    /// message becomes the latest. Returns None if there is no such message.
+    pub fn edit_message(&mut self, message_id: &str, content: String) -> Option<SessionStateUpdate> {
+        let idx = self.chat_items.iter().position(|item| {
+            matches!(item, ChatItem::Message { message } if message.id == message_id)
+        })?;
+        self.chat_items.truncate(idx + 1);
+        self.rebuild_index();
+
+        let now = Utc::now().timestamp_millis();
+        self.updated_at = now;
+        match &mut self.chat_items[idx] {
+            ChatItem::Message { message } => {
+                message.content = content;
+                message.timestamp = now;
+                Some(SessionStateUpdate::MessageEdited { message: message.clone() })
+            }
+            ChatItem::ToolCall { .. } => None,
+        }
+    }
+
+    /// Remove a chat item, or with `whole_turn` the entire turn containing it (the
+    /// user message and every reply and tool call up to the next user message).
+    /// Returns None if there is no such item.
+    pub fn delete_message(&mut self, message_id: &str, whole_turn: bool) -> Option<SessionStateUpdate> {
+        let idx = self.chat_items.iter().position(|item| item.id() == message_id)?;
+        let range = if whole_turn { self.turn_range(idx) } else { idx..idx + 1 };
+        let item_ids = self.chat_items
+            .drain(range)
+            .map(|item| item.id().to_string())
+            .collect();
+        self.rebuild_index();
+        self.updated_at = Utc::now().timestamp_millis();
+        Some(SessionStateUpdate::ItemsDeleted { item_ids })
+    }
+
+    /// Drop all but the latest `count` chat items. Returns how many were dropped.
+    pub fn keep_latest(&mut self, count: usize) -> usize {
+        let dropped = self.chat_items.len().saturating_sub(count);
+        if dropped > 0 {
+            self.chat_items.drain(..dropped);
+            self.rebuild_index();
+        }
+        dropped
+    }
+
+    /// Insert older history items before the loaded ones, skipping any already
+    /// present. Returns None if nothing was added.
+    pub fn prepend_history(&mut self, items: Vec<ChatItem>) -> Option<SessionStateUpdate> {
+        let known: HashSet<String> = self.chat_items.iter().map(|item| item.id().to_string()).collect();
+        let items: Vec<ChatItem> = items.into_iter().filter(|item| !known.contains(item.id())).collect();
+        if items.is_empty() {
+            return None;
+        }
+        self.chat_items.splice(0..0, items.iter().cloned());
+        self.rebuild_index();
+        self.updated_at = Utc::now().timestamp_millis();
+        Some(SessionStateUpdate::ItemsPrepended { items })
+    }
+
+    /// Merge history loaded from the session file into the live items instead of
+    /// replacing them. History comes first in file order (keeping the live copy of
+    /// any item already present), followed by live items the file doesn't have yet,
+    /// such as a just-added user message. Returns how many history items were added.
+    pub fn append_history_if_absent(&mut self, items: Vec<ChatItem>) -> usize {
+        let history_ids: HashSet<String> = items.iter().map(|item| item.id().to_string()).collect();
+        let mut live: HashMap<String, ChatItem> = HashMap::new();
+        let mut pending = Vec::new();
+        for item in std::mem::take(&mut self.chat_items) {
+            if history_ids.contains(item.id()) {
+                live.insert(item.id().to_string(), item);
+            } else {
+                pending.push(item);
+            }
+        }
+
+        let mut added = 0;
+        for item in items {
+            match live.remove(item.id()) {
+                Some(known) => self.chat_items.push(known),
+                None => {
+                    added += 1;
+                    self.chat_items.push(item);
+                }
+            }
+        }
+        self.chat_items.extend(pending);
+        self.rebuild_index();
+        if added > 0 {
+            self.updated_at = Utc::now().timestamp_millis();
+        }
+        added
+    }
+
+    /// Index range of the turn containing the item at `idx`
+    pub fn turn_range(&self, idx: usize) -> std::ops::Range<usize> {
+        let is_user_message = |item: &ChatItem| {
+            matches!(item, ChatItem::Message { message } if message.role == MessageRole::User)
+        };
+        let start = self.chat_items[..=idx].iter().rposition(is_user_message).unwrap_or(0);
+        let end = self.chat_items[idx + 1..]
+            .iter()
+            .position(is_user_message)
+            .map_or(self.chat_items.len(), |offset| idx + 1 + offset);
+        start..end
+    }
+
     /// Apply a SessionUpdate from ACP agent and return the delta for broadcasting
     pub fn apply_update(&mut self, update: &SessionUpdate) -> SessionStateUpdate {
         self.updated_at = Utc::now().timestamp_millis();
@@ -222,6 +547,10 @@ impl SessionState {
             }
             SessionUpdate::CurrentModeUpdate { current_mode_id } => {
                 if let Some(ref mut modes) = self.modes {
+                    // The agent confirming a mode set optimistically is no change
+                    if modes.current_mode_id == *current_mode_id {
+                        return SessionStateUpdate::Noop;
+                    }
                     modes.current_mode_id = current_mode_id.clone();
                 }
                 SessionStateUpdate::CurrentModeUpdated {
@@ -340,6 +669,16 @@ impl SessionState {
     }
 }
 
+/// Key for an "always allow" decision: the tool kind plus the tool call's title
+/// (e.g. `execute:npm test`), so only matching tool calls are approved again.
+pub fn permission_signature(tool_call: &ToolCallUpdate) -> String {
+    let kind = tool_call.kind.as_ref()
+        .and_then(|kind| serde_json::to_value(kind).ok())
+        .and_then(|kind| kind.as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| "other".to_string());
+    format!("{}:{}", kind, tool_call.title.as_deref().unwrap_or_default())
+}
+
 /// Delta update for broadcasting to clients
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "updateType", rename_all = "snake_case")]
@@ -348,21 +687,45 @@ pub enum SessionStateUpdate {
     MessageChunk { content: String },
     /// A new message was added
     MessageAdded { message: Message },
+    /// A message's content was replaced and all items after it removed
+    MessageEdited { message: Message },
+    /// Chat items were removed
+    #[serde(rename_all = "camelCase")]
+    ItemsDeleted { item_ids: Vec<String> },
+    /// Older history items were loaded in front of the existing ones
+    ItemsPrepended { items: Vec<ChatItem> },
     /// A new tool call was added
+    #[serde(rename_all = "camelCase")]
     ToolCallAdded { tool_call: ToolCall },
     /// An existing tool call was updated
+    #[serde(rename_all = "camelCase")]
     ToolCallUpdated { tool_call: ToolCall },
     /// Plan was updated
     PlanUpdated { plan: Plan },
     /// Available commands were updated
     AvailableCommandsUpdated { commands: Vec<AvailableCommand> },
     /// Current mode was updated
+    #[serde(rename_all = "camelCase")]
     CurrentModeUpdated { mode_id: SessionModeId },
+    /// Current model was switched
+    #[serde(rename_all = "camelCase")]
+    ModelUpdated { model_id: String },
     /// Full state sync (for new subscribers)
     FullState { state: Box<SessionState> },
     /// Dangerous mode was updated
     #[serde(rename_all = "camelCase")]
     DangerousModeUpdated { dangerous_mode: bool },
+    /// Permission policy was updated
+    #[serde(rename_all = "camelCase")]
+    PermissionPolicyUpdated { permission_policy: Option<PermissionPolicy> },
+    /// "Always allow" decisions were added or revoked
+    #[serde(rename_all = "camelCase")]
+    AlwaysAllowedUpdated { always_allowed: Vec<String> },
+    /// Provider override was updated
+    #[serde(rename_all = "camelCase")]
+    ProviderUpdated { provider_id: Option<String> },
+    /// Token usage was updated
+    UsageUpdated { usage: TokenUsage },
     /// No operation (used for unhandled updates)
     Noop,
 }
@@ -393,6 +756,162 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_always_allowed() {
+        let tool_call: ToolCallUpdate = serde_json::from_value(serde_json::json!({
+            "toolCallId": "t1",
+            "title": "npm test",
+            "kind": "execute",
+        }))
+        .unwrap();
+        let signature = permission_signature(&tool_call);
+        assert_eq!(signature, "execute:npm test");
+
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        assert!(state.allow_always(signature.clone()));
+        assert!(!state.allow_always(signature.clone()));
+        assert_eq!(state.always_allowed, vec![signature.clone()]);
+        assert!(state.revoke_always_allowed(Some(&signature)));
+        assert!(!state.revoke_always_allowed(Some(&signature)));
+        assert!(state.always_allowed.is_empty());
+    }
+
+    #[test]
+    fn test_edit_message_truncates_after_it() {
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        state.add_user_message("first".to_string(), Some("m1".to_string()));
+        state.add_user_message("second".to_string(), Some("m2".to_string()));
+        state.add_user_message("third".to_string(), Some("m3".to_string()));
+
+        match state.edit_message("m2", "edited".to_string()) {
+            Some(SessionStateUpdate::MessageEdited { message }) => {
+                assert_eq!(message.id, "m2");
+                assert_eq!(message.content, "edited");
+            }
+            other => panic!("Expected MessageEdited update, got {:?}", other),
+        }
+        assert_eq!(state.chat_items.len(), 2);
+        assert_eq!(state.chat_items[1].id(), "m2");
+        assert!(state.edit_message("m3", "gone".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_delete_message() {
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        state.add_user_message("first".to_string(), Some("m1".to_string()));
+        state.chat_items.push(ChatItem::Message {
+            message: Message {
+                id: "a1".to_string(),
+                role: MessageRole::Assistant,
+                content: "reply".to_string(),
+                timestamp: 0,
+            },
+        });
+        let tool_call: ToolCall = serde_json::from_value(serde_json::json!({
+            "toolCallId": "t1",
+            "title": "Read file",
+        }))
+        .unwrap();
+        state.chat_items.push(ChatItem::ToolCall { tool_call });
+        state.add_user_message("second".to_string(), Some("m2".to_string()));
+        state.rebuild_index();
+
+        // Deleting from the middle of a turn removes the whole turn
+        match state.delete_message("a1", true) {
+            Some(SessionStateUpdate::ItemsDeleted { item_ids }) => assert_eq!(item_ids, vec!["m1", "a1", "t1"]),
+            other => panic!("Expected ItemsDeleted update, got {:?}", other),
+        }
+        assert!(state.get_tool_call(&"t1".to_string()).is_none());
+        assert_eq!(state.chat_items.len(), 1);
+
+        assert!(state.delete_message("m2", false).is_some());
+        assert!(state.chat_items.is_empty());
+        assert!(state.delete_message("m2", false).is_none());
+    }
+
+    #[test]
+    fn test_prepend_history() {
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        state.add_user_message("second".to_string(), Some("m2".to_string()));
+
+        let mut older = SessionState::new("older".to_string(), "/".to_string());
+        older.add_user_message("first".to_string(), Some("m1".to_string()));
+        older.add_user_message("second".to_string(), Some("m2".to_string()));
+
+        match state.prepend_history(older.chat_items) {
+            Some(SessionStateUpdate::ItemsPrepended { items }) => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].id(), "m1");
+            }
+            other => panic!("Expected ItemsPrepended update, got {:?}", other),
+        }
+        let ids: Vec<&str> = state.chat_items.iter().map(|item| item.id()).collect();
+        assert_eq!(ids, vec!["m1", "m2"]);
+        assert!(state.prepend_history(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_append_history_if_absent() {
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        state.add_user_message("second".to_string(), Some("m2".to_string()));
+        state.add_user_message("third".to_string(), Some("m3".to_string()));
+
+        let mut file = SessionState::new("file".to_string(), "/".to_string());
+        file.add_user_message("first".to_string(), Some("m1".to_string()));
+        file.add_user_message("stale".to_string(), Some("m2".to_string()));
+
+        assert_eq!(state.append_history_if_absent(file.chat_items.clone()), 1);
+        let ids: Vec<&str> = state.chat_items.iter().map(|item| item.id()).collect();
+        assert_eq!(ids, vec!["m1", "m2", "m3"]);
+        match &state.chat_items[1] {
+            ChatItem::Message { message } => assert_eq!(message.content, "second"),
+            other => panic!("Expected a message, got {:?}", other),
+        }
+
+        // Loading the same history again adds nothing
+        assert_eq!(state.append_history_if_absent(file.chat_items), 0);
+        assert_eq!(state.chat_items.len(), 3);
+    }
+
+    #[test]
+    fn test_command_prompt() {
+        use crate::acp::CommandInput;
+
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        // Nothing advertised yet: passed through unchecked
+        assert_eq!(state.command_prompt("/deploy", Some("prod")).unwrap(), "/deploy prod");
+
+        state.apply_update(&SessionUpdate::AvailableCommandsUpdate {
+            available_commands: vec![
+                AvailableCommand { name: "compact".to_string(), description: "Compact".to_string(), input: None },
+                AvailableCommand {
+                    name: "review".to_string(),
+                    description: "Review a PR".to_string(),
+                    input: Some(CommandInput { hint: "PR number".to_string() }),
+                },
+            ],
+        });
+
+        assert_eq!(state.command_prompt("/review", Some(" 42 ")).unwrap(), "/review 42");
+        assert_eq!(state.command_prompt("review", None).unwrap(), "/review");
+        assert_eq!(state.command_prompt("compact", Some("")).unwrap(), "/compact");
+        assert!(state.command_prompt("compact", Some("now")).unwrap_err().contains("does not take an argument"));
+        assert!(state.command_prompt("deploy", None).unwrap_err().contains("Unknown command: /deploy"));
+    }
+
+    #[test]
+    fn test_keep_latest() {
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        for i in 1..=3 {
+            state.add_user_message(format!("message {}", i), Some(format!("m{}", i)));
+        }
+
+        assert_eq!(state.keep_latest(5), 0);
+        assert_eq!(state.keep_latest(2), 1);
+        let ids: Vec<&str> = state.chat_items.iter().map(|item| item.id()).collect();
+        assert_eq!(ids, vec!["m2", "m3"]);
+    }
+
     #[test]
     fn test_add_user_message_with_id() {
         let mut state = SessionState::new("test".to_string(), "/".to_string());
@@ -434,4 +953,32 @@ mod tests {
             assert_eq!(message.content, "Hello World");
         }
     }
+
+    #[test]
+    fn test_merge_history() {
+        let message = |id: &str| ChatItem::Message {
+            message: Message {
+                id: id.to_string(),
+                role: MessageRole::User,
+                content: id.to_string(),
+                timestamp: 0,
+            },
+        };
+        let mut state = SessionState::new("test".to_string(), "/".to_string());
+        state.load_history(vec![message("a"), message("b")]);
+
+        // Appended lines become individual deltas
+        let updates = state.merge_history(vec![message("a"), message("b"), message("c")]);
+        assert_eq!(updates.len(), 1);
+        assert!(matches!(&updates[0], SessionStateUpdate::MessageAdded { message } if message.id == "c"));
+        assert_eq!(state.chat_items.len(), 3);
+
+        // Nothing new
+        assert!(state.merge_history(vec![message("b"), message("c")]).is_empty());
+
+        // Diverged (e.g. truncated) history is replaced
+        let updates = state.merge_history(vec![message("x")]);
+        assert!(matches!(updates[0], SessionStateUpdate::FullState { .. }));
+        assert_eq!(state.chat_items.len(), 1);
+    }
 }
@@ -3,16 +3,21 @@
 //! Manages all session states and handles subscriptions for real-time updates.
 //! This is the central point for session data management.
 
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
 use tokio::sync::broadcast;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::acp::{PermissionRequest, SessionId, SessionModeState, SessionModelState, SessionUpdate};
+use crate::acp::{PermissionRequest, SessionId, SessionModeId, SessionModeState, SessionModelState, SessionUpdate};
 
-use super::session_state::{SessionState, SessionStateUpdate};
+use super::config::PermissionPolicy;
+use super::session_state::{ChatItem, SessionState, SessionStateUpdate, TokenUsage};
 
 /// Client identifier for subscription management
 pub type ClientId = String;
@@ -25,12 +30,50 @@ struct SessionSubscription {
     subscribers: HashSet<ClientId>,
 }
 
+/// How often modified session states are written to disk
+const PERSIST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Deltas kept per session for clients that reconnect after missing some
+const REPLAY_BUFFER_SIZE: usize = 500;
+
+/// Held while a prompt of the session runs (see `try_lock_prompt`)
+pub type PromptGuard = tokio::sync::OwnedMutexGuard<()>;
+
+/// Result of `set_current_mode`: the mode to restore if the agent rejects the
+/// new one, and the delta that was broadcast
+#[derive(Debug, Clone)]
+pub struct ModeChange {
+    pub previous: SessionModeId,
+    pub update: Option<SequencedUpdate>,
+}
+
+/// A session delta with its sequence number
+#[derive(Debug, Clone, Serialize)]
+pub struct SequencedUpdate {
+    pub seq: u64,
+    pub update: SessionStateUpdate,
+}
+
 /// Session State Manager - single source of truth for all session data
 pub struct SessionStateManager {
     /// Session states by session ID
     states: RwLock<HashMap<SessionId, SessionState>>,
     /// Subscriptions by session ID
     subscriptions: RwLock<HashMap<SessionId, SessionSubscription>>,
+    /// Directory for session snapshots (None = persistence disabled)
+    persist_dir: RwLock<Option<PathBuf>>,
+    /// Sessions modified since the last snapshot
+    dirty: Mutex<HashSet<SessionId>>,
+    /// Recent deltas by session ID, for replay on reconnect
+    replay: Mutex<HashMap<SessionId, VecDeque<SequencedUpdate>>>,
+    /// Source of sequence numbers. Shared by all sessions and seeded from the
+    /// clock, so a number never identifies deltas of two different state
+    /// instances (e.g. before and after a restart or re-resume).
+    next_seq: AtomicU64,
+    /// One prompt runs per session at a time
+    prompt_locks: Mutex<HashMap<SessionId, Arc<tokio::sync::Mutex<()>>>>,
+    /// Prompts waiting for the running one, by session ID
+    queued_prompts: Mutex<HashMap<SessionId, usize>>,
 }
 
 impl SessionStateManager {
@@ -38,6 +81,121 @@ impl SessionStateManager {
         Self {
             states: RwLock::new(HashMap::new()),
             subscriptions: RwLock::new(HashMap::new()),
+            persist_dir: RwLock::new(None),
+            dirty: Mutex::new(HashSet::new()),
+            replay: Mutex::new(HashMap::new()),
+            next_seq: AtomicU64::new(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_micros() as u64)
+                    .unwrap_or(0),
+            ),
+            prompt_locks: Mutex::new(HashMap::new()),
+            queued_prompts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Snapshot session states to `dir/{id}.json` so in-progress sessions survive a restart.
+    /// Writes are debounced: modified sessions are flushed every PERSIST_INTERVAL.
+    pub fn enable_persistence(self: &Arc<Self>, dir: PathBuf) {
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            warn!("Failed to create session snapshot directory {:?}: {}", dir, e);
+            return;
+        }
+        *self.persist_dir.write() = Some(dir);
+
+        let manager: Weak<Self> = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("session-persist".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(PERSIST_INTERVAL);
+                match manager.upgrade() {
+                    Some(manager) => manager.flush_dirty(),
+                    None => break,
+                }
+            })
+            .map_err(|e| warn!("Failed to start session persistence thread: {}", e))
+            .ok();
+    }
+
+    /// Write all modified sessions to disk now
+    pub fn flush_dirty(&self) {
+        let dirty: Vec<SessionId> = self.dirty.lock().drain().collect();
+        for session_id in dirty {
+            self.persist_session(&session_id);
+        }
+    }
+
+    /// Load a session snapshot written by a previous run (tool call index rebuilt)
+    pub fn load_persisted(&self, session_id: &SessionId) -> Option<SessionState> {
+        let path = self.snapshot_path(session_id)?;
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<SessionState>(&content) {
+            Ok(mut state) => {
+                state.rebuild_index();
+                // The agent request behind a pending permission died with the old process
+                state.pending_permission = None;
+                Some(state)
+            }
+            Err(e) => {
+                warn!("Failed to parse session snapshot {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Delete the on-disk snapshot of a session
+    pub fn delete_persisted(&self, session_id: &SessionId) {
+        self.dirty.lock().remove(session_id);
+        if let Some(path) = self.snapshot_path(session_id) {
+            if path.exists() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to delete session snapshot {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    fn snapshot_path(&self, session_id: &SessionId) -> Option<PathBuf> {
+        // Session IDs are UUIDs; refuse anything that could escape the directory
+        if session_id.is_empty() || session_id.contains(['/', '\\', '.']) {
+            return None;
+        }
+        self.persist_dir
+            .read()
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", session_id)))
+    }
+
+    fn persist_session(&self, session_id: &SessionId) {
+        let Some(path) = self.snapshot_path(session_id) else {
+            return;
+        };
+        let Some(state) = self.get_state(session_id) else {
+            return;
+        };
+
+        let json = match serde_json::to_string(&state) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize session {}: {}", session_id, e);
+                return;
+            }
+        };
+
+        // Write to a temp file and rename so a crash never leaves a truncated snapshot
+        let tmp_path = path.with_extension("json.tmp");
+        if let Err(e) = std::fs::write(&tmp_path, json).and_then(|_| std::fs::rename(&tmp_path, &path)) {
+            warn!("Failed to persist session {}: {}", session_id, e);
+        } else {
+            debug!("Persisted session snapshot: {}", session_id);
+        }
+    }
+
+    /// Mark a session as modified so the next flush writes it
+    fn mark_dirty(&self, session_id: &SessionId) {
+        if self.persist_dir.read().is_some() {
+            self.dirty.lock().insert(session_id.clone());
         }
     }
 
@@ -59,6 +217,8 @@ impl SessionStateManager {
 
         let mut states = self.states.write();
         states.insert(id.clone(), state.clone());
+        self.replay.lock().remove(&id);
+        drop(states);
 
         // Create subscription channel for this session
         let (tx, _) = broadcast::channel(1000);
@@ -71,6 +231,7 @@ impl SessionStateManager {
             },
         );
 
+        self.mark_dirty(&id);
         info!("Created session state: {}", id);
         state
     }
@@ -97,6 +258,8 @@ impl SessionStateManager {
 
         let mut states = self.states.write();
         states.insert(id.clone(), state.clone());
+        self.replay.lock().remove(&id);
+        drop(states);
 
         // Create subscription channel for this session
         let (tx, _) = broadcast::channel(1000);
@@ -109,69 +272,314 @@ impl SessionStateManager {
             },
         );
 
+        self.mark_dirty(&id);
         info!("Created session state with history: {} ({} items)", id, state.chat_items.len());
         state
     }
 
-    /// Remove a session state
+    /// Merge pre-loaded chat items into a session that may already be live (e.g.
+    /// resuming while a prompt is being sent), keeping its items and subscribers.
+    /// Creates the session with the history if it isn't loaded.
+    pub fn append_history_if_absent(
+        &self,
+        id: SessionId,
+        cwd: String,
+        modes: Option<SessionModeState>,
+        models: Option<SessionModelState>,
+        chat_items: Vec<ChatItem>,
+    ) -> SessionState {
+        let (state, added, full_state) = {
+            let mut states = self.states.write();
+            let Some(state) = states.get_mut(&id) else {
+                drop(states);
+                return self.create_session_with_history(id, cwd, modes, models, chat_items);
+            };
+            if let Some(m) = modes {
+                state.set_modes(m);
+            }
+            if let Some(m) = models {
+                state.set_models(m);
+            }
+            let added = state.append_history_if_absent(chat_items);
+            // Clients see the merged history as one full state
+            let full_state = (added > 0).then(|| SessionStateUpdate::FullState {
+                state: Box::new(state.clone()),
+            });
+            if let Some(update) = &full_state {
+                self.record_update(state, update);
+            }
+            (state.clone(), added, full_state)
+        };
+
+        if let Some(update) = full_state {
+            self.mark_dirty(&id);
+            self.broadcast_update(&id, update);
+        }
+        info!("Merged {} history items into live session {}", added, id);
+        state
+    }
+
+    /// Remove a session state (its latest snapshot is written first)
     pub fn remove_session(&self, id: &SessionId) {
+        if self.dirty.lock().remove(id) {
+            self.persist_session(id);
+        }
+
         let mut states = self.states.write();
         states.remove(id);
+        self.prompt_locks.lock().remove(id);
 
         let mut subs = self.subscriptions.write();
         subs.remove(id);
+        self.replay.lock().remove(id);
 
         info!("Removed session state: {}", id);
     }
 
+    /// Move a session state, with its settings, subscribers and replay buffer, to a
+    /// new ID (a placeholder session getting its real agent session). Returns false
+    /// if `old_id` is unknown.
+    pub fn rename_session(
+        &self,
+        old_id: &SessionId,
+        new_id: SessionId,
+        modes: Option<SessionModeState>,
+        models: Option<SessionModelState>,
+    ) -> bool {
+        {
+            let mut states = self.states.write();
+            let Some(mut state) = states.remove(old_id) else {
+                return false;
+            };
+            state.id = new_id.clone();
+            if let Some(m) = modes {
+                state.set_modes(m);
+            }
+            if let Some(m) = models {
+                state.set_models(m);
+            }
+            states.insert(new_id.clone(), state);
+        }
+
+        let mut subs = self.subscriptions.write();
+        if let Some(sub) = subs.remove(old_id) {
+            subs.insert(new_id.clone(), sub);
+        }
+        drop(subs);
+        let mut replay = self.replay.lock();
+        if let Some(updates) = replay.remove(old_id) {
+            replay.insert(new_id.clone(), updates);
+        }
+        drop(replay);
+
+        self.delete_persisted(old_id);
+        self.mark_dirty(&new_id);
+        info!("Renamed session state {} -> {}", old_id, new_id);
+        true
+    }
+
+    fn prompt_lock(&self, session_id: &SessionId) -> Arc<tokio::sync::Mutex<()>> {
+        self.prompt_locks.lock().entry(session_id.clone()).or_default().clone()
+    }
+
+    /// Start a prompt of the session, or None while another one is running
+    pub fn try_lock_prompt(&self, session_id: &SessionId) -> Option<PromptGuard> {
+        self.prompt_lock(session_id).try_lock_owned().ok()
+    }
+
+    /// Wait until the session's running prompt (and any queued before this
+    /// one) has finished. `queue_prompt` is counted while waiting.
+    pub async fn lock_prompt(&self, session_id: &SessionId) -> PromptGuard {
+        let lock = self.prompt_lock(session_id);
+        lock.lock_owned().await
+    }
+
+    /// Count a prompt waiting in `lock_prompt`. Returns the number waiting.
+    pub fn queue_prompt(&self, session_id: &SessionId) -> usize {
+        let mut queued = self.queued_prompts.lock();
+        let count = queued.entry(session_id.clone()).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// Stop counting a prompt that left `lock_prompt`. Returns the number still waiting.
+    pub fn dequeue_prompt(&self, session_id: &SessionId) -> usize {
+        let mut queued = self.queued_prompts.lock();
+        let Some(count) = queued.get_mut(session_id) else {
+            return 0;
+        };
+        *count = count.saturating_sub(1);
+        let remaining = *count;
+        if remaining == 0 {
+            queued.remove(session_id);
+        }
+        remaining
+    }
+
     /// Check if a session exists
     pub fn has_session(&self, id: &SessionId) -> bool {
         let states = self.states.read();
         states.contains_key(id)
     }
 
-    /// Apply an update from ACP agent
-    pub fn apply_update(&self, session_id: &SessionId, update: SessionUpdate) {
-        let delta = {
+    /// Apply an update from ACP agent. Returns the delta with its sequence number
+    /// (None if the session is unknown or the update changed nothing).
+    pub fn apply_update(&self, session_id: &SessionId, update: SessionUpdate) -> Option<SequencedUpdate> {
+        let (delta, seq) = {
             let mut states = self.states.write();
             if let Some(state) = states.get_mut(session_id) {
-                state.apply_update(&update)
+                let delta = state.apply_update(&update);
+                if matches!(delta, SessionStateUpdate::Noop) {
+                    return None;
+                }
+                let seq = self.record_update(state, &delta);
+                (delta, seq)
             } else {
                 debug!("Session not found for update: {}", session_id);
-                return;
+                return None;
             }
         };
 
-        // Broadcast delta to subscribers (skip Noop)
-        if !matches!(delta, SessionStateUpdate::Noop) {
-            self.broadcast_update(session_id, delta);
-        }
+        self.mark_dirty(session_id);
+        self.broadcast_update(session_id, delta.clone());
+        Some(SequencedUpdate { seq, update: delta })
     }
 
-    /// Load historical chat items into an existing session
-    pub fn load_history(&self, session_id: &SessionId, chat_items: Vec<super::session_state::ChatItem>) {
+    /// Load historical chat items into an existing session. Returns the
+    /// resulting full state as a delta, for clients to replace theirs with.
+    pub fn load_history(&self, session_id: &SessionId, chat_items: Vec<super::session_state::ChatItem>) -> Option<SequencedUpdate> {
         let mut states = self.states.write();
-        if let Some(state) = states.get_mut(session_id) {
-            state.load_history(chat_items);
-            info!("Loaded history into session {}", session_id);
-        } else {
+        let Some(state) = states.get_mut(session_id) else {
             debug!("Session not found for history load: {}", session_id);
+            return None;
+        };
+        state.load_history(chat_items);
+        // Clients that reconnect across this point need the whole history
+        let full_state = SessionStateUpdate::FullState {
+            state: Box::new(state.clone()),
+        };
+        let seq = self.record_update(state, &full_state);
+        drop(states);
+        self.mark_dirty(session_id);
+        info!("Loaded history into session {}", session_id);
+        Some(SequencedUpdate { seq, update: full_state })
+    }
+
+    /// Merge chat items re-read from the session file and broadcast the deltas
+    pub fn merge_history(
+        &self,
+        session_id: &SessionId,
+        chat_items: Vec<super::session_state::ChatItem>,
+    ) -> Vec<SequencedUpdate> {
+        let updates: Vec<SequencedUpdate> = {
+            let mut states = self.states.write();
+            match states.get_mut(session_id) {
+                Some(state) => state
+                    .merge_history(chat_items)
+                    .into_iter()
+                    .map(|update| SequencedUpdate {
+                        seq: self.record_update(state, &update),
+                        update,
+                    })
+                    .collect(),
+                None => return Vec::new(),
+            }
+        };
+
+        if !updates.is_empty() {
+            self.mark_dirty(session_id);
+            for sequenced in &updates {
+                self.broadcast_update(session_id, sequenced.update.clone());
+            }
         }
+        updates
     }
 
-    /// Add a user message to session
+    /// Add a user message to session and return the delta
     /// If message_id is provided, use it; otherwise generate a new UUID
-    pub fn add_user_message(&self, session_id: &SessionId, content: String, message_id: Option<String>) {
-        let delta = {
+    pub fn add_user_message(&self, session_id: &SessionId, content: String, message_id: Option<String>) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| Some(state.add_user_message(content, message_id)))
+    }
+
+    /// Edit a message (dropping everything after it) and broadcast the delta
+    pub fn edit_message(&self, session_id: &SessionId, message_id: &str, content: String) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| state.edit_message(message_id, content))
+    }
+
+    /// Delete a chat item (or its whole turn) and broadcast the delta
+    pub fn delete_message(&self, session_id: &SessionId, message_id: &str, whole_turn: bool) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| state.delete_message(message_id, whole_turn))
+    }
+
+    /// Prepend older history items and broadcast the delta
+    pub fn prepend_history(&self, session_id: &SessionId, items: Vec<ChatItem>) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| state.prepend_history(items))
+    }
+
+    /// Apply a change that yields a delta, then sequence, persist and broadcast it.
+    /// None if the session is unknown or the change produced no delta.
+    fn record_change(
+        &self,
+        session_id: &SessionId,
+        change: impl FnOnce(&mut SessionState) -> Option<SessionStateUpdate>,
+    ) -> Option<SequencedUpdate> {
+        let (delta, seq) = {
             let mut states = self.states.write();
-            if let Some(state) = states.get_mut(session_id) {
-                state.add_user_message(content, message_id)
-            } else {
-                return;
-            }
+            let state = states.get_mut(session_id)?;
+            let delta = change(state)?;
+            let seq = self.record_update(state, &delta);
+            (delta, seq)
         };
 
-        self.broadcast_update(session_id, delta);
+        self.mark_dirty(session_id);
+        self.broadcast_update(session_id, delta.clone());
+        Some(SequencedUpdate { seq, update: delta })
+    }
+
+    /// Whether a session holds a chat item with this ID
+    pub fn has_item(&self, session_id: &SessionId, item_id: &str) -> bool {
+        let states = self.states.read();
+        states.get(session_id).is_some_and(|state| state.chat_items.iter().any(|item| item.id() == item_id))
+    }
+
+    /// ID of the latest chat item in a session
+    pub fn last_item_id(&self, session_id: &SessionId) -> Option<String> {
+        let states = self.states.read();
+        states.get(session_id)?.chat_items.last().map(|item| item.id().to_string())
+    }
+
+    /// Deltas a client missed since it last saw `seq`, oldest first. Returns None
+    /// when they can't all be replayed (too old, or `seq` belongs to another
+    /// instance of the session); the client then needs the full state.
+    pub fn updates_since(&self, session_id: &SessionId, seq: u64) -> Option<Vec<SequencedUpdate>> {
+        let states = self.states.read();
+        let state = states.get(session_id)?;
+        if state.seq == seq {
+            return Some(Vec::new());
+        }
+
+        let replay = self.replay.lock();
+        let buffer = replay.get(session_id)?;
+        let seen = buffer.iter().position(|u| u.seq == seq)?;
+        Some(buffer.iter().skip(seen + 1).cloned().collect())
+    }
+
+    /// Give a delta the next sequence number and keep it for replay.
+    /// Called with the session's state locked, so snapshots and sequence agree.
+    fn record_update(&self, state: &mut SessionState, update: &SessionStateUpdate) -> u64 {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        state.seq = seq;
+
+        let mut replay = self.replay.lock();
+        let buffer = replay.entry(state.id.clone()).or_default();
+        if buffer.len() == REPLAY_BUFFER_SIZE {
+            buffer.pop_front();
+        }
+        buffer.push_back(SequencedUpdate {
+            seq,
+            update: update.clone(),
+        });
+        seq
     }
 
     /// Subscribe a client to session updates
@@ -286,27 +694,166 @@ impl SessionStateManager {
         None
     }
 
-    /// Set dangerous mode for a session and broadcast to all subscribers
-    pub fn set_dangerous_mode(&self, session_id: &SessionId, enabled: bool) -> bool {
-        let updated = {
-            let mut states = self.states.write();
-            if let Some(state) = states.get_mut(session_id) {
-                state.set_dangerous_mode(enabled);
-                info!("Set dangerous mode for session {}: {}", session_id, enabled);
-                true
-            } else {
-                false
+    /// Set dangerous mode for a session and broadcast to all subscribers.
+    /// Returns the delta (None if the session is unknown).
+    pub fn set_dangerous_mode(&self, session_id: &SessionId, enabled: bool) -> Option<SequencedUpdate> {
+        let update = self.record_change(session_id, |state| {
+            state.set_dangerous_mode(enabled);
+            Some(SessionStateUpdate::DangerousModeUpdated { dangerous_mode: enabled })
+        });
+        if update.is_some() {
+            info!("Set dangerous mode for session {}: {}", session_id, enabled);
+        }
+        update
+    }
+
+    /// Set the permission policy for a session and broadcast to all subscribers.
+    /// Returns the delta (None if the session is unknown).
+    pub fn set_permission_policy(&self, session_id: &SessionId, policy: Option<PermissionPolicy>) -> Option<SequencedUpdate> {
+        let update = self.record_change(session_id, |state| {
+            state.set_permission_policy(policy.clone());
+            Some(SessionStateUpdate::PermissionPolicyUpdated { permission_policy: policy.clone() })
+        });
+        if update.is_some() {
+            info!("Set permission policy for session {}: {:?}", session_id, policy);
+        }
+        update
+    }
+
+    /// Get the permission policy set on a session
+    pub fn get_permission_policy(&self, session_id: &SessionId) -> Option<PermissionPolicy> {
+        let states = self.states.read();
+        states.get(session_id).and_then(|s| s.permission_policy.clone())
+    }
+
+    /// Remember an "always allow" decision for a session
+    pub fn allow_always(&self, session_id: &SessionId, signature: String) -> Option<SequencedUpdate> {
+        self.update_always_allowed(session_id, |state| state.allow_always(signature))
+    }
+
+    /// Revoke one "always allow" decision for a session, or all of them with None
+    pub fn revoke_always_allowed(&self, session_id: &SessionId, signature: Option<&str>) -> Option<SequencedUpdate> {
+        self.update_always_allowed(session_id, |state| state.revoke_always_allowed(signature))
+    }
+
+    /// Get a session's "always allow" decisions
+    pub fn get_always_allowed(&self, session_id: &SessionId) -> Vec<String> {
+        let states = self.states.read();
+        states.get(session_id)
+            .map(|s| s.always_allowed.clone())
+            .unwrap_or_default()
+    }
+
+    /// Check if the user chose "always allow" for a tool call signature
+    pub fn is_always_allowed(&self, session_id: &SessionId, signature: &str) -> bool {
+        let states = self.states.read();
+        states.get(session_id)
+            .is_some_and(|s| s.always_allowed.iter().any(|allowed| allowed == signature))
+    }
+
+    /// Apply a change to a session's allow-list, broadcasting the new list if it
+    /// changed. Returns the delta (None if nothing changed).
+    fn update_always_allowed(
+        &self,
+        session_id: &SessionId,
+        change: impl FnOnce(&mut SessionState) -> bool,
+    ) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| {
+            change(state).then(|| SessionStateUpdate::AlwaysAllowedUpdated {
+                always_allowed: state.always_allowed.clone(),
+            })
+        })
+    }
+
+    /// Set the provider override for a session.
+    /// Returns the delta (None if the session is unknown).
+    pub fn set_session_provider(&self, session_id: &SessionId, provider_id: Option<String>) -> Option<SequencedUpdate> {
+        let update = self.record_change(session_id, |state| {
+            state.set_provider(provider_id.clone());
+            Some(SessionStateUpdate::ProviderUpdated { provider_id: provider_id.clone() })
+        });
+        if update.is_some() {
+            info!("Set provider for session {}: {:?}", session_id, provider_id);
+        }
+        update
+    }
+
+    /// Check that a model can be selected for a session without changing it
+    pub fn validate_model(&self, session_id: &SessionId, model_id: &str) -> Result<(), String> {
+        let states = self.states.read();
+        let state = states.get(session_id).ok_or_else(|| format!("Session not found: {}", session_id))?;
+        state.check_model(model_id)
+    }
+
+    /// Switch a session's current model and broadcast `model_updated`.
+    /// Returns the delta.
+    pub fn set_current_model(&self, session_id: &SessionId, model_id: &str) -> Result<SequencedUpdate, String> {
+        let mut error = None;
+        let update = self.record_change(session_id, |state| match state.set_current_model(model_id) {
+            Ok(()) => Some(SessionStateUpdate::ModelUpdated { model_id: model_id.to_string() }),
+            Err(e) => {
+                error = Some(e);
+                None
             }
-        };
+        });
+        match (update, error) {
+            (Some(update), _) => {
+                info!("Set model for session {}: {}", session_id, model_id);
+                Ok(update)
+            }
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(format!("Session not found: {}", session_id)),
+        }
+    }
 
-        if updated {
-            // Broadcast to all subscribers
-            self.broadcast_update(session_id, SessionStateUpdate::DangerousModeUpdated {
-                dangerous_mode: enabled,
-            });
+    /// Switch a session's current mode ahead of the agent confirming it, and
+    /// broadcast `current_mode_updated`. Returns the previous mode with the delta
+    /// (None if the mode didn't change), or None if the session isn't loaded or
+    /// doesn't report modes.
+    pub fn set_current_mode(&self, session_id: &SessionId, mode_id: &str) -> Result<Option<ModeChange>, String> {
+        let mut previous = Ok(None);
+        let update = self.record_change(session_id, |state| {
+            state.modes.as_ref()?;
+            match state.set_current_mode(mode_id) {
+                Ok(old) => {
+                    let changed = old != mode_id;
+                    previous = Ok(Some(old));
+                    changed.then(|| SessionStateUpdate::CurrentModeUpdated { mode_id: mode_id.to_string() })
+                }
+                Err(e) => {
+                    previous = Err(e);
+                    None
+                }
+            }
+        });
+        if update.is_some() {
+            info!("Set mode for session {}: {}", session_id, mode_id);
         }
+        Ok(previous?.map(|previous| ModeChange { previous, update }))
+    }
 
-        updated
+    /// Set a session's token usage, broadcasting it if it changed.
+    /// Returns the delta (None if the usage is unchanged).
+    pub fn set_usage(&self, session_id: &SessionId, usage: TokenUsage) -> Option<SequencedUpdate> {
+        self.record_change(session_id, |state| {
+            if state.usage == usage {
+                return None;
+            }
+            state.usage = usage;
+            Some(SessionStateUpdate::UsageUpdated { usage })
+        })
+    }
+
+    /// Get the working directory of a session
+    pub fn get_session_cwd(&self, session_id: &SessionId) -> Option<String> {
+        let states = self.states.read();
+        states.get(session_id).map(|s| s.cwd.clone())
+    }
+
+    /// Get the provider override for a session
+    pub fn get_session_provider(&self, session_id: &SessionId) -> Option<String> {
+        let states = self.states.read();
+        states.get(session_id).and_then(|s| s.provider_id.clone())
     }
 
     /// Get dangerous mode status for a session
@@ -355,6 +902,29 @@ mod tests {
         assert_eq!(retrieved.unwrap().id, "test");
     }
 
+    #[test]
+    fn test_append_history_keeps_live_user_message() {
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        manager.create_session(id.clone(), "/path".to_string(), None, None);
+        manager.add_user_message(&id, "while resuming".to_string(), Some("live".to_string()));
+
+        let mut file = SessionState::new("file".to_string(), "/path".to_string());
+        file.add_user_message("first".to_string(), Some("m1".to_string()));
+        file.add_user_message("second".to_string(), Some("m2".to_string()));
+
+        let state = manager.append_history_if_absent(id.clone(), "/path".to_string(), None, None, file.chat_items);
+        let ids: Vec<&str> = state.chat_items.iter().map(|item| item.id()).collect();
+        assert_eq!(ids, vec!["m1", "m2", "live"]);
+        assert!(manager.has_item(&id, "live"));
+        assert_eq!(manager.last_item_id(&id).as_deref(), Some("live"));
+
+        // Not loaded yet: created from the history alone
+        let other = manager.append_history_if_absent("other".to_string(), "/path".to_string(), None, None, Vec::new());
+        assert!(other.chat_items.is_empty());
+        assert!(manager.has_session(&"other".to_string()));
+    }
+
     #[test]
     fn test_subscribe_unsubscribe() {
         let manager = SessionStateManager::new();
@@ -374,6 +944,192 @@ mod tests {
         assert_eq!(manager.subscriber_count(&"test".to_string()), 1);
     }
 
+    #[test]
+    fn test_persist_and_load_snapshot() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("sessions");
+        let manager = Arc::new(SessionStateManager::new());
+        manager.enable_persistence(dir.clone());
+
+        let id = "persisted-session".to_string();
+        manager.create_session(id.clone(), "/path".to_string(), None, None);
+        manager.add_user_message(&id, "hello".to_string(), Some("m1".to_string()));
+        manager.flush_dirty();
+        assert!(dir.join("persisted-session.json").exists());
+
+        let loaded = manager.load_persisted(&id).unwrap();
+        assert_eq!(loaded.cwd, "/path");
+        assert_eq!(loaded.chat_items.len(), 1);
+
+        manager.delete_persisted(&id);
+        assert!(manager.load_persisted(&id).is_none());
+        assert!(manager.load_persisted(&"../escape".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_session_provider() {
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        assert!(manager.set_session_provider(&id, Some("ollama".to_string())).is_none());
+
+        manager.create_session(id.clone(), "/".to_string(), None, None);
+        assert_eq!(manager.get_session_provider(&id), None);
+        assert!(manager.set_session_provider(&id, Some("ollama".to_string())).is_some());
+        assert_eq!(manager.get_session_provider(&id).as_deref(), Some("ollama"));
+        assert!(manager.set_session_provider(&id, None).is_some());
+        assert_eq!(manager.get_session_provider(&id), None);
+    }
+
+    #[test]
+    fn test_permission_policy() {
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        let policy = PermissionPolicy::allow_all();
+        assert!(manager.set_permission_policy(&id, Some(policy.clone())).is_none());
+
+        manager.create_session(id.clone(), "/".to_string(), None, None);
+        assert_eq!(manager.get_permission_policy(&id), None);
+        let sequenced = manager.set_permission_policy(&id, Some(policy.clone())).unwrap();
+        assert_eq!(manager.get_state(&id).unwrap().seq, sequenced.seq);
+        assert_eq!(manager.get_permission_policy(&id), Some(policy));
+        assert!(manager.set_permission_policy(&id, None).is_some());
+        assert_eq!(manager.get_permission_policy(&id), None);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_prompts_are_serialized() {
+        let manager = Arc::new(SessionStateManager::new());
+        let id = "test".to_string();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let first = manager.try_lock_prompt(&id).unwrap();
+        // A second prompt can't start while the first runs
+        assert!(manager.try_lock_prompt(&id).is_none());
+        // Other sessions are not affected
+        assert!(manager.try_lock_prompt(&"other".to_string()).is_some());
+
+        assert_eq!(manager.queue_prompt(&id), 1);
+        let second = tokio::spawn({
+            let (manager, id, log) = (manager.clone(), id.clone(), log.clone());
+            async move {
+                let _guard = manager.lock_prompt(&id).await;
+                manager.dequeue_prompt(&id);
+                log.lock().push("second start");
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                log.lock().push("second end");
+            }
+        });
+
+        log.lock().push("first start");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        log.lock().push("first end");
+        drop(first);
+        second.await.unwrap();
+
+        assert_eq!(*log.lock(), vec!["first start", "first end", "second start", "second end"]);
+        assert_eq!(manager.dequeue_prompt(&id), 0);
+        assert!(manager.try_lock_prompt(&id).is_some());
+    }
+
+    #[test]
+    fn test_updates_since() {
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        manager.create_session(id.clone(), "/".to_string(), None, None);
+        let seen = manager.add_user_message(&id, "one".to_string(), None).unwrap().seq;
+        let second = manager.add_user_message(&id, "two".to_string(), None).unwrap().seq;
+        let third = manager.add_user_message(&id, "three".to_string(), None).unwrap().seq;
+        assert!(seen < second && second < third);
+
+        let missed = manager.updates_since(&id, seen).unwrap();
+        assert_eq!(missed.iter().map(|u| u.seq).collect::<Vec<_>>(), vec![second, third]);
+        assert!(manager.updates_since(&id, third).unwrap().is_empty());
+        assert_eq!(manager.get_state(&id).unwrap().seq, third);
+
+        // Unknown sequence numbers (e.g. from before the session was re-created) need a full sync
+        assert!(manager.updates_since(&id, seen - 1).is_none());
+        manager.create_session(id.clone(), "/".to_string(), None, None);
+        assert!(manager.updates_since(&id, seen).is_none());
+    }
+
+    #[test]
+    fn test_apply_update_returns_mode_delta() {
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        manager.create_session(id.clone(), "/".to_string(), None, None);
+
+        let sequenced = manager
+            .apply_update(&id, SessionUpdate::CurrentModeUpdate { current_mode_id: "plan".to_string() })
+            .unwrap();
+        assert_eq!(sequenced.seq, manager.get_state(&id).unwrap().seq);
+        let update = serde_json::to_value(&sequenced.update).unwrap();
+        assert_eq!(update, serde_json::json!({ "updateType": "current_mode_updated", "modeId": "plan" }));
+    }
+
+    #[test]
+    fn test_set_current_mode() {
+        use crate::acp::SessionMode;
+
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        let mode = |id: &str| SessionMode { id: id.to_string(), name: id.to_string(), description: None };
+        let modes = SessionModeState {
+            current_mode_id: "default".to_string(),
+            available_modes: vec![mode("default"), mode("plan")],
+        };
+        assert!(manager.set_current_mode(&id, "plan").unwrap().is_none());
+
+        manager.create_session(id.clone(), "/".to_string(), Some(modes), None);
+        let seq = manager.add_user_message(&id, "switch".to_string(), None).unwrap().seq;
+        assert!(manager.set_current_mode(&id, "yolo").unwrap_err().contains("default, plan"));
+        let change = manager.set_current_mode(&id, "plan").unwrap().unwrap();
+        assert_eq!(change.previous, "default");
+        assert_eq!(change.update.unwrap().seq, seq + 1);
+        assert_eq!(manager.get_state(&id).unwrap().modes.unwrap().current_mode_id, "plan");
+        // Setting the current mode again broadcasts nothing
+        assert!(manager.set_current_mode(&id, "plan").unwrap().unwrap().update.is_none());
+
+        let replay = manager.updates_since(&id, seq).unwrap();
+        assert_eq!(replay.len(), 1);
+        let update = serde_json::to_value(&replay[0].update).unwrap();
+        assert_eq!(update, serde_json::json!({ "updateType": "current_mode_updated", "modeId": "plan" }));
+
+        // The agent's matching echo changes nothing; a different mode wins
+        assert!(manager.apply_update(&id, SessionUpdate::CurrentModeUpdate { current_mode_id: "plan".to_string() }).is_none());
+        assert!(manager.apply_update(&id, SessionUpdate::CurrentModeUpdate { current_mode_id: "default".to_string() }).is_some());
+        assert_eq!(manager.get_state(&id).unwrap().modes.unwrap().current_mode_id, "default");
+    }
+
+    #[test]
+    fn test_set_current_model() {
+        use crate::acp::SessionModel;
+
+        let manager = SessionStateManager::new();
+        let id = "test".to_string();
+        let model = |id: &str| SessionModel { model_id: id.to_string(), name: id.to_string(), description: None };
+        let models = SessionModelState {
+            current_model_id: "opus".to_string(),
+            available_models: vec![model("opus"), model("haiku")],
+        };
+        assert!(manager.set_current_model(&id, "haiku").is_err());
+
+        manager.create_session(id.clone(), "/".to_string(), None, Some(models));
+        let seq = manager.add_user_message(&id, "switch".to_string(), None).unwrap().seq;
+        assert!(manager.validate_model(&id, "haiku").is_ok());
+        assert!(manager.validate_model(&id, "gpt").unwrap_err().contains("opus, haiku"));
+        let sequenced = manager.set_current_model(&id, "haiku").unwrap();
+        assert_eq!(sequenced.seq, seq + 1);
+        assert_eq!(manager.get_state(&id).unwrap().models.unwrap().current_model_id, "haiku");
+
+        let replay = manager.updates_since(&id, seq).unwrap();
+        let update = serde_json::to_value(&replay[0].update).unwrap();
+        assert_eq!(update, serde_json::json!({ "updateType": "model_updated", "modelId": "haiku" }));
+
+        let bare = "bare".to_string();
+        manager.create_session(bare.clone(), "/".to_string(), None, None);
+        assert!(manager.validate_model(&bare, "haiku").is_err());
+    }
+
     #[test]
     fn test_remove_session() {
         let manager = SessionStateManager::new();
@@ -383,4 +1139,20 @@ mod tests {
         manager.remove_session(&"test".to_string());
         assert!(!manager.has_session(&"test".to_string()));
     }
+
+    #[test]
+    fn test_rename_session() {
+        let manager = SessionStateManager::new();
+        let (placeholder, real) = ("pending-1".to_string(), "real-1".to_string());
+        manager.create_session(placeholder.clone(), "/".to_string(), None, None);
+        manager.set_dangerous_mode(&placeholder, true);
+        manager.subscribe("client-1".to_string(), &placeholder).unwrap();
+
+        assert!(manager.rename_session(&placeholder, real.clone(), None, None));
+        assert!(!manager.has_session(&placeholder));
+        assert_eq!(manager.get_state(&real).unwrap().id, real);
+        assert!(manager.is_dangerous_mode(&real));
+        assert_eq!(manager.subscriber_count(&real), 1);
+        assert!(!manager.rename_session(&placeholder, "other".to_string(), None, None));
+    }
 }
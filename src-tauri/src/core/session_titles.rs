@@ -0,0 +1,225 @@
+//! Session Titles Module
+//!
+//! User-defined session titles and pins, stored in a sidecar file under the
+//! config directory. Titles override the summary derived from the session file.
+//! Resumed sessions may get a new ID, so titles and pins are indexed by the
+//! original session ID and resumed IDs are recorded as aliases.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::acp::SessionId;
+
+/// Sidecar file name under config_dir()
+pub const SESSION_TITLES_FILE: &str = "session_titles.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TitlesFile {
+    /// Title by original session ID
+    #[serde(default)]
+    titles: HashMap<SessionId, String>,
+    /// Pinned original session IDs
+    #[serde(default)]
+    pinned: HashSet<SessionId>,
+    /// Resumed session ID -> original session ID
+    #[serde(default)]
+    aliases: HashMap<SessionId, SessionId>,
+}
+
+/// Persisted session title overrides and pins
+pub struct SessionTitles {
+    path: PathBuf,
+    data: RwLock<TitlesFile>,
+}
+
+impl SessionTitles {
+    /// Load titles from a file (missing or invalid files start empty)
+    pub fn load(path: PathBuf) -> Self {
+        let data = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| match serde_json::from_str(&content) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    warn!("Failed to parse session titles {:?}: {}", path, e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            data: RwLock::new(data),
+        }
+    }
+
+    /// Title override for a session (or the session it was resumed from)
+    pub fn get(&self, session_id: &str) -> Option<String> {
+        let data = self.data.read();
+        let original = data.aliases.get(session_id).map(|s| s.as_str()).unwrap_or(session_id);
+        data.titles.get(original).cloned()
+    }
+
+    /// Whether a session (or the session it was resumed from) is pinned
+    pub fn is_pinned(&self, session_id: &str) -> bool {
+        let data = self.data.read();
+        let original = data.aliases.get(session_id).map(|s| s.as_str()).unwrap_or(session_id);
+        data.pinned.contains(original)
+    }
+
+    /// Pin or unpin a session
+    pub fn set_pinned(&self, session_id: &str, pinned: bool) -> Result<(), String> {
+        {
+            let mut data = self.data.write();
+            let original = data
+                .aliases
+                .get(session_id)
+                .cloned()
+                .unwrap_or_else(|| session_id.to_string());
+            let changed = if pinned {
+                data.pinned.insert(original)
+            } else {
+                data.pinned.remove(&original)
+            };
+            if !changed {
+                return Ok(());
+            }
+        }
+        self.save()
+    }
+
+    /// Set or remove (None / blank) a session's title
+    pub fn set(&self, session_id: &str, title: Option<&str>) -> Result<(), String> {
+        {
+            let mut data = self.data.write();
+            let original = data
+                .aliases
+                .get(session_id)
+                .cloned()
+                .unwrap_or_else(|| session_id.to_string());
+
+            match title.map(str::trim).filter(|t| !t.is_empty()) {
+                Some(title) => {
+                    data.titles.insert(original, title.to_string());
+                }
+                None => {
+                    data.titles.remove(&original);
+                }
+            }
+        }
+        self.save()
+    }
+
+    /// Record that `resumed_id` continues `original_id` so both share a title and pin
+    pub fn link(&self, original_id: &str, resumed_id: &str) -> Result<(), String> {
+        if original_id == resumed_id {
+            return Ok(());
+        }
+        {
+            let mut data = self.data.write();
+            // Chain resumes back to the first ID
+            let root = data
+                .aliases
+                .get(original_id)
+                .cloned()
+                .unwrap_or_else(|| original_id.to_string());
+            data.aliases.insert(resumed_id.to_string(), root);
+        }
+        self.save()
+    }
+
+    /// Forget a deleted session's title, pin and aliases
+    pub fn remove(&self, session_id: &str) -> Result<(), String> {
+        {
+            let mut data = self.data.write();
+            let before = (data.titles.len(), data.pinned.len(), data.aliases.len());
+            data.titles.remove(session_id);
+            data.pinned.remove(session_id);
+            data.aliases.remove(session_id);
+            data.aliases.retain(|_, original| original != session_id);
+            if before == (data.titles.len(), data.pinned.len(), data.aliases.len()) {
+                return Ok(());
+            }
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create config directory: {}", e))?;
+        }
+        let content = serde_json::to_string_pretty(&*self.data.read())
+            .map_err(|e| format!("Failed to serialize session titles: {}", e))?;
+        // Atomic, so a crash mid-write can't lose every title, pin and alias
+        crate::commands::file::write_atomic(&self.path, |file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("Failed to write session titles: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SESSION_TITLES_FILE);
+        (dir, path)
+    }
+
+    #[test]
+    fn test_set_get_remove_title() {
+        let (_dir, path) = temp_path();
+        let titles = SessionTitles::load(path.clone());
+        assert_eq!(titles.get("s1"), None);
+
+        titles.set("s1", Some("  Auth refactor ")).unwrap();
+        assert_eq!(titles.get("s1").as_deref(), Some("Auth refactor"));
+
+        // Persisted across loads
+        let reloaded = SessionTitles::load(path.clone());
+        assert_eq!(reloaded.get("s1").as_deref(), Some("Auth refactor"));
+
+        titles.set("s1", None).unwrap();
+        assert_eq!(titles.get("s1"), None);
+    }
+
+    #[test]
+    fn test_title_survives_resume() {
+        let (_dir, path) = temp_path();
+        let titles = SessionTitles::load(path.clone());
+        titles.set("original", Some("My session")).unwrap();
+        titles.link("original", "resumed-1").unwrap();
+        titles.link("resumed-1", "resumed-2").unwrap();
+        assert_eq!(titles.get("resumed-2").as_deref(), Some("My session"));
+
+        // Renaming the resumed session renames the original too
+        titles.set("resumed-2", Some("Renamed")).unwrap();
+        assert_eq!(titles.get("original").as_deref(), Some("Renamed"));
+    }
+
+    #[test]
+    fn test_pin_survives_resume() {
+        let (_dir, path) = temp_path();
+        let titles = SessionTitles::load(path.clone());
+        assert!(!titles.is_pinned("original"));
+
+        titles.set_pinned("original", true).unwrap();
+        titles.link("original", "resumed-1").unwrap();
+        assert!(titles.is_pinned("resumed-1"));
+        assert!(SessionTitles::load(path.clone()).is_pinned("resumed-1"));
+
+        // Unpinning through the resumed ID unpins the original too
+        titles.set_pinned("resumed-1", false).unwrap();
+        assert!(!titles.is_pinned("original"));
+
+        titles.set_pinned("original", true).unwrap();
+        titles.remove("original").unwrap();
+        assert!(!titles.is_pinned("original"));
+    }
+}
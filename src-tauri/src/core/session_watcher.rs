@@ -0,0 +1,88 @@
+//! Session File Watcher
+//!
+//! Watches the JSONL file of the current session so turns written by another
+//! Claude Code process (e.g. the CLI in a terminal) reach the in-memory state.
+//! The parent directory is watched instead of the file itself, so a file that
+//! is replaced or recreated keeps being tracked.
+
+use std::path::PathBuf;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::acp::SessionId;
+
+/// A watched session file changed on disk
+#[derive(Debug, Clone)]
+pub struct SessionFileChange {
+    pub session_id: SessionId,
+}
+
+struct WatchedSession {
+    session_id: SessionId,
+    // Dropping the watcher stops it
+    _watcher: RecommendedWatcher,
+}
+
+/// Watches one session file at a time
+pub struct SessionFileWatcher {
+    change_tx: mpsc::Sender<SessionFileChange>,
+    watched: Mutex<Option<WatchedSession>>,
+}
+
+impl SessionFileWatcher {
+    pub fn new(change_tx: mpsc::Sender<SessionFileChange>) -> Self {
+        Self {
+            change_tx,
+            watched: Mutex::new(None),
+        }
+    }
+
+    /// Watch a session's file, replacing the previously watched session
+    pub fn watch(&self, session_id: &SessionId, path: PathBuf) {
+        let mut watched = self.watched.lock();
+        if watched.as_ref().is_some_and(|w| &w.session_id == session_id) {
+            return;
+        }
+        *watched = None;
+
+        let Some(dir) = path.parent().map(|p| p.to_path_buf()) else {
+            return;
+        };
+        let change_tx = self.change_tx.clone();
+        let change = SessionFileChange {
+            session_id: session_id.clone(),
+        };
+        let handler = move |result: notify::Result<notify::Event>| match result {
+            Ok(event) => {
+                if !matches!(event.kind, EventKind::Access(_)) && event.paths.contains(&path) {
+                    // Full channel means a sync is already queued
+                    let _ = change_tx.try_send(change.clone());
+                }
+            }
+            Err(e) => debug!("Session file watch error: {}", e),
+        };
+
+        let watcher = notify::recommended_watcher(handler).and_then(|mut watcher| {
+            watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        });
+        match watcher {
+            Ok(watcher) => {
+                info!("Watching session file for {} in {:?}", session_id, dir);
+                *watched = Some(WatchedSession {
+                    session_id: session_id.clone(),
+                    _watcher: watcher,
+                });
+            }
+            Err(e) => warn!("Failed to watch session file for {}: {}", session_id, e),
+        }
+    }
+
+    /// Stop watching
+    pub fn unwatch(&self) {
+        *self.watched.lock() = None;
+    }
+}
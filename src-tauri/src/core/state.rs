@@ -1,17 +1,35 @@
 #[cfg(not(target_os = "android"))]
+use std::collections::HashMap;
+#[cfg(not(target_os = "android"))]
 use std::sync::Arc;
 
 #[cfg(not(target_os = "android"))]
 use tokio::sync::{mpsc, RwLock};
 
 #[cfg(not(target_os = "android"))]
-use crate::acp::{AcpClient, PermissionRequest, SessionId, SessionNotification};
+use crate::acp::{
+    AcpClient, AgentEvent, AgentLog, InitializeResponse, PermissionRequest, SessionId, SessionNotification,
+};
+#[cfg(not(target_os = "android"))]
+use crate::core::agent_command::AgentCommand;
+#[cfg(not(target_os = "android"))]
+use crate::core::config::{BusyPromptPolicy, McpServerConfig, McpTransport, PermissionPolicy, ServerConfig};
+#[cfg(not(target_os = "android"))]
+use crate::core::notifier::DesktopNotifier;
+#[cfg(not(target_os = "android"))]
+use crate::core::opener::{DesktopOpener, OpenTarget};
+#[cfg(not(target_os = "android"))]
+use crate::core::sandbox::PathSandbox;
 #[cfg(not(target_os = "android"))]
 use crate::core::session_registry::SessionRegistry;
 #[cfg(not(target_os = "android"))]
-use crate::core::session_state_manager::SessionStateManager;
+use crate::core::session_state::ChatItem;
+#[cfg(not(target_os = "android"))]
+use crate::core::session_state_manager::{SequencedUpdate, SessionStateManager};
+#[cfg(not(target_os = "android"))]
+use crate::core::session_watcher::{SessionFileChange, SessionFileWatcher};
 #[cfg(not(target_os = "android"))]
-use crate::core::terminal::{TerminalManager, TerminalOutput};
+use crate::core::terminal::{TerminalExit, TerminalManager, TerminalOutput};
 
 /// Notification for session activation changes
 #[cfg(not(target_os = "android"))]
@@ -20,16 +38,58 @@ pub struct SessionActivated {
     pub session_id: Option<SessionId>,
 }
 
+/// Which agent process serves a session. The default is the shared agent;
+/// sessions with a provider override or a project agent command run in a
+/// dedicated process per combination.
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct AgentKey {
+    pub provider_id: Option<String>,
+    /// `agentCommand` from the project's `.aerowork.json`
+    pub command: Option<String>,
+}
+
+#[cfg(not(target_os = "android"))]
+impl std::fmt::Display for AgentKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.provider_id, &self.command) {
+            (None, None) => write!(f, "shared"),
+            (Some(provider_id), None) => write!(f, "{}", provider_id),
+            (None, Some(command)) => write!(f, "`{}`", command),
+            (Some(provider_id), Some(command)) => write!(f, "{} `{}`", provider_id, command),
+        }
+    }
+}
+
+/// The client of an agent process, None until it is started
+#[cfg(not(target_os = "android"))]
+pub type AgentClientSlot = Arc<RwLock<Option<AcpClient>>>;
+
+/// A session whose coalesced message chunks should be sent now, and the sender
+/// acknowledging it
+#[cfg(not(target_os = "android"))]
+pub type ChunkFlushRequest = (SessionId, tokio::sync::oneshot::Sender<()>);
+
 /// Desktop AppState - full featured with agent, terminal, sessions
 #[cfg(not(target_os = "android"))]
 pub struct AppState {
     pub client: Arc<RwLock<Option<AcpClient>>>,
     pub notification_tx: mpsc::Sender<SessionNotification>,
     pub notification_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<SessionNotification>>>>,
+    /// Requests to send a session's coalesced message chunks now (acknowledged when sent)
+    pub chunk_flush_tx: mpsc::UnboundedSender<ChunkFlushRequest>,
+    pub chunk_flush_rx: Arc<parking_lot::RwLock<Option<mpsc::UnboundedReceiver<ChunkFlushRequest>>>>,
     pub permission_tx: mpsc::Sender<PermissionRequest>,
     pub permission_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<PermissionRequest>>>>,
+    /// Agent process lifecycle events (e.g. unexpected exit)
+    pub agent_event_tx: mpsc::Sender<AgentEvent>,
+    pub agent_event_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<AgentEvent>>>>,
+    /// Recent agent stderr, kept across agent restarts and failed startups
+    pub agent_log: Arc<AgentLog>,
     pub terminal_manager: Arc<TerminalManager>,
     pub terminal_output_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<TerminalOutput>>>>,
+    /// Terminal processes that exited
+    pub terminal_exit_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<TerminalExit>>>>,
     /// Session registry for managing session metadata across clients
     pub session_registry: Arc<SessionRegistry>,
     /// Session state manager - single source of truth for session data
@@ -39,35 +99,422 @@ pub struct AppState {
     /// Channel for session activation notifications
     pub session_activated_tx: mpsc::Sender<SessionActivated>,
     pub session_activated_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<SessionActivated>>>>,
+    /// Watches the current session's JSONL file for writes by other processes
+    pub session_watcher: Arc<SessionFileWatcher>,
+    pub session_file_rx: Arc<parking_lot::RwLock<Option<mpsc::Receiver<SessionFileChange>>>>,
     /// Actual WebSocket server port (may differ from configured port if it was occupied)
     pub ws_port: Arc<std::sync::atomic::AtomicU16>,
+    /// Host the WebSocket server is bound to
+    pub ws_host: Arc<parking_lot::RwLock<String>>,
+    /// Whether the WebSocket server is serving over TLS (wss://)
+    pub ws_tls: Arc<std::sync::atomic::AtomicBool>,
+    /// When this state (and so the backend) was created, for uptime reporting
+    pub started_at: std::time::Instant,
     /// Current pending permission request (for resending on client reconnect)
     pub pending_permission: Arc<parking_lot::RwLock<Option<PermissionRequest>>>,
+    /// Initialize response of the running agent (capabilities, agent info)
+    pub agent_init: Arc<parking_lot::RwLock<Option<InitializeResponse>>>,
+    /// Agent command set by a client, overriding `agent.defaultCommand` until restart
+    agent_command: parking_lot::RwLock<Option<AgentCommand>>,
+    /// Dedicated agent processes for sessions with a provider override or a
+    /// project agent command
+    pub provider_clients: Arc<parking_lot::RwLock<HashMap<AgentKey, AgentClientSlot>>>,
+    /// Allowed roots for client file commands (project directories)
+    pub path_sandbox: Arc<PathSandbox>,
+    /// Whether recent project directories have been added to the sandbox
+    sandbox_seeded: std::sync::atomic::AtomicBool,
+    /// OS notification backend (only set by the desktop GUI)
+    notifier: parking_lot::RwLock<Option<Arc<dyn DesktopNotifier>>>,
+    /// Opens files and links in OS apps (only set by the desktop GUI)
+    opener: parking_lot::RwLock<Option<Arc<dyn DesktopOpener>>>,
+    /// Whether desktop notifications are enabled (Config.notifications.enabled)
+    notifications_enabled: std::sync::atomic::AtomicBool,
+    /// Sessions created without an agent session, started on their first prompt
+    placeholder_sessions: parking_lot::RwLock<std::collections::HashSet<SessionId>>,
+    /// Agent session IDs of started placeholder sessions, by placeholder ID
+    session_aliases: parking_lot::RwLock<HashMap<SessionId, SessionId>>,
+    /// Held while a placeholder session is started, so concurrent prompts start it once
+    pub placeholder_start_lock: tokio::sync::Mutex<()>,
+    /// Replaces `agent.busyPrompts` from the config when set
+    busy_prompts: Option<BusyPromptPolicy>,
 }
 
+/// Number of recent sessions whose project directories are allowed by default
+#[cfg(not(target_os = "android"))]
+const RECENT_PROJECT_SESSIONS: usize = 50;
+
 #[cfg(not(target_os = "android"))]
 impl AppState {
     pub fn new() -> Self {
-        let (notification_tx, notification_rx) = mpsc::channel(100);
-        let (permission_tx, permission_rx) = mpsc::channel(100);
-        let (terminal_output_tx, terminal_output_rx) = mpsc::channel(100);
+        crate::core::config::migrate_legacy_config();
+        let config = crate::core::ConfigManager::new().config().clone();
+
+        // High-volume streams get a configurable buffer; see ChunkBacklog for
+        // how the agent reader avoids blocking when it fills up
+        let event_buffer_size = config.server.event_buffer_size.max(1);
+        let (notification_tx, notification_rx) = mpsc::channel(event_buffer_size);
+        let (chunk_flush_tx, chunk_flush_rx) = mpsc::unbounded_channel();
+        let (permission_tx, permission_rx) = mpsc::channel(event_buffer_size);
+        let (terminal_output_tx, terminal_output_rx) = mpsc::channel(event_buffer_size);
+        let (terminal_exit_tx, terminal_exit_rx) = mpsc::channel(100);
         let (session_activated_tx, session_activated_rx) = mpsc::channel(100);
+        let (agent_event_tx, agent_event_rx) = mpsc::channel(100);
+        let (session_file_tx, session_file_rx) = mpsc::channel(100);
+
+        let session_state_manager = Arc::new(SessionStateManager::new());
+        session_state_manager.enable_persistence(crate::core::data_dir().join("sessions"));
 
         Self {
             client: Arc::new(RwLock::new(None)),
             notification_tx,
             notification_rx: Arc::new(parking_lot::RwLock::new(Some(notification_rx))),
+            chunk_flush_tx,
+            chunk_flush_rx: Arc::new(parking_lot::RwLock::new(Some(chunk_flush_rx))),
             permission_tx,
             permission_rx: Arc::new(parking_lot::RwLock::new(Some(permission_rx))),
-            terminal_manager: Arc::new(TerminalManager::new(terminal_output_tx)),
+            agent_event_tx,
+            agent_event_rx: Arc::new(parking_lot::RwLock::new(Some(agent_event_rx))),
+            agent_log: Arc::new(AgentLog::default()),
+            terminal_manager: Arc::new(
+                TerminalManager::new(terminal_output_tx, terminal_exit_tx)
+                    .with_max_terminals(config.terminal.max_terminals),
+            ),
             terminal_output_rx: Arc::new(parking_lot::RwLock::new(Some(terminal_output_rx))),
+            terminal_exit_rx: Arc::new(parking_lot::RwLock::new(Some(terminal_exit_rx))),
             session_registry: Arc::new(SessionRegistry::new()),
-            session_state_manager: Arc::new(SessionStateManager::new()),
+            session_state_manager,
             current_session_id: Arc::new(parking_lot::RwLock::new(None)),
             session_activated_tx,
             session_activated_rx: Arc::new(parking_lot::RwLock::new(Some(session_activated_rx))),
+            session_watcher: Arc::new(SessionFileWatcher::new(session_file_tx)),
+            session_file_rx: Arc::new(parking_lot::RwLock::new(Some(session_file_rx))),
             ws_port: Arc::new(std::sync::atomic::AtomicU16::new(0)),
+            ws_host: Arc::new(parking_lot::RwLock::new(String::new())),
+            ws_tls: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            started_at: std::time::Instant::now(),
             pending_permission: Arc::new(parking_lot::RwLock::new(None)),
+            agent_init: Arc::new(parking_lot::RwLock::new(None)),
+            agent_command: parking_lot::RwLock::new(None),
+            provider_clients: Arc::new(parking_lot::RwLock::new(HashMap::new())),
+            path_sandbox: Arc::new(trusted_sandbox(&config.server)),
+            sandbox_seeded: std::sync::atomic::AtomicBool::new(false),
+            notifier: parking_lot::RwLock::new(None),
+            opener: parking_lot::RwLock::new(None),
+            notifications_enabled: std::sync::atomic::AtomicBool::new(config.notifications.enabled),
+            placeholder_sessions: parking_lot::RwLock::new(std::collections::HashSet::new()),
+            session_aliases: parking_lot::RwLock::new(HashMap::new()),
+            placeholder_start_lock: tokio::sync::Mutex::new(()),
+            busy_prompts: None,
+        }
+    }
+
+    /// Handle prompts sent to a busy session with `policy`, whatever the config says
+    pub fn with_busy_prompts(mut self, policy: BusyPromptPolicy) -> Self {
+        self.busy_prompts = Some(policy);
+        self
+    }
+
+    /// What a prompt sent while its session runs another one does
+    pub fn busy_prompts(&self) -> BusyPromptPolicy {
+        self.busy_prompts.unwrap_or_else(|| crate::core::ConfigManager::new().config().agent.busy_prompts)
+    }
+
+    /// Stop everything that would otherwise outlive the process: PTY children,
+    /// agent subprocesses and session snapshots not yet written to disk.
+    pub async fn shutdown(&self) {
+        let terminals = self.terminal_manager.kill_all();
+        if terminals > 0 {
+            tracing::info!("Killed {} terminal(s)", terminals);
+        }
+
+        self.disconnect_agents().await;
+
+        self.session_watcher.unwatch();
+        self.session_state_manager.flush_dirty();
+    }
+
+    /// Wait until a session's coalesced message chunks have been broadcast, so
+    /// they reach clients before anything that follows the turn
+    pub async fn flush_chunks(&self, session_id: &str) {
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        if self.chunk_flush_tx.send((session_id.to_string(), done_tx)).is_ok() {
+            let _ = tokio::time::timeout(std::time::Duration::from_millis(500), done_rx).await;
+        }
+    }
+
+    /// Disconnect the shared agent and every per-provider agent
+    pub async fn disconnect_agents(&self) {
+        let mut clients = vec![self.client.clone()];
+        clients.extend(self.provider_clients.write().drain().map(|(_, client)| client));
+        for client in clients {
+            if let Some(mut client) = client.write().await.take() {
+                let _ = client.disconnect().await;
+            }
+        }
+        self.set_agent_init(None);
+    }
+
+    /// Agent command set by a client, if any
+    pub fn agent_command(&self) -> Option<AgentCommand> {
+        self.agent_command.read().clone()
+    }
+
+    /// Set (or with None, clear) the agent command used when an agent next starts
+    pub fn set_agent_command(&self, command: Option<AgentCommand>) {
+        *self.agent_command.write() = command;
+    }
+
+    /// Agent client slot for an agent. The default key is the shared agent,
+    /// which uses the global active provider from ~/.claude/settings.json.
+    pub fn agent_client(&self, agent: &AgentKey) -> AgentClientSlot {
+        if *agent == AgentKey::default() {
+            return self.client.clone();
+        }
+        self.provider_clients
+            .write()
+            .entry(agent.clone())
+            .or_insert_with(|| Arc::new(RwLock::new(None)))
+            .clone()
+    }
+
+    /// The agent for a session with `provider_id` in `cwd`: the project's agent
+    /// command applies. An invalid project config is logged and ignored here.
+    pub fn agent_for(&self, provider_id: Option<String>, cwd: &str) -> AgentKey {
+        let command = match crate::core::ProjectConfig::load(std::path::Path::new(cwd)) {
+            Ok(project) => project.and_then(|p| p.config.agent_command),
+            Err(e) => {
+                tracing::warn!("{}", e);
+                None
+            }
+        };
+        AgentKey { provider_id, command }
+    }
+
+    /// The agent serving a session
+    pub fn session_agent(&self, session_id: &str) -> AgentKey {
+        let provider_id = self.session_provider(session_id);
+        let cwd = self.session_state_manager.get_session_cwd(&session_id.to_string())
+            .or_else(|| self.session_registry.get_session_info(session_id).map(|info| info.cwd));
+        match cwd {
+            Some(cwd) => self.agent_for(provider_id, &cwd),
+            None => AgentKey { provider_id, command: None },
+        }
+    }
+
+    /// Provider override of a session, from memory or its persisted snapshot
+    pub fn session_provider(&self, session_id: &str) -> Option<String> {
+        let session_id = session_id.to_string();
+        if let Some(provider_id) = self.session_registry.get_provider(&session_id) {
+            return Some(provider_id);
+        }
+        if self.session_state_manager.has_session(&session_id) {
+            return self.session_state_manager.get_session_provider(&session_id);
+        }
+        self.session_state_manager
+            .load_persisted(&session_id)
+            .and_then(|s| s.provider_id)
+    }
+
+    /// Record a session's provider override in the registry and session state.
+    /// Returns the `provider_updated` delta if the session is loaded.
+    pub fn set_session_provider(&self, session_id: &str, provider_id: Option<String>) -> Option<SequencedUpdate> {
+        let session_id = session_id.to_string();
+        self.session_registry.set_provider(&session_id, provider_id.clone());
+        self.session_state_manager.set_session_provider(&session_id, provider_id)
+    }
+
+    /// Record a session that has no agent session yet
+    pub fn add_placeholder_session(&self, session_id: &str) {
+        self.placeholder_sessions.write().insert(session_id.to_string());
+    }
+
+    /// Whether a session is a placeholder still waiting for its first prompt
+    pub fn is_placeholder_session(&self, session_id: &str) -> bool {
+        self.placeholder_sessions.read().contains(session_id)
+    }
+
+    /// Mark a placeholder as started: its ID now refers to `real_id`
+    pub fn complete_placeholder_session(&self, placeholder_id: &str, real_id: &str) {
+        self.placeholder_sessions.write().remove(placeholder_id);
+        self.session_aliases.write().insert(placeholder_id.to_string(), real_id.to_string());
+    }
+
+    /// The agent session ID a started placeholder ID refers to
+    pub fn session_alias(&self, session_id: &str) -> Option<SessionId> {
+        self.session_aliases.read().get(session_id).cloned()
+    }
+
+    /// Agent client slot serving a session
+    pub fn session_client(&self, session_id: &str) -> AgentClientSlot {
+        self.agent_client(&self.session_agent(session_id))
+    }
+
+    /// Install the OS notification backend (desktop GUI only)
+    pub fn set_notifier(&self, notifier: Arc<dyn DesktopNotifier>) {
+        *self.notifier.write() = Some(notifier);
+    }
+
+    /// Install the backend for opening files and links (desktop GUI only)
+    pub fn set_opener(&self, opener: Arc<dyn DesktopOpener>) {
+        *self.opener.write() = Some(opener);
+    }
+
+    /// Open a checked file or URL with the OS default app. Fails in headless
+    /// mode, where there is no desktop to open it on.
+    pub fn open_external(&self, target: &OpenTarget) -> Result<(), String> {
+        let opener = self.opener.read().clone()
+            .ok_or("Opening files needs the desktop app; the server is running headless")?;
+        match target {
+            OpenTarget::Path(path) => opener.open_path(path),
+            OpenTarget::Url(url) => opener.open_url(url),
+        }
+    }
+
+    /// Enable or disable desktop notifications and persist the choice
+    pub fn set_notifications_enabled(&self, enabled: bool) -> Result<(), String> {
+        self.notifications_enabled.store(enabled, std::sync::atomic::Ordering::SeqCst);
+        let mut manager = crate::core::ConfigManager::new();
+        manager.config_mut().notifications.enabled = enabled;
+        manager.save()
+    }
+
+    /// Permission policy in effect for a session: dangerous mode allows everything,
+    /// then the session's own policy, then the one saved for its project.
+    pub fn permission_policy(&self, session_id: &str) -> PermissionPolicy {
+        let session_id = session_id.to_string();
+        if self.session_state_manager.is_dangerous_mode(&session_id) {
+            return PermissionPolicy::allow_all();
+        }
+        if let Some(policy) = self.session_state_manager.get_permission_policy(&session_id) {
+            return policy;
+        }
+        self.session_registry
+            .get_session_info(&session_id)
+            .and_then(|info| self.project_permission_policy(&info.cwd))
+            .unwrap_or_default()
+    }
+
+    /// Enabled MCP servers from the config, in ACP format. Servers whose
+    /// transport the agent doesn't advertise, or that are misconfigured, are left out.
+    pub fn mcp_servers(&self) -> Vec<serde_json::Value> {
+        let capabilities = self.get_agent_init()
+            .and_then(|init| init.agent_capabilities)
+            .and_then(|caps| caps.mcp_capabilities)
+            .unwrap_or_default();
+
+        crate::core::ConfigManager::new()
+            .config()
+            .mcp_servers
+            .iter()
+            .filter(|server| server.enabled)
+            .filter(|server| {
+                let supported = match server.transport {
+                    McpTransport::Stdio => true,
+                    McpTransport::Http => capabilities.http == Some(true),
+                    McpTransport::Sse => capabilities.sse == Some(true),
+                };
+                if !supported {
+                    tracing::warn!("Skipping MCP server {}: the agent doesn't support {:?} servers", server.name, server.transport);
+                    return false;
+                }
+                match server.validate() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        tracing::warn!("Skipping MCP server: {}", e);
+                        false
+                    }
+                }
+            })
+            .map(|server| server.to_acp())
+            .collect()
+    }
+
+    /// Validate and save the configured MCP servers
+    pub fn set_mcp_servers(&self, servers: Vec<McpServerConfig>) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for server in &servers {
+            server.validate()?;
+            if !names.insert(server.name.as_str()) {
+                return Err(format!("Duplicate MCP server name: {}", server.name));
+            }
+        }
+        let mut manager = crate::core::ConfigManager::new();
+        manager.config_mut().mcp_servers = servers;
+        manager.save()
+    }
+
+    /// Permission policy saved for a project
+    pub fn project_permission_policy(&self, cwd: &str) -> Option<PermissionPolicy> {
+        crate::core::ConfigManager::new().config().permissions.get(cwd).cloned()
+    }
+
+    /// Save (or with None, remove) the permission policy for a project
+    pub fn set_project_permission_policy(&self, cwd: &str, policy: Option<PermissionPolicy>) -> Result<(), String> {
+        let mut manager = crate::core::ConfigManager::new();
+        match policy {
+            Some(policy) => manager.config_mut().permissions.insert(cwd.to_string(), policy),
+            None => manager.config_mut().permissions.remove(cwd),
+        };
+        manager.save()
+    }
+
+    pub fn notifications_enabled(&self) -> bool {
+        self.notifications_enabled.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Show an OS notification if enabled and the window is not focused.
+    /// No-op in headless mode (no notifier installed).
+    pub fn notify_desktop(&self, title: &str, body: &str) {
+        if !self.notifications_enabled() {
+            return;
+        }
+        if let Some(notifier) = self.notifier.read().as_ref() {
+            if !notifier.is_focused() {
+                notifier.notify(title, body);
+            }
+        }
+    }
+
+    /// Load chat history for a session being resumed.
+    /// Prefers the live snapshot from a previous run when it is newer than the
+    /// agent's JSONL transcript (e.g. the app was killed mid-turn).
+    pub fn load_chat_items(&self, session_id: &str) -> Vec<ChatItem> {
+        if let Some(snapshot) = self.session_state_manager.load_persisted(&session_id.to_string()) {
+            let jsonl_modified = self
+                .session_registry
+                .find_session_file(session_id)
+                .and_then(|path| std::fs::metadata(path).ok())
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64);
+
+            if jsonl_modified.is_none_or(|modified| snapshot.updated_at >= modified) {
+                tracing::info!("Restoring session {} from snapshot ({} items)", session_id, snapshot.chat_items.len());
+                return snapshot.chat_items;
+            }
+        }
+
+        self.session_registry.load_chat_items(session_id)
+    }
+
+    /// Allow client file commands under a project directory. Only for trusted
+    /// callers (the desktop app); RPC handlers must `check_path` instead.
+    pub fn allow_project_root(&self, cwd: &str) {
+        self.path_sandbox.allow_root(cwd);
+    }
+
+    /// Resolve a client-supplied path, rejecting it if it escapes every project root.
+    /// Recent project directories are added lazily on the first miss.
+    pub fn check_path(&self, path: &str) -> Result<std::path::PathBuf, String> {
+        match self.path_sandbox.check(path) {
+            Err(_) if !self.sandbox_seeded.swap(true, std::sync::atomic::Ordering::SeqCst) => {
+                let recent = self.session_registry.list_sessions(None, RECENT_PROJECT_SESSIONS, 0, false);
+                for session in recent.sessions {
+                    self.path_sandbox.allow_root(&session.cwd);
+                }
+                self.path_sandbox.check(path)
+            }
+            result => result,
         }
     }
 
@@ -82,6 +529,16 @@ impl AppState {
         self.pending_permission.read().clone()
     }
 
+    /// Store the initialize response of a freshly started agent
+    pub fn set_agent_init(&self, response: Option<InitializeResponse>) {
+        *self.agent_init.write() = response;
+    }
+
+    /// Get the initialize response of the running agent (clone)
+    pub fn get_agent_init(&self) -> Option<InitializeResponse> {
+        self.agent_init.read().clone()
+    }
+
     /// Set the WebSocket server port
     pub fn set_ws_port(&self, port: u16) {
         self.ws_port.store(port, std::sync::atomic::Ordering::SeqCst);
@@ -98,6 +555,15 @@ impl AppState {
             let mut current = self.current_session_id.write();
             *current = session_id.clone();
         }
+        // Follow writes to the session file by other processes (e.g. the CLI).
+        // Sessions without a file yet are picked up when activated again.
+        match session_id
+            .as_ref()
+            .and_then(|id| Some((id, self.session_registry.find_session_file(id)?)))
+        {
+            Some((id, path)) => self.session_watcher.watch(id, path),
+            None => self.session_watcher.unwatch(),
+        }
         // Broadcast to all connected clients
         let _ = self.session_activated_tx.send(SessionActivated { session_id }).await;
     }
@@ -108,6 +574,20 @@ impl AppState {
     }
 }
 
+/// Sandbox seeded from trusted sources only: configured project roots and the
+/// directory the server was started in. Client requests never add roots.
+#[cfg(not(target_os = "android"))]
+fn trusted_sandbox(config: &ServerConfig) -> PathSandbox {
+    let sandbox = PathSandbox::new(config.sandbox_files);
+    for root in &config.project_roots {
+        sandbox.allow_root(root);
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        sandbox.allow_root(cwd);
+    }
+    sandbox
+}
+
 #[cfg(not(target_os = "android"))]
 impl Default for AppState {
     fn default() -> Self {
@@ -1,16 +1,30 @@
-use parking_lot::RwLock;
-use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use parking_lot::{Mutex, RwLock};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, PtySize};
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tracing::warn;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
+/// Default number of output bytes retained per terminal
+const DEFAULT_OUTPUT_BYTE_LIMIT: usize = 1024 * 1024;
+
+/// How long an exited terminal kept for the agent waits to be released before
+/// it is dropped anyway
+const EXITED_TERMINAL_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
 pub struct TerminalOutput {
     pub terminal_id: String,
+    /// Output decoded as UTF-8 (multi-byte characters split across reads are kept whole)
     pub data: String,
+    /// The exact bytes read from the PTY
+    pub bytes: Vec<u8>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -19,10 +33,84 @@ pub struct TerminalInfo {
     pub working_dir: String,
 }
 
+/// Exit status of the process running in a terminal
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalExitStatus {
+    pub exit_code: Option<u32>,
+    pub signal: Option<String>,
+}
+
+/// A terminal's process exited
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalExit {
+    pub terminal_id: String,
+    #[serde(flatten)]
+    pub status: TerminalExitStatus,
+}
+
+/// Output captured so far for a terminal
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalOutputSnapshot {
+    pub output: String,
+    /// Whether older output was dropped to stay within the byte limit
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<TerminalExitStatus>,
+}
+
+/// Output retained for a terminal, trimmed from the front to stay within `limit` bytes
+struct OutputBuffer {
+    data: String,
+    limit: usize,
+    truncated: bool,
+}
+
+impl OutputBuffer {
+    fn new(limit: usize) -> Self {
+        Self {
+            data: String::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    fn push(&mut self, chunk: &str) {
+        self.data.push_str(chunk);
+        if self.data.len() > self.limit {
+            let mut cut = self.data.len() - self.limit;
+            while !self.data.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.data.drain(..cut);
+            self.truncated = true;
+        }
+    }
+
+    /// The last `max_bytes` of the retained output, and whether anything before it is missing
+    fn tail(&self, max_bytes: usize) -> (&str, bool) {
+        if self.data.len() <= max_bytes {
+            return (&self.data, self.truncated);
+        }
+        let mut cut = self.data.len() - max_bytes;
+        while !self.data.is_char_boundary(cut) {
+            cut += 1;
+        }
+        (&self.data[cut..], true)
+    }
+}
+
 // Channel-based handle to communicate with the terminal thread
 struct TerminalHandle {
     input_tx: mpsc::UnboundedSender<TerminalInput>,
     info: TerminalInfo,
+    output: Arc<Mutex<OutputBuffer>>,
+    exit_rx: watch::Receiver<Option<TerminalExitStatus>>,
+    killer: Mutex<Box<dyn ChildKiller + Send + Sync>>,
+    /// When the process exited, for terminals kept around after exit
+    exited_at: Option<Instant>,
 }
 
 enum TerminalInput {
@@ -32,39 +120,121 @@ enum TerminalInput {
 }
 
 pub struct TerminalManager {
-    terminals: RwLock<HashMap<String, TerminalHandle>>,
+    terminals: Arc<RwLock<HashMap<String, TerminalHandle>>>,
     output_tx: mpsc::Sender<TerminalOutput>,
+    exit_tx: mpsc::Sender<TerminalExit>,
+    /// Most terminals with a running process (0 = no limit)
+    max_terminals: usize,
+    /// Terminals that passed the limit check and are still being started
+    starting: AtomicUsize,
+}
+
+/// A terminal slot taken by `reserve_slot`, released when dropped
+struct SlotReservation<'a>(&'a AtomicUsize);
+
+impl Drop for SlotReservation<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl TerminalManager {
-    pub fn new(output_tx: mpsc::Sender<TerminalOutput>) -> Self {
+    pub fn new(output_tx: mpsc::Sender<TerminalOutput>, exit_tx: mpsc::Sender<TerminalExit>) -> Self {
         Self {
-            terminals: RwLock::new(HashMap::new()),
+            terminals: Arc::new(RwLock::new(HashMap::new())),
             output_tx,
+            exit_tx,
+            max_terminals: 0,
+            starting: AtomicUsize::new(0),
         }
     }
 
+    /// Refuse to start terminals while `max` are running (0 = no limit)
+    pub fn with_max_terminals(mut self, max: usize) -> Self {
+        self.max_terminals = max;
+        self
+    }
+
+    /// Start an interactive shell. `shell` overrides the user's default shell and
+    /// falls back to it if the program can't be found; `env` is added to the PTY environment.
     pub fn create_terminal(
         &self,
         working_dir: String,
         cols: u16,
         rows: u16,
+        shell: Option<&str>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let shell = match shell {
+            Some(shell) if find_program(shell).is_some() => shell.to_string(),
+            Some(shell) => {
+                warn!("Shell {} not found, using the default shell", shell);
+                get_default_shell()
+            }
+            None => get_default_shell(),
+        };
+        let cmd = CommandBuilder::new(shell);
+        let env: Vec<(String, String)> = env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        self.spawn_terminal(cmd, &env, working_dir, pty_size(cols, rows), DEFAULT_OUTPUT_BYTE_LIMIT, true)
+    }
+
+    /// Run a specific command (instead of the login shell) in a new terminal.
+    /// Used for agent-requested commands via the ACP `terminal/create` method.
+    pub fn create_command_terminal(
+        &self,
+        command: &str,
+        args: &[String],
+        env: &[(String, String)],
+        working_dir: String,
+        output_byte_limit: Option<usize>,
+    ) -> Result<String, String> {
+        let mut cmd = CommandBuilder::new(command);
+        cmd.args(args);
+        self.spawn_terminal(
+            cmd,
+            env,
+            working_dir,
+            pty_size(80, 24),
+            output_byte_limit.unwrap_or(DEFAULT_OUTPUT_BYTE_LIMIT),
+            // The agent reads the exit status and output after exit, then releases it
+            false,
+        )
+    }
+
+    /// Take a slot for a new terminal, or fail if `max_terminals` are running
+    /// or starting. Nothing is spawned until this succeeds.
+    fn reserve_slot(&self) -> Result<SlotReservation<'_>, String> {
+        // Check and reserve under one lock so concurrent creates can't both pass the limit
+        let mut terminals = self.terminals.write();
+        reap_exited(&mut terminals, Instant::now());
+        // Exited terminals kept for the agent to read don't hold a process, so they don't count
+        let running = terminals.values().filter(|h| h.exit_rx.borrow().is_none()).count();
+        if self.max_terminals > 0 && running + self.starting.load(Ordering::SeqCst) >= self.max_terminals {
+            warn!("Terminal limit reached ({} running)", self.max_terminals);
+            return Err(format!("Terminal limit reached ({} terminals running)", self.max_terminals));
+        }
+        self.starting.fetch_add(1, Ordering::SeqCst);
+        Ok(SlotReservation(&self.starting))
+    }
+
+    fn spawn_terminal(
+        &self,
+        mut cmd: CommandBuilder,
+        env: &[(String, String)],
+        working_dir: String,
+        size: PtySize,
+        output_byte_limit: usize,
+        remove_on_exit: bool,
     ) -> Result<String, String> {
+        let _slot = self.reserve_slot()?;
         let pty_system = native_pty_system();
 
         let pty_pair = pty_system
-            .openpty(PtySize {
-                rows,
-                cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            })
+            .openpty(size)
             .map_err(|e| format!("Failed to open PTY: {}", e))?;
 
         let terminal_id = Uuid::new_v4().to_string();
 
-        // Build the shell command
-        let mut cmd = CommandBuilder::new(get_default_shell());
         cmd.cwd(&working_dir);
 
         // Set TERM for terminal capabilities (required for tmux and other TUI apps)
@@ -99,13 +269,12 @@ impl TerminalManager {
             cmd.env("USER", user);
         }
 
-        // Spawn the shell in the slave PTY
-        let _child = pty_pair
-            .slave
-            .spawn_command(cmd)
-            .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+        // Caller-provided variables take precedence over the defaults above
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
 
-        // Get reader and writer
+        // Get reader and writer before spawning, so no later error leaves a child behind
         let mut reader = pty_pair
             .master
             .try_clone_reader()
@@ -115,6 +284,13 @@ impl TerminalManager {
             .take_writer()
             .map_err(|e| format!("Failed to take writer: {}", e))?;
 
+        // Spawn the shell in the slave PTY
+        let mut child = pty_pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+        let killer = child.clone_killer();
+
         // Create channel for input to the terminal
         let (input_tx, mut input_rx) = mpsc::unbounded_channel::<TerminalInput>();
 
@@ -123,26 +299,50 @@ impl TerminalManager {
             working_dir: working_dir.clone(),
         };
 
+        let output = Arc::new(Mutex::new(OutputBuffer::new(output_byte_limit)));
+        let (exit_tx, exit_rx) = watch::channel(None);
+
+        let reap_tx = input_tx.clone();
         let handle = TerminalHandle {
             input_tx,
             info,
+            output: output.clone(),
+            exit_rx,
+            killer: Mutex::new(killer),
+            exited_at: None,
         };
 
         self.terminals.write().insert(terminal_id.clone(), handle);
 
+        // Spawn a thread to wait for the process to exit
+        let waiter = thread::spawn(move || {
+            let exit_code = child.wait().ok().map(|status| status.exit_code());
+            let status = TerminalExitStatus {
+                exit_code,
+                signal: None,
+            };
+            let _ = exit_tx.send(Some(status.clone()));
+            status
+        });
+
         // Spawn a thread to read output from the PTY
         let output_tx = self.output_tx.clone();
+        let terminal_exit_tx = self.exit_tx.clone();
+        let terminals = self.terminals.clone();
         let tid = terminal_id.clone();
         thread::spawn(move || {
             let mut buf = [0u8; 4096];
+            let mut pending = Vec::new();
             loop {
                 match reader.read(&mut buf) {
                     Ok(0) => break, // EOF
                     Ok(n) => {
-                        let data = String::from_utf8_lossy(&buf[..n]).to_string();
+                        let data = decode_utf8(&mut pending, &buf[..n]);
+                        output.lock().push(&data);
                         let output = TerminalOutput {
                             terminal_id: tid.clone(),
                             data,
+                            bytes: buf[..n].to_vec(),
                         };
                         if output_tx.blocking_send(output).is_err() {
                             break;
@@ -151,6 +351,26 @@ impl TerminalManager {
                     Err(_) => break,
                 }
             }
+            // A sequence cut off by EOF will never complete
+            if !pending.is_empty() {
+                output.lock().push(&String::from_utf8_lossy(&pending));
+            }
+
+            // The PTY closes when the process exits; report it after the last output
+            let Ok(status) = waiter.join() else {
+                return;
+            };
+            // Stop the input thread so the PTY is released even if the handle is kept
+            let _ = reap_tx.send(TerminalInput::Kill);
+            if remove_on_exit {
+                terminals.write().remove(&tid);
+            } else if let Some(handle) = terminals.write().get_mut(&tid) {
+                handle.exited_at = Some(Instant::now());
+            }
+            let _ = terminal_exit_tx.blocking_send(TerminalExit {
+                terminal_id: tid,
+                status,
+            });
         });
 
         // Spawn a thread to handle input to the PTY
@@ -165,12 +385,7 @@ impl TerminalManager {
                         let _ = writer.flush();
                     }
                     TerminalInput::Resize(cols, rows) => {
-                        let _ = master.resize(PtySize {
-                            rows,
-                            cols,
-                            pixel_width: 0,
-                            pixel_height: 0,
-                        });
+                        let _ = master.resize(pty_size(cols, rows));
                     }
                     TerminalInput::Kill => {
                         break;
@@ -212,19 +427,144 @@ impl TerminalManager {
             .remove(terminal_id)
             .ok_or_else(|| format!("Terminal {} not found", terminal_id))?;
 
+        let _ = handle.killer.lock().kill();
         let _ = handle.input_tx.send(TerminalInput::Kill);
         Ok(())
     }
 
+    /// Kill the process running in a terminal but keep the terminal (and its output) around
+    pub fn kill_process(&self, terminal_id: &str) -> Result<(), String> {
+        let terminals = self.terminals.read();
+        let handle = terminals
+            .get(terminal_id)
+            .ok_or_else(|| format!("Terminal {} not found", terminal_id))?;
+
+        // Killing an already exited process is not an error
+        let _ = handle.killer.lock().kill();
+        Ok(())
+    }
+
+    /// Get the output captured so far, plus the exit status if the process has exited
+    pub fn get_output(&self, terminal_id: &str) -> Result<TerminalOutputSnapshot, String> {
+        self.get_recent_output(terminal_id, usize::MAX)
+    }
+
+    /// Like `get_output`, limited to the last `max_bytes` of output
+    pub fn get_recent_output(&self, terminal_id: &str, max_bytes: usize) -> Result<TerminalOutputSnapshot, String> {
+        let terminals = self.terminals.read();
+        let handle = terminals
+            .get(terminal_id)
+            .ok_or_else(|| format!("Terminal {} not found", terminal_id))?;
+
+        let buffer = handle.output.lock();
+        let (output, truncated) = buffer.tail(max_bytes);
+        let snapshot = TerminalOutputSnapshot {
+            output: output.to_string(),
+            truncated,
+            exit_status: handle.exit_rx.borrow().clone(),
+        };
+        Ok(snapshot)
+    }
+
+    /// Wait until the process running in a terminal exits
+    pub async fn wait_for_exit(&self, terminal_id: &str) -> Result<TerminalExitStatus, String> {
+        let mut exit_rx = {
+            let terminals = self.terminals.read();
+            terminals
+                .get(terminal_id)
+                .map(|h| h.exit_rx.clone())
+                .ok_or_else(|| format!("Terminal {} not found", terminal_id))?
+        };
+
+        let status = exit_rx
+            .wait_for(|status| status.is_some())
+            .await
+            .map_err(|_| format!("Terminal {} closed before exit status was known", terminal_id))?;
+        Ok(status.clone().unwrap_or(TerminalExitStatus {
+            exit_code: None,
+            signal: None,
+        }))
+    }
+
+    /// Kill every terminal, returning how many there were
+    pub fn kill_all(&self) -> usize {
+        let handles: Vec<_> = self.terminals.write().drain().map(|(_, h)| h).collect();
+        for handle in &handles {
+            let _ = handle.killer.lock().kill();
+            let _ = handle.input_tx.send(TerminalInput::Kill);
+        }
+        handles.len()
+    }
+
+    /// Terminals whose process is still running
     pub fn list_terminals(&self) -> Vec<TerminalInfo> {
         self.terminals
             .read()
             .values()
+            .filter(|h| h.exit_rx.borrow().is_none())
             .map(|h| h.info.clone())
             .collect()
     }
 }
 
+fn pty_size(cols: u16, rows: u16) -> PtySize {
+    PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Drop exited terminals the agent never released
+fn reap_exited(terminals: &mut HashMap<String, TerminalHandle>, now: Instant) {
+    terminals.retain(|_, h| h.exited_at.is_none_or(|exited_at| now.duration_since(exited_at) < EXITED_TERMINAL_TTL));
+}
+
+/// Decode PTY output as UTF-8. An incomplete multi-byte sequence at the end of
+/// `bytes` is kept in `pending` and completed by the next read instead of being
+/// replaced with U+FFFD; invalid bytes are still replaced.
+fn decode_utf8(pending: &mut Vec<u8>, bytes: &[u8]) -> String {
+    pending.extend_from_slice(bytes);
+    let complete = pending.len() - incomplete_utf8_suffix(pending);
+    let text = String::from_utf8_lossy(&pending[..complete]).into_owned();
+    pending.drain(..complete);
+    text
+}
+
+/// Length of an unfinished UTF-8 sequence at the end of `bytes` (0 if none)
+fn incomplete_utf8_suffix(bytes: &[u8]) -> usize {
+    // A sequence is at most 4 bytes, so only the last 3 can be an unfinished one
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        if byte & 0xC0 == 0x80 {
+            // Continuation byte; the lead byte is further back
+            continue;
+        }
+        let needed = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if needed > len { len } else { 0 };
+    }
+    0
+}
+
+/// Resolve a program name or path to an existing file, searching PATH for bare names
+pub(crate) fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| {
+            candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+        })
+}
+
 fn get_default_shell() -> String {
     #[cfg(target_os = "windows")]
     {
@@ -235,3 +575,108 @@ fn get_default_shell() -> String {
         std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_buffer_trims_from_front() {
+        let mut buffer = OutputBuffer::new(8);
+        buffer.push("hello ");
+        assert!(!buffer.truncated);
+        buffer.push("world");
+        assert_eq!(buffer.data, "lo world");
+        assert!(buffer.truncated);
+    }
+
+    #[test]
+    fn test_output_buffer_respects_char_boundaries() {
+        let mut buffer = OutputBuffer::new(4);
+        buffer.push("aé€");
+        assert!(buffer.data.len() <= 4);
+        assert_eq!(buffer.data, "€");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_program() {
+        assert!(find_program("sh").is_some());
+        assert!(find_program("/bin/sh").is_some());
+        assert!(find_program("definitely-not-a-shell-xyz").is_none());
+        assert!(find_program("/nonexistent/bin/sh").is_none());
+    }
+
+    #[test]
+    fn test_output_buffer_tail() {
+        let mut buffer = OutputBuffer::new(64);
+        buffer.push("hello é world");
+        assert_eq!(buffer.tail(64), ("hello é world", false));
+        assert_eq!(buffer.tail(5), ("world", true));
+        // Never splits a character
+        assert_eq!(buffer.tail(7), (" world", true));
+    }
+
+    #[test]
+    fn test_decode_utf8_across_reads() {
+        let bytes = "a😀é".as_bytes();
+        let mut pending = Vec::new();
+        // Split inside the emoji, then inside the é
+        assert_eq!(decode_utf8(&mut pending, &bytes[..3]), "a");
+        assert_eq!(pending.len(), 2);
+        assert_eq!(decode_utf8(&mut pending, &bytes[3..6]), "😀");
+        assert_eq!(decode_utf8(&mut pending, &bytes[6..]), "é");
+        assert!(pending.is_empty());
+
+        // Invalid bytes are replaced, not held back
+        assert_eq!(decode_utf8(&mut pending, b"x\xffy"), "x\u{fffd}y");
+        assert!(pending.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_terminal_limit() {
+        let (output_tx, _output_rx) = mpsc::channel(100);
+        let (exit_tx, _exit_rx) = mpsc::channel(100);
+        let manager = TerminalManager::new(output_tx, exit_tx).with_max_terminals(1);
+        let cwd = std::env::temp_dir().to_string_lossy().to_string();
+        let args = vec!["30".to_string()];
+
+        manager.create_command_terminal("sleep", &args, &[], cwd.clone(), None).unwrap();
+        let err = manager.create_command_terminal("sleep", &args, &[], cwd.clone(), None).unwrap_err();
+        assert!(err.contains("Terminal limit reached"));
+
+        // A terminal still being started holds its slot
+        assert_eq!(manager.kill_all(), 1);
+        let slot = manager.reserve_slot().unwrap();
+        assert!(manager.reserve_slot().is_err());
+        drop(slot);
+        manager.create_command_terminal("sleep", &args, &[], cwd, None).unwrap();
+        manager.kill_all();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exited_terminals_are_reaped() {
+        let (output_tx, _output_rx) = mpsc::channel(100);
+        let (exit_tx, _exit_rx) = mpsc::channel(100);
+        let manager = TerminalManager::new(output_tx, exit_tx);
+        let cwd = std::env::temp_dir().to_string_lossy().to_string();
+        let id = manager.create_command_terminal("true", &[], &[], cwd, None).unwrap();
+
+        let exited = || manager.terminals.read().get(&id).is_some_and(|h| h.exited_at.is_some());
+        for _ in 0..100 {
+            if exited() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(exited());
+
+        // Kept for the agent to read until the TTL runs out
+        reap_exited(&mut manager.terminals.write(), Instant::now());
+        assert!(manager.get_output(&id).is_ok());
+        reap_exited(&mut manager.terminals.write(), Instant::now() + EXITED_TERMINAL_TTL);
+        assert!(manager.get_output(&id).is_err());
+    }
+}
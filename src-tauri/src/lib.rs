@@ -9,6 +9,7 @@ pub mod server;
 pub mod core;
 
 use std::sync::Arc;
+#[cfg(target_os = "android")]
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 /// Check if running in headless environment (no display available)
@@ -45,48 +46,38 @@ pub fn run_headless() {
     use tokio::runtime::Runtime;
     use axum::{Router, routing::get};
 
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "aero_work=info,tower_http=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Stdout, plus a rotating log file with --log-file/AERO_LOG_FILE
+    let _log_guard = crate::core::logging::init_tracing("aero_work=info,tower_http=info");
 
     let rt = Runtime::new().expect("Failed to create tokio runtime");
     rt.block_on(async {
         // Parse ports from args or env
         let ws_port: u16 = parse_arg_or_env("--ws-port", "AERO_WS_PORT", 9527);
         let web_port: u16 = parse_arg_or_env("--web-port", "AERO_WEB_PORT", 9521);
+        let host = server::resolve_bind_host_arg();
 
         // Create app state
         let state = Arc::new(AppState::new());
 
-        // Drain notification channels (forwarded via WebSocket broadcast)
-        let notification_rx = state.notification_rx.write().take();
-        if let Some(mut rx) = notification_rx {
-            tokio::spawn(async move {
-                while rx.recv().await.is_some() {}
-            });
-        }
-
-        let permission_rx = state.permission_rx.write().take();
-        if let Some(mut rx) = permission_rx {
-            tokio::spawn(async move {
-                while rx.recv().await.is_some() {}
-            });
-        }
-
-        let terminal_rx = state.terminal_output_rx.write().take();
-        if let Some(mut rx) = terminal_rx {
-            tokio::spawn(async move {
-                while rx.recv().await.is_some() {}
-            });
-        }
+        // Optional TLS for both servers (AERO_TLS_CERT/AERO_TLS_KEY or server.tlsCert/tlsKey)
+        let tls = server::resolve_tls();
+        let web_tls = match &tls {
+            Some(paths) => match server::load_tls(paths).await {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    eprintln!("Failed to load TLS certificate/key: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+        let (http, ws) = if tls.is_some() { ("https", "wss") } else { ("http", "ws") };
 
-        // Start WebSocket server
-        let ws_server = server::WebSocketServer::new(state);
-        let actual_ws_port = match ws_server.start(ws_port).await {
+        // Start WebSocket server (it consumes the notification channels and
+        // forwards them to clients)
+        let ws_server = server::WebSocketServer::new(state.clone()).with_tls(tls).with_host(host.clone());
+        let event_tx = ws_server.event_sender();
+        let actual_ws_port = match ws_server.spawn(ws_port).await {
             Ok(port) => port,
             Err(e) => {
                 eprintln!("Failed to start WebSocket server: {}", e);
@@ -94,12 +85,16 @@ pub fn run_headless() {
             }
         };
 
-        // Start Web client server with embedded assets
+        // Start Web client server with embedded assets.
+        // Only static assets are served here; any sensitive route added later must
+        // check the same token as `/ws` (see `server::resolve_auth_token`).
+        // Responses are gzip/brotli-compressed when the browser accepts it.
         let app = Router::new()
             .route("/", get(serve_index))
-            .route("/*path", get(serve_embedded_file));
+            .route("/*path", get(serve_embedded_file))
+            .layer(tower_http::compression::CompressionLayer::new());
 
-        let (listener, actual_web_port) = match find_available_port(web_port).await {
+        let (listener, actual_web_port) = match server::find_available_port(&host, web_port).await {
             Ok(result) => result,
             Err(e) => {
                 eprintln!("Failed to bind web server: {}", e);
@@ -107,8 +102,12 @@ pub fn run_headless() {
             }
         };
 
+        let web_addr = listener
+            .local_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| format!("{}:{}", host, actual_web_port));
         tokio::spawn(async move {
-            axum::serve(listener, app).await.ok();
+            server::serve(listener, app, web_tls).await.ok();
         });
 
         // Print startup info
@@ -116,9 +115,14 @@ pub fn run_headless() {
         println!("╔════════════════════════════════════════════════════════╗");
         println!("║           Aero Work - Headless Mode                    ║");
         println!("╠════════════════════════════════════════════════════════╣");
-        println!("║  Web Client:       http://0.0.0.0:{:<5}               ║", actual_web_port);
-        println!("║  WebSocket Server: ws://0.0.0.0:{:<5}/ws              ║", actual_ws_port);
+        println!("║  Web Client:       {:<36}║", format!("{}://{}", http, web_addr));
+        println!("║  WebSocket Server: {:<36}║", format!("{}://{}:{}/ws", ws, host, actual_ws_port));
         println!("║                                                        ║");
+        if !server::is_public_host(&host) {
+            println!("║  Local only. For remote access use --host 0.0.0.0      ║");
+            println!("║  (or AERO_BIND_HOST) together with AERO_WS_TOKEN.      ║");
+            println!("║                                                        ║");
+        }
         println!("║  Open the Web Client URL in your browser to start.    ║");
         println!("║                                                        ║");
         println!("║  Press Ctrl+C to stop                                  ║");
@@ -128,43 +132,66 @@ pub fn run_headless() {
         // Keep running until interrupted
         tokio::signal::ctrl_c().await.ok();
         println!("\nShutting down...");
+        server::graceful_shutdown(&state, &event_tx).await;
     });
 }
 
 /// Serve index.html for root path
 #[cfg(all(feature = "websocket", not(target_os = "android")))]
-async fn serve_index() -> impl axum::response::IntoResponse {
-    serve_file("index.html")
+async fn serve_index(headers: axum::http::HeaderMap) -> impl axum::response::IntoResponse {
+    serve_file("index.html", &headers)
 }
 
 /// Serve embedded file or fallback to index.html for SPA routing
 #[cfg(all(feature = "websocket", not(target_os = "android")))]
 async fn serve_embedded_file(
     axum::extract::Path(path): axum::extract::Path<String>,
+    headers: axum::http::HeaderMap,
 ) -> impl axum::response::IntoResponse {
     // Try to serve the requested file
-    let response = serve_file(&path);
+    let response = serve_file(&path, &headers);
 
     // If file not found and it's not a file with extension, serve index.html (SPA fallback)
     if response.status() == axum::http::StatusCode::NOT_FOUND && !path.contains('.') {
-        return serve_file("index.html");
+        return serve_file("index.html", &headers);
     }
 
     response
 }
 
-/// Serve a file from embedded assets
+/// Serve a file from embedded assets, answering 304 when the browser's copy is current
 #[cfg(all(feature = "websocket", not(target_os = "android")))]
-fn serve_file(path: &str) -> axum::response::Response {
+fn serve_file(path: &str, headers: &axum::http::HeaderMap) -> axum::response::Response {
     use axum::http::{header, StatusCode};
     use axum::response::IntoResponse;
 
     match WebAssets::get(path) {
         Some(content) => {
+            // rust-embed hashes each file when embedding it
+            let hash = content.metadata.sha256_hash();
+            let etag = format!(
+                "\"{}\"",
+                hash[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>()
+            );
+            let cache_control = asset_cache_control(path);
+
+            let if_none_match = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok());
+            if if_none_match.is_some_and(|value| etag_matches(value, &etag)) {
+                return (
+                    StatusCode::NOT_MODIFIED,
+                    [(header::ETAG, etag.as_str()), (header::CACHE_CONTROL, cache_control)],
+                )
+                    .into_response();
+            }
+
             let mime = mime_guess::from_path(path).first_or_octet_stream();
             (
                 StatusCode::OK,
-                [(header::CONTENT_TYPE, mime.as_ref())],
+                [
+                    (header::CONTENT_TYPE, mime.as_ref()),
+                    (header::ETAG, etag.as_str()),
+                    (header::CACHE_CONTROL, cache_control),
+                ],
                 content.data.into_owned(),
             )
                 .into_response()
@@ -173,6 +200,27 @@ fn serve_file(path: &str) -> axum::response::Response {
     }
 }
 
+/// Vite puts content-hashed bundles under `assets/`, so a URL there never changes
+/// content. Everything else (`index.html`, the service worker, icons) keeps its
+/// name across builds and is revalidated with its ETag on every load.
+#[cfg(all(feature = "websocket", not(target_os = "android")))]
+fn asset_cache_control(path: &str) -> &'static str {
+    if path.starts_with("assets/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+/// Whether an `If-None-Match` header (`*` or a list of possibly weak tags) matches `etag`
+#[cfg(all(feature = "websocket", not(target_os = "android")))]
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
 /// Parse command line argument or environment variable
 #[cfg(all(feature = "websocket", not(target_os = "android")))]
 fn parse_arg_or_env(arg_name: &str, env_name: &str, default: u16) -> u16 {
@@ -184,37 +232,6 @@ fn parse_arg_or_env(arg_name: &str, env_name: &str, default: u16) -> u16 {
         .unwrap_or(default)
 }
 
-/// Find an available port, starting with preferred port and trying alternatives if occupied
-#[cfg(all(feature = "websocket", not(target_os = "android")))]
-async fn find_available_port(preferred_port: u16) -> Result<(tokio::net::TcpListener, u16), std::io::Error> {
-    use std::net::SocketAddr;
-
-    // Try the preferred port first
-    let addr = SocketAddr::from(([0, 0, 0, 0], preferred_port));
-    match tokio::net::TcpListener::bind(addr).await {
-        Ok(listener) => return Ok((listener, preferred_port)),
-        Err(e) => {
-            tracing::warn!("Port {} is occupied: {}, trying alternative ports...", preferred_port, e);
-        }
-    }
-
-    // Try a range of alternative ports
-    for port in preferred_port.saturating_add(1)..=preferred_port.saturating_add(100) {
-        let addr = SocketAddr::from(([0, 0, 0, 0], port));
-        if let Ok(listener) = tokio::net::TcpListener::bind(addr).await {
-            tracing::info!("Found available port: {}", port);
-            return Ok((listener, port));
-        }
-    }
-
-    // Let the OS choose an available port
-    let addr = SocketAddr::from(([0, 0, 0, 0], 0));
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    let actual_port = listener.local_addr()?.port();
-    tracing::info!("OS assigned port: {}", actual_port);
-    Ok((listener, actual_port))
-}
-
 /// Desktop entry point - full featured with agent, terminal, WebSocket server
 #[cfg(not(target_os = "android"))]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -222,25 +239,23 @@ pub fn run() {
     use tauri::Manager;
     use crate::commands::{
         cancel_session, connect_agent, create_directory, create_file, create_session, delete_path,
-        disconnect_agent, initialize_agent, list_directory, read_file, rename_path, respond_permission,
+        disconnect_agent, initialize_agent, list_directory, list_directory_tree, read_file, rename_path, respond_permission,
         send_prompt, set_session_mode, write_file,
-        resume_session, fork_session, list_sessions, get_session_info,
+        resume_session, fork_session, list_sessions, get_session_info, add_project_root,
         create_terminal, write_terminal, resize_terminal, kill_terminal, list_terminals,
+        copy_path, git_status, git_diff, read_file_at_revision,
+        set_notifications_enabled, TauriNotifier, open_path, TauriOpener,
     };
 
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "aero_work=debug,tauri=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Stdout, plus a rotating log file with --log-file/AERO_LOG_FILE
+    let _log_guard = crate::core::logging::init_tracing("aero_work=debug,tauri=info");
 
     let state = Arc::new(AppState::new());
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(state)
         .invoke_handler(tauri::generate_handler![
             connect_agent,
@@ -256,22 +271,38 @@ pub fn run() {
             fork_session,
             list_sessions,
             get_session_info,
+            add_project_root,
             // File operations
             list_directory,
+            list_directory_tree,
             read_file,
             write_file,
             create_file,
             create_directory,
             delete_path,
             rename_path,
+            copy_path,
+            // Git
+            git_status,
+            git_diff,
+            read_file_at_revision,
             // Terminal operations
             create_terminal,
             write_terminal,
             resize_terminal,
             kill_terminal,
             list_terminals,
+            // Notifications
+            set_notifications_enabled,
+            // Open in external app
+            open_path,
         ])
         .setup(|app| {
+            app.state::<Arc<AppState>>()
+                .set_notifier(Arc::new(TauriNotifier::new(app.handle().clone())));
+            app.state::<Arc<AppState>>()
+                .set_opener(Arc::new(TauriOpener::new(app.handle().clone())));
+
             // Start WebSocket server if enabled
             #[cfg(feature = "websocket")]
             {
@@ -0,0 +1,139 @@
+//! Coalescing of streamed message chunks before they are broadcast
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::acp::SessionId;
+use crate::core::{SequencedUpdate, SessionStateUpdate};
+
+/// Chunks of one session waiting to be sent as a single `message_chunk`
+#[derive(Debug)]
+struct PendingChunks {
+    content: String,
+    seq: u64,
+    chunks: usize,
+    due: Instant,
+}
+
+/// Buffers `message_chunk` deltas per session for a short window. The combined
+/// delta carries the seq of the last chunk it contains.
+#[derive(Debug)]
+pub struct ChunkCoalescer {
+    window: Duration,
+    pending: HashMap<SessionId, PendingChunks>,
+    /// Chunks received and frames sent, for the frame-count reduction log
+    chunks: u64,
+    frames: u64,
+}
+
+impl ChunkCoalescer {
+    pub fn new(window: Duration) -> Self {
+        Self { window, pending: HashMap::new(), chunks: 0, frames: 0 }
+    }
+
+    /// Whether coalescing is enabled (a zero window sends every chunk as is)
+    pub fn enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// Buffer a chunk; it is due one window after the first buffered chunk
+    pub fn push(&mut self, session_id: &SessionId, content: &str, seq: u64) {
+        self.push_at(session_id, content, seq, Instant::now());
+    }
+
+    fn push_at(&mut self, session_id: &SessionId, content: &str, seq: u64, now: Instant) {
+        self.chunks += 1;
+        let window = self.window;
+        let pending = self.pending.entry(session_id.clone()).or_insert_with(|| PendingChunks {
+            content: String::new(),
+            seq,
+            chunks: 0,
+            due: now + window,
+        });
+        pending.content.push_str(content);
+        pending.seq = seq;
+        pending.chunks += 1;
+    }
+
+    /// Take a session's buffered chunks as one delta
+    pub fn take(&mut self, session_id: &SessionId) -> Option<SequencedUpdate> {
+        let pending = self.pending.remove(session_id)?;
+        self.frames += 1;
+        Some(SequencedUpdate {
+            seq: pending.seq,
+            update: SessionStateUpdate::MessageChunk { content: pending.content },
+        })
+    }
+
+    /// Take every session whose window has elapsed
+    pub fn take_due(&mut self, now: Instant) -> Vec<(SessionId, SequencedUpdate)> {
+        let due: Vec<SessionId> = self.pending
+            .iter()
+            .filter(|(_, pending)| pending.due <= now)
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|session_id| self.take(&session_id).map(|update| (session_id, update)))
+            .collect()
+    }
+
+    /// When the earliest buffered chunks are due
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|pending| pending.due).min()
+    }
+
+    /// Chunks received and frames sent since the last call
+    pub fn take_stats(&mut self) -> (u64, u64) {
+        let stats = (self.chunks, self.frames);
+        self.chunks = 0;
+        self.frames = 0;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesces_chunks_per_session() {
+        let start = Instant::now();
+        let mut coalescer = ChunkCoalescer::new(Duration::from_millis(40));
+        let (a, b) = ("a".to_string(), "b".to_string());
+        coalescer.push_at(&a, "Hel", 1, start);
+        coalescer.push_at(&b, "Other", 2, start + Duration::from_millis(20));
+        coalescer.push_at(&a, "lo", 3, start + Duration::from_millis(30));
+        assert_eq!(coalescer.next_deadline(), Some(start + Duration::from_millis(40)));
+
+        // Only the first session's window has elapsed
+        let due = coalescer.take_due(start + Duration::from_millis(45));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, "a");
+        assert_eq!(due[0].1.seq, 3);
+        match &due[0].1.update {
+            SessionStateUpdate::MessageChunk { content } => assert_eq!(content, "Hello"),
+            other => panic!("Expected MessageChunk, got {:?}", other),
+        }
+
+        assert!(coalescer.take(&b).is_some());
+        assert!(coalescer.take(&b).is_none());
+        assert_eq!(coalescer.next_deadline(), None);
+        assert_eq!(coalescer.take_stats(), (3, 2));
+    }
+
+    #[test]
+    fn test_frame_reduction() {
+        // 2000 chunks at 200 tokens/s, flushed the way the forwarding loop does
+        let start = Instant::now();
+        let mut coalescer = ChunkCoalescer::new(Duration::from_millis(40));
+        let session = "s".to_string();
+        let mut frames = 0;
+        for i in 0..2000u64 {
+            let now = start + Duration::from_millis(i * 5);
+            frames += coalescer.take_due(now).len();
+            coalescer.push_at(&session, "x", i + 1, now);
+        }
+        frames += usize::from(coalescer.take(&session).is_some());
+        assert_eq!(frames, 250);
+    }
+}
@@ -0,0 +1,180 @@
+//! Resources attached to a prompt as embedded context
+
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::Deserialize;
+
+use super::error::RpcError;
+use crate::acp::{ContentBlock, ResourceContents};
+
+/// Largest single attached resource, before base64
+pub const MAX_CONTEXT_RESOURCE_BYTES: u64 = 1024 * 1024;
+/// Largest total of attached resources in one prompt
+pub const MAX_CONTEXT_TOTAL_BYTES: u64 = 8 * 1024 * 1024;
+
+/// A resource a client attaches to a prompt. Either `path` (a file the server
+/// reads), `uri` + `text`, `uri` + `blob` (base64), or a bare `uri`, which is
+/// sent as a link for the agent to fetch itself.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextResource {
+    pub path: Option<String>,
+    pub uri: Option<String>,
+    pub text: Option<String>,
+    pub blob: Option<String>,
+    pub mime_type: Option<String>,
+    /// Display name for links; defaults to the last path segment of the uri
+    pub name: Option<String>,
+}
+
+impl ContextResource {
+    /// Build the content block for this resource. `check_path` resolves `path`
+    /// inside the sandbox. Returns the block and the bytes it embeds.
+    pub fn into_block(
+        self,
+        check_path: impl Fn(&str) -> Result<PathBuf, RpcError>,
+    ) -> Result<(ContentBlock, u64), RpcError> {
+        if let Some(path) = self.path {
+            let resolved = check_path(&path)?;
+            let size = std::fs::metadata(&resolved)
+                .map_err(|e| RpcError::InvalidParams(format!("Cannot read {}: {}", path, e)))?
+                .len();
+            check_size(&path, size)?;
+            let bytes = std::fs::read(&resolved).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let uri = self.uri.unwrap_or_else(|| format!("file://{}", resolved.to_string_lossy()));
+            let resource = match String::from_utf8(bytes) {
+                Ok(text) => ResourceContents::Text { uri, text, mime_type: self.mime_type },
+                Err(e) => ResourceContents::Blob {
+                    uri,
+                    blob: BASE64.encode(e.as_bytes()),
+                    mime_type: self.mime_type.or_else(|| Some("application/octet-stream".to_string())),
+                },
+            };
+            return Ok((ContentBlock::Resource { resource }, size));
+        }
+
+        let uri = self.uri.ok_or("Context resource needs a path or uri")?;
+        match (self.text, self.blob) {
+            (Some(_), Some(_)) => Err(RpcError::InvalidParams(format!("{}: pass text or blob, not both", uri))),
+            (Some(text), None) => {
+                let size = text.len() as u64;
+                check_size(&uri, size)?;
+                let resource = ResourceContents::Text { uri, text, mime_type: self.mime_type };
+                Ok((ContentBlock::Resource { resource }, size))
+            }
+            (None, Some(blob)) => {
+                // Estimate the decoded size first so oversized blobs are never decoded
+                check_size(&uri, blob.len() as u64 / 4 * 3)?;
+                let size = BASE64
+                    .decode(&blob)
+                    .map_err(|e| RpcError::InvalidParams(format!("{}: invalid base64: {}", uri, e)))?
+                    .len() as u64;
+                let resource = ResourceContents::Blob { uri, blob, mime_type: self.mime_type };
+                Ok((ContentBlock::Resource { resource }, size))
+            }
+            (None, None) => {
+                let name = self.name.unwrap_or_else(|| {
+                    uri.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_string()
+                });
+                Ok((ContentBlock::ResourceLink { uri, name }, 0))
+            }
+        }
+    }
+}
+
+fn check_size(name: &str, size: u64) -> Result<(), RpcError> {
+    if size > MAX_CONTEXT_RESOURCE_BYTES {
+        return Err(RpcError::InvalidParams(format!(
+            "{} is too large to attach ({} bytes, limit {})",
+            name, size, MAX_CONTEXT_RESOURCE_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Build content blocks for all attached resources, enforcing the total size cap
+pub fn build_context_blocks(
+    resources: Vec<ContextResource>,
+    check_path: impl Fn(&str) -> Result<PathBuf, RpcError>,
+) -> Result<Vec<ContentBlock>, RpcError> {
+    let mut total = 0;
+    let mut blocks = Vec::with_capacity(resources.len());
+    for resource in resources {
+        let (block, size) = resource.into_block(&check_path)?;
+        total += size;
+        if total > MAX_CONTEXT_TOTAL_BYTES {
+            return Err(RpcError::InvalidParams(format!(
+                "Attached resources are too large (limit {} bytes in total)",
+                MAX_CONTEXT_TOTAL_BYTES
+            )));
+        }
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::error::PATH_NOT_ALLOWED_CODE;
+
+    #[test]
+    fn test_build_context_blocks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.join("logo.png"), [0x89, 0x50, 0xff, 0x00]).unwrap();
+        let allowed = dir.to_path_buf();
+        let check_path = move |path: &str| {
+            let path = PathBuf::from(path);
+            if path.starts_with(&allowed) {
+                Ok(path)
+            } else {
+                Err(RpcError::PathNotAllowed(format!("Path not allowed: {:?}", path)))
+            }
+        };
+
+        let resources = vec![
+            ContextResource { path: Some(dir.join("main.rs").to_string_lossy().to_string()), ..Default::default() },
+            ContextResource { path: Some(dir.join("logo.png").to_string_lossy().to_string()), ..Default::default() },
+            ContextResource { uri: Some("note://todo".to_string()), text: Some("ship it".to_string()), ..Default::default() },
+            ContextResource { uri: Some("https://example.com/docs/api".to_string()), ..Default::default() },
+        ];
+        let blocks = build_context_blocks(resources, &check_path).unwrap();
+        assert_eq!(blocks.len(), 4);
+        match &blocks[0] {
+            ContentBlock::Resource { resource: ResourceContents::Text { uri, text, .. } } => {
+                assert!(uri.starts_with("file://") && uri.ends_with("main.rs"));
+                assert_eq!(text, "fn main() {}");
+            }
+            other => panic!("Expected text resource, got {:?}", other),
+        }
+        match &blocks[1] {
+            ContentBlock::Resource { resource: ResourceContents::Blob { blob, .. } } => {
+                assert_eq!(BASE64.decode(blob).unwrap(), vec![0x89, 0x50, 0xff, 0x00]);
+            }
+            other => panic!("Expected blob resource, got {:?}", other),
+        }
+        match &blocks[3] {
+            ContentBlock::ResourceLink { name, .. } => assert_eq!(name, "api"),
+            other => panic!("Expected resource link, got {:?}", other),
+        }
+
+        // Sandbox, size and encoding checks
+        let outside = ContextResource { path: Some("/etc/hosts".to_string()), ..Default::default() };
+        assert_eq!(outside.into_block(&check_path).unwrap_err().code(), PATH_NOT_ALLOWED_CODE);
+        let large = ContextResource {
+            uri: Some("note://large".to_string()),
+            text: Some("x".repeat(MAX_CONTEXT_RESOURCE_BYTES as usize + 1)),
+            ..Default::default()
+        };
+        assert!(large.into_block(&check_path).is_err());
+        let invalid = ContextResource {
+            uri: Some("note://blob".to_string()),
+            blob: Some("not base64!".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.into_block(&check_path).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Errors returned by WebSocket and REST methods
+
+use crate::acp::AcpError;
+
+/// JSON-RPC error codes. The negative ones are from the JSON-RPC spec (plus
+/// -32001 for rate limiting); the 1xxx codes are ours and stay stable.
+pub const PARSE_ERROR_CODE: i32 = -32700;
+pub const METHOD_NOT_FOUND_CODE: i32 = -32601;
+pub const INVALID_PARAMS_CODE: i32 = -32602;
+pub const INTERNAL_ERROR_CODE: i32 = -32603;
+/// Rejected by the per-connection request limits
+pub const TOO_MANY_REQUESTS_CODE: i32 = -32001;
+pub const SESSION_NOT_FOUND_CODE: i32 = 1001;
+pub const AGENT_DISCONNECTED_CODE: i32 = 1002;
+pub const PATH_NOT_ALLOWED_CODE: i32 = 1003;
+pub const AUTH_REQUIRED_CODE: i32 = 1004;
+pub const SUPERSEDED_CODE: i32 = 1005;
+pub const SESSION_BUSY_CODE: i32 = 1006;
+pub const READ_ONLY_CODE: i32 = 1007;
+
+/// A method failure with a code clients can act on
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    /// A parameter is missing or malformed
+    InvalidParams(String),
+    /// No method with this name
+    MethodNotFound(String),
+    /// The session doesn't exist or isn't loaded
+    SessionNotFound(String),
+    /// No agent is running, or it went away during the request
+    AgentDisconnected(String),
+    /// The path is outside the allowed project directories
+    PathNotAllowed(String),
+    /// The agent needs the user to authenticate; `data` lists the auth methods
+    AuthRequired { message: String, auth_methods: serde_json::Value },
+    /// A newer request from the same client made this one obsolete
+    Superseded(String),
+    /// The session is running another prompt
+    SessionBusy(String),
+    /// The server is in read-only mode and the method would change something
+    ReadOnly(String),
+    /// Anything else
+    Internal(String),
+}
+
+impl RpcError {
+    pub fn session_not_found(session_id: &str) -> Self {
+        Self::SessionNotFound(format!("Session not found: {}", session_id))
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::InvalidParams(_) => INVALID_PARAMS_CODE,
+            Self::MethodNotFound(_) => METHOD_NOT_FOUND_CODE,
+            Self::SessionNotFound(_) => SESSION_NOT_FOUND_CODE,
+            Self::AgentDisconnected(_) => AGENT_DISCONNECTED_CODE,
+            Self::PathNotAllowed(_) => PATH_NOT_ALLOWED_CODE,
+            Self::AuthRequired { .. } => AUTH_REQUIRED_CODE,
+            Self::Superseded(_) => SUPERSEDED_CODE,
+            Self::SessionBusy(_) => SESSION_BUSY_CODE,
+            Self::ReadOnly(_) => READ_ONLY_CODE,
+            Self::Internal(_) => INTERNAL_ERROR_CODE,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::InvalidParams(message)
+            | Self::MethodNotFound(message)
+            | Self::SessionNotFound(message)
+            | Self::AgentDisconnected(message)
+            | Self::PathNotAllowed(message)
+            | Self::AuthRequired { message, .. }
+            | Self::Superseded(message)
+            | Self::SessionBusy(message)
+            | Self::ReadOnly(message)
+            | Self::Internal(message) => message,
+        }
+    }
+
+    /// Extra detail for the JSON-RPC `data` field
+    pub fn data(&self) -> Option<serde_json::Value> {
+        match self {
+            Self::AuthRequired { auth_methods, .. } => Some(serde_json::json!({ "authMethods": auth_methods })),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// Handlers that haven't been given a specific error report it as internal
+impl From<String> for RpcError {
+    fn from(message: String) -> Self {
+        Self::Internal(message)
+    }
+}
+
+/// String literals in method dispatch are parameter checks
+/// (`.ok_or("Missing sessionId parameter")?`)
+impl From<&str> for RpcError {
+    fn from(message: &str) -> Self {
+        Self::InvalidParams(message.to_string())
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Internal(error.to_string())
+    }
+}
+
+impl From<AcpError> for RpcError {
+    fn from(error: AcpError) -> Self {
+        Self::from(&error)
+    }
+}
+
+impl From<&AcpError> for RpcError {
+    fn from(error: &AcpError) -> Self {
+        match error {
+            AcpError::NotConnected | AcpError::ChannelClosed | AcpError::ProcessExited(_) => {
+                Self::AgentDisconnected(error.to_string())
+            }
+            _ => Self::Internal(error.to_string()),
+        }
+    }
+}
+
+/// For helpers shared with code that still reports plain strings
+impl From<RpcError> for String {
+    fn from(error: RpcError) -> Self {
+        error.message().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(RpcError::session_not_found("s1").code(), SESSION_NOT_FOUND_CODE);
+        assert_eq!(RpcError::from("Missing path parameter").code(), INVALID_PARAMS_CODE);
+        assert_eq!(RpcError::from("boom".to_string()).code(), INTERNAL_ERROR_CODE);
+        assert_eq!(RpcError::from(AcpError::NotConnected).code(), AGENT_DISCONNECTED_CODE);
+        assert_eq!(RpcError::from(AcpError::Timeout).code(), INTERNAL_ERROR_CODE);
+        assert_eq!(RpcError::Superseded("stale".to_string()).code(), SUPERSEDED_CODE);
+        assert_eq!(RpcError::SessionBusy("busy".to_string()).code(), SESSION_BUSY_CODE);
+        assert_eq!(RpcError::ReadOnly("read-only".to_string()).code(), READ_ONLY_CODE);
+
+        let auth = RpcError::AuthRequired {
+            message: "Authentication required".to_string(),
+            auth_methods: serde_json::json!([{ "id": "login" }]),
+        };
+        assert_eq!(auth.data().unwrap()["authMethods"][0]["id"], "login");
+        assert_eq!(String::from(auth), "Authentication required");
+    }
+}
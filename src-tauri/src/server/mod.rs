@@ -1,4 +1,14 @@
 #[cfg(feature = "websocket")]
+mod coalesce;
+#[cfg(feature = "websocket")]
+mod context;
+#[cfg(feature = "websocket")]
+mod error;
+#[cfg(feature = "websocket")]
+mod rate_limit;
+#[cfg(feature = "websocket")]
+mod recent_projects;
+#[cfg(feature = "websocket")]
 mod websocket;
 
 #[cfg(feature = "websocket")]
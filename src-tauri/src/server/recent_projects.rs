@@ -0,0 +1,220 @@
+//! Recent projects persistence (`recent-projects.json` in the config dir)
+//!
+//! Pinned projects stay at the top and are never pruned. Unpinned projects are
+//! ordered by `lastOpened`, capped at `MAX_RECENT_PROJECTS`, and dropped once
+//! their directory no longer exists.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+pub const MAX_RECENT_PROJECTS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentProject {
+    pub path: String,
+    pub name: String,
+    pub last_opened: u64,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Whether the directory exists; recomputed on every listing
+    #[serde(default = "default_exists", skip_deserializing)]
+    pub exists: bool,
+}
+
+fn default_exists() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecentProjectsConfig {
+    projects: Vec<RecentProject>,
+}
+
+/// Get the config directory path (`config_dir()`, created if missing)
+fn get_config_dir() -> Result<PathBuf, String> {
+    let config_dir = crate::core::config_dir();
+
+    // Create directory if it doesn't exist
+    if !config_dir.exists() {
+        std::fs::create_dir_all(&config_dir)
+            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+
+    Ok(config_dir)
+}
+
+/// Get the recent projects config file path
+fn get_recent_projects_path() -> Result<PathBuf, String> {
+    Ok(get_config_dir()?.join("recent-projects.json"))
+}
+
+/// Load recent projects from config file, as stored
+pub fn load_recent_projects() -> Result<Vec<RecentProject>, String> {
+    let config_path = get_recent_projects_path()?;
+
+    if !config_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(&config_path)
+        .map_err(|e| format!("Failed to read recent projects: {}", e))?;
+
+    let config: RecentProjectsConfig = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse recent projects: {}", e))?;
+
+    Ok(config.projects)
+}
+
+/// Save recent projects to config file
+fn save_recent_projects(projects: &[RecentProject]) -> Result<(), String> {
+    let config_path = get_recent_projects_path()?;
+    let config = RecentProjectsConfig {
+        projects: projects.to_vec(),
+    };
+
+    let content = serde_json::to_string_pretty(&config)
+        .map_err(|e| format!("Failed to serialize recent projects: {}", e))?;
+
+    std::fs::write(&config_path, content)
+        .map_err(|e| format!("Failed to write recent projects: {}", e))?;
+
+    Ok(())
+}
+
+/// Sort pinned projects first, then by last opened, and apply the cap.
+/// Unpinned projects whose directory is gone are dropped; pinned ones are
+/// kept with `exists: false` (e.g. on an unmounted drive).
+fn organize(mut projects: Vec<RecentProject>, exists: impl Fn(&Path) -> bool) -> Vec<RecentProject> {
+    for project in &mut projects {
+        project.exists = exists(Path::new(&project.path));
+    }
+    projects.retain(|p| p.pinned || p.exists);
+    projects.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.last_opened.cmp(&a.last_opened)));
+
+    let mut unpinned = 0;
+    projects.retain(|p| {
+        if p.pinned {
+            return true;
+        }
+        unpinned += 1;
+        unpinned <= MAX_RECENT_PROJECTS
+    });
+    projects
+}
+
+/// Recent projects for the client, pruning missing directories from the file
+pub fn list_recent_projects() -> Result<Vec<RecentProject>, String> {
+    let stored = load_recent_projects()?;
+    let count = stored.len();
+    let projects = organize(stored, Path::is_dir);
+    if projects.len() != count {
+        save_recent_projects(&projects)?;
+    }
+    Ok(projects)
+}
+
+/// Add a project to recent projects list, keeping its pin
+pub fn add_recent_project(path: &str, name: Option<&str>) -> Result<(), String> {
+    let mut projects = load_recent_projects().unwrap_or_default();
+
+    // Remove existing entry with same path
+    let pinned = projects.iter().any(|p| p.path == path && p.pinned);
+    projects.retain(|p| p.path != path);
+
+    // Create new entry
+    let project_name = name
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| {
+            Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string())
+        });
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    projects.push(RecentProject {
+        path: path.to_string(),
+        name: project_name,
+        last_opened: now,
+        pinned,
+        exists: true,
+    });
+
+    save_recent_projects(&organize(projects, |_| true))
+}
+
+/// Pin or unpin a project, adding it if it isn't in the list yet
+pub fn set_recent_project_pinned(path: &str, pinned: bool) -> Result<(), String> {
+    let mut projects = load_recent_projects().unwrap_or_default();
+    match projects.iter_mut().find(|p| p.path == path) {
+        Some(project) => project.pinned = pinned,
+        None if pinned => {
+            add_recent_project(path, None)?;
+            return set_recent_project_pinned(path, true);
+        }
+        None => return Ok(()),
+    }
+    save_recent_projects(&organize(projects, |_| true))
+}
+
+/// Remove a project from recent projects list
+pub fn remove_recent_project(path: &str) -> Result<(), String> {
+    let mut projects = load_recent_projects().unwrap_or_default();
+    projects.retain(|p| p.path != path);
+    save_recent_projects(&projects)
+}
+
+/// Clear all recent projects
+pub fn clear_recent_projects() -> Result<(), String> {
+    save_recent_projects(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(path: &str, last_opened: u64, pinned: bool) -> RecentProject {
+        RecentProject { path: path.to_string(), name: path.to_string(), last_opened, pinned, exists: true }
+    }
+
+    #[test]
+    fn test_organize_recent_projects() {
+        let projects = vec![
+            project("/a", 1, false),
+            project("/gone", 5, false),
+            project("/pinned-old", 0, true),
+            project("/b", 3, false),
+            project("/pinned-gone", 2, true),
+        ];
+        let organized = organize(projects, |path| !path.to_string_lossy().contains("gone"));
+        let paths: Vec<_> = organized.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["/pinned-gone", "/pinned-old", "/b", "/a"]);
+        assert!(!organized[0].exists);
+        assert!(organized[1].exists);
+
+        // The cap only counts unpinned projects
+        let mut many: Vec<_> = (0..MAX_RECENT_PROJECTS as u64 + 5).map(|i| project(&format!("/p{}", i), i, false)).collect();
+        many.push(project("/fav", 0, true));
+        let organized = organize(many, |_| true);
+        assert_eq!(organized.len(), MAX_RECENT_PROJECTS + 1);
+        assert_eq!(organized[0].path, "/fav");
+        assert_eq!(organized[1].path, format!("/p{}", MAX_RECENT_PROJECTS + 4));
+    }
+
+    #[test]
+    fn test_recent_project_serde() {
+        // Files written before pinning existed have no `pinned` field
+        let stored: RecentProject = serde_json::from_str(r#"{"path":"/a","name":"a","lastOpened":1}"#).unwrap();
+        assert!(!stored.pinned);
+        assert!(stored.exists);
+        let value = serde_json::to_value(&stored).unwrap();
+        assert_eq!(value["lastOpened"], 1);
+        assert_eq!(value["exists"], true);
+    }
+}
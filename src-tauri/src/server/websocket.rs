@@ -1,21 +1,35 @@
 use std::sync::Arc;
+use std::collections::HashMap;
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
     },
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{broadcast, mpsc};
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::acp::{AcpError, InitializeResponse, NewSessionResponse, PermissionOutcome, PromptResponse, SessionId};
-use crate::core::{AgentManager, AppState, ClientId, SessionState};
+use crate::acp::{AcpError, AgentEvent, ContentBlock, InitializeResponse, ResourceContents, NewSessionResponse, PermissionOption, PermissionOptionKind, PermissionOutcome, PermissionRequest, PromptResponse, SessionId};
+use crate::core::{AgentCommand, AgentKey, AgentManager, AppState, ChatItem, ClientId, MessageRole, ModeChange, PermissionAction, PermissionPolicy, SequencedUpdate, SessionState, SessionStateUpdate, TokenUsage};
+use crate::core::file_watcher::FileWatch;
+use crate::core::session_state::permission_signature;
+use crate::core::session_registry::resolve_session_cwd;
+
+use super::coalesce::ChunkCoalescer;
+use super::context::{build_context_blocks, ContextResource};
+use super::error::{RpcError, PARSE_ERROR_CODE, TOO_MANY_REQUESTS_CODE};
+use super::rate_limit::TokenBucket;
+use super::recent_projects::{
+    add_recent_project, clear_recent_projects, list_recent_projects, remove_recent_project,
+    set_recent_project_pinned,
+};
 
 #[derive(Debug, Deserialize)]
 struct JsonRpcRequest {
@@ -47,6 +61,8 @@ struct JsonRpcNotification {
 struct JsonRpcError {
     code: i32,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
 }
 
 impl JsonRpcResponse {
@@ -63,112 +79,395 @@ impl JsonRpcResponse {
         Self {
             jsonrpc: "2.0".to_string(),
             result: None,
-            error: Some(JsonRpcError { code, message }),
+            error: Some(JsonRpcError { code, message, data: None }),
+            id,
+        }
+    }
+
+    fn from_error(id: serde_json::Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(JsonRpcError { code: error.code(), data: error.data(), message: error.message().to_string() }),
             id,
         }
     }
 }
 
+/// Environment variable overriding `server.bindHost`
+pub const BIND_HOST_ENV: &str = "AERO_BIND_HOST";
+
+/// Resolve the address to bind to from AERO_BIND_HOST, falling back to
+/// `server.bindHost` in config (127.0.0.1 unless set, so local-only)
+pub fn resolve_bind_host() -> String {
+    std::env::var(BIND_HOST_ENV)
+        .ok()
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| crate::core::ConfigManager::new().config().server.bind_host())
+}
+
+/// Like `resolve_bind_host`, with a `--host` command-line argument taking precedence
+pub fn resolve_bind_host_arg() -> String {
+    std::env::args()
+        .skip_while(|arg| arg != "--host")
+        .nth(1)
+        .unwrap_or_else(resolve_bind_host)
+}
+
+/// Whether `host` accepts connections from other machines
+pub fn is_public_host(host: &str) -> bool {
+    match host.parse::<std::net::IpAddr>() {
+        Ok(ip) => !ip.is_loopback(),
+        Err(_) => host != "localhost",
+    }
+}
+
+/// Environment variable holding the WebSocket auth token
+pub const AUTH_TOKEN_ENV: &str = "AERO_WS_TOKEN";
+
+/// Resolve the shared auth token from AERO_WS_TOKEN, falling back to `server.authToken` in config.
+///
+/// When set, the token gates `/ws` and must also gate any sensitive routes
+/// added to the HTTP asset server. `/health` stays unauthenticated.
+pub fn resolve_auth_token() -> Option<String> {
+    std::env::var(AUTH_TOKEN_ENV)
+        .ok()
+        .or_else(|| crate::core::ConfigManager::new().config().server.auth_token.clone())
+        .filter(|token| !token.is_empty())
+}
+
+/// Environment variables holding the PEM certificate and key for TLS
+pub const TLS_CERT_ENV: &str = "AERO_TLS_CERT";
+pub const TLS_KEY_ENV: &str = "AERO_TLS_KEY";
+
+/// Certificate and key files for serving HTTPS/WSS
+#[derive(Debug, Clone)]
+pub struct TlsPaths {
+    pub cert: std::path::PathBuf,
+    pub key: std::path::PathBuf,
+}
+
+/// Resolve TLS files from AERO_TLS_CERT/AERO_TLS_KEY, falling back to
+/// `server.tlsCert`/`server.tlsKey` in config. None (plain HTTP/WS) unless both are set.
+pub fn resolve_tls() -> Option<TlsPaths> {
+    let server = crate::core::ConfigManager::new().config().server.clone();
+    let cert = std::env::var(TLS_CERT_ENV).ok().or(server.tls_cert).filter(|p| !p.is_empty())?;
+    let key = std::env::var(TLS_KEY_ENV).ok().or(server.tls_key).filter(|p| !p.is_empty())?;
+    Some(TlsPaths { cert: cert.into(), key: key.into() })
+}
+
+/// Environment variable overriding `server.readOnly`
+pub const READ_ONLY_ENV: &str = "AERO_READ_ONLY";
+
+/// Whether the server rejects mutating methods, from AERO_READ_ONLY ("1"/"true"),
+/// falling back to `server.readOnly` in config
+pub fn resolve_read_only() -> bool {
+    match std::env::var(READ_ONLY_ENV) {
+        Ok(value) if !value.is_empty() => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"),
+        _ => crate::core::ConfigManager::new().config().server.read_only,
+    }
+}
+
+/// Load a certificate and key for `serve`, so bad files are reported before serving starts
+pub async fn load_tls(tls: &TlsPaths) -> std::io::Result<axum_server::tls_rustls::RustlsConfig> {
+    // Use ring like reqwest does; fails harmlessly if a provider is already installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert, &tls.key).await
+}
+
+/// Serve `app` on `listener`, over TLS when a config is given
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: Option<axum_server::tls_rustls::RustlsConfig>,
+) -> std::io::Result<()> {
+    match tls {
+        None => axum::serve(listener, app).await,
+        Some(config) => {
+            axum_server::from_tcp_rustls(listener.into_std()?, config)
+                .serve(app.into_make_service())
+                .await
+        }
+    }
+}
+
+/// Bind `host`, starting with the preferred port and trying alternatives if occupied
+/// (+1 to +100, then OS-assigned). Used by the WebSocket and web client servers.
+pub async fn find_available_port(host: &str, preferred_port: u16) -> std::io::Result<(tokio::net::TcpListener, u16)> {
+    // Try the preferred port first
+    match tokio::net::TcpListener::bind((host, preferred_port)).await {
+        Ok(listener) => return Ok((listener, preferred_port)),
+        Err(e) => {
+            warn!("Port {} is occupied: {}, trying alternative ports...", preferred_port, e);
+        }
+    }
+
+    // Try a range of alternative ports
+    for port in preferred_port.saturating_add(1)..=preferred_port.saturating_add(100) {
+        if let Ok(listener) = tokio::net::TcpListener::bind((host, port)).await {
+            info!("Found available port: {}", port);
+            return Ok((listener, port));
+        }
+    }
+
+    // Let the OS choose an available port
+    let listener = tokio::net::TcpListener::bind((host, 0)).await?;
+    let actual_port = listener.local_addr()?.port();
+    info!("OS assigned port: {}", actual_port);
+    Ok((listener, actual_port))
+}
+
+/// How long the shutdown sequence may take before the process exits anyway
+pub const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Tell connected clients the server is going away, then kill terminals,
+/// stop agents and flush session state. Gives up after `SHUTDOWN_TIMEOUT`
+/// so a hung agent cannot keep the process alive.
+pub async fn graceful_shutdown(state: &Arc<AppState>, event_tx: &broadcast::Sender<String>) {
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "server/shutdown".to_string(),
+        params: serde_json::json!({ "reason": "Server is shutting down" }),
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
+    }
+
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, state.shutdown()).await.is_err() {
+        warn!("Shutdown did not finish within {}s, exiting anyway", SHUTDOWN_TIMEOUT.as_secs());
+    }
+
+    // Give the socket tasks a moment to deliver server/shutdown
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+}
+
 pub struct WebSocketServer {
     state: Arc<AppState>,
     event_tx: broadcast::Sender<String>,
+    auth_token: Option<String>,
+    tls: Option<TlsPaths>,
+    host: String,
 }
 
 impl WebSocketServer {
     pub fn new(state: Arc<AppState>) -> Self {
         let (event_tx, _) = broadcast::channel(1000);
-        Self { state, event_tx }
+        Self { state, event_tx, auth_token: resolve_auth_token(), tls: None, host: resolve_bind_host() }
+    }
+
+    /// Override the bind host (defaults to `resolve_bind_host`)
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Override the auth token (None disables authentication)
+    pub fn with_auth_token(mut self, token: Option<String>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// Serve over WSS with the given certificate (None keeps plain WS).
+    /// The desktop app's own webview connects over plain ws://, so only
+    /// headless servers use this.
+    pub fn with_tls(mut self, tls: Option<TlsPaths>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Sender for server-wide notifications, still usable after the server is started
+    pub fn event_sender(&self) -> broadcast::Sender<String> {
+        self.event_tx.clone()
     }
 
     /// Start the WebSocket server, automatically finding an available port if the preferred port is occupied.
     /// Returns the actual port that was bound.
     pub async fn start(self, preferred_port: u16) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
+        let (listener, actual_port, app, tls) = self.bind(preferred_port).await?;
+
+        // Blocks until server shuts down
+        serve(listener, app, tls).await?;
+
+        Ok(actual_port)
+    }
+
+    /// Like `start`, but serves in a background task and returns once the port is bound
+    pub async fn spawn(self, preferred_port: u16) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
+        let (listener, actual_port, app, tls) = self.bind(preferred_port).await?;
+
+        tokio::spawn(async move {
+            if let Err(e) = serve(listener, app, tls).await {
+                error!("WebSocket server error: {}", e);
+            }
+        });
+
+        Ok(actual_port)
+    }
+
+    async fn bind(
+        self,
+        preferred_port: u16,
+    ) -> Result<
+        (tokio::net::TcpListener, u16, Router, Option<axum_server::tls_rustls::RustlsConfig>),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let tls = match &self.tls {
+            Some(paths) => Some(load_tls(paths).await.map_err(|e| {
+                format!("Failed to load TLS certificate {} / key {}: {}", paths.cert.display(), paths.key.display(), e)
+            })?),
+            None => None,
+        };
+
+        let server_config = crate::core::ConfigManager::new().config().server.clone();
         let server_state = Arc::new(ServerState {
             app_state: self.state.clone(),
             event_tx: self.event_tx.clone(),
+            auth_token: self.auth_token.clone(),
+            clients: std::sync::RwLock::new(HashMap::new()),
+            max_concurrent_requests: server_config.max_concurrent_requests,
+            requests_per_second: server_config.requests_per_second,
+            request_burst: server_config.request_burst,
+            ping_interval: (server_config.ping_interval_secs > 0)
+                .then(|| std::time::Duration::from_secs(server_config.ping_interval_secs)),
+            max_missed_pongs: server_config.max_missed_pongs.max(1),
+            read_only: resolve_read_only(),
         });
 
+        if server_state.read_only {
+            info!("WebSocket: read-only mode, only read methods are allowed");
+        }
+
+        if self.auth_token.is_some() {
+            info!("WebSocket: connections require an auth token");
+        } else {
+            warn!("WebSocket: no auth token configured, accepting all connections (set {})", AUTH_TOKEN_ENV);
+        }
+
         // Start event forwarding from AppState channels
         Self::start_event_forwarding(self.state.clone(), self.event_tx.clone()).await;
+        Self::start_terminal_output_forwarding(server_state.clone());
 
         let app = Router::new()
             .route("/ws", get(ws_handler))
             .route("/health", get(health_handler))
+            .route("/api/:method", get(api_handler).post(api_handler))
             .with_state(server_state);
 
         // Try to bind to the preferred port first, then try alternative ports if occupied
-        let (listener, actual_port) = Self::find_available_port(preferred_port).await?;
+        let (listener, actual_port) = find_available_port(&self.host, preferred_port).await?;
 
-        info!("WebSocket server listening on 0.0.0.0:{}", actual_port);
+        info!("WebSocket server listening on {}{}", listener.local_addr()?, if tls.is_some() { " (TLS)" } else { "" });
+        if is_public_host(&self.host) && self.auth_token.is_none() {
+            warn!("WebSocket: bound to {} without an auth token, anyone on the network can connect", self.host);
+        }
 
         // Store the actual port in AppState BEFORE starting the server
-        // (axum::serve blocks until server shuts down)
         self.state.set_ws_port(actual_port);
+        *self.state.ws_host.write() = self.host.clone();
+        self.state.ws_tls.store(tls.is_some(), std::sync::atomic::Ordering::SeqCst);
 
-        axum::serve(listener, app).await?;
-
-        Ok(actual_port)
+        Ok((listener, actual_port, app, tls))
     }
 
-    /// Find an available port, starting with the preferred port and trying alternatives if occupied
-    async fn find_available_port(preferred_port: u16) -> Result<(tokio::net::TcpListener, u16), Box<dyn std::error::Error + Send + Sync>> {
-        // Try the preferred port first
-        let addr = format!("0.0.0.0:{}", preferred_port);
-        match tokio::net::TcpListener::bind(&addr).await {
-            Ok(listener) => {
-                return Ok((listener, preferred_port));
-            }
-            Err(e) => {
-                warn!("Port {} is occupied: {}, trying alternative ports...", preferred_port, e);
-            }
-        }
+    /// Forward terminal output. The base64 copy of the bytes is only built while
+    /// some client reads the terminal with `encoding: "base64"`.
+    fn start_terminal_output_forwarding(server_state: Arc<ServerState>) {
+        use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 
-        // Try a range of alternative ports
-        let port_range = preferred_port.saturating_add(1)..=preferred_port.saturating_add(100);
-        for port in port_range {
-            let addr = format!("0.0.0.0:{}", port);
-            match tokio::net::TcpListener::bind(&addr).await {
-                Ok(listener) => {
-                    info!("Found available port: {}", port);
-                    return Ok((listener, port));
+        let Some(mut rx) = server_state.app_state.terminal_output_rx.write().take() else {
+            return;
+        };
+        tokio::spawn(async move {
+            while let Some(output) = rx.recv().await {
+                let raw = server_state.clients.read().unwrap().values()
+                    .any(|client| client.reads_terminal_raw(&output.terminal_id));
+                let mut params = serde_json::json!({ "terminalId": output.terminal_id, "data": output.data });
+                if raw {
+                    params["dataBase64"] = serde_json::Value::String(BASE64.encode(&output.bytes));
+                }
+                let msg = JsonRpcNotification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "terminal/output".to_string(),
+                    params,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = server_state.event_tx.send(json);
                 }
-                Err(_) => continue,
             }
-        }
-
-        // If no port found in the range, let the OS choose one
-        let addr = "0.0.0.0:0";
-        let listener = tokio::net::TcpListener::bind(addr).await?;
-        let actual_port = listener.local_addr()?.port();
-        info!("OS assigned port: {}", actual_port);
-        Ok((listener, actual_port))
+        });
     }
 
     async fn start_event_forwarding(state: Arc<AppState>, event_tx: broadcast::Sender<String>) {
         // Forward session notifications and apply to SessionStateManager
         let notification_rx = state.notification_rx.write().take();
-        if let Some(mut rx) = notification_rx {
+        let chunk_flush_rx = state.chunk_flush_rx.write().take();
+        if let (Some(mut rx), Some(mut flush_rx)) = (notification_rx, chunk_flush_rx) {
             let tx = event_tx.clone();
             let session_state_manager = state.session_state_manager.clone();
+            let window = crate::core::ConfigManager::new().config().server.chunk_coalesce_ms;
+            let mut coalescer = ChunkCoalescer::new(std::time::Duration::from_millis(window));
             tokio::spawn(async move {
-                while let Some(notification) = rx.recv().await {
-                    // Apply update to SessionStateManager (single source of truth)
-                    session_state_manager.apply_update(
-                        &notification.session_id,
-                        notification.update.clone(),
-                    );
+                loop {
+                    let deadline = coalescer.next_deadline();
+                    tokio::select! {
+                        // Notifications first: a flush must not overtake chunks already queued
+                        biased;
+                        notification = rx.recv() => {
+                            let Some(notification) = notification else { break };
+                            // Apply update to SessionStateManager (single source of truth)
+                            let sequenced = session_state_manager.apply_update(
+                                &notification.session_id,
+                                notification.update.clone(),
+                            );
+                            let seq = sequenced.as_ref().map(|s| s.seq);
+
+                            // Forward to all clients (backward compatibility). `seq` lets a
+                            // client resume from here with subscribe_session's lastSeq.
+                            let msg = JsonRpcNotification {
+                                jsonrpc: "2.0".to_string(),
+                                method: "session/update".to_string(),
+                                params: serde_json::json!({
+                                    "sessionId": notification.session_id,
+                                    "update": notification.update,
+                                    "seq": seq,
+                                }),
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                let _ = tx.send(json);
+                            }
 
-                    // Forward to all clients (backward compatibility)
-                    let msg = JsonRpcNotification {
-                        jsonrpc: "2.0".to_string(),
-                        method: "session/update".to_string(),
-                        params: serde_json::json!({
-                            "sessionId": notification.session_id,
-                            "update": notification.update,
-                        }),
-                    };
-                    if let Ok(json) = serde_json::to_string(&msg) {
-                        let _ = tx.send(json);
+                            // The structured delta (message, tool call, plan, mode and
+                            // command changes alike), as for every other state change.
+                            // Message chunks are buffered briefly and sent combined; any
+                            // other delta sends the session's buffered chunks first.
+                            if let Some(sequenced) = sequenced {
+                                if let SessionStateUpdate::MessageChunk { content } = &sequenced.update {
+                                    if coalescer.enabled() {
+                                        coalescer.push(&notification.session_id, content, sequenced.seq);
+                                        continue;
+                                    }
+                                }
+                                if let Some(pending) = coalescer.take(&notification.session_id) {
+                                    send_state_update(&tx, &notification.session_id, pending);
+                                }
+                                send_state_update(&tx, &notification.session_id, sequenced);
+                            }
+                        }
+                        Some((session_id, done)) = flush_rx.recv() => {
+                            if let Some(pending) = coalescer.take(&session_id) {
+                                send_state_update(&tx, &session_id, pending);
+                            }
+                            let (chunks, frames) = coalescer.take_stats();
+                            if chunks > 0 {
+                                debug!("Coalesced {} message chunk(s) into {} update(s)", chunks, frames);
+                            }
+                            let _ = done.send(());
+                        }
+                        _ = tokio::time::sleep_until(tokio::time::Instant::from_std(deadline.unwrap_or_else(std::time::Instant::now))), if deadline.is_some() => {
+                            for (session_id, pending) in coalescer.take_due(std::time::Instant::now()) {
+                                send_state_update(&tx, &session_id, pending);
+                            }
+                        }
                     }
                 }
             });
@@ -181,23 +480,29 @@ impl WebSocketServer {
             let state_clone = state.clone();
             tokio::spawn(async move {
                 while let Some(request) = rx.recv().await {
-                    // Check if session has dangerous mode enabled - auto-approve if so
-                    if state_clone.session_state_manager.is_dangerous_mode(&request.session_id) {
-                        info!("Dangerous mode enabled for session {}, auto-approving permission", request.session_id);
-                        // Find allow option and auto-respond
-                        if let Some(allow_option) = request.options.iter().find(|opt| {
-                            matches!(opt.kind, crate::acp::PermissionOptionKind::AllowOnce | crate::acp::PermissionOptionKind::AllowAlways)
-                        }) {
-                            let outcome = PermissionOutcome::Selected {
-                                option_id: allow_option.option_id.clone(),
-                            };
-                            // Respond to the permission request
-                            let client_guard = state_clone.client.read().await;
-                            if let Some(ref client) = *client_guard {
-                                let _ = client.respond_permission(request.request_id.clone(), outcome).await;
-                            }
-                            continue; // Skip forwarding to clients
+                    // Apply the session's tool policy (dangerous mode allows everything)
+                    let kind = request.tool_call.kind.as_ref()
+                        .and_then(|kind| serde_json::to_value(kind).ok())
+                        .and_then(|kind| kind.as_str().map(|s| s.to_string()));
+                    let mut action = state_clone.permission_policy(&request.session_id).action_for(kind.as_deref());
+                    // Tool calls the user already chose "always allow" for
+                    if action == PermissionAction::Prompt
+                        && state_clone.session_state_manager.is_always_allowed(&request.session_id, &permission_signature(&request.tool_call))
+                    {
+                        action = PermissionAction::Allow;
+                    }
+                    if let Some(option) = option_for_action(&request.options, action) {
+                        info!("Permission policy for session {} answered {:?} tool call: {:?}", request.session_id, kind, action);
+                        let outcome = PermissionOutcome::Selected {
+                            option_id: option.option_id.clone(),
+                        };
+                        // Respond to the permission request
+                        let client = state_clone.session_client(&request.session_id);
+                        let client_guard = client.read().await;
+                        if let Some(ref client) = *client_guard {
+                            let _ = client.respond_permission(request.request_id.clone(), outcome).await;
                         }
+                        continue; // Skip forwarding to clients
                     }
 
                     // Save the pending permission request to session state
@@ -208,8 +513,15 @@ impl WebSocketServer {
                     // Also keep in global state for backward compatibility
                     state_clone.set_pending_permission(Some(request.clone()));
 
+                    state_clone.notify_desktop(
+                        "Permission requested",
+                        request.tool_call.title.as_deref().unwrap_or("The agent needs your approval"),
+                    );
+
                     // Set session status to Pending (waiting for user response)
-                    state_clone.session_registry.update_status(
+                    set_session_status(
+                        &state_clone,
+                        &tx,
                         &request.session_id,
                         crate::core::SessionStatus::Pending,
                     );
@@ -217,7 +529,7 @@ impl WebSocketServer {
                     // Broadcast session list update for pending status
                     let session_cwd = state_clone.session_registry.get_session_info(&request.session_id)
                         .map(|info| info.cwd.clone());
-                    let sessions = state_clone.session_registry.list_sessions(session_cwd.as_deref(), 50, 0);
+                    let sessions = state_clone.session_registry.list_sessions(session_cwd.as_deref(), 50, 0, false);
                     let sessions_msg = JsonRpcNotification {
                         jsonrpc: "2.0".to_string(),
                         method: "sessions/updated".to_string(),
@@ -235,20 +547,26 @@ impl WebSocketServer {
                     if let Ok(json) = serde_json::to_string(&msg) {
                         let _ = tx.send(json);
                     }
+
+                    // Unattended sessions can have unanswered prompts resolved for them
+                    if let Some((timeout, action)) = state_clone.permission_policy(&request.session_id).timeout() {
+                        tokio::spawn(auto_resolve_permission(state_clone.clone(), tx.clone(), request, timeout, action));
+                    }
                 }
             });
         }
 
-        // Forward terminal output
-        let terminal_rx = state.terminal_output_rx.write().take();
-        if let Some(mut rx) = terminal_rx {
+        // Forward terminal exits so clients can mark dead terminals
+        let terminal_exit_rx = state.terminal_exit_rx.write().take();
+        if let Some(mut rx) = terminal_exit_rx {
             let tx = event_tx.clone();
             tokio::spawn(async move {
-                while let Some(output) = rx.recv().await {
+                while let Some(exit) = rx.recv().await {
+                    info!("Terminal {} exited with {:?}", exit.terminal_id, exit.status.exit_code);
                     let msg = JsonRpcNotification {
                         jsonrpc: "2.0".to_string(),
-                        method: "terminal/output".to_string(),
-                        params: serde_json::to_value(&output).unwrap_or_default(),
+                        method: "terminal/exit".to_string(),
+                        params: serde_json::to_value(&exit).unwrap_or_default(),
                     };
                     if let Ok(json) = serde_json::to_string(&msg) {
                         let _ = tx.send(json);
@@ -257,6 +575,64 @@ impl WebSocketServer {
             });
         }
 
+        // Forward agent lifecycle events (e.g. the agent process crashed)
+        let agent_event_rx = state.agent_event_rx.write().take();
+        if let Some(mut rx) = agent_event_rx {
+            let tx = event_tx.clone();
+            let state_clone = state.clone();
+            tokio::spawn(async move {
+                while let Some(event) = rx.recv().await {
+                    match event {
+                        AgentEvent::Disconnected { reason } => {
+                            warn!("ACP agent disconnected: {}", reason);
+
+                            // Sessions that were mid-turn will never complete on the dead agent
+                            for session in state_clone.session_registry.get_active_sessions() {
+                                if matches!(session.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending) {
+                                    set_session_status(&state_clone, &tx, &session.id, crate::core::SessionStatus::Idle);
+                                    state_clone.session_state_manager.set_pending_permission(&session.id, None);
+                                }
+                            }
+                            state_clone.set_pending_permission(None);
+                            broadcast_sessions_update(&state_clone, &tx, None);
+
+                            let msg = JsonRpcNotification {
+                                jsonrpc: "2.0".to_string(),
+                                method: "agent/disconnected".to_string(),
+                                params: serde_json::json!({ "reason": reason }),
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                let _ = tx.send(json);
+                            }
+                        }
+                        AgentEvent::Stderr { line } => {
+                            let msg = JsonRpcNotification {
+                                jsonrpc: "2.0".to_string(),
+                                method: "agent/stderr".to_string(),
+                                params: serde_json::json!({ "line": line }),
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                let _ = tx.send(json);
+                            }
+                        }
+                        AgentEvent::Initialized { response } => {
+                            let msg = JsonRpcNotification {
+                                jsonrpc: "2.0".to_string(),
+                                method: "agent/initialized".to_string(),
+                                params: serde_json::json!({
+                                    "agentInfo": response.agent_info,
+                                    "agentCapabilities": response.agent_capabilities,
+                                }),
+                            };
+                            if let Ok(json) = serde_json::to_string(&msg) {
+                                let _ = tx.send(json);
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
         // Forward session activation events
         let session_activated_rx = state.session_activated_rx.write().take();
         if let Some(mut rx) = session_activated_rx {
@@ -276,25 +652,405 @@ impl WebSocketServer {
                 }
             });
         }
+
+        // Sync the current session when another process writes its file.
+        // Bursts of writes are debounced into one sync.
+        let session_file_rx = state.session_file_rx.write().take();
+        if let Some(mut rx) = session_file_rx {
+            let tx = event_tx.clone();
+            let state_clone = state.clone();
+            tokio::spawn(async move {
+                let mut pending = std::collections::HashSet::new();
+                loop {
+                    let next = if pending.is_empty() {
+                        rx.recv().await.map(Some)
+                    } else {
+                        match tokio::time::timeout(SESSION_FILE_DEBOUNCE, rx.recv()).await {
+                            Ok(received) => received.map(Some),
+                            // Quiet period over
+                            Err(_) => Some(None),
+                        }
+                    };
+                    match next {
+                        Some(Some(change)) => {
+                            pending.insert(change.session_id);
+                        }
+                        Some(None) => {
+                            for session_id in pending.drain() {
+                                sync_session_file(&state_clone, &tx, &session_id);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Quiet period before a changed session file is re-read
+const SESSION_FILE_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+/// Writes this soon after one of our own turns are assumed to be the agent's
+const SESSION_SELF_WRITE_GRACE_SECS: i64 = 3;
+
+/// Merge a session's JSONL file into its in-memory state and broadcast the deltas
+fn sync_session_file(state: &Arc<AppState>, event_tx: &broadcast::Sender<String>, session_id: &SessionId) {
+    // Our own agent writes the file while a turn runs; those updates already
+    // arrive over ACP
+    if let Some(session) = state.session_registry.get_active_session(session_id) {
+        let recently_active = (chrono::Utc::now() - session.last_activity).num_seconds() < SESSION_SELF_WRITE_GRACE_SECS;
+        if recently_active
+            || matches!(session.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending)
+        {
+            debug!("Ignoring session file change for busy session {}", session_id);
+            return;
+        }
+    }
+
+    let chat_items = state.session_registry.load_chat_items(session_id);
+    let updates = state.session_state_manager.merge_history(session_id, chat_items);
+    if updates.is_empty() {
+        return;
+    }
+    info!("Synced {} external change(s) for session {}", updates.len(), session_id);
+
+    for sequenced in updates {
+        send_state_update(event_tx, session_id, sequenced);
+    }
+}
+
+/// Broadcast a structured session delta
+fn send_state_update(event_tx: &broadcast::Sender<String>, session_id: &str, sequenced: SequencedUpdate) {
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "session/state_update".to_string(),
+        params: serde_json::json!({
+            "sessionId": session_id,
+            "update": sequenced.update,
+            "seq": sequenced.seq,
+        }),
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
     }
 }
 
 struct ServerState {
     app_state: Arc<AppState>,
     event_tx: broadcast::Sender<String>,
+    /// Shared token required to upgrade `/ws` (None = no auth)
+    auth_token: Option<String>,
+    /// Connected WebSocket clients, for the admin methods
+    clients: std::sync::RwLock<HashMap<ClientId, Arc<ClientState>>>,
+    /// Per-connection request limits (0 = unlimited), from `server` in the config
+    max_concurrent_requests: usize,
+    requests_per_second: u32,
+    request_burst: u32,
+    /// Heartbeat: ping every `ping_interval` (None = off), close after `max_missed_pongs` go unanswered
+    ping_interval: Option<std::time::Duration>,
+    max_missed_pongs: u32,
+    /// Reject all but `READ_ONLY_ALLOWED_METHODS` (`server.readOnly` / AERO_READ_ONLY)
+    read_only: bool,
+}
+
+/// Methods that bypass the request limits: a client must always be able to
+/// answer a permission prompt or cancel, even with every slot held by prompts
+/// waiting on exactly that.
+const UNLIMITED_METHODS: &[&str] = &["respond_permission", "cancel_session", "cancel_file_stream"];
+
+/// Methods that operate on the whole server. They are only available when an
+/// auth token is configured, since every connection could otherwise call them.
+const ADMIN_METHODS: &[&str] = &["list_clients", "reset", "disconnect_idle_clients"];
+
+/// Methods allowed in read-only mode besides every `get_*`/`list_*` method
+/// (`READ_ONLY_ALLOWED_PREFIXES`): subscriptions, watches, reads, searches and
+/// git status/diff. Everything else, including methods added later, is rejected.
+const READ_ONLY_ALLOWED_METHODS: &[&str] = &[
+    "ping",
+    // Subscriptions
+    "subscribe_session", "unsubscribe_session", "subscribe_all", "unsubscribe_all", "watch_file", "unwatch_file",
+    // Sessions
+    "load_more_history", "search_sessions", "resolve_session",
+    // Files and git
+    "read_file", "read_file_binary", "read_file_binary_stream", "cancel_file_stream", "read_file_at_revision",
+    "search_files", "preview_delete", "preview_rename", "git_status", "git_diff",
+];
+const READ_ONLY_ALLOWED_PREFIXES: &[&str] = &["get_", "list_"];
+
+/// The error for `method` if the server is read-only and the method isn't a read
+fn read_only_rejection(state: &ServerState, method: &str) -> Option<RpcError> {
+    let allowed = READ_ONLY_ALLOWED_METHODS.contains(&method)
+        || READ_ONLY_ALLOWED_PREFIXES.iter().any(|prefix| method.starts_with(prefix));
+    (state.read_only && !allowed)
+        .then(|| RpcError::ReadOnly(format!("{} is not allowed: the server is read-only", method)))
+}
+
+/// Streaming deltas reported to `subscribe_all` clients at most once per
+/// `ACTIVITY_INTERVAL` per session, so a long reply doesn't flood dashboards
+const THROTTLED_ACTIVITY: &[&str] = &["message_chunk", "tool_call_updated", "usage_updated"];
+const ACTIVITY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A client's `subscribe_all` registration
+struct AllSessionsSubscription {
+    /// Only report activity of sessions in this project
+    cwd: Option<String>,
+    /// When each session last reported each throttled kind of activity
+    last_activity: HashMap<(SessionId, String), std::time::Instant>,
 }
 
 /// Per-client state for WebSocket connections
 struct ClientState {
     client_id: ClientId,
+    connected_at: std::time::Instant,
+    /// When this client last sent a request
+    last_request: std::sync::Mutex<std::time::Instant>,
+    /// Notified to close this client's connection
+    disconnect: tokio::sync::Notify,
+    /// Heartbeat pings sent since the client was last heard from
+    missed_pongs: std::sync::atomic::AtomicU32,
+    /// Bumped when the client starts auto-resuming a different session, so an
+    /// auto-resume still running for the previous one can tell it is stale
+    resume_generation: std::sync::atomic::AtomicU64,
+    /// Session the client last started auto-resuming
+    resume_target: std::sync::Mutex<Option<SessionId>>,
+    /// Background history load of that session, aborted when the client moves on
+    resume_history: std::sync::Mutex<Option<tokio::task::AbortHandle>>,
+    /// Sessions this client receives session/permission notifications for
     subscribed_sessions: std::sync::RwLock<std::collections::HashSet<SessionId>>,
+    /// Terminals this client created or interacted with (receives their output)
+    attached_terminals: std::sync::RwLock<std::collections::HashSet<String>>,
+    /// Attached terminals whose output this client receives base64-encoded
+    raw_terminals: std::sync::RwLock<std::collections::HashSet<String>>,
     /// Current working directory (project) for this client
-    /// Used to filter broadcasts - clients only receive updates for their current project
     current_cwd: std::sync::RwLock<Option<String>>,
+    /// Direct channel to this client's socket (bypasses the broadcast)
+    outbox: mpsc::Sender<String>,
+    /// Cancel flags of this client's running file streams, by stream ID
+    file_streams: std::sync::Mutex<HashMap<String, Arc<std::sync::atomic::AtomicBool>>>,
+    /// Files this client watches for changes, by the path it asked for
+    file_watches: std::sync::Mutex<HashMap<String, FileWatch>>,
+    /// Set by `subscribe_all`: activity summaries from every session
+    all_sessions: std::sync::Mutex<Option<AllSessionsSubscription>>,
+}
+
+impl ClientState {
+    fn new(client_id: ClientId, outbox: mpsc::Sender<String>) -> Self {
+        Self {
+            client_id,
+            connected_at: std::time::Instant::now(),
+            last_request: std::sync::Mutex::new(std::time::Instant::now()),
+            disconnect: tokio::sync::Notify::new(),
+            missed_pongs: std::sync::atomic::AtomicU32::new(0),
+            resume_generation: std::sync::atomic::AtomicU64::new(0),
+            resume_target: std::sync::Mutex::new(None),
+            resume_history: std::sync::Mutex::new(None),
+            subscribed_sessions: std::sync::RwLock::new(std::collections::HashSet::new()),
+            attached_terminals: std::sync::RwLock::new(std::collections::HashSet::new()),
+            raw_terminals: std::sync::RwLock::new(std::collections::HashSet::new()),
+            current_cwd: std::sync::RwLock::new(None),
+            outbox,
+            file_streams: std::sync::Mutex::new(HashMap::new()),
+            file_watches: std::sync::Mutex::new(HashMap::new()),
+            all_sessions: std::sync::Mutex::new(None),
+        }
+    }
+
+    fn is_subscribed(&self, session_id: &str) -> bool {
+        self.subscribed_sessions.read().unwrap().contains(session_id)
+    }
+
+    /// Start auto-resuming a session. Resumes of other sessions still running
+    /// become stale; repeated requests for the same session share a generation.
+    fn begin_resume(&self, session_id: &str) -> u64 {
+        let mut target = self.resume_target.lock().unwrap();
+        if target.as_deref() == Some(session_id) {
+            return self.resume_generation.load(std::sync::atomic::Ordering::SeqCst);
+        }
+        *target = Some(session_id.to_string());
+        if let Some(history) = self.resume_history.lock().unwrap().take() {
+            history.abort();
+        }
+        self.resume_generation.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    }
+
+    /// Keep the history load of the auto-resume started as `generation`, so a
+    /// later switch to another session aborts it. Aborts it now if that
+    /// already happened.
+    fn track_resume_history(&self, generation: u64, history: tokio::task::AbortHandle) {
+        let _target = self.resume_target.lock().unwrap();
+        if self.is_superseded(generation) {
+            history.abort();
+        } else {
+            *self.resume_history.lock().unwrap() = Some(history);
+        }
+    }
+
+    /// Whether the client switched sessions again after `generation` started
+    fn is_superseded(&self, generation: u64) -> bool {
+        self.resume_generation.load(std::sync::atomic::Ordering::SeqCst) != generation
+    }
+
+    fn attach_terminal(&self, terminal_id: &str) {
+        self.attached_terminals.write().unwrap().insert(terminal_id.to_string());
+    }
+
+    /// Receive a terminal's output as base64 (`raw`) or as UTF-8 text
+    fn set_terminal_raw(&self, terminal_id: &str, raw: bool) {
+        let mut raw_terminals = self.raw_terminals.write().unwrap();
+        if raw {
+            raw_terminals.insert(terminal_id.to_string());
+        } else {
+            raw_terminals.remove(terminal_id);
+        }
+    }
+
+    fn reads_terminal_raw(&self, terminal_id: &str) -> bool {
+        self.raw_terminals.read().unwrap().contains(terminal_id)
+    }
+
+    /// `terminal/output` carries `dataBase64` next to `data` while any client reads
+    /// the terminal raw; keep only the one this client asked for. Output broadcast
+    /// before the client switched to base64 (such as a new shell's first prompt)
+    /// has only `data`, which is re-encoded instead.
+    fn encode_terminal_output(&self, mut notification: serde_json::Value) -> String {
+        use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+
+        if let Some(params) = notification.get_mut("params").and_then(|p| p.as_object_mut()) {
+            let raw = params
+                .get("terminalId")
+                .and_then(|v| v.as_str())
+                .is_some_and(|terminal_id| self.reads_terminal_raw(terminal_id));
+            if !raw {
+                params.remove("dataBase64");
+            } else if let Some(serde_json::Value::String(data)) = params.remove("data") {
+                params.entry("dataBase64").or_insert_with(|| BASE64.encode(data.as_bytes()).into());
+            }
+        }
+        notification.to_string()
+    }
+
+    /// On `session/started`, move a subscription from the placeholder ID to the
+    /// real one, so the started session's updates keep reaching this client
+    fn follow_started_session(&self, notification: &serde_json::Value) {
+        if notification.get("method").and_then(|v| v.as_str()) != Some("session/started") {
+            return;
+        }
+        let params = notification.get("params");
+        let id = |key| params.and_then(|p| p.get(key)).and_then(|v| v.as_str());
+        let (Some(placeholder), Some(real_id)) = (id("placeholderId"), id("sessionId")) else {
+            return;
+        };
+        let mut subscribed = self.subscribed_sessions.write().unwrap();
+        if subscribed.remove(placeholder) {
+            subscribed.insert(real_id.to_string());
+        }
+    }
+
+    /// Whether a broadcast notification should be delivered to this client.
+    /// Session-scoped events only go to subscribers; global events go to everyone.
+    fn wants_notification(&self, notification: &serde_json::Value) -> bool {
+        let params = notification.get("params");
+        match notification.get("method").and_then(|v| v.as_str()) {
+            Some("session/update") | Some("session/state_update") | Some("permission/request") => params
+                .and_then(|p| p.get("sessionId").or_else(|| p.get("session_id")))
+                .and_then(|s| s.as_str())
+                .is_some_and(|session_id| self.is_subscribed(session_id)),
+            Some("terminal/output") => params
+                .and_then(|p| p.get("terminalId").or_else(|| p.get("terminal_id")))
+                .and_then(|s| s.as_str())
+                .is_some_and(|terminal_id| self.attached_terminals.read().unwrap().contains(terminal_id)),
+            // sessions/updated, permission/resolved, agent/disconnected, etc.
+            _ => true,
+        }
+    }
+
+    /// For a `subscribe_all` client, the `session/activity` summary of a
+    /// `session/state_update` it doesn't receive itself. `session_cwd` looks up
+    /// the session's project, for the subscription's cwd filter.
+    ///
+    /// Throttled kinds are dropped, not delayed, so the last chunk of a burst
+    /// may go unreported; the unthrottled update ending the turn still arrives.
+    fn activity_notification(
+        &self,
+        notification: &serde_json::Value,
+        session_cwd: impl FnOnce(&str) -> Option<String>,
+    ) -> Option<String> {
+        if notification.get("method").and_then(|v| v.as_str()) != Some("session/state_update") {
+            return None;
+        }
+        let mut all_sessions = self.all_sessions.lock().unwrap();
+        let subscription = all_sessions.as_mut()?;
+        let params = notification.get("params")?;
+        let session_id = params.get("sessionId").and_then(|v| v.as_str())?;
+        let kind = params.get("update").and_then(|u| u.get("updateType")).and_then(|v| v.as_str())?;
+
+        // Checked before the cwd lookup, which reads the session registry
+        let now = std::time::Instant::now();
+        let throttle_key = THROTTLED_ACTIVITY.contains(&kind).then(|| (session_id.to_string(), kind.to_string()));
+        if let Some(key) = &throttle_key {
+            if subscription.last_activity.get(key).is_some_and(|last| now.duration_since(*last) < ACTIVITY_INTERVAL) {
+                return None;
+            }
+        }
+
+        let cwd = session_cwd(session_id);
+        if subscription.cwd.is_some() && subscription.cwd != cwd {
+            return None;
+        }
+        if let Some(key) = throttle_key {
+            subscription.last_activity.insert(key, now);
+        }
+
+        let activity = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "session/activity".to_string(),
+            params: serde_json::json!({
+                "sessionId": session_id,
+                "cwd": cwd,
+                "kind": kind,
+                "seq": params.get("seq"),
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+            }),
+        };
+        serde_json::to_string(&activity).ok()
+    }
+}
+
+/// The `encoding` a client wants terminal output in: `"base64"` (raw bytes) or `"utf8"`.
+/// `Some(true)` for base64, `None` when not given.
+fn terminal_encoding_param(params: &serde_json::Value) -> Result<Option<bool>, &'static str> {
+    match params.get("encoding").and_then(|v| v.as_str()) {
+        None => Ok(None),
+        Some("utf8") => Ok(Some(false)),
+        Some("base64") => Ok(Some(true)),
+        Some(_) => Err("Invalid encoding parameter (expected \"utf8\" or \"base64\")"),
+    }
+}
+
+/// Subscribe a client to a session it created or resumed so it receives its updates
+fn track_session(state: &Arc<AppState>, client_state: &Arc<ClientState>, session_id: &str) {
+    let session_id = session_id.to_string();
+    if client_state.is_subscribed(&session_id) {
+        return;
+    }
+    state.session_state_manager.subscribe(client_state.client_id.clone(), &session_id);
+    client_state.subscribed_sessions.write().unwrap().insert(session_id);
 }
 
-async fn health_handler() -> &'static str {
-    "OK"
+/// Plain "OK" for liveness probes; `?format=json` returns `server_status`
+/// (which needs the auth token, if one is configured)
+async fn health_handler(
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    if query.get("format").map(|f| f.as_str()) != Some("json") {
+        return "OK".into_response();
+    }
+    if !is_authorized(&state, &query, &headers) {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    Json(server_status(&state).await).into_response()
 }
 
 /// Broadcast session list update to all clients
@@ -304,7 +1060,7 @@ fn broadcast_sessions_update(
     event_tx: &broadcast::Sender<String>,
     cwd: Option<&str>,
 ) {
-    let sessions = state.session_registry.list_sessions(cwd, 50, 0);
+    let sessions = state.session_registry.list_sessions(cwd, 50, 0, false);
     let notification = JsonRpcNotification {
         jsonrpc: "2.0".to_string(),
         method: "sessions/updated".to_string(),
@@ -315,144 +1071,501 @@ fn broadcast_sessions_update(
     }
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(state): State<Arc<ServerState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+/// Update a session's status and broadcast `session/status_changed` if it changed
+fn set_session_status(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    session_id: &str,
+    status: crate::core::SessionStatus,
+) {
+    if !state.session_registry.update_status(&session_id.to_string(), status) {
+        return;
+    }
+    let notification = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "session/status_changed".to_string(),
+        params: serde_json::json!({ "sessionId": session_id, "status": status }),
+    };
+    if let Ok(json) = serde_json::to_string(&notification) {
+        let _ = event_tx.send(json);
+    }
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<ServerState>) {
-    let (mut sender, mut receiver) = socket.split();
-
-    // Generate unique client ID for this connection
-    let client_id = Uuid::new_v4().to_string();
-    let client_state = Arc::new(ClientState {
-        client_id: client_id.clone(),
-        subscribed_sessions: std::sync::RwLock::new(std::collections::HashSet::new()),
-        current_cwd: std::sync::RwLock::new(None),
+/// Tell clients how many prompts of a session wait for the running one
+fn broadcast_prompt_queue(event_tx: &broadcast::Sender<String>, session_id: &str, queued: usize) {
+    let notification = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "session/prompt_queue".to_string(),
+        params: serde_json::json!({ "sessionId": session_id, "queued": queued }),
+    };
+    if let Ok(json) = serde_json::to_string(&notification) {
+        let _ = event_tx.send(json);
+    }
+}
+
+/// Wait for the session's running prompt, or fail right away with
+/// `agent.busyPrompts: "reject"`. The guard must be held for the whole prompt.
+async fn acquire_prompt(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    session_id: &str,
+) -> Result<crate::core::session_state_manager::PromptGuard, RpcError> {
+    use crate::core::BusyPromptPolicy;
+
+    let manager = &state.session_state_manager;
+    let session_id = session_id.to_string();
+    if let Some(guard) = manager.try_lock_prompt(&session_id) {
+        return Ok(guard);
+    }
+    if state.busy_prompts() == BusyPromptPolicy::Reject {
+        return Err(RpcError::SessionBusy(format!("Session {} is busy with another prompt", session_id)));
+    }
+
+    info!("WebSocket: Session {} is busy, queueing prompt", session_id);
+    broadcast_prompt_queue(event_tx, &session_id, manager.queue_prompt(&session_id));
+    let guard = manager.lock_prompt(&session_id).await;
+    broadcast_prompt_queue(event_tx, &session_id, manager.dequeue_prompt(&session_id));
+    Ok(guard)
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    State(state): State<Arc<ServerState>>,
+) -> Response {
+    if !is_authorized(&state, &query, &headers) {
+        warn!("WebSocket: rejected connection with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+
+    ws.on_upgrade(|socket| handle_socket(socket, state))
+}
+
+/// Check the shared token from `?token=` or an `Authorization: Bearer` header
+fn is_authorized(state: &ServerState, query: &HashMap<String, String>, headers: &HeaderMap) -> bool {
+    let Some(expected) = state.auth_token.as_deref() else {
+        return true;
+    };
+    let provided = query.get("token").map(|s| s.as_str()).or_else(|| {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim())
     });
+    provided.is_some_and(|token| token_matches(token, expected))
+}
 
-    info!("WebSocket client connected: {}", client_id);
+/// REST entry point: `GET /api/<method>?param=...` or `POST /api/<method>` with
+/// a JSON object body. Only read-only methods are exposed; streaming and
+/// notification-based methods need the WebSocket.
+async fn api_handler(
+    Path(method): Path<String>,
+    Query(mut query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    State(state): State<Arc<ServerState>>,
+    body: Option<Json<serde_json::Value>>,
+) -> Response {
+    if !is_authorized(&state, &query, &headers) {
+        warn!("REST API: rejected request with missing or invalid token");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+    query.remove("token");
+
+    // Query parameters first, JSON body fields override them
+    let mut params: serde_json::Map<String, serde_json::Value> = query
+        .into_iter()
+        .map(|(k, v)| (k, serde_json::Value::String(v)))
+        .collect();
+    if let Some(Json(serde_json::Value::Object(fields))) = body {
+        params.extend(fields);
+    }
 
-    // Subscribe to broadcast events
-    let mut event_rx = state.event_tx.subscribe();
+    let params = serde_json::Value::Object(params);
+    let result = if let Some(e) = read_only_rejection(&state, &method) {
+        Some(Err(e))
+    } else {
+        match admin_dispatch(&method, &params, &state, None).await {
+            Some(result) => Some(result.map_err(RpcError::from)),
+            None if method == "get_status" => Some(Ok(server_status(&state).await)),
+            None => api_dispatch(&method, &params, &state.app_state).await,
+        }
+    };
+    match result {
+        Some(Ok(value)) => Json(value).into_response(),
+        Some(Err(e)) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": e.message(), "code": e.code() })),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("Method not available over REST: {}", method) })),
+        )
+            .into_response(),
+    }
+}
+
+/// Run an admin method (over WebSocket or REST). Returns None for other methods.
+/// `caller` is the requesting WebSocket client, which is never disconnected.
+async fn admin_dispatch(
+    method: &str,
+    params: &serde_json::Value,
+    state: &Arc<ServerState>,
+    caller: Option<&ClientId>,
+) -> Option<Result<serde_json::Value, String>> {
+    if !ADMIN_METHODS.contains(&method) {
+        return None;
+    }
+    if state.auth_token.is_none() {
+        return Some(Err(format!("{} requires an auth token to be configured (set {})", method, AUTH_TOKEN_ENV)));
+    }
+
+    let result = match method {
+        "list_clients" => Ok(list_clients(state)),
+        "reset" => {
+            let disconnect_agent = api_bool(params, "disconnectAgent").unwrap_or(false);
+            Ok(reset_handler(state, disconnect_agent).await)
+        }
+        "disconnect_idle_clients" => {
+            let idle_secs = api_u64(params, "idleSecs").unwrap_or(300);
+            Ok(serde_json::json!({ "disconnected": disconnect_idle_clients(state, idle_secs, caller) }))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Connected clients with their subscriptions and idle time
+fn list_clients(state: &ServerState) -> serde_json::Value {
+    let clients = state.clients.read().unwrap();
+    let mut list: Vec<serde_json::Value> = clients
+        .values()
+        .map(|client| {
+            let mut sessions: Vec<String> = client.subscribed_sessions.read().unwrap().iter().cloned().collect();
+            sessions.sort();
+            let mut terminals: Vec<String> = client.attached_terminals.read().unwrap().iter().cloned().collect();
+            terminals.sort();
+            serde_json::json!({
+                "clientId": client.client_id,
+                "subscribedSessions": sessions,
+                "attachedTerminals": terminals,
+                "cwd": client.current_cwd.read().unwrap().clone(),
+                "allSessions": client.all_sessions.lock().unwrap().as_ref().map(|all| serde_json::json!({ "cwd": all.cwd })),
+                "connectedSecs": client.connected_at.elapsed().as_secs(),
+                "idleSecs": client.last_request.lock().unwrap().elapsed().as_secs(),
+            })
+        })
+        .collect();
+    list.sort_by_key(|client| std::cmp::Reverse(client["connectedSecs"].as_u64()));
+    serde_json::Value::Array(list)
+}
+
+/// Cancel every running session, kill all terminals and drop all loaded session
+/// state, optionally disconnecting the agents too. Clients are told with `server/reset`.
+async fn reset_handler(state: &Arc<ServerState>, disconnect_agent: bool) -> serde_json::Value {
+    let app_state = &state.app_state;
+    warn!("Admin: resetting server state (disconnect agent: {})", disconnect_agent);
+
+    let running: Vec<SessionId> = app_state.session_registry.get_active_sessions()
+        .into_iter()
+        .filter(|s| matches!(s.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending))
+        .map(|s| s.id)
+        .collect();
+    for session_id in &running {
+        if let Err(e) = cancel_session_handler(app_state, session_id, &state.event_tx).await {
+            warn!("Admin: failed to cancel session {}: {}", session_id, e);
+        }
+    }
+
+    let terminals = app_state.terminal_manager.kill_all();
+
+    let loaded = app_state.session_state_manager.get_session_ids();
+    for session_id in &loaded {
+        app_state.session_state_manager.remove_session(session_id);
+    }
+    for client in state.clients.read().unwrap().values() {
+        client.subscribed_sessions.write().unwrap().clear();
+        client.attached_terminals.write().unwrap().clear();
+        client.raw_terminals.write().unwrap().clear();
+    }
+
+    if disconnect_agent {
+        app_state.disconnect_agents().await;
+    }
+
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "server/reset".to_string(),
+        params: serde_json::json!({ "agentDisconnected": disconnect_agent }),
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = state.event_tx.send(json);
+    }
+
+    serde_json::json!({
+        "cancelledSessions": running.len(),
+        "killedTerminals": terminals,
+        "clearedSessions": loaded.len(),
+        "agentDisconnected": disconnect_agent,
+    })
+}
+
+/// Close connections that haven't sent a request for `idle_secs`
+fn disconnect_idle_clients(state: &ServerState, idle_secs: u64, caller: Option<&ClientId>) -> usize {
+    let idle_after = std::time::Duration::from_secs(idle_secs);
+    let clients = state.clients.read().unwrap();
+    let mut disconnected = 0;
+    for client in clients.values() {
+        if Some(&client.client_id) == caller || client.last_request.lock().unwrap().elapsed() < idle_after {
+            continue;
+        }
+        client.disconnect.notify_one();
+        disconnected += 1;
+    }
+    info!("Admin: disconnecting {} idle client(s)", disconnected);
+    disconnected
+}
+
+/// Run a REST-exposed method. Returns None for methods not in the REST API.
+async fn api_dispatch(
+    method: &str,
+    params: &serde_json::Value,
+    state: &Arc<AppState>,
+) -> Option<Result<serde_json::Value, RpcError>> {
+    let result = match method {
+        "list_sessions" => {
+            let cwd = api_str(params, "cwd");
+            let limit = api_u64(params, "limit").map(|v| v as usize);
+            let offset = api_u64(params, "offset").map(|v| v as usize);
+            let cursor = api_str(params, "cursor");
+            let pinned_only = params.get("pinnedOnly").and_then(|v| v.as_bool()).unwrap_or(false);
+            list_sessions_handler(state, cwd, limit, offset, cursor, pinned_only)
+                .await
+                .map_err(RpcError::from)
+                .and_then(|response| serde_json::to_value(response).map_err(RpcError::from))
+        }
+        "get_session_info" => match api_str(params, "sessionId") {
+            Some(session_id) => get_session_info_handler(state, session_id)
+                .await
+                .and_then(|info| serde_json::to_value(info).map_err(RpcError::from)),
+            None => Err("Missing sessionId parameter".into()),
+        },
+        "get_session_state" => match api_str(params, "sessionId") {
+            // Resuming would start an agent, so it is opt-in here
+            Some(session_id) => {
+                let auto_resume = api_bool(params, "autoResume").unwrap_or(false);
+                get_session_state_handler(state, None, session_id, auto_resume)
+                    .await
+                    .and_then(|s| serde_json::to_value(s).map_err(RpcError::from))
+            }
+            None => Err("Missing sessionId parameter".into()),
+        },
+        "list_directory" => match api_str(params, "path") {
+            Some(path) => {
+                let show_hidden = api_bool(params, "showHidden").unwrap_or(false);
+                let include_ignored = api_bool(params, "includeIgnored").unwrap_or(false);
+                list_directory_handler(path, show_hidden, include_ignored)
+                    .await
+                    .map_err(RpcError::from)
+                    .and_then(|entries| serde_json::to_value(entries).map_err(RpcError::from))
+            }
+            None => Err("Missing path parameter".into()),
+        },
+        "read_file" => match api_str(params, "path") {
+            Some(path) => {
+                let max_bytes = api_u64(params, "maxBytes");
+                let truncate = api_bool(params, "truncate").unwrap_or(false);
+                read_file_handler(state, path, max_bytes, truncate).await.and_then(|file| {
+                    if truncate {
+                        serde_json::to_value(file).map_err(RpcError::from)
+                    } else {
+                        Ok(serde_json::Value::String(file.content))
+                    }
+                })
+            }
+            None => Err("Missing path parameter".into()),
+        },
+        "list_terminals" => list_terminals_handler(state)
+            .await
+            .map_err(RpcError::from)
+            .and_then(|terminals| serde_json::to_value(terminals).map_err(RpcError::from)),
+        "get_server_info" => Ok(server_info(state)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn api_str<'a>(params: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    params.get(key).and_then(|v| v.as_str())
+}
+
+/// Numbers arrive as strings from the query string and as numbers from JSON bodies
+fn api_u64(params: &serde_json::Value, key: &str) -> Option<u64> {
+    let value = params.get(key)?;
+    value.as_u64().or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn api_bool(params: &serde_json::Value, key: &str) -> Option<bool> {
+    let value = params.get(key)?;
+    value.as_bool().or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Compare tokens without short-circuiting on the first differing byte
+fn token_matches(provided: &str, expected: &str) -> bool {
+    let (a, b) = (provided.as_bytes(), expected.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn handle_socket(socket: WebSocket, state: Arc<ServerState>) {
+    let (mut sender, mut receiver) = socket.split();
 
     // Channel for sending messages to WebSocket
     let (ws_tx, mut ws_rx) = mpsc::channel::<String>(100);
 
+    // Generate unique client ID for this connection
+    let client_id = Uuid::new_v4().to_string();
+    let client_state = Arc::new(ClientState::new(client_id.clone(), ws_tx.clone()));
+
+    state.clients.write().unwrap().insert(client_id.clone(), client_state.clone());
+    info!("WebSocket client connected: {}", client_id);
+
+    // Subscribe to broadcast events
+    let mut event_rx = state.event_tx.subscribe();
+
     // NOTE: Don't push pending permission here - client will discover it
     // from SessionState.pendingPermission when it fetches session state
 
-    // Task to forward broadcast events to this WebSocket (with cwd filtering)
+    // Task to forward broadcast events to this WebSocket (filtered by subscription)
     let ws_tx_clone = ws_tx.clone();
     let client_state_clone = client_state.clone();
-    let state_clone = state.clone();
+    let app_state = state.app_state.clone();
     let event_task = tokio::spawn(async move {
         while let Ok(msg) = event_rx.recv().await {
-            // Parse message to extract session_id (if present) for cwd filtering
-            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&msg) {
-                let method = parsed.get("method").and_then(|v| v.as_str());
-
-                // Filter based on message type and client's current cwd
-                let should_send = match method {
-                    Some("session/update") | Some("session/state_update") => {
-                        // Extract sessionId from the notification
-                        if let Some(session_id) = parsed.get("params")
-                            .and_then(|p| p.get("sessionId"))
-                            .and_then(|s| s.as_str()) {
-                            // Get session's cwd
-                            if let Some(session_info) = state_clone.app_state.session_registry.get_session_info(session_id) {
-                                let session_cwd = session_info.cwd.clone();
-                                // Get client's current cwd
-                                let client_cwd = client_state_clone.current_cwd.read().unwrap().clone();
-                                // Only send if cwds match (or client has no cwd set)
-                                client_cwd.is_none() || client_cwd.as_deref() == Some(&session_cwd)
-                            } else {
-                                false // Session not found, don't send
-                            }
-                        } else {
-                            false // No sessionId, don't send
-                        }
-                    }
-                    Some("permission/request") => {
-                        // Extract sessionId from permission request
-                        if let Some(session_id) = parsed.get("params")
-                            .and_then(|p| p.get("sessionId"))
-                            .or_else(|| parsed.get("params").and_then(|p| p.get("session_id")))
-                            .and_then(|s| s.as_str()) {
-                            // Get session's cwd
-                            if let Some(session_info) = state_clone.app_state.session_registry.get_session_info(session_id) {
-                                let session_cwd = session_info.cwd.clone();
-                                // Get client's current cwd
-                                let client_cwd = client_state_clone.current_cwd.read().unwrap().clone();
-                                // Only send if cwds match (or client has no cwd set)
-                                client_cwd.is_none() || client_cwd.as_deref() == Some(&session_cwd)
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    }
-                    Some("sessions/updated") => {
-                        // Sessions list is already filtered by cwd in the handler
-                        // But we still need to check if this update is relevant to this client
-                        let client_cwd = client_state_clone.current_cwd.read().unwrap().clone();
-                        // If client has no cwd, send all updates
-                        // Otherwise, the sessions list should already be filtered
-                        true
-                    }
-                    Some("terminal/output") => {
-                        // Terminal output doesn't have session association, always send
-                        // (terminals are created per-client, not globally broadcast)
-                        true
-                    }
-                    _ => {
-                        // Unknown message types or non-session messages, send
-                        true
-                    }
-                };
-
-                if should_send {
-                    if ws_tx_clone.send(msg).await.is_err() {
-                        break;
+            let parsed = serde_json::from_str::<serde_json::Value>(&msg);
+            if let Ok(parsed) = &parsed {
+                client_state_clone.follow_started_session(parsed);
+            }
+            let msg = match parsed {
+                // subscribe_all clients get a summary of other sessions' updates
+                Ok(parsed) if !client_state_clone.wants_notification(&parsed) => {
+                    let session_cwd = |id: &str| app_state.session_registry.get_session_info(id).map(|info| info.cwd);
+                    match client_state_clone.activity_notification(&parsed, session_cwd) {
+                        Some(activity) => activity,
+                        None => continue,
                     }
                 }
-            } else {
-                // If we can't parse, send it anyway (backward compatibility)
-                if ws_tx_clone.send(msg).await.is_err() {
-                    break;
+                Ok(parsed) if parsed.get("method").and_then(|m| m.as_str()) == Some("terminal/output") => {
+                    client_state_clone.encode_terminal_output(parsed)
                 }
+                // If we can't parse, send it anyway (backward compatibility)
+                _ => msg,
+            };
+
+            if ws_tx_clone.send(msg).await.is_err() {
+                break;
             }
         }
     });
 
-    // Task to write messages to WebSocket
+    // Task to write messages to WebSocket. It also sends the heartbeat pings:
+    // proxies and NAT drop idle connections without a reset, so a client that
+    // stops answering is closed here instead of silently missing updates.
+    let ping_interval = state.ping_interval;
+    let max_missed_pongs = state.max_missed_pongs;
+    let writer_client = client_state.clone();
     let write_task = tokio::spawn(async move {
-        while let Some(msg) = ws_rx.recv().await {
-            if sender.send(Message::Text(msg)).await.is_err() {
-                break;
+        let mut heartbeat = ping_interval
+            .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+        loop {
+            tokio::select! {
+                msg = ws_rx.recv() => {
+                    let Some(msg) = msg else { break };
+                    if sender.send(Message::Text(msg)).await.is_err() {
+                        break;
+                    }
+                }
+                _ = async { heartbeat.as_mut().unwrap().tick().await }, if heartbeat.is_some() => {
+                    let missed = writer_client.missed_pongs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    if missed >= max_missed_pongs {
+                        warn!("WebSocket: client {} missed {} heartbeat(s), closing", writer_client.client_id, missed);
+                        writer_client.disconnect.notify_one();
+                        break;
+                    }
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        break;
+                    }
+                }
             }
         }
     });
 
+    // Bound what one connection can have running so it can't exhaust the agent
+    let in_flight = (state.max_concurrent_requests > 0)
+        .then(|| Arc::new(tokio::sync::Semaphore::new(state.max_concurrent_requests)));
+    let mut rate_limiter = (state.requests_per_second > 0)
+        .then(|| TokenBucket::new(state.requests_per_second, state.request_burst));
+
     // Handle incoming messages concurrently to avoid deadlocks
     // (e.g., send_prompt waiting for respond_permission)
-    while let Some(result) = receiver.next().await {
+    loop {
+        let result = tokio::select! {
+            result = receiver.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = client_state.disconnect.notified() => {
+                info!("WebSocket: disconnecting client {} on request", client_state.client_id);
+                break;
+            }
+        };
+        // Any frame, not just a pong, shows the connection is alive
+        if result.is_ok() {
+            client_state.missed_pongs.store(0, std::sync::atomic::Ordering::SeqCst);
+        }
         match result {
             Ok(Message::Text(text)) => {
+                *client_state.last_request.lock().unwrap() = std::time::Instant::now();
+                let request: JsonRpcRequest = match serde_json::from_str(&text) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let response = JsonRpcResponse::error(serde_json::Value::Null, PARSE_ERROR_CODE, format!("Parse error: {}", e));
+                        let _ = ws_tx.send(serde_json::to_string(&response).unwrap_or_default()).await;
+                        continue;
+                    }
+                };
+
+                let mut permit = None;
+                if !UNLIMITED_METHODS.contains(&request.method.as_str()) {
+                    let limited = if rate_limiter.as_mut().is_some_and(|bucket| !bucket.try_take()) {
+                        Some("Rate limit exceeded, slow down")
+                    } else if let Some(in_flight) = &in_flight {
+                        permit = in_flight.clone().try_acquire_owned().ok();
+                        permit.is_none().then_some("Too many requests in progress")
+                    } else {
+                        None
+                    };
+                    if let Some(message) = limited {
+                        warn!("WebSocket: rejected {} from client {}: {}", request.method, client_state.client_id, message);
+                        let response = JsonRpcResponse::error(request.id, TOO_MANY_REQUESTS_CODE, message.to_string());
+                        let _ = ws_tx.send(serde_json::to_string(&response).unwrap_or_default()).await;
+                        continue;
+                    }
+                }
+
                 // Spawn a task for each message to allow concurrent processing
                 let state_clone = state.clone();
                 let client_state_clone = client_state.clone();
                 let ws_tx_clone = ws_tx.clone();
                 tokio::spawn(async move {
-                    let response = handle_message(&text, &state_clone, &client_state_clone).await;
+                    let response = handle_request(request, &state_clone, &client_state_clone).await;
                     let _ = ws_tx_clone.send(response).await;
+                    drop(permit);
                 });
             }
             Ok(Message::Close(_)) => break,
-            Ok(_) => {} // Ignore other message types
+            // axum answers client pings itself; pongs only reset the heartbeat above
+            Ok(_) => {}
             Err(e) => {
                 warn!("WebSocket error for client {}: {}", client_state.client_id, e);
                 break;
@@ -467,30 +1580,33 @@ async fn handle_socket(socket: WebSocket, state: Arc<ServerState>) {
             state.app_state.session_state_manager.unsubscribe(&client_state.client_id, session_id);
         }
     }
+    client_state.file_watches.lock().unwrap().clear();
+    client_state.all_sessions.lock().unwrap().take();
+    for cancelled in client_state.file_streams.lock().unwrap().values() {
+        cancelled.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
 
     event_task.abort();
     write_task.abort();
+    state.clients.write().unwrap().remove(&client_state.client_id);
     info!("WebSocket client disconnected: {}", client_state.client_id);
 }
 
-async fn handle_message(text: &str, state: &Arc<ServerState>, client_state: &Arc<ClientState>) -> String {
-    let request: JsonRpcRequest = match serde_json::from_str(text) {
-        Ok(r) => r,
-        Err(e) => {
-            return serde_json::to_string(&JsonRpcResponse::error(
-                serde_json::Value::Null,
-                -32700,
-                format!("Parse error: {}", e),
-            ))
-            .unwrap_or_default();
+async fn handle_request(request: JsonRpcRequest, state: &Arc<ServerState>, client_state: &Arc<ClientState>) -> String {
+    let params = request.params.unwrap_or(serde_json::Value::Null);
+    let result = if let Some(e) = read_only_rejection(state, &request.method) {
+        Err(e)
+    } else {
+        match admin_dispatch(&request.method, &params, state, Some(&client_state.client_id)).await {
+            Some(result) => result.map_err(RpcError::from),
+            None if request.method == "get_status" => Ok(server_status(state).await),
+            None => dispatch_method(&request.method, Some(params), &state.app_state, client_state, &state.event_tx).await,
         }
     };
 
-    let result = dispatch_method(&request.method, request.params, &state.app_state, client_state, &state.event_tx).await;
-
     match result {
         Ok(value) => serde_json::to_string(&JsonRpcResponse::success(request.id, value)),
-        Err(e) => serde_json::to_string(&JsonRpcResponse::error(request.id, -32603, e)),
+        Err(e) => serde_json::to_string(&JsonRpcResponse::from_error(request.id, e)),
     }
     .unwrap_or_default()
 }
@@ -501,8 +1617,13 @@ async fn dispatch_method(
     state: &Arc<AppState>,
     client_state: &Arc<ClientState>,
     event_tx: &broadcast::Sender<String>,
-) -> Result<serde_json::Value, String> {
-    let params = params.unwrap_or(serde_json::Value::Null);
+) -> Result<serde_json::Value, RpcError> {
+    let mut params = params.unwrap_or(serde_json::Value::Null);
+
+    // A started placeholder session stays reachable under its placeholder ID
+    if let Some(real_id) = params.get("sessionId").and_then(|v| v.as_str()).and_then(|id| state.session_alias(id)) {
+        params["sessionId"] = serde_json::Value::String(real_id);
+    }
 
     match method {
         // Session state subscription methods
@@ -513,8 +1634,56 @@ async fn dispatch_method(
             let auto_resume = params.get("autoResume")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(true);
+            let last_seq = params.get("lastSeq").and_then(|v| v.as_u64());
             let session_state = subscribe_session_handler(state, client_state, session_id, auto_resume, event_tx).await?;
-            serde_json::to_value(session_state).map_err(|e| e.to_string())
+
+            // A reconnecting client only needs the deltas it missed, if they are still buffered
+            if let Some(last_seq) = last_seq {
+                if let Some(replay) = state.session_state_manager.updates_since(&session_state.id, last_seq) {
+                    info!("WebSocket: Replaying {} update(s) for session {}", replay.len(), session_state.id);
+                    let seq = replay.last().map(|u| u.seq).unwrap_or(last_seq);
+                    return Ok(serde_json::json!({
+                        "sessionId": session_state.id,
+                        "seq": seq,
+                        "replay": replay,
+                    }));
+                }
+            }
+            Ok(serde_json::to_value(session_state)?)
+        }
+        "get_session_tail" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let count = params.get("count").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+
+            // A loaded session is subscribed to; any other is read from its file without resuming it
+            let (session_state, total, has_more, subscribed) =
+                match subscribe_session_handler(state, client_state, session_id, false, event_tx).await {
+                    Ok(mut session_state) => {
+                        let total = session_state.chat_items.len();
+                        let dropped = session_state.keep_latest(count);
+                        // Loading stops at the history limit, so older items may be left in the file
+                        let has_more = dropped > 0 || total >= state.session_registry.history_limit();
+                        (session_state, total, has_more, true)
+                    }
+                    Err(RpcError::SessionNotFound(_)) => {
+                        let info = state.session_registry.get_session_info(session_id)
+                            .ok_or_else(|| RpcError::session_not_found(session_id))?;
+                        let (chat_items, total) = state.session_registry.load_chat_tail(session_id, count);
+                        let mut session_state = SessionState::new(session_id.to_string(), info.cwd);
+                        session_state.usage = info.usage;
+                        session_state.provider_id = state.session_provider(session_id);
+                        session_state.load_history(chat_items);
+                        (session_state, total, total > count, false)
+                    }
+                    Err(e) => return Err(e),
+                };
+            let mut result = serde_json::to_value(session_state)?;
+            result["totalItems"] = serde_json::json!(total);
+            result["hasMore"] = serde_json::json!(has_more);
+            result["subscribed"] = serde_json::json!(subscribed);
+            Ok(result)
         }
         "unsubscribe_session" => {
             let session_id = params.get("sessionId")
@@ -523,6 +1692,21 @@ async fn dispatch_method(
             unsubscribe_session_handler(state, client_state, session_id);
             Ok(serde_json::Value::Null)
         }
+        "subscribe_all" => {
+            // Replaces the client's earlier subscribe_all, if any
+            let cwd = params.get("cwd").and_then(|v| v.as_str()).filter(|cwd| !cwd.is_empty());
+            *client_state.all_sessions.lock().unwrap() = Some(AllSessionsSubscription {
+                cwd: cwd.map(String::from),
+                last_activity: HashMap::new(),
+            });
+            info!("WebSocket: Client {} subscribed to all sessions (cwd={:?})", client_state.client_id, cwd);
+            let sessions = state.session_registry.list_sessions(cwd, 50, 0, false);
+            Ok(serde_json::json!({ "cwd": cwd, "sessions": sessions.sessions }))
+        }
+        "unsubscribe_all" => {
+            let subscribed = client_state.all_sessions.lock().unwrap().take().is_some();
+            Ok(serde_json::json!({ "unsubscribed": subscribed }))
+        }
         "get_session_state" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
@@ -530,8 +1714,8 @@ async fn dispatch_method(
             let auto_resume = params.get("autoResume")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(true);
-            let session_state = get_session_state_handler(state, session_id, auto_resume).await?;
-            serde_json::to_value(session_state).map_err(|e| e.to_string())
+            let session_state = get_session_state_handler(state, Some(client_state), session_id, auto_resume).await?;
+            Ok(serde_json::to_value(session_state)?)
         }
         "get_client_id" => {
             Ok(serde_json::json!({ "clientId": client_state.client_id }))
@@ -540,6 +1724,9 @@ async fn dispatch_method(
             let cwd = params.get("cwd")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
+            if let Some(ref cwd) = cwd {
+                check_path(state, cwd)?;
+            }
             *client_state.current_cwd.write().unwrap() = cwd.clone();
             Ok(serde_json::json!({ "cwd": cwd }))
         }
@@ -554,28 +1741,23 @@ async fn dispatch_method(
             let enabled = params.get("enabled")
                 .and_then(|v| v.as_bool())
                 .ok_or("Missing enabled parameter")?;
-            let success = state.session_state_manager.set_dangerous_mode(&session_id.to_string(), enabled);
+            let update = state.session_state_manager.set_dangerous_mode(&session_id.to_string(), enabled);
+            let success = update.is_some();
 
             // Broadcast update to all WebSocket clients
-            if success {
-                let msg = JsonRpcNotification {
-                    jsonrpc: "2.0".to_string(),
-                    method: "session/state_update".to_string(),
-                    params: serde_json::json!({
-                        "sessionId": session_id,
-                        "update": {
-                            "updateType": "dangerous_mode_updated",
-                            "dangerousMode": enabled
-                        }
-                    }),
-                };
-                if let Ok(json) = serde_json::to_string(&msg) {
-                    let _ = event_tx.send(json);
-                }
+            if let Some(sequenced) = update {
+                send_state_update(event_tx, session_id, sequenced);
             }
 
             Ok(serde_json::json!({ "success": success, "dangerousMode": enabled }))
         }
+        "set_notifications_enabled" => {
+            let enabled = params.get("enabled")
+                .and_then(|v| v.as_bool())
+                .ok_or("Missing enabled parameter")?;
+            state.set_notifications_enabled(enabled)?;
+            Ok(serde_json::json!({ "enabled": enabled }))
+        }
         "get_dangerous_mode" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
@@ -583,6 +1765,71 @@ async fn dispatch_method(
             let enabled = state.session_state_manager.is_dangerous_mode(&session_id.to_string());
             Ok(serde_json::json!({ "dangerousMode": enabled }))
         }
+        "get_always_allowed" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let always_allowed = state.session_state_manager.get_always_allowed(&session_id.to_string());
+            Ok(serde_json::json!({ "alwaysAllowed": always_allowed }))
+        }
+        "revoke_always_allowed" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            // Without a signature every entry is revoked
+            let signature = params.get("signature").and_then(|v| v.as_str());
+            let revoked = state.session_state_manager.revoke_always_allowed(&session_id.to_string(), signature);
+            let revoked = match revoked {
+                Some(sequenced) => {
+                    send_state_update(event_tx, session_id, sequenced);
+                    true
+                }
+                None => false,
+            };
+            Ok(serde_json::json!({ "revoked": revoked }))
+        }
+        "set_permission_policy" => {
+            let session_id = params.get("sessionId").and_then(|v| v.as_str());
+            let cwd = params.get("cwd").and_then(|v| v.as_str());
+            // null clears the policy
+            let policy: Option<PermissionPolicy> = match params.get("policy") {
+                Some(value) => serde_json::from_value(value.clone())
+                    .map_err(|e| RpcError::InvalidParams(format!("Invalid policy: {}", e)))?,
+                None => return Err("Missing policy parameter".into()),
+            };
+            let persist = params.get("persist").and_then(|v| v.as_bool()).unwrap_or(false);
+            set_permission_policy_handler(state, event_tx, session_id, cwd, policy, persist)
+        }
+        "get_permission_policy" => {
+            if let Some(session_id) = params.get("sessionId").and_then(|v| v.as_str()) {
+                let policy = state.permission_policy(session_id);
+                return Ok(serde_json::json!({ "policy": policy }));
+            }
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId or cwd parameter")?;
+            let policy = state.project_permission_policy(cwd).unwrap_or_default();
+            Ok(serde_json::json!({ "policy": policy }))
+        }
+
+        "get_mcp_servers" => {
+            let servers = crate::core::ConfigManager::new().config().mcp_servers.clone();
+            // Lets the settings UI flag http/sse servers the agent can't use
+            let capabilities = state.get_agent_init()
+                .and_then(|init| init.agent_capabilities)
+                .and_then(|caps| caps.mcp_capabilities);
+            Ok(serde_json::json!({ "servers": servers, "capabilities": capabilities }))
+        }
+        "set_mcp_servers" => {
+            let servers: Vec<crate::core::McpServerConfig> = serde_json::from_value(
+                params.get("servers").cloned().ok_or("Missing servers parameter")?
+            ).map_err(|e| RpcError::InvalidParams(format!("Invalid servers parameter: {}", e)))?;
+            state.set_mcp_servers(servers)?;
+            Ok(serde_json::json!({ "success": true }))
+        }
+        "get_agent_logs" => {
+            Ok(serde_json::json!({ "lines": state.agent_log.lines() }))
+        }
 
         // Agent commands
         "connect" => {
@@ -593,9 +1840,32 @@ async fn dispatch_method(
             disconnect_handler(state).await?;
             Ok(serde_json::Value::Null)
         }
+        "get_agent_command" => Ok(agent_command_info(state)),
+        "set_agent_command" => {
+            let command = params.get("command").and_then(|v| v.as_str());
+            let persist = params.get("persist").and_then(|v| v.as_bool()).unwrap_or(false);
+            set_agent_command_handler(state, command, persist)?;
+            Ok(agent_command_info(state))
+        }
         "initialize" => {
             let response = initialize_handler(state).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
+        }
+        "get_agent_capabilities" => {
+            // What the agent advertised when it last started; null until then
+            let init = state.get_agent_init();
+            Ok(serde_json::json!({
+                "agentInfo": init.as_ref().and_then(|init| init.agent_info.clone()),
+                "agentCapabilities": init.and_then(|init| init.agent_capabilities),
+            }))
+        }
+        "authenticate" => {
+            let method_id = params.get("methodId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing methodId parameter")?;
+            let provider_id = params.get("providerId").and_then(|v| v.as_str());
+            authenticate_handler(state, method_id, provider_id).await?;
+            Ok(serde_json::json!({ "success": true }))
         }
         "respond_permission" => {
             let request_id = params.get("requestId").cloned().unwrap_or_default();
@@ -605,33 +1875,7 @@ async fn dispatch_method(
             let outcome: PermissionOutcome = serde_json::from_value(
                 params.get("outcome").cloned().unwrap_or_default()
             ).map_err(|e| e.to_string())?;
-            // Clear pending permission from session state
-            if let Some(ref sid) = session_id {
-                state.session_state_manager.set_pending_permission(sid, None);
-                // Set session status back to Running (continuing to process)
-                state.session_registry.update_status(sid, crate::core::SessionStatus::Running);
-                // Broadcast session list update for status change
-                let session_cwd = state.session_registry.get_session_info(sid)
-                    .map(|info| info.cwd.clone());
-                broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
-            }
-            // Also clear global state for backward compatibility
-            state.set_pending_permission(None);
-            respond_permission_handler(state, request_id.clone(), outcome).await?;
-
-            // Broadcast permission resolved to all clients so they can close their dialogs
-            let msg = JsonRpcNotification {
-                jsonrpc: "2.0".to_string(),
-                method: "permission/resolved".to_string(),
-                params: serde_json::json!({
-                    "requestId": request_id,
-                    "sessionId": session_id,
-                }),
-            };
-            if let Ok(json) = serde_json::to_string(&msg) {
-                let _ = event_tx.send(json);
-            }
-
+            resolve_permission(state, event_tx, session_id.as_deref(), request_id, outcome, false).await?;
             Ok(serde_json::Value::Null)
         }
 
@@ -640,27 +1884,118 @@ async fn dispatch_method(
             let cwd = params.get("cwd")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing cwd parameter")?;
-            let response = create_session_handler(state, cwd, event_tx).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            // Fail clearly on a moved or renamed project instead of in the agent
+            let create_if_missing = params.get("createIfMissing")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            check_path(state, cwd)?;
+            let cwd = &resolve_session_cwd(cwd, create_if_missing).map_err(RpcError::InvalidParams)?;
+            let provider_id = params.get("providerId").and_then(|v| v.as_str());
+            let lazy = params.get("lazy")
+                .and_then(|v| v.as_bool())
+                .unwrap_or_else(|| crate::core::ConfigManager::new().config().agent.lazy_sessions);
+            let response = if lazy {
+                create_placeholder_session_handler(state, cwd, provider_id, event_tx).await?
+            } else {
+                create_session_handler(state, cwd, provider_id, event_tx).await?
+            };
+            track_session(state, client_state, &response.session_id);
+            let mut response = serde_json::to_value(response)?;
+            response["lazy"] = serde_json::Value::Bool(lazy);
+            Ok(response)
         }
         "send_prompt" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing sessionId parameter")?;
+            // A placeholder session gets its agent session on the first prompt
+            let session_id = &start_placeholder_session(state, client_state, session_id, event_tx).await?;
             let content = params.get("content")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing content parameter")?;
             let message_id = params.get("messageId")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
-            let response = send_prompt_handler(state, session_id, content, message_id, event_tx).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            let blocks = vec![ContentBlock::Text { text: content.to_string() }];
+            track_session(state, client_state, session_id);
+            let response = send_prompt_handler(state, session_id, blocks, message_id, event_tx).await?;
+            Ok(serde_json::to_value(response)?)
+        }
+        "send_prompt_blocks" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            // A placeholder session gets its agent session on the first prompt
+            let session_id = &start_placeholder_session(state, client_state, session_id, event_tx).await?;
+            let blocks: Vec<ContentBlock> = serde_json::from_value(
+                params.get("blocks").cloned().ok_or("Missing blocks parameter")?
+            ).map_err(|e| RpcError::InvalidParams(format!("Invalid blocks parameter: {}", e)))?;
+            if blocks.is_empty() {
+                return Err("Prompt must contain at least one content block".into());
+            }
+            let message_id = params.get("messageId")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            ensure_session_agent_connected(state, &state.session_agent(session_id)).await?;
+            validate_prompt_blocks(state, &blocks)?;
+            track_session(state, client_state, session_id);
+            let response = send_prompt_handler(state, session_id, blocks, message_id, event_tx).await?;
+            Ok(serde_json::to_value(response)?)
+        }
+        "send_prompt_with_context" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            // A placeholder session gets its agent session on the first prompt
+            let session_id = &start_placeholder_session(state, client_state, session_id, event_tx).await?;
+            let content = params.get("content")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let resources: Vec<ContextResource> = serde_json::from_value(
+                params.get("resources").cloned().ok_or("Missing resources parameter")?
+            ).map_err(|e| RpcError::InvalidParams(format!("Invalid resources parameter: {}", e)))?;
+            let message_id = params.get("messageId")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let mut blocks = build_context_blocks(resources, |path| check_path(state, path))?;
+            if !content.is_empty() {
+                blocks.insert(0, ContentBlock::Text { text: content.to_string() });
+            }
+            if blocks.is_empty() {
+                return Err("Prompt must contain at least one content block".into());
+            }
+            ensure_session_agent_connected(state, &state.session_agent(session_id)).await?;
+            validate_prompt_blocks(state, &blocks)?;
+            track_session(state, client_state, session_id);
+            let response = send_prompt_handler(state, session_id, blocks, message_id, event_tx).await?;
+            Ok(serde_json::to_value(response)?)
+        }
+        "send_command" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let command = params.get("command")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing command parameter")?;
+            let argument = params.get("argument").and_then(|v| v.as_str());
+            let message_id = params.get("messageId")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let session_state = state.session_state_manager.get_state(&session_id.to_string())
+                .ok_or_else(|| RpcError::session_not_found(session_id))?;
+            let text = session_state.command_prompt(command, argument).map_err(RpcError::InvalidParams)?;
+            // A placeholder session gets its agent session on the first prompt
+            let session_id = &start_placeholder_session(state, client_state, session_id, event_tx).await?;
+            let blocks = vec![ContentBlock::Text { text }];
+            track_session(state, client_state, session_id);
+            let response = send_prompt_handler(state, session_id, blocks, message_id, event_tx).await?;
+            Ok(serde_json::to_value(response)?)
         }
         "cancel_session" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing sessionId parameter")?;
-            cancel_session_handler(state, session_id).await?;
+            cancel_session_handler(state, session_id, event_tx).await?;
             Ok(serde_json::Value::Null)
         }
         "stop_session" => {
@@ -670,6 +2005,17 @@ async fn dispatch_method(
             stop_session_handler(state, session_id, event_tx).await?;
             Ok(serde_json::Value::Null)
         }
+        "set_session_provider" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let provider_id = params.get("providerId").and_then(|v| v.as_str());
+            set_session_provider_handler(state, session_id, provider_id, event_tx)?;
+            let session_cwd = state.session_registry.get_session_info(session_id)
+                .map(|info| info.cwd.clone());
+            broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
+            Ok(serde_json::Value::Null)
+        }
         "set_session_mode" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
@@ -677,15 +2023,57 @@ async fn dispatch_method(
             let mode_id = params.get("modeId")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing modeId parameter")?;
-            set_session_mode_handler(state, session_id, mode_id).await?;
+            set_session_mode_handler(state, session_id, mode_id, event_tx).await?;
+            Ok(serde_json::Value::Null)
+        }
+        "set_session_model" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let model_id = params.get("modelId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing modelId parameter")?;
+            set_session_model_handler(state, session_id, model_id, event_tx).await?;
             Ok(serde_json::Value::Null)
         }
         "list_sessions" => {
             let cwd = params.get("cwd").and_then(|v| v.as_str());
             let limit = params.get("limit").and_then(|v| v.as_u64()).map(|v| v as usize);
             let offset = params.get("offset").and_then(|v| v.as_u64()).map(|v| v as usize);
-            let response = list_sessions_handler(state, cwd, limit, offset).await;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            let cursor = params.get("cursor").and_then(|v| v.as_str());
+            let pinned_only = params.get("pinnedOnly").and_then(|v| v.as_bool()).unwrap_or(false);
+            let response = list_sessions_handler(state, cwd, limit, offset, cursor, pinned_only).await?;
+            Ok(serde_json::to_value(response)?)
+        }
+        "get_project_stats" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            // Reading many session files the first time; later calls hit the metadata cache
+            let registry = state.session_registry.clone();
+            let cwd = cwd.to_string();
+            let stats = tokio::task::spawn_blocking(move || registry.project_stats(&cwd))
+                .await
+                .map_err(|e| format!("Project stats task failed: {}", e))?;
+            Ok(serde_json::to_value(stats)?)
+        }
+        "resolve_session" => {
+            let query = params.get("query")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing query parameter")?;
+            let cwd = params.get("cwd").and_then(|v| v.as_str());
+            let limit = params.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+            let candidates = state.session_registry.resolve(query, cwd, limit);
+            Ok(serde_json::to_value(candidates)?)
+        }
+        "search_sessions" => {
+            let query = params.get("query")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing query parameter")?;
+            let cwd = params.get("cwd").and_then(|v| v.as_str());
+            let limit = params.get("limit").and_then(|v| v.as_u64()).map(|v| v as usize);
+            let results = search_sessions_handler(state, query, cwd, limit);
+            Ok(serde_json::to_value(results)?)
         }
         "resume_session" => {
             let session_id = params.get("sessionId")
@@ -694,8 +2082,34 @@ async fn dispatch_method(
             let cwd = params.get("cwd")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing cwd parameter")?;
+            check_path(state, cwd)?;
             let response = resume_session_handler(state, session_id, cwd).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            track_session(state, client_state, &response.session_id);
+            Ok(serde_json::to_value(response)?)
+        }
+        "edit_message" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let message_id = params.get("messageId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing messageId parameter")?;
+            let content = params.get("content")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing content parameter")?;
+            let response = edit_message_handler(state, client_state, session_id, message_id, content, event_tx).await?;
+            Ok(serde_json::to_value(response)?)
+        }
+        "delete_message" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let message_id = params.get("messageId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing messageId parameter")?;
+            let whole_turn = params.get("wholeTurn").and_then(|v| v.as_bool()).unwrap_or(false);
+            let item_ids = delete_message_handler(state, session_id, message_id, whole_turn, event_tx)?;
+            Ok(serde_json::json!({ "itemIds": item_ids }))
         }
         "fork_session" => {
             let session_id = params.get("sessionId")
@@ -704,15 +2118,125 @@ async fn dispatch_method(
             let cwd = params.get("cwd")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing cwd parameter")?;
+            check_path(state, cwd)?;
             let response = fork_session_handler(state, session_id, cwd).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            track_session(state, client_state, &response.session_id);
+            Ok(serde_json::to_value(response)?)
+        }
+        "load_more_history" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let before_index = params.get("beforeIndex").and_then(|v| v.as_u64()).map(|v| v as usize);
+            let count = params.get("count").and_then(|v| v.as_u64()).unwrap_or(100) as usize;
+            load_more_history_handler(state, session_id, before_index, count, event_tx)
+        }
+        "clone_session" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let info = state.session_registry.clone_session(session_id)?;
+            broadcast_sessions_update(state, event_tx, Some(&info.cwd));
+            Ok(serde_json::to_value(info)?)
+        }
+        "export_session" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let format = params.get("format").and_then(|v| v.as_str()).unwrap_or("markdown");
+            let path = params.get("path").and_then(|v| v.as_str());
+            export_session_handler(state, session_id, format, path).await
+        }
+        "import_session" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            let content = params.get("content").and_then(|v| v.as_str());
+            let path = params.get("path").and_then(|v| v.as_str());
+            let info = import_session_handler(state, content, path, cwd).await?;
+            broadcast_sessions_update(state, event_tx, Some(&info.cwd));
+            Ok(serde_json::to_value(info)?)
+        }
+        "get_session_usage" => {
+            let session_id = params.get("sessionId").and_then(|v| v.as_str());
+            let cwd = params.get("cwd").and_then(|v| v.as_str());
+            get_session_usage_handler(state, session_id, cwd)
         }
         "get_session_info" => {
             let session_id = params.get("sessionId")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing sessionId parameter")?;
             let response = get_session_info_handler(state, session_id).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
+        }
+        "get_system_message_patterns" => {
+            let filter = crate::core::session_registry::system_message_filter();
+            Ok(serde_json::json!({ "patterns": filter.patterns() }))
+        }
+        "get_session_status" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            // Sessions that aren't loaded are stopped, as in list_sessions
+            let status = match state.session_registry.get_status(&session_id.to_string()) {
+                Some(status) => status,
+                None if state.session_registry.get_session_info(session_id).is_some() => crate::core::SessionStatus::Stopped,
+                None => return Err(RpcError::session_not_found(session_id)),
+            };
+            Ok(serde_json::json!({ "sessionId": session_id, "status": status }))
+        }
+        "get_available_commands" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let session_state = state.session_state_manager.get_state(&session_id.to_string())
+                .ok_or_else(|| RpcError::session_not_found(session_id))?;
+            // Empty until the agent has sent its first available_commands_update
+            let commands = session_state.available_commands.unwrap_or_default();
+            Ok(serde_json::json!({ "sessionId": session_id, "commands": commands }))
+        }
+        "get_session_modes" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let session_state = state.session_state_manager.get_state(&session_id.to_string())
+                .ok_or_else(|| RpcError::session_not_found(session_id))?;
+            // Agents that don't support modes report none
+            let (current_mode_id, available_modes) = match session_state.modes {
+                Some(modes) => (Some(modes.current_mode_id), modes.available_modes),
+                None => (None, Vec::new()),
+            };
+            Ok(serde_json::json!({
+                "sessionId": session_id,
+                "currentModeId": current_mode_id,
+                "availableModes": available_modes,
+            }))
+        }
+        "rename_session" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            // null or empty title removes the override
+            let title = params.get("title").and_then(|v| v.as_str());
+            let title = state.session_registry.set_title(session_id, title)?;
+            let session_cwd = state.session_registry.get_session_info(session_id)
+                .map(|info| info.cwd.clone());
+            broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
+            Ok(serde_json::json!({ "sessionId": session_id, "title": title }))
+        }
+        "pin_session" => {
+            let session_id = params.get("sessionId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing sessionId parameter")?;
+            let pinned = params.get("pinned")
+                .and_then(|v| v.as_bool())
+                .ok_or("Missing pinned parameter")?;
+            let session_cwd = state.session_registry.get_session_info(session_id)
+                .map(|info| info.cwd.clone())
+                .ok_or_else(|| RpcError::session_not_found(session_id))?;
+            state.session_registry.set_pinned(session_id, pinned)?;
+            broadcast_sessions_update(state, event_tx, Some(&session_cwd));
+            Ok(serde_json::json!({ "sessionId": session_id, "pinned": pinned }))
         }
         "delete_session" => {
             let session_id = params.get("sessionId")
@@ -721,6 +2245,21 @@ async fn dispatch_method(
             let deleted = delete_session_handler(state, session_id)?;
             Ok(serde_json::json!({ "deleted": deleted }))
         }
+        "delete_sessions" => {
+            let session_ids: Vec<String> = serde_json::from_value(
+                params.get("sessionIds").cloned().ok_or("Missing sessionIds parameter")?
+            ).map_err(|e| RpcError::InvalidParams(format!("Invalid sessionIds parameter: {}", e)))?;
+            let force = params.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+            Ok(delete_sessions_handler(state, &session_ids, force, None, event_tx).await)
+        }
+        "delete_project_sessions" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            let force = params.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+            let session_ids = state.session_registry.project_session_ids(cwd);
+            Ok(delete_sessions_handler(state, &session_ids, force, Some(cwd), event_tx).await)
+        }
         "get_current_session" => {
             let session_id = state.get_current_session();
             Ok(serde_json::json!({ "sessionId": session_id }))
@@ -741,15 +2280,36 @@ async fn dispatch_method(
             let show_hidden = params.get("showHidden")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false);
-            let entries = list_directory_handler(path, show_hidden).await?;
-            serde_json::to_value(entries).map_err(|e| e.to_string())
+            let include_ignored = params.get("includeIgnored").and_then(|v| v.as_bool()).unwrap_or(false);
+            let entries = list_directory_handler(path, show_hidden, include_ignored).await?;
+            Ok(serde_json::to_value(entries)?)
+        }
+        "list_directory_tree" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let max_depth = params.get("maxDepth").and_then(|v| v.as_u64()).map(|v| v as usize);
+            let show_hidden = params.get("showHidden")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let include_ignored = params.get("includeIgnored").and_then(|v| v.as_bool()).unwrap_or(false);
+            let tree = list_directory_tree_handler(state, path, max_depth, show_hidden, include_ignored).await?;
+            Ok(serde_json::to_value(tree)?)
         }
         "read_file" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            let content = read_file_handler(path).await?;
-            Ok(serde_json::Value::String(content))
+            let max_bytes = params.get("maxBytes").and_then(|v| v.as_u64());
+            // With truncate, oversized files return their start as {content, size, truncated}
+            // instead of failing; without it the plain content string is returned
+            let truncate = params.get("truncate").and_then(|v| v.as_bool()).unwrap_or(false);
+            let file = read_file_handler(state, path, max_bytes, truncate).await?;
+            if truncate {
+                Ok(serde_json::to_value(file)?)
+            } else {
+                Ok(serde_json::Value::String(file.content))
+            }
         }
         "write_file" => {
             let path = params.get("path")
@@ -758,9 +2318,19 @@ async fn dispatch_method(
             let content = params.get("content")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing content parameter")?;
-            write_file_handler(path, content).await?;
+            write_file_handler(state, path, content).await?;
             Ok(serde_json::Value::Null)
         }
+        "write_file_with_diff" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let content = params.get("content")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing content parameter")?;
+            let diff = write_file_with_diff_handler(state, path, content).await?;
+            Ok(serde_json::to_value(diff)?)
+        }
         "write_file_binary" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
@@ -768,28 +2338,58 @@ async fn dispatch_method(
             let content = params.get("content")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing content parameter (base64)")?;
-            write_file_binary_handler(path, content).await?;
+            write_file_binary_handler(state, path, content).await?;
             Ok(serde_json::Value::Null)
         }
         "create_file" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            create_file_handler(path).await?;
+            create_file_handler(state, path).await?;
             Ok(serde_json::Value::Null)
         }
         "create_directory" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            create_directory_handler(path).await?;
+            create_directory_handler(state, path).await?;
             Ok(serde_json::Value::Null)
         }
         "delete_path" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            delete_path_handler(path).await?;
+            let to_trash = params.get("toTrash").and_then(|v| v.as_bool()).unwrap_or(true);
+            let trashed = delete_path_handler(state, path, to_trash).await?;
+            Ok(serde_json::json!({ "trashed": trashed }))
+        }
+        "preview_delete" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let preview = preview_delete_handler(state, path).await?;
+            Ok(serde_json::to_value(preview)?)
+        }
+        "preview_rename" => {
+            let from = params.get("from")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing from parameter")?;
+            let to = params.get("to")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing to parameter")?;
+            let preview = preview_rename_handler(state, from, to)?;
+            Ok(serde_json::to_value(preview)?)
+        }
+        "copy_path" => {
+            let from = params.get("from")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing from parameter")?;
+            let to = params.get("to")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing to parameter")?;
+            let overwrite = params.get("overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+            let include_ignored = params.get("includeIgnored").and_then(|v| v.as_bool()).unwrap_or(false);
+            copy_path_handler(state, from, to, overwrite, include_ignored).await?;
             Ok(serde_json::Value::Null)
         }
         "rename_path" => {
@@ -799,22 +2399,119 @@ async fn dispatch_method(
             let to = params.get("to")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing to parameter")?;
-            rename_path_handler(from, to).await?;
+            rename_path_handler(state, from, to).await?;
             Ok(serde_json::Value::Null)
         }
         "read_file_binary" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            let content = read_file_binary_handler(path).await?;
-            serde_json::to_value(content).map_err(|e| e.to_string())
+            let max_bytes = params.get("maxBytes").and_then(|v| v.as_u64());
+            let truncate = params.get("truncate").and_then(|v| v.as_bool()).unwrap_or(false);
+            let content = read_file_binary_handler(state, path, max_bytes, truncate).await?;
+            Ok(serde_json::to_value(content)?)
+        }
+        "read_file_binary_stream" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let stream_id = params.get("streamId")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let chunk_size = params.get("chunkSize")
+                .and_then(|v| v.as_u64())
+                .map(|v| (v as usize).clamp(MIN_FILE_CHUNK_SIZE, MAX_FILE_CHUNK_SIZE))
+                .unwrap_or(DEFAULT_FILE_CHUNK_SIZE);
+            let size = read_file_binary_stream_handler(state, client_state, path, stream_id.clone(), chunk_size).await?;
+            Ok(serde_json::json!({ "streamId": stream_id, "size": size }))
+        }
+        "cancel_file_stream" => {
+            let stream_id = params.get("streamId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing streamId parameter")?;
+            let cancelled = match client_state.file_streams.lock().unwrap().get(stream_id) {
+                Some(flag) => {
+                    flag.store(true, std::sync::atomic::Ordering::SeqCst);
+                    true
+                }
+                None => false,
+            };
+            Ok(serde_json::Value::Bool(cancelled))
+        }
+        "watch_file" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let include_content = params.get("includeContent").and_then(|v| v.as_bool()).unwrap_or(true);
+            watch_file_handler(state, client_state, path, include_content)?;
+            Ok(serde_json::Value::Null)
+        }
+        "unwatch_file" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let removed = client_state.file_watches.lock().unwrap().remove(path).is_some();
+            Ok(serde_json::Value::Bool(removed))
+        }
+        "search_files" => {
+            let root = params.get("root")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing root parameter")?;
+            let query = params.get("query")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing query parameter")?;
+            let options: crate::commands::file::SearchOptions = match params.get("options") {
+                Some(options) => serde_json::from_value(options.clone())
+                    .map_err(|e| RpcError::InvalidParams(format!("Invalid options parameter: {}", e)))?,
+                None => Default::default(),
+            };
+            let search_id = params.get("searchId")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            search_files_handler(state, client_state, root, query, options, search_id.clone())?;
+            Ok(serde_json::json!({ "searchId": search_id }))
         }
         "get_file_info" => {
             let path = params.get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
-            let info = get_file_info_handler(path).await?;
-            serde_json::to_value(info).map_err(|e| e.to_string())
+            let info = get_file_info_handler(state, path).await?;
+            Ok(serde_json::to_value(info)?)
+        }
+
+        // Git commands
+        "git_status" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            let status = git_status_handler(state, cwd).await?;
+            Ok(serde_json::to_value(status)?)
+        }
+        "git_diff" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            let path = params.get("path").and_then(|v| v.as_str());
+            let staged = params.get("staged").and_then(|v| v.as_bool()).unwrap_or(false);
+            let diff = git_diff_handler(state, cwd, path, staged).await?;
+            Ok(serde_json::json!({ "diff": diff }))
+        }
+        "read_file_at_revision" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let revision = params.get("revision").and_then(|v| v.as_str()).unwrap_or("HEAD");
+            let content = read_file_at_revision_handler(state, path, revision).await?;
+            Ok(serde_json::json!({ "path": path, "revision": revision, "content": content }))
+        }
+        "open_path" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            open_path_handler(state, path)?;
+            Ok(serde_json::json!({ "opened": path }))
         }
 
         // Terminal commands
@@ -822,7 +2519,16 @@ async fn dispatch_method(
             let cwd = params.get("cwd").and_then(|v| v.as_str());
             let cols = params.get("cols").and_then(|v| v.as_u64()).unwrap_or(80) as u16;
             let rows = params.get("rows").and_then(|v| v.as_u64()).unwrap_or(24) as u16;
-            let terminal_id = create_terminal_handler(state, cwd, cols, rows).await?;
+            let shell = params.get("shell").and_then(|v| v.as_str());
+            let env: HashMap<String, String> = match params.get("env") {
+                Some(env) => serde_json::from_value(env.clone())
+                    .map_err(|e| RpcError::InvalidParams(format!("Invalid env parameter: {}", e)))?,
+                None => HashMap::new(),
+            };
+            let raw = terminal_encoding_param(&params)?.unwrap_or(false);
+            let terminal_id = create_terminal_handler(state, cwd, cols, rows, shell, &env).await?;
+            client_state.attach_terminal(&terminal_id);
+            client_state.set_terminal_raw(&terminal_id, raw);
             Ok(serde_json::Value::String(terminal_id))
         }
         "write_terminal" => {
@@ -833,6 +2539,7 @@ async fn dispatch_method(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing data parameter")?;
             write_terminal_handler(state, terminal_id, data).await?;
+            client_state.attach_terminal(terminal_id);
             Ok(serde_json::Value::Null)
         }
         "resize_terminal" => {
@@ -846,6 +2553,7 @@ async fn dispatch_method(
                 .and_then(|v| v.as_u64())
                 .ok_or("Missing rows parameter")? as u16;
             resize_terminal_handler(state, terminal_id, cols, rows).await?;
+            client_state.attach_terminal(terminal_id);
             Ok(serde_json::Value::Null)
         }
         "kill_terminal" => {
@@ -857,13 +2565,54 @@ async fn dispatch_method(
         }
         "list_terminals" => {
             let terminals = list_terminals_handler(state).await?;
-            serde_json::to_value(terminals).map_err(|e| e.to_string())
+            if !params.get("includeOutput").and_then(|v| v.as_bool()).unwrap_or(false) {
+                return Ok(serde_json::to_value(terminals)?);
+            }
+            // Attach first so output arriving meanwhile is duplicated rather than lost
+            let terminals = terminals.into_iter().map(|terminal| {
+                client_state.attach_terminal(&terminal.id);
+                let buffer = state.terminal_manager
+                    .get_recent_output(&terminal.id, LIST_TERMINALS_OUTPUT_BYTES)
+                    .ok();
+                let mut value = serde_json::to_value(&terminal).unwrap_or_default();
+                value["buffer"] = serde_json::to_value(buffer).unwrap_or_default();
+                value
+            }).collect::<Vec<_>>();
+            Ok(serde_json::Value::Array(terminals))
+        }
+        "run_command" => {
+            let cwd = params.get("cwd")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing cwd parameter")?;
+            let program = params.get("program")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing program parameter")?;
+            let args: Vec<String> = match params.get("args") {
+                Some(args) => serde_json::from_value(args.clone())
+                    .map_err(|e| RpcError::InvalidParams(format!("Invalid args parameter: {}", e)))?,
+                None => Vec::new(),
+            };
+            let timeout_ms = params.get("timeoutMs").and_then(|v| v.as_u64());
+            let output = run_command_handler(state, cwd, program, &args, timeout_ms).await?;
+            Ok(serde_json::to_value(output)?)
+        }
+        "get_terminal_buffer" => {
+            let terminal_id = params.get("terminalId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing terminalId parameter")?;
+            // Re-attach so a reconnecting client keeps receiving live output
+            client_state.attach_terminal(terminal_id);
+            if let Some(raw) = terminal_encoding_param(&params)? {
+                client_state.set_terminal_raw(terminal_id, raw);
+            }
+            let buffer = state.terminal_manager.get_output(terminal_id)?;
+            Ok(serde_json::to_value(buffer)?)
         }
 
         // Plugin commands
         "list_plugins" => {
             let response = list_plugins_handler()?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "add_marketplace" => {
             let name = params.get("name")
@@ -873,21 +2622,21 @@ async fn dispatch_method(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing gitUrl parameter")?;
             let response = add_marketplace_handler(name, git_url).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "delete_marketplace" => {
             let name = params.get("name")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing name parameter")?;
             let response = delete_marketplace_handler(name)?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "update_marketplace" => {
             let name = params.get("name")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing name parameter")?;
             let response = update_marketplace_handler(name).await?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "install_plugin" => {
             let plugin_name = params.get("pluginName")
@@ -897,14 +2646,14 @@ async fn dispatch_method(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing marketplaceName parameter")?;
             let response = install_plugin_handler(plugin_name, marketplace_name)?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "uninstall_plugin" => {
             let plugin_key = params.get("pluginKey")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing pluginKey parameter")?;
             let response = uninstall_plugin_handler(plugin_key)?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
         "toggle_marketplace" => {
             let name = params.get("name")
@@ -914,7 +2663,7 @@ async fn dispatch_method(
                 .and_then(|v| v.as_bool())
                 .ok_or("Missing enabled parameter")?;
             let response = toggle_marketplace_handler(name, enabled)?;
-            serde_json::to_value(response).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(response)?)
         }
 
         // Heartbeat/keep-alive
@@ -923,26 +2672,11 @@ async fn dispatch_method(
         }
 
         // Server info commands
-        "get_server_info" => {
-            let port = state.get_ws_port();
-            let cwd = std::env::current_dir()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|_| "/".to_string());
-            let home = dirs::home_dir()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|| "/".to_string());
-            let lan_addresses = get_lan_addresses(port);
-            Ok(serde_json::json!({
-                "port": port,
-                "cwd": cwd,
-                "home": home,
-                "lanAddresses": lan_addresses
-            }))
-        }
+        "get_server_info" => Ok(server_info(state)),
 
         // Recent projects commands
         "get_recent_projects" => {
-            let projects = load_recent_projects()?;
+            let projects = list_recent_projects()?;
             Ok(serde_json::json!({ "projects": projects }))
         }
 
@@ -952,8 +2686,21 @@ async fn dispatch_method(
                 .ok_or("Missing path parameter")?;
             let name = params.get("name")
                 .and_then(|v| v.as_str());
+            check_path(state, path)?;
             add_recent_project(path, name)?;
-            let projects = load_recent_projects()?;
+            let projects = list_recent_projects()?;
+            Ok(serde_json::json!({ "projects": projects }))
+        }
+
+        "pin_recent_project" => {
+            let path = params.get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing path parameter")?;
+            let pinned = params.get("pinned")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            set_recent_project_pinned(path, pinned)?;
+            let projects = list_recent_projects()?;
             Ok(serde_json::json!({ "projects": projects }))
         }
 
@@ -962,7 +2709,7 @@ async fn dispatch_method(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing path parameter")?;
             remove_recent_project(path)?;
-            let projects = load_recent_projects()?;
+            let projects = list_recent_projects()?;
             Ok(serde_json::json!({ "projects": projects }))
         }
 
@@ -974,12 +2721,12 @@ async fn dispatch_method(
         // Model config commands
         "get_model_config" => {
             let config = get_model_config_handler()?;
-            serde_json::to_value(config).map_err(|e| e.to_string())
+            Ok(serde_json::to_value(config)?)
         }
         "set_model_config" => {
             let config: crate::core::model_config::ModelConfig = serde_json::from_value(
                 params.clone()
-            ).map_err(|e| format!("Invalid model config: {}", e))?;
+            ).map_err(|e| RpcError::InvalidParams(format!("Invalid model config: {}", e)))?;
             set_model_config_handler(config)?;
             Ok(serde_json::Value::Null)
         }
@@ -990,8 +2737,22 @@ async fn dispatch_method(
             set_active_provider_handler(provider)?;
             Ok(serde_json::Value::Null)
         }
+        "test_provider" => {
+            let provider_id = params.get("providerId")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing providerId parameter")?;
+            let result = test_provider_handler(provider_id).await?;
+            Ok(serde_json::to_value(result)?)
+        }
+        "get_provider_schema" => {
+            use crate::core::model_config::{custom_provider_schema, provider_schemas};
+            Ok(serde_json::json!({
+                "providers": provider_schemas(),
+                "customProvider": custom_provider_schema(),
+            }))
+        }
 
-        _ => Err(format!("Unknown method: {}", method)),
+        _ => Err(RpcError::MethodNotFound(format!("Unknown method: {}", method))),
     }
 }
 
@@ -1007,7 +2768,7 @@ async fn subscribe_session_handler(
     session_id: &str,
     auto_resume: bool,
     event_tx: &broadcast::Sender<String>,
-) -> Result<SessionState, String> {
+) -> Result<SessionState, RpcError> {
     let session_id = session_id.to_string();
 
     // First, try to subscribe if session already exists in memory
@@ -1036,27 +2797,40 @@ async fn subscribe_session_handler(
 
     // Session not in memory - try auto-resume if enabled
     if !auto_resume {
-        return Err(format!("Session not found: {}", session_id));
+        return Err(RpcError::session_not_found(&session_id));
     }
 
     info!("Session {} not in memory, attempting auto-resume...", session_id);
+    // Auto-resumes of other sessions this client still has running are now stale
+    let generation = client_state.begin_resume(&session_id);
 
     // Check if session exists on disk
     let session_info = state.session_registry.get_session_info(&session_id)
-        .ok_or_else(|| format!("Session not found on disk: {}", session_id))?;
+        .ok_or_else(|| RpcError::SessionNotFound(format!("Session not found on disk: {}", session_id)))?;
 
     let cwd = session_info.cwd.clone();
+    let provider_id = state.session_provider(&session_id);
 
     // Ensure ACP agent is running before resuming session
-    ensure_agent_connected(state).await?;
+    let client = ensure_session_agent_connected(state, &state.agent_for(provider_id.clone(), &cwd)).await?;
 
     // Resume the session via ACP agent
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
     let response = manager.resume_session(&session_id, &cwd).await
         .map_err(|e| format!("Failed to resume session: {}", e))?;
+    state.session_registry.link_resumed_session(&session_id, &response.session_id);
 
     info!("Auto-resumed session: {} -> {}", session_id, response.session_id);
 
+    // Another request for the same session resumed it first: share its state
+    if let Some((session_state, _rx)) = state.session_state_manager.subscribe(
+        client_state.client_id.clone(),
+        &response.session_id,
+    ) {
+        client_state.subscribed_sessions.write().unwrap().insert(response.session_id.clone());
+        return Ok(session_state);
+    }
+
     // Register in session registry
     state.session_registry.register_session(
         response.session_id.clone(),
@@ -1066,12 +2840,25 @@ async fn subscribe_session_handler(
     );
 
     // Create EMPTY session state first (for immediate response)
-    let initial_state = state.session_state_manager.create_session(
+    let mut initial_state = state.session_state_manager.create_session(
         response.session_id.clone(),
         cwd.clone(),
         response.modes,
         response.models,
     );
+    if provider_id.is_some() {
+        state.set_session_provider(&response.session_id, provider_id.clone());
+        initial_state.provider_id = provider_id;
+    }
+
+    if client_state.is_superseded(generation) {
+        // The client moved to another session meanwhile. Keep the resumed
+        // session with its history for whoever opens it next, but leave the
+        // client's subscriptions and the current session alone.
+        let chat_items = state.load_chat_items(&session_id);
+        state.session_state_manager.load_history(&response.session_id, chat_items);
+        return Err(superseded_resume(&session_id));
+    }
 
     // Set as current active session
     state.set_current_session(Some(response.session_id.clone())).await;
@@ -1083,7 +2870,7 @@ async fn subscribe_session_handler(
     );
 
     if result.is_none() {
-        return Err(format!("Failed to subscribe to resumed session: {}", response.session_id));
+        return Err(format!("Failed to subscribe to resumed session: {}", response.session_id).into());
     }
 
     // Track subscription in client state
@@ -1097,47 +2884,120 @@ async fn subscribe_session_handler(
         client_state.client_id, response.session_id
     );
 
-    // Spawn background task to load history
-    let state_clone = state.clone();
+    // Spawn background task to load history. Clients are told when it starts
+    // and ends; the start may arrive before this response, so it also carries
+    // the id the client subscribed with.
     let original_session_id = session_id.clone();
     let new_session_id = response.session_id.clone();
-    let event_tx_clone = event_tx.clone();
+    send_history_notification(event_tx, "session/history_loading", serde_json::json!({
+        "sessionId": new_session_id,
+        "originalSessionId": original_session_id,
+    }));
+    let started = std::time::Instant::now();
+
+    let load = {
+        let state = state.clone();
+        let event_tx = event_tx.clone();
+        let original_session_id = original_session_id.clone();
+        let new_session_id = new_session_id.clone();
+        tokio::spawn(async move {
+            let count = load_resumed_history(&state, &event_tx, &original_session_id, &new_session_id).await;
+            send_history_loaded(&event_tx, &new_session_id, &original_session_id, count, started, false);
+        })
+    };
+    client_state.track_resume_history(generation, load.abort_handle());
 
+    // The client moved on and aborted the load: drop the half-loaded session,
+    // unless another client subscribed to it meanwhile and still needs it
+    let state = state.clone();
+    let client_state = client_state.clone();
+    let event_tx = event_tx.clone();
     tokio::spawn(async move {
-        // Load historical chat items from JSONL file
-        let chat_items = state_clone.session_registry.load_chat_items(&original_session_id);
-
-        if chat_items.is_empty() {
-            debug!("No historical chat items to load for session {}", original_session_id);
+        if !load.await.is_err_and(|e| e.is_cancelled()) {
+            return;
+        }
+        unsubscribe_session_handler(&state, &client_state, &new_session_id);
+        if state.session_state_manager.subscriber_count(&new_session_id) > 0 {
+            let count = load_resumed_history(&state, &event_tx, &original_session_id, &new_session_id).await;
+            send_history_loaded(&event_tx, &new_session_id, &original_session_id, count, started, false);
             return;
         }
+        info!("Background: History load for session {} superseded, dropping it", new_session_id);
+        state.session_state_manager.remove_session(&new_session_id);
+        state.session_registry.unregister_session(&new_session_id);
+        send_history_loaded(&event_tx, &new_session_id, &original_session_id, 0, started, true);
+    });
 
-        info!("Background: Loaded {} historical chat items for session {}", chat_items.len(), original_session_id);
+    Ok(initial_state)
+}
 
-        // Update session state with history
-        state_clone.session_state_manager.load_history(&new_session_id, chat_items);
+/// Load a resumed session's history from the JSONL file of `original_session_id`
+/// into `session_id` and broadcast it, returning the number of items
+async fn load_resumed_history(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    original_session_id: &str,
+    session_id: &str,
+) -> usize {
+    // Parsed on the blocking pool, so aborting the caller stops waiting for it
+    let loader = state.clone();
+    let original = original_session_id.to_string();
+    let chat_items = tokio::task::spawn_blocking(move || loader.load_chat_items(&original))
+        .await
+        .unwrap_or_default();
+    let count = chat_items.len();
+    if chat_items.is_empty() {
+        debug!("No historical chat items to load for session {}", original_session_id);
+        return 0;
+    }
 
-        // Broadcast full state update to all subscribers
-        if let Some(updated_state) = state_clone.session_state_manager.get_state(&new_session_id) {
-            let msg = JsonRpcNotification {
-                jsonrpc: "2.0".to_string(),
-                method: "session/state_update".to_string(),
-                params: serde_json::json!({
-                    "sessionId": new_session_id,
-                    "update": {
-                        "updateType": "full_state",
-                        "state": updated_state
-                    }
-                }),
-            };
-            if let Ok(json) = serde_json::to_string(&msg) {
-                let _ = event_tx_clone.send(json);
-            }
-            info!("Background: Broadcasted full state for session {}", new_session_id);
-        }
+    info!("Background: Loaded {} historical chat items for session {}", count, original_session_id);
+
+    // Update session state with history
+    // Broadcast full state update to all subscribers
+    if let Some(sequenced) = state.session_state_manager.load_history(&session_id.to_string(), chat_items) {
+        send_state_update(event_tx, session_id, sequenced);
+        info!("Background: Broadcasted full state for session {}", session_id);
+    }
+    count
+}
+
+/// Broadcast `session/history_loaded`, with `cancelled: true` for a dropped load
+fn send_history_loaded(
+    event_tx: &broadcast::Sender<String>,
+    session_id: &str,
+    original_session_id: &str,
+    count: usize,
+    started: std::time::Instant,
+    cancelled: bool,
+) {
+    let mut params = serde_json::json!({
+        "sessionId": session_id,
+        "originalSessionId": original_session_id,
+        "count": count,
+        "durationMs": started.elapsed().as_millis() as u64,
     });
+    if cancelled {
+        params["cancelled"] = serde_json::Value::Bool(true);
+    }
+    send_history_notification(event_tx, "session/history_loaded", params);
+}
 
-    Ok(initial_state)
+/// Result of an auto-resume the client no longer waits for
+fn superseded_resume(session_id: &str) -> RpcError {
+    RpcError::Superseded(format!("Resume of session {} superseded by a newer request", session_id))
+}
+
+/// Broadcast a `session/history_loading` or `session/history_loaded` notification
+fn send_history_notification(event_tx: &broadcast::Sender<String>, method: &str, params: serde_json::Value) {
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params,
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
+    }
 }
 
 fn unsubscribe_session_handler(
@@ -1165,9 +3025,10 @@ fn unsubscribe_session_handler(
 /// Get session state, with optional auto-resume for historical sessions
 async fn get_session_state_handler(
     state: &Arc<AppState>,
+    client_state: Option<&Arc<ClientState>>,
     session_id: &str,
     auto_resume: bool,
-) -> Result<SessionState, String> {
+) -> Result<SessionState, RpcError> {
     let session_id_str = session_id.to_string();
 
     // First, check if session exists in SessionStateManager
@@ -1177,24 +3038,27 @@ async fn get_session_state_handler(
 
     // Session not in memory - try auto-resume if enabled
     if !auto_resume {
-        return Err(format!("Session not found: {}", session_id));
+        return Err(RpcError::session_not_found(session_id));
     }
 
     info!("Session {} not in memory, attempting auto-resume for get_state...", session_id);
+    let generation = client_state.map(|c| c.begin_resume(session_id));
 
     // Check if session exists on disk
     let session_info = state.session_registry.get_session_info(session_id)
-        .ok_or_else(|| format!("Session not found on disk: {}", session_id))?;
+        .ok_or_else(|| RpcError::SessionNotFound(format!("Session not found on disk: {}", session_id)))?;
 
     let cwd = session_info.cwd.clone();
+    let provider_id = state.session_provider(session_id);
 
     // Ensure ACP agent is running before resuming session
-    ensure_agent_connected(state).await?;
+    let client = ensure_session_agent_connected(state, &state.agent_for(provider_id.clone(), &cwd)).await?;
 
     // Resume the session via ACP agent
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
     let response = manager.resume_session(session_id, &cwd).await
         .map_err(|e| format!("Failed to resume session: {}", e))?;
+    state.session_registry.link_resumed_session(session_id, &response.session_id);
 
     info!("Auto-resumed session for get_state: {} -> {}", session_id, response.session_id);
 
@@ -1207,7 +3071,7 @@ async fn get_session_state_handler(
     );
 
     // Load historical chat items from JSONL file
-    let chat_items = state.session_registry.load_chat_items(session_id);
+    let chat_items = state.load_chat_items(session_id);
     info!("Loaded {} historical chat items for session {}", chat_items.len(), session_id);
 
     // Create session state with historical chat items
@@ -1218,13 +3082,27 @@ async fn get_session_state_handler(
         response.models,
         chat_items,
     );
+    if let Some(usage) = state.session_registry.session_usage(session_id) {
+        state.session_state_manager.set_usage(&response.session_id, usage);
+    }
+    if provider_id.is_some() {
+        state.set_session_provider(&response.session_id, provider_id);
+    }
+
+    // The client moved to another session meanwhile: the resumed session stays
+    // loaded, but doesn't become the current one
+    if let (Some(client_state), Some(generation)) = (client_state, generation) {
+        if client_state.is_superseded(generation) {
+            return Err(superseded_resume(session_id));
+        }
+    }
 
     // Set as current active session
     state.set_current_session(Some(response.session_id.clone())).await;
 
     // Return the new session state
     state.session_state_manager.get_state(&response.session_id)
-        .ok_or_else(|| format!("Failed to get state for resumed session: {}", response.session_id))
+        .ok_or_else(|| RpcError::Internal(format!("Failed to get state for resumed session: {}", response.session_id)))
 }
 
 /// Bundled agent paths configuration (shared runtime approach)
@@ -1308,6 +3186,9 @@ fn find_bundled_agents() -> BundledAgentPaths {
 
 /// Find the agent command to use and any environment variables needed.
 ///
+/// A command set with `set_agent_command`, or else `agent.defaultCommand` from
+/// the config, is used when present; it fails if the program doesn't exist.
+///
 /// When `bundled-agent` feature is enabled:
 /// - Uses bundled bun-runtime + claude-code-agent.js
 /// - Sets CLAUDE_CODE_EXECUTABLE to bundled claude-code-cli wrapper
@@ -1315,7 +3196,25 @@ fn find_bundled_agents() -> BundledAgentPaths {
 /// When `bundled-agent` feature is disabled (default):
 /// - Uses npx @zed-industries/claude-code-acp directly
 /// - Requires Node.js/npx to be installed on the system
-fn find_agent_command() -> (String, Vec<String>, Option<Vec<(String, String)>>) {
+fn find_agent_command(state: &Arc<AppState>) -> Result<AgentCommand, String> {
+    use crate::core::ConfigManager;
+
+    let configured = match state.agent_command() {
+        Some(command) => Some(command),
+        None => ConfigManager::new()
+            .config()
+            .agent
+            .default_command
+            .as_deref()
+            .map(AgentCommand::parse)
+            .transpose()?,
+    };
+    if let Some(agent) = configured {
+        let program = agent.resolve()?;
+        info!("Using configured agent command: {:?} {:?}", program, agent.args);
+        return Ok(AgentCommand { command: program.to_string_lossy().to_string(), ..agent });
+    }
+
     #[cfg(feature = "bundled-agent")]
     {
         let bundled = find_bundled_agents();
@@ -1330,9 +3229,8 @@ fn find_agent_command() -> (String, Vec<String>, Option<Vec<(String, String)>>)
                 env_vars.push(("CLAUDE_CODE_EXECUTABLE".to_string(), cli_wrapper_path));
             }
 
-            let env_vars_opt = if env_vars.is_empty() { None } else { Some(env_vars) };
             info!("Using bundled agent: {} {}", bun_path, acp_js_path);
-            return (bun_path, vec![acp_js_path], env_vars_opt);
+            return Ok(AgentCommand { command: bun_path, args: vec![acp_js_path], env: env_vars });
         }
 
         // Bundled feature enabled but files not found - warn and fallback
@@ -1341,56 +3239,178 @@ fn find_agent_command() -> (String, Vec<String>, Option<Vec<(String, String)>>)
 
     // Use npx (default mode, or fallback when bundled files not found)
     info!("Using npx @zed-industries/claude-code-acp");
-    ("npx".to_string(), vec!["@zed-industries/claude-code-acp".to_string()], None)
+    Ok(AgentCommand {
+        command: "npx".to_string(),
+        args: vec!["@zed-industries/claude-code-acp".to_string()],
+        env: Vec::new(),
+    })
+}
+
+/// The agent command a project's `.aerowork.json` sets. The program must exist.
+fn project_agent_command(line: &str) -> Result<AgentCommand, String> {
+    let agent = AgentCommand::parse(line)?;
+    let program = agent.resolve().map_err(|e| format!("Project agent command: {}", e))?;
+    info!("Using project agent command: {:?} {:?}", program, agent.args);
+    Ok(AgentCommand { command: program.to_string_lossy().to_string(), ..agent })
+}
+
+/// Set (or with None, clear) the agent command for this server run, and with
+/// `persist` also save it as `agent.defaultCommand`. The program must exist.
+/// Running agents keep their command until they restart.
+fn set_agent_command_handler(state: &Arc<AppState>, command: Option<&str>, persist: bool) -> Result<(), RpcError> {
+    let agent_command = command
+        .map(|line| {
+            let agent_command = AgentCommand::parse(line).map_err(RpcError::InvalidParams)?;
+            agent_command.resolve().map_err(RpcError::InvalidParams)?;
+            Ok::<_, RpcError>(agent_command)
+        })
+        .transpose()?;
+    info!("WebSocket: Setting agent command: {:?} (persist: {})", command, persist);
+
+    if persist {
+        let mut manager = crate::core::ConfigManager::new();
+        manager.config_mut().agent.default_command = command.map(|s| s.to_string());
+        manager.save()?;
+    }
+    state.set_agent_command(agent_command);
+    Ok(())
+}
+
+/// The agent command in effect, and where it comes from
+fn agent_command_info(state: &Arc<AppState>) -> serde_json::Value {
+    let default_command = crate::core::ConfigManager::new().config().agent.default_command.clone();
+    let agent_command = find_agent_command(state);
+    serde_json::json!({
+        "source": if state.agent_command().is_some() {
+            "client"
+        } else if default_command.is_some() {
+            "config"
+        } else {
+            "builtin"
+        },
+        "defaultCommand": default_command,
+        "command": agent_command.as_ref().ok(),
+        "error": agent_command.err(),
+    })
 }
 
-/// Ensure ACP agent is running, start if not connected
-/// This is called lazily when creating/resuming/forking sessions
-async fn ensure_agent_connected(state: &Arc<AppState>) -> Result<(), String> {
-    use crate::acp::AcpClient;
-    use crate::core::ModelConfig;
+/// Ensure `agent` is running and return its client slot.
+///
+/// One agent process reads a single set of env vars and runs one command, so
+/// sessions with a provider override or a project agent command run in a
+/// dedicated agent process, started with that provider's env vars and that
+/// command. The default key is the shared agent, which uses the global active
+/// provider synced to ~/.claude/settings.json.
+async fn ensure_session_agent_connected(
+    state: &Arc<AppState>,
+    agent: &AgentKey,
+) -> Result<Arc<tokio::sync::RwLock<Option<crate::acp::AcpClient>>>, String> {
+    use crate::acp::{retry_with_backoff, AcpClient, DEFAULT_CONNECT_ATTEMPTS, DEFAULT_CONNECT_BACKOFF, DEFAULT_REQUEST_TIMEOUT};
+    use crate::core::{ConfigManager, ModelConfig};
+
+    let slot = state.agent_client(agent);
 
     // Check if already connected
     {
-        let guard = state.client.read().await;
+        let guard = slot.read().await;
         if let Some(ref client) = *guard {
             if client.is_connected() {
-                return Ok(());
+                return Ok(slot.clone());
             }
         }
     }
 
     // Not connected, create new connection
     info!("Starting ACP agent (lazy initialization)...");
-    let notification_tx = state.notification_tx.clone();
-    let permission_tx = state.permission_tx.clone();
 
     // Log active provider (config is synced to ~/.claude/settings.json when user saves)
     let model_config = ModelConfig::load().unwrap_or_default();
-    info!("Active provider: {}", model_config.active_provider);
+    let provider_env = match agent.provider_id.as_deref() {
+        Some(id) => {
+            info!("Session provider override: {}", id);
+            Some(model_config.provider_env_vars(id)?)
+        }
+        None => {
+            info!("Active provider: {}", model_config.active_provider);
+            None
+        }
+    };
+
+    let agent_config = ConfigManager::new().config().agent.clone();
 
-    let mut client = AcpClient::new(notification_tx, permission_tx);
+    // Allow slow providers to run long turns without the request timing out
+    let request_timeout = agent_config
+        .request_timeout_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+    info!("ACP request timeout: {:?}", request_timeout);
 
-    // Try to find bundled agent first, fallback to npx
-    let (command, args, env_vars) = find_agent_command();
+    // The project's command, else a configured one, else the bundled agent, else npx
+    let agent_command = match &agent.command {
+        Some(line) => project_agent_command(line),
+        None => find_agent_command(state),
+    }
+    .map_err(|e| {
+        state.agent_log.push(format!("Failed to start agent: {}", e));
+        e
+    })?;
+    let mut env_vars = agent_command.env_vars();
+    let AgentCommand { command, args, .. } = agent_command;
     info!("Using agent command: {} {:?}", command, args);
+    if let Some(provider_env) = provider_env {
+        env_vars.get_or_insert_with(Vec::new).extend(provider_env);
+    }
 
-    client
-        .connect(&command, &args.iter().map(|s| s.as_str()).collect::<Vec<_>>(), env_vars)
-        .await
-        .map_err(|e| e.to_string())?;
+    // Startup errors go to the agent log too, next to whatever the agent printed
+    let log_failure = |e: AcpError| {
+        let message = e.to_string();
+        state.agent_log.push(format!("Failed to start agent ({}): {}", command, message));
+        message
+    };
 
-    // Initialize the agent
-    let init_response = client.initialize().await.map_err(|e| e.to_string())?;
+    // Retry the whole start, so a transient failure (e.g. npx failing to reach
+    // the registry) doesn't fail the session that needed the agent
+    let attempts = agent_config.connect_attempts.unwrap_or(DEFAULT_CONNECT_ATTEMPTS);
+    let backoff = agent_config
+        .connect_backoff_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(DEFAULT_CONNECT_BACKOFF);
+    let (client, init_response) = retry_with_backoff(attempts, backoff, |attempt| {
+        info!("Starting agent, attempt {}/{}", attempt, attempts);
+        let mut client = AcpClient::new(state.notification_tx.clone(), state.permission_tx.clone(), state.agent_event_tx.clone())
+            .with_request_timeout(request_timeout)
+            .with_terminal_manager(state.terminal_manager.clone())
+            .with_agent_log(state.agent_log.clone())
+            .with_extra_path(agent_config.extra_path.clone());
+        let command = command.clone();
+        let args = args.clone();
+        let env_vars = env_vars.clone();
+        async move {
+            client
+                .connect(&command, &args.iter().map(|s| s.as_str()).collect::<Vec<_>>(), env_vars)
+                .await?;
+            let init_response = client.initialize().await?;
+            Ok((client, init_response))
+        }
+    })
+    .await
+    .map_err(log_failure)?;
     info!("ACP agent initialized: {:?}", init_response.agent_info);
+    if *agent == AgentKey::default() || state.get_agent_init().is_none() {
+        state.set_agent_init(Some(init_response.clone()));
+        // Clients only learn what the agent supports once it has started
+        if let Err(e) = state.agent_event_tx.try_send(AgentEvent::Initialized { response: init_response }) {
+            warn!("Failed to forward agent initialize response: {}", e);
+        }
+    }
 
     {
-        let mut guard = state.client.write().await;
+        let mut guard = slot.write().await;
         *guard = Some(client);
     }
 
     info!("ACP agent started and ready");
-    Ok(())
+    Ok(slot)
 }
 
 async fn connect_handler(state: &Arc<AppState>) -> Result<(), String> {
@@ -1412,13 +3432,19 @@ async fn disconnect_handler(state: &Arc<AppState>) -> Result<(), String> {
     info!("WebSocket: Disconnecting from ACP agent...");
     let manager = AgentManager::new(state.client.clone());
     manager.disconnect().await.map_err(|e: AcpError| e.to_string())?;
+    let provider_clients: Vec<_> = state.provider_clients.write().drain().collect();
+    for (agent, client) in provider_clients {
+        if let Err(e) = AgentManager::new(client).disconnect().await {
+            warn!("WebSocket: Failed to disconnect {} agent: {}", agent, e);
+        }
+    }
     info!("WebSocket: Disconnected from ACP agent");
     Ok(())
 }
 
 async fn initialize_handler(state: &Arc<AppState>) -> Result<InitializeResponse, String> {
     // Initialize is now a no-op since we return cached info
-    // Real initialization happens lazily in ensure_agent_connected
+    // Real initialization happens lazily in ensure_session_agent_connected
     info!("WebSocket: Initialize called (agent will start when session is created/resumed)");
 
     // Check if agent is already connected and return its info
@@ -1432,6 +3458,11 @@ async fn initialize_handler(state: &Arc<AppState>) -> Result<InitializeResponse,
         }
     }
 
+    // Agent not running now; return what it advertised when it last started
+    if let Some(init) = state.get_agent_init() {
+        return Ok(init);
+    }
+
     // Agent not running yet, return empty response
     // Real initialization will happen when session is created/resumed
     Ok(InitializeResponse {
@@ -1442,24 +3473,163 @@ async fn initialize_handler(state: &Arc<AppState>) -> Result<InitializeResponse,
     })
 }
 
+/// The first option of a permission request that carries out `action`
+/// (None for `Prompt`)
+fn option_for_action(options: &[PermissionOption], action: PermissionAction) -> Option<&PermissionOption> {
+    options.iter().find(|opt| match action {
+        PermissionAction::Allow => matches!(opt.kind, PermissionOptionKind::AllowOnce | PermissionOptionKind::AllowAlways),
+        PermissionAction::Deny => matches!(opt.kind, PermissionOptionKind::RejectOnce | PermissionOptionKind::RejectAlways),
+        PermissionAction::Prompt => false,
+    })
+}
+
+/// Answer a permission request shown to clients: clear it from the session,
+/// resume the session, send the outcome to the agent and tell clients to close
+/// their dialogs
+async fn resolve_permission(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    session_id: Option<&str>,
+    request_id: serde_json::Value,
+    outcome: PermissionOutcome,
+    auto_resolved: bool,
+) -> Result<(), RpcError> {
+    // Clear pending permission from session state
+    if let Some(sid) = session_id {
+        let sid = sid.to_string();
+        remember_always_allow(state, event_tx, &sid, &request_id, &outcome);
+        state.session_state_manager.set_pending_permission(&sid, None);
+        // Set session status back to Running (continuing to process)
+        set_session_status(state, event_tx, &sid, crate::core::SessionStatus::Running);
+        // Broadcast session list update for status change
+        let session_cwd = state.session_registry.get_session_info(&sid)
+            .map(|info| info.cwd.clone());
+        broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
+    }
+    // Also clear global state for backward compatibility
+    state.set_pending_permission(None);
+    let mut params = serde_json::json!({
+        "requestId": request_id,
+        "sessionId": session_id,
+    });
+    if auto_resolved {
+        params["autoResolved"] = serde_json::Value::Bool(true);
+        params["outcome"] = serde_json::to_value(&outcome)?;
+    }
+    respond_permission_handler(state, session_id, request_id, outcome).await?;
+
+    // Broadcast permission resolved to all clients so they can close their dialogs
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "permission/resolved".to_string(),
+        params,
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
+    }
+    Ok(())
+}
+
+/// Answer `request` with `action` once `timeout` passes, unless a user has
+/// answered it by then
+async fn auto_resolve_permission(
+    state: Arc<AppState>,
+    event_tx: broadcast::Sender<String>,
+    request: PermissionRequest,
+    timeout: std::time::Duration,
+    action: PermissionAction,
+) {
+    tokio::time::sleep(timeout).await;
+    let still_pending = state.session_state_manager
+        .get_pending_permission(&request.session_id)
+        .is_some_and(|pending| pending.request_id == request.request_id);
+    if !still_pending {
+        return;
+    }
+    let Some(option) = option_for_action(&request.options, action) else {
+        warn!("Permission request in session {} has no option to {:?} it", request.session_id, action);
+        return;
+    };
+    info!("Permission request in session {} timed out after {:?}, answering {:?}", request.session_id, timeout, action);
+    let outcome = PermissionOutcome::Selected { option_id: option.option_id.clone() };
+    if let Err(e) = resolve_permission(&state, &event_tx, Some(&request.session_id), request.request_id.clone(), outcome, true).await {
+        warn!("Failed to auto-resolve permission request in session {}: {}", request.session_id, e);
+    }
+}
+
 async fn respond_permission_handler(
     state: &Arc<AppState>,
+    session_id: Option<&str>,
     request_id: serde_json::Value,
     outcome: PermissionOutcome,
 ) -> Result<(), String> {
     info!("WebSocket: Responding to permission request id={:?}", request_id);
-    let manager = AgentManager::new(state.client.clone());
+    // Request IDs belong to the agent process serving the session
+    let client = session_id
+        .map(|sid| state.session_client(sid))
+        .unwrap_or_else(|| state.client.clone());
+    let manager = AgentManager::new(client);
     manager.respond_permission(request_id, outcome).await.map_err(|e: AcpError| e.to_string())
 }
 
-async fn create_session_handler(state: &Arc<AppState>, cwd: &str, event_tx: &broadcast::Sender<String>) -> Result<NewSessionResponse, String> {
+/// Authenticate the agent with one of its advertised auth methods
+async fn authenticate_handler(state: &Arc<AppState>, method_id: &str, provider_id: Option<&str>) -> Result<(), String> {
+    let methods = state.get_agent_init().and_then(|init| init.auth_methods).unwrap_or_default();
+    if !methods.is_empty() && !methods.iter().any(|m| m.id == method_id) {
+        return Err(format!("Unknown auth method: {}", method_id));
+    }
+
+    let agent = AgentKey { provider_id: provider_id.map(String::from), command: None };
+    let client = ensure_session_agent_connected(state, &agent).await?;
+    AgentManager::new(client).authenticate(method_id).await.map_err(|e: AcpError| e.to_string())?;
+    info!("WebSocket: Authenticated with method {}", method_id);
+    Ok(())
+}
+
+/// Turn an agent error into a client error. When the agent needs authentication,
+/// clients are told which methods it offers (`agent/auth_required`) so the UI can
+/// ask the user to pick one and call `authenticate`.
+fn agent_error(state: &Arc<AppState>, event_tx: &broadcast::Sender<String>, error: &AcpError) -> RpcError {
+    if !error.is_auth_required() {
+        return RpcError::from(error);
+    }
+
+    let methods = state.get_agent_init().and_then(|init| init.auth_methods).unwrap_or_default();
+    warn!("WebSocket: Agent requires authentication ({} method(s) available)", methods.len());
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "agent/auth_required".to_string(),
+        params: serde_json::json!({ "authMethods": methods }),
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
+    }
+    RpcError::AuthRequired {
+        message: format!("Authentication required: {}", error),
+        auth_methods: serde_json::to_value(&methods).unwrap_or_default(),
+    }
+}
+
+async fn create_session_handler(
+    state: &Arc<AppState>,
+    cwd: &str,
+    provider_id: Option<&str>,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<NewSessionResponse, RpcError> {
     info!("WebSocket: Creating new session in {}", cwd);
+    let project = load_project_config(state, cwd)?;
+    // An explicit provider beats the project's, which beats the active one
+    let provider_id = provider_id.or(project.as_ref().and_then(|p| p.provider.as_deref()));
+    let agent = AgentKey {
+        provider_id: provider_id.map(String::from),
+        command: project.as_ref().and_then(|p| p.agent_command.clone()),
+    };
 
     // Ensure ACP agent is running before creating session
-    ensure_agent_connected(state).await?;
+    let client = ensure_session_agent_connected(state, &agent).await?;
 
-    let manager = AgentManager::new(state.client.clone());
-    let response = manager.create_session(cwd).await.map_err(|e: AcpError| e.to_string())?;
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
+    let response = manager.create_session(cwd).await.map_err(|e: AcpError| agent_error(state, event_tx, &e))?;
 
     // Register session in the registry
     state.session_registry.register_session(
@@ -1476,6 +3646,9 @@ async fn create_session_handler(state: &Arc<AppState>, cwd: &str, event_tx: &bro
         response.modes.clone(),
         response.models.clone(),
     );
+    if let Some(provider_id) = provider_id {
+        state.set_session_provider(&response.session_id, Some(provider_id.to_string()));
+    }
 
     // Set as current active session and broadcast to all clients
     state.set_current_session(Some(response.session_id.clone())).await;
@@ -1487,49 +3660,176 @@ async fn create_session_handler(state: &Arc<AppState>, cwd: &str, event_tx: &bro
     Ok(response)
 }
 
-async fn send_prompt_handler(state: &Arc<AppState>, session_id: &str, content: &str, message_id: Option<String>, event_tx: &broadcast::Sender<String>) -> Result<PromptResponse, String> {
+/// Create a session without starting the agent: a placeholder is registered so
+/// the chat opens at once, and `start_placeholder_session` creates the agent
+/// session on the first prompt.
+async fn create_placeholder_session_handler(
+    state: &Arc<AppState>,
+    cwd: &str,
+    provider_id: Option<&str>,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<NewSessionResponse, RpcError> {
+    let project = load_project_config(state, cwd)?;
+    let provider_id = provider_id.or(project.as_ref().and_then(|p| p.provider.as_deref()));
+
+    let session_id = format!("pending-{}", uuid::Uuid::new_v4());
+    info!("WebSocket: Creating placeholder session {} in {}", session_id, cwd);
+    state.session_registry.register_session(session_id.clone(), cwd.to_string(), None, None);
+    state.session_state_manager.create_session(session_id.clone(), cwd.to_string(), None, None);
+    if let Some(provider_id) = provider_id {
+        state.set_session_provider(&session_id, Some(provider_id.to_string()));
+    }
+    state.add_placeholder_session(&session_id);
+
+    state.set_current_session(Some(session_id.clone())).await;
+    broadcast_sessions_update(state, event_tx, Some(cwd));
+
+    Ok(NewSessionResponse { session_id, modes: None, models: None })
+}
+
+/// The session a prompt goes to. For a placeholder session, the agent session is
+/// created now and the placeholder's state (chat, settings, subscribers) moves
+/// to it; clients are told with `session/started`. Other IDs are returned as is.
+async fn start_placeholder_session(
+    state: &Arc<AppState>,
+    client_state: &Arc<ClientState>,
+    session_id: &str,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<String, RpcError> {
+    if !state.is_placeholder_session(session_id) {
+        return Ok(session_id.to_string());
+    }
+    let _guard = state.placeholder_start_lock.lock().await;
+    // Another prompt may have started it while we waited
+    if let Some(real_id) = state.session_alias(session_id) {
+        return Ok(real_id);
+    }
+    let placeholder = session_id.to_string();
+    let cwd = state.session_registry.get_active_session(&placeholder)
+        .map(|session| session.cwd)
+        .ok_or_else(|| RpcError::session_not_found(session_id))?;
+    let provider_id = state.session_provider(session_id);
+    info!("WebSocket: Starting placeholder session {} in {}", session_id, cwd);
+
+    let client = ensure_session_agent_connected(state, &state.agent_for(provider_id.clone(), &cwd)).await?;
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
+    let response = manager.create_session(&cwd).await.map_err(|e: AcpError| agent_error(state, event_tx, &e))?;
+    let real_id = response.session_id.clone();
+
+    state.session_registry.unregister_session(&placeholder);
+    state.session_registry.register_session(real_id.clone(), cwd.clone(), response.modes.clone(), response.models.clone());
+    state.session_state_manager.rename_session(&placeholder, real_id.clone(), response.modes.clone(), response.models.clone());
+    if provider_id.is_some() {
+        state.set_session_provider(&real_id, provider_id);
+    }
+    state.complete_placeholder_session(session_id, &real_id);
+    track_session(state, client_state, &real_id);
+    if state.get_current_session().as_deref() == Some(session_id) {
+        state.set_current_session(Some(real_id.clone())).await;
+    }
+
+    let msg = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "session/started".to_string(),
+        params: serde_json::json!({
+            "placeholderId": session_id,
+            "sessionId": real_id,
+            "modes": response.modes,
+            "models": response.models,
+        }),
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        let _ = event_tx.send(json);
+    }
+    broadcast_sessions_update(state, event_tx, Some(&cwd));
+
+    info!("WebSocket: Placeholder session {} started as {}", session_id, real_id);
+    Ok(real_id)
+}
+
+/// Load the `.aerowork.json` of a session's project and allow its sandbox roots.
+/// An invalid file fails the session start, so mistakes surface right away.
+fn load_project_config(state: &Arc<AppState>, cwd: &str) -> Result<Option<crate::core::ProjectConfig>, String> {
+    let Some(loaded) = crate::core::ProjectConfig::load(std::path::Path::new(cwd))? else {
+        return Ok(None);
+    };
+    info!("Using project config {:?}", loaded.path);
+    for root in loaded.sandbox_roots()? {
+        state.path_sandbox.allow_root(root);
+    }
+    Ok(Some(loaded.config))
+}
+
+/// Check prompt content blocks against the capabilities the agent advertised
+fn validate_prompt_blocks(state: &Arc<AppState>, blocks: &[ContentBlock]) -> Result<(), String> {
+    let prompt_capabilities = state.get_agent_init()
+        .and_then(|init| init.agent_capabilities)
+        .and_then(|caps| caps.prompt_capabilities)
+        .unwrap_or_default();
+
+    let has_image = blocks.iter().any(|b| matches!(b, ContentBlock::Image { .. }));
+    if has_image && prompt_capabilities.image != Some(true) {
+        return Err("The agent does not accept image content".to_string());
+    }
+    let has_resource = blocks.iter().any(|b| matches!(b, ContentBlock::Resource { .. }));
+    if has_resource && prompt_capabilities.embedded_context != Some(true) {
+        return Err("The agent does not accept embedded context".to_string());
+    }
+
+    Ok(())
+}
+
+/// Text shown in the chat for a prompt made of content blocks
+fn prompt_display_text(blocks: &[ContentBlock]) -> String {
+    blocks
+        .iter()
+        .map(|block| match block {
+            ContentBlock::Text { text } => text.clone(),
+            ContentBlock::Image { mime_type, .. } => format!("[Image: {}]", mime_type),
+            ContentBlock::ResourceLink { name, .. } => format!("[Resource: {}]", name),
+            ContentBlock::Resource { resource } => match resource {
+                ResourceContents::Text { uri, .. } | ResourceContents::Blob { uri, .. } => {
+                    format!("[Resource: {}]", uri)
+                }
+            },
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+async fn send_prompt_handler(state: &Arc<AppState>, session_id: &str, blocks: Vec<ContentBlock>, message_id: Option<String>, event_tx: &broadcast::Sender<String>) -> Result<PromptResponse, RpcError> {
+    // Prompts of one session run one after another, never interleaved
+    let _prompt_guard = acquire_prompt(state, event_tx, session_id).await?;
     info!("WebSocket: Sending prompt to session {}", session_id);
+    let content = prompt_display_text(&blocks);
 
     // Get session cwd for filtering broadcasts
     let session_cwd = state.session_registry.get_session_info(session_id)
         .map(|info| info.cwd.clone());
 
     // Set session status to Running and broadcast
-    state.session_registry.update_status(&session_id.to_string(), crate::core::SessionStatus::Running);
+    set_session_status(state, event_tx, session_id, crate::core::SessionStatus::Running);
     broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
 
     // Add user message to SessionStateManager (single source of truth)
-    // If message_id is provided (from frontend optimistic update), use it to avoid duplicates
-    state.session_state_manager.add_user_message(&session_id.to_string(), content.to_string(), message_id.clone());
-
-    // Broadcast user message to all WebSocket clients
-    if let Some(session_state) = state.session_state_manager.get_state(&session_id.to_string()) {
-        // Get the last chat item which should be the user message we just added
-        if let Some(last_item) = session_state.chat_items.last() {
-            let msg = JsonRpcNotification {
-                jsonrpc: "2.0".to_string(),
-                method: "session/state_update".to_string(),
-                params: serde_json::json!({
-                    "sessionId": session_id,
-                    "update": {
-                        "updateType": "message_added",
-                        "message": match last_item {
-                            crate::core::session_state::ChatItem::Message { message } => serde_json::to_value(message).ok(),
-                            _ => None,
-                        }
-                    }
-                }),
-            };
-            if let Ok(json) = serde_json::to_string(&msg) {
-                let _ = event_tx.send(json);
-            }
+    // If message_id is provided (from frontend optimistic update), use it to avoid duplicates.
+    // A message that is already the latest item (an edited one) is not added again.
+    let already_recorded = message_id.is_some()
+        && state.session_state_manager.last_item_id(&session_id.to_string()) == message_id;
+    if !already_recorded {
+        // Broadcast user message to all WebSocket clients
+        if let Some(sequenced) = state.session_state_manager.add_user_message(&session_id.to_string(), content.clone(), message_id.clone()) {
+            send_state_update(event_tx, session_id, sequenced);
         }
     }
+    let user_message_id = state.session_state_manager.last_item_id(&session_id.to_string());
 
-    let manager = AgentManager::new(state.client.clone());
+    let provider_id = state.session_provider(session_id);
+    let agent = state.session_agent(session_id);
+    let manager = AgentManager::new(state.agent_client(&agent));
 
     // Try to send prompt, auto-resume if session not found in ACP agent
-    let response = match manager.prompt(session_id, content).await {
+    let response = match manager.prompt_with_blocks(session_id, blocks.clone()).await {
         Ok(resp) => resp,
         Err(e) => {
             // Check if error is "Session not found" - need to resume
@@ -1551,16 +3851,20 @@ async fn send_prompt_handler(state: &Arc<AppState>, session_id: &str, content: &
 
                 // Get session info to find cwd
                 let session_info = state.session_registry.get_session_info(session_id)
-                    .ok_or_else(|| format!("Session {} not found in registry", session_id))?;
+                    .ok_or_else(|| RpcError::SessionNotFound(format!("Session {} not found in registry", session_id)))?;
 
                 let cwd = session_info.cwd;
 
                 // Ensure ACP agent is running before resuming
-                ensure_agent_connected(state).await?;
+                ensure_session_agent_connected(state, &agent).await?;
 
                 // Resume the session
-                let resume_response = manager.resume_session(session_id, &cwd).await
+                let resume_response = AgentManager::new(state.agent_client(&agent))
+                    .with_mcp_servers(state.mcp_servers())
+                    .resume_session(session_id, &cwd)
+                    .await
                     .map_err(|e| format!("Failed to auto-resume session: {}", e))?;
+                state.session_registry.link_resumed_session(session_id, &resume_response.session_id);
 
                 info!("WebSocket: Auto-resumed session {} -> {}", session_id, resume_response.session_id);
 
@@ -1573,41 +3877,29 @@ async fn send_prompt_handler(state: &Arc<AppState>, session_id: &str, content: &
                 );
 
                 // Load historical chat items from JSONL file
-                let history_items = state.session_registry.load_chat_items(session_id);
+                let history_items = state.load_chat_items(session_id);
                 info!("Loaded {} historical chat items for auto-resumed session {}", history_items.len(), session_id);
 
-                // Create/update session state with historical chat items
-                state.session_state_manager.create_session_with_history(
+                // Merge the history into the live state, which already holds the user message
+                state.session_state_manager.append_history_if_absent(
                     resume_response.session_id.clone(),
                     cwd,
                     resume_response.modes,
                     resume_response.models,
                     history_items,
                 );
+                if provider_id.is_some() {
+                    state.set_session_provider(&resume_response.session_id, provider_id.clone());
+                }
 
-                // Re-add the user message to the new session state
-                state.session_state_manager.add_user_message(&resume_response.session_id, content.to_string(), message_id.clone());
-
-                // Broadcast user message to all WebSocket clients
-                if let Some(session_state) = state.session_state_manager.get_state(&resume_response.session_id) {
-                    if let Some(last_item) = session_state.chat_items.last() {
-                        let msg = JsonRpcNotification {
-                            jsonrpc: "2.0".to_string(),
-                            method: "session/state_update".to_string(),
-                            params: serde_json::json!({
-                                "sessionId": resume_response.session_id,
-                                "update": {
-                                    "updateType": "message_added",
-                                    "message": match last_item {
-                                        crate::core::session_state::ChatItem::Message { message } => serde_json::to_value(message).ok(),
-                                        _ => None,
-                                    }
-                                }
-                            }),
-                        };
-                        if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = event_tx.send(json);
-                        }
+                // Re-add the user message only if the resumed session doesn't have it
+                // (it resumed under a new ID)
+                let has_user_message = user_message_id.as_deref()
+                    .is_some_and(|id| state.session_state_manager.has_item(&resume_response.session_id, id));
+                if !has_user_message {
+                    // Broadcast user message to all WebSocket clients
+                    if let Some(sequenced) = state.session_state_manager.add_user_message(&resume_response.session_id, content.clone(), message_id.clone()) {
+                        send_state_update(event_tx, &resume_response.session_id, sequenced);
                     }
                 }
 
@@ -1615,27 +3907,243 @@ async fn send_prompt_handler(state: &Arc<AppState>, session_id: &str, content: &
                 state.set_current_session(Some(resume_response.session_id.clone())).await;
 
                 // Retry the prompt with the resumed session
-                manager.prompt(&resume_response.session_id, content).await
+                manager.prompt_with_blocks(&resume_response.session_id, blocks).await
                     .map_err(|e| format!("Failed to send prompt after resume: {}", e))?
             } else {
-                return Err(e.to_string());
+                return Err(agent_error(state, event_tx, &e));
             }
         }
     };
 
     info!("WebSocket: Prompt completed with stop_reason: {:?}", response.stop_reason);
+    state.flush_chunks(session_id).await;
+
+    if !matches!(response.stop_reason, crate::acp::StopReason::Cancelled) {
+        let summary = state.session_registry.get_session_info(session_id)
+            .map(|info| info.summary)
+            .unwrap_or_default();
+        state.notify_desktop("Agent finished", &summary);
+    }
+
+    refresh_session_usage(state, session_id, event_tx);
 
     // Set session status back to Idle after prompt completes and broadcast
-    state.session_registry.update_status(&session_id.to_string(), crate::core::SessionStatus::Idle);
+    set_session_status(state, event_tx, session_id, crate::core::SessionStatus::Idle);
     broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
 
     Ok(response)
 }
 
-async fn cancel_session_handler(state: &Arc<AppState>, session_id: &str) -> Result<(), String> {
+/// Re-read a session's token usage from its file and broadcast it if it changed
+fn refresh_session_usage(state: &Arc<AppState>, session_id: &str, event_tx: &broadcast::Sender<String>) {
+    let Some(usage) = state.session_registry.session_usage(session_id) else {
+        return;
+    };
+    if let Some(sequenced) = state.session_state_manager.set_usage(&session_id.to_string(), usage) {
+        send_state_update(event_tx, session_id, sequenced);
+    }
+}
+
+/// Remember the tool call of a pending permission request when the user picked
+/// its "always allow" option, so matching requests are approved automatically
+fn remember_always_allow(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    session_id: &str,
+    request_id: &serde_json::Value,
+    outcome: &PermissionOutcome,
+) {
+    let PermissionOutcome::Selected { option_id } = outcome else {
+        return;
+    };
+    let Some(pending) = state.session_state_manager.get_pending_permission(&session_id.to_string()) else {
+        return;
+    };
+    if &pending.request_id != request_id {
+        return;
+    }
+    let always = pending.options.iter().any(|opt| {
+        &opt.option_id == option_id && matches!(opt.kind, PermissionOptionKind::AllowAlways)
+    });
+    if always {
+        let signature = permission_signature(&pending.tool_call);
+        info!("WebSocket: Always allowing {} in session {}", signature, session_id);
+        if let Some(sequenced) = state.session_state_manager.allow_always(&session_id.to_string(), signature) {
+            send_state_update(event_tx, session_id, sequenced);
+        }
+    }
+}
+
+/// Set a session's permission policy and/or save it for a project.
+/// With `persist`, a session's policy is also saved for the session's project.
+fn set_permission_policy_handler(
+    state: &Arc<AppState>,
+    event_tx: &broadcast::Sender<String>,
+    session_id: Option<&str>,
+    cwd: Option<&str>,
+    policy: Option<PermissionPolicy>,
+    persist: bool,
+) -> Result<serde_json::Value, RpcError> {
+    let mut project = cwd.map(|cwd| cwd.to_string());
+
+    if let Some(session_id) = session_id {
+        let sequenced = state.session_state_manager.set_permission_policy(&session_id.to_string(), policy.clone())
+            .ok_or_else(|| RpcError::session_not_found(session_id))?;
+        send_state_update(event_tx, session_id, sequenced);
+
+        if !persist {
+            project = None;
+        } else if project.is_none() {
+            project = state.session_registry.get_session_info(session_id).map(|info| info.cwd);
+        }
+    } else if cwd.is_none() {
+        return Err("Missing sessionId or cwd parameter".into());
+    }
+
+    if let Some(ref project) = project {
+        state.set_project_permission_policy(project, policy.clone())?;
+        info!("WebSocket: Saved permission policy for project {}", project);
+    }
+
+    Ok(serde_json::json!({ "policy": policy, "cwd": project }))
+}
+
+/// Export a session as Markdown or JSON. Sessions that aren't loaded are read
+/// from disk without resuming them. Writes to `path` when given, otherwise
+/// returns the content.
+async fn export_session_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    format: &str,
+    path: Option<&str>,
+) -> Result<serde_json::Value, RpcError> {
+    let info = state.session_registry.get_session_info(session_id);
+    let session = match state.session_state_manager.get_state(&session_id.to_string()) {
+        Some(session) => session,
+        None => {
+            let info = info.as_ref().ok_or_else(|| RpcError::session_not_found(session_id))?;
+            let mut session = SessionState::new(session_id.to_string(), info.cwd.clone());
+            session.load_history(state.load_chat_items(session_id));
+            session
+        }
+    };
+
+    let content = match format {
+        "markdown" => {
+            let title = info.map(|info| info.summary).unwrap_or_else(|| session_id.to_string());
+            crate::core::session_export::to_markdown(&session, &title)
+        }
+        "json" => serde_json::to_string_pretty(&session).map_err(|e| e.to_string())?,
+        other => return Err(RpcError::InvalidParams(format!("Unknown export format: {}", other))),
+    };
+
+    match path {
+        Some(path) => {
+            write_file_handler(state, path, &content).await?;
+            info!("WebSocket: Exported session {} to {}", session_id, path);
+            Ok(serde_json::json!({ "path": path }))
+        }
+        None => Ok(serde_json::json!({ "content": content })),
+    }
+}
+
+/// Largest transcript `import_session` accepts
+const MAX_IMPORT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Import a JSONL transcript given inline (`content`) or as a file on the server (`path`)
+async fn import_session_handler(
+    state: &Arc<AppState>,
+    content: Option<&str>,
+    path: Option<&str>,
+    cwd: &str,
+) -> Result<SessionInfo, RpcError> {
+    check_path(state, cwd)?;
+    if !std::path::Path::new(cwd).is_dir() {
+        return Err(RpcError::InvalidParams(format!("Not a directory: {}", cwd)));
+    }
+    let content = match (content, path) {
+        (Some(content), None) => content.to_string(),
+        (None, Some(path)) => {
+            let resolved = check_path(state, path)?;
+            let size = tokio::fs::metadata(&resolved)
+                .await
+                .map_err(|e| RpcError::InvalidParams(format!("Cannot read {}: {}", path, e)))?
+                .len();
+            if size > MAX_IMPORT_BYTES {
+                return Err(RpcError::InvalidParams(format!(
+                    "{} is too large to import ({} bytes, limit {})",
+                    path, size, MAX_IMPORT_BYTES
+                )));
+            }
+            tokio::fs::read_to_string(&resolved)
+                .await
+                .map_err(|e| RpcError::InvalidParams(format!("Cannot read {}: {}", path, e)))?
+        }
+        _ => return Err(RpcError::InvalidParams("Pass either content or path".to_string())),
+    };
+
+    info!("WebSocket: Importing session into {}", cwd);
+    state.session_registry.import_session(&content, cwd).map_err(RpcError::InvalidParams)
+}
+
+/// Token usage of one session, or summed over a project's sessions
+fn get_session_usage_handler(
+    state: &Arc<AppState>,
+    session_id: Option<&str>,
+    cwd: Option<&str>,
+) -> Result<serde_json::Value, RpcError> {
+    if let Some(session_id) = session_id {
+        let usage = state.session_registry.session_usage(session_id)
+            .or_else(|| state.session_state_manager.get_state(&session_id.to_string()).map(|s| s.usage))
+            .ok_or_else(|| RpcError::session_not_found(session_id))?;
+        return Ok(serde_json::json!({ "sessionId": session_id, "usage": usage }));
+    }
+
+    let cwd = cwd.ok_or("Missing sessionId or cwd parameter")?;
+    let sessions = state.session_registry.list_sessions(Some(cwd), usize::MAX, 0, false).sessions;
+    let mut usage = TokenUsage::default();
+    for session in &sessions {
+        usage += session.usage;
+    }
+    Ok(serde_json::json!({ "cwd": cwd, "sessions": sessions.len(), "usage": usage }))
+}
+
+async fn cancel_session_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<(), String> {
     info!("WebSocket: Cancelling session {}", session_id);
-    let manager = AgentManager::new(state.client.clone());
-    manager.cancel(session_id).await.map_err(|e: AcpError| e.to_string())
+    let manager = AgentManager::new(state.session_client(session_id));
+
+    // ACP requires answering an outstanding permission request with `cancelled`
+    if let Some(permission) = state.session_state_manager.get_pending_permission(&session_id.to_string()) {
+        let _ = manager.respond_permission(permission.request_id.clone(), PermissionOutcome::Cancelled).await;
+        state.session_state_manager.set_pending_permission(&session_id.to_string(), None);
+        state.set_pending_permission(None);
+
+        let msg = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "permission/resolved".to_string(),
+            params: serde_json::json!({
+                "requestId": permission.request_id,
+                "sessionId": session_id,
+            }),
+        };
+        if let Ok(json) = serde_json::to_string(&msg) {
+            let _ = event_tx.send(json);
+        }
+    }
+
+    // Resolves the in-flight prompt immediately; send_prompt_handler then returns Cancelled
+    let result = manager.cancel(session_id).await.map_err(|e: AcpError| e.to_string());
+
+    set_session_status(state, event_tx, session_id, crate::core::SessionStatus::Idle);
+    let session_cwd = state.session_registry.get_session_info(session_id)
+        .map(|info| info.cwd.clone());
+    broadcast_sessions_update(state, event_tx, session_cwd.as_deref());
+
+    result
 }
 
 /// Stop a session: cancel if running, unload from memory, update status to stopped
@@ -1650,6 +4158,24 @@ async fn stop_session_handler(
     let session_cwd = state.session_registry.get_session_info(session_id)
         .map(|info| info.cwd.clone());
 
+    stop_session(state, session_id).await;
+
+    // Broadcast sessions update to all clients (filtered by cwd if available)
+    let sessions = state.session_registry.list_sessions(session_cwd.as_deref(), 50, 0, false);
+    let notification = JsonRpcNotification {
+        jsonrpc: "2.0".to_string(),
+        method: "sessions/updated".to_string(),
+        params: serde_json::json!({ "sessions": sessions.sessions }),
+    };
+    let _ = event_tx.send(serde_json::to_string(&notification).unwrap());
+
+    info!("Session {} stopped successfully", session_id);
+    Ok(())
+}
+
+/// Cancel a session's turn if one is running, then drop it from memory and
+/// from the active sessions
+async fn stop_session(state: &Arc<AppState>, session_id: &str) {
     // Check current session status
     let current_status = state.session_registry.get_status(&session_id.to_string());
 
@@ -1657,7 +4183,7 @@ async fn stop_session_handler(
     if let Some(status) = current_status {
         if status == crate::core::SessionStatus::Running || status == crate::core::SessionStatus::Pending {
             info!("Session {} is {:?}, cancelling first...", session_id, status);
-            let manager = AgentManager::new(state.client.clone());
+            let manager = AgentManager::new(state.session_client(session_id));
             // Ignore cancel errors (session might already be done)
             let _ = manager.cancel(session_id).await;
         }
@@ -1668,50 +4194,143 @@ async fn stop_session_handler(
 
     // Unregister from active sessions (this sets active=false in list_sessions output)
     state.session_registry.unregister_session(&session_id.to_string());
+}
 
-    // Broadcast sessions update to all clients (filtered by cwd if available)
-    let sessions = state.session_registry.list_sessions(session_cwd.as_deref(), 50, 0);
-    let notification = JsonRpcNotification {
-        jsonrpc: "2.0".to_string(),
-        method: "sessions/updated".to_string(),
-        params: serde_json::json!({ "sessions": sessions.sessions }),
-    };
-    let _ = event_tx.send(serde_json::to_string(&notification).unwrap());
+/// Set or clear (None) a session's provider override.
+/// The session moves to the provider's agent on its next prompt: that agent
+/// doesn't know the session yet, so send_prompt_handler auto-resumes it there.
+fn set_session_provider_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    provider_id: Option<&str>,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<(), String> {
+    info!("WebSocket: Setting session {} provider to {:?}", session_id, provider_id);
+    if let Some(provider_id) = provider_id {
+        // Validate before storing
+        ModelConfig::load()?.provider_env_vars(provider_id)?;
+    }
 
-    info!("Session {} stopped successfully", session_id);
+    let status = state.session_registry.get_status(&session_id.to_string());
+    if matches!(status, Some(crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending)) {
+        return Err("Cannot change the provider while the session is running".to_string());
+    }
+    if status.is_none() && !state.session_state_manager.has_session(&session_id.to_string()) {
+        return Err(format!("Session not loaded: {}", session_id));
+    }
+
+    if let Some(update) = state.set_session_provider(session_id, provider_id.map(|s| s.to_string())) {
+        send_state_update(event_tx, session_id, update);
+    }
     Ok(())
 }
 
-async fn set_session_mode_handler(state: &Arc<AppState>, session_id: &str, mode_id: &str) -> Result<(), String> {
+/// Switch the session's mode. `current_mode_updated` reaches subscribers right
+/// away; it is rolled back if the agent rejects the mode, and the agent's own
+/// `CurrentModeUpdate` wins if it settles on a different one.
+async fn set_session_mode_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    mode_id: &str,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<(), RpcError> {
     info!("WebSocket: Setting session {} mode to {}", session_id, mode_id);
-    let manager = AgentManager::new(state.client.clone());
-    manager.set_session_mode(session_id, mode_id).await.map_err(|e: AcpError| e.to_string())
+    let change = state.session_state_manager
+        .set_current_mode(&session_id.to_string(), mode_id)
+        .map_err(RpcError::InvalidParams)?;
+    let previous = change.map(|change| {
+        if let Some(update) = change.update {
+            send_state_update(event_tx, session_id, update);
+        }
+        change.previous
+    });
+
+    let manager = AgentManager::new(state.session_client(session_id));
+    if let Err(e) = manager.set_session_mode(session_id, mode_id).await {
+        if let Some(previous) = previous {
+            if let Ok(Some(ModeChange { update: Some(update), .. })) =
+                state.session_state_manager.set_current_mode(&session_id.to_string(), &previous)
+            {
+                send_state_update(event_tx, session_id, update);
+            }
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Switch the session's model; `model_updated` reaches subscribers once the agent accepts it
+async fn set_session_model_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    model_id: &str,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<(), RpcError> {
+    info!("WebSocket: Setting session {} model to {}", session_id, model_id);
+    let session_id = session_id.to_string();
+    if !state.session_state_manager.has_session(&session_id) {
+        return Err(RpcError::session_not_found(&session_id));
+    }
+    state.session_state_manager.validate_model(&session_id, model_id).map_err(RpcError::InvalidParams)?;
+
+    let manager = AgentManager::new(state.session_client(&session_id));
+    manager.set_session_model(&session_id, model_id).await?;
+
+    let update = state.session_state_manager.set_current_model(&session_id, model_id)?;
+    send_state_update(event_tx, &session_id, update);
+    state.session_registry.update_current_model(&session_id, model_id);
+    Ok(())
 }
 
-use crate::core::{ListSessionsResponse, SessionInfo};
+use crate::core::{ListSessionsResponse, SessionInfo, SessionSearchResult};
 
 async fn list_sessions_handler(
     state: &Arc<AppState>,
     cwd: Option<&str>,
     limit: Option<usize>,
     offset: Option<usize>,
-) -> ListSessionsResponse {
+    cursor: Option<&str>,
+    pinned_only: bool,
+) -> Result<ListSessionsResponse, String> {
     let limit = limit.unwrap_or(20);
     let offset = offset.unwrap_or(0);
-    info!("WebSocket: Listing sessions (cwd={:?}, limit={}, offset={})", cwd, limit, offset);
-    let response = state.session_registry.list_sessions(cwd, limit, offset);
+    info!(
+        "WebSocket: Listing sessions (cwd={:?}, limit={}, offset={}, cursor={:?}, pinned_only={})",
+        cwd, limit, offset, cursor, pinned_only
+    );
+    // The cursor takes precedence; offset is kept for older clients
+    let response = match cursor {
+        Some(cursor) => state.session_registry.list_sessions_after(cwd, limit, cursor, pinned_only)?,
+        None => state.session_registry.list_sessions(cwd, limit, offset, pinned_only),
+    };
     info!("WebSocket: Found {} sessions (total: {})", response.sessions.len(), response.total);
-    response
+    Ok(response)
+}
+
+fn search_sessions_handler(
+    state: &Arc<AppState>,
+    query: &str,
+    cwd: Option<&str>,
+    limit: Option<usize>,
+) -> Vec<SessionSearchResult> {
+    let limit = limit.unwrap_or(20);
+    info!("WebSocket: Searching sessions for {:?} (cwd={:?}, limit={})", query, cwd, limit);
+    state.session_registry.search(query, cwd, limit)
 }
 
 async fn resume_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &str) -> Result<NewSessionResponse, String> {
     info!("WebSocket: Resuming session {} in {}", session_id, cwd);
+    let project = load_project_config(state, cwd)?;
+    let (project_provider, command) = project.map(|p| (p.provider, p.agent_command)).unwrap_or_default();
+    let provider_id = state.session_provider(session_id).or(project_provider);
 
     // Ensure ACP agent is running before resuming session
-    ensure_agent_connected(state).await?;
+    let agent = AgentKey { provider_id: provider_id.clone(), command };
+    let client = ensure_session_agent_connected(state, &agent).await?;
 
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
     let response = manager.resume_session(session_id, cwd).await.map_err(|e: AcpError| e.to_string())?;
+    state.session_registry.link_resumed_session(session_id, &response.session_id);
 
     // Register session in the registry
     state.session_registry.register_session(
@@ -1722,7 +4341,7 @@ async fn resume_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &s
     );
 
     // Load historical chat items from JSONL file
-    let chat_items = state.session_registry.load_chat_items(session_id);
+    let chat_items = state.load_chat_items(session_id);
     info!("Loaded {} historical chat items for session {}", chat_items.len(), session_id);
 
     // Create session state with historical chat items
@@ -1733,6 +4352,12 @@ async fn resume_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &s
         response.models.clone(),
         chat_items,
     );
+    if let Some(usage) = state.session_registry.session_usage(session_id) {
+        state.session_state_manager.set_usage(&response.session_id, usage);
+    }
+    if provider_id.is_some() {
+        state.set_session_provider(&response.session_id, provider_id);
+    }
 
     // Set as current active session and broadcast to all clients
     state.set_current_session(Some(response.session_id.clone())).await;
@@ -1743,11 +4368,13 @@ async fn resume_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &s
 
 async fn fork_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &str) -> Result<NewSessionResponse, String> {
     info!("WebSocket: Forking session {} in {}", session_id, cwd);
+    // Forks keep the source session's provider
+    let provider_id = state.session_provider(session_id);
 
     // Ensure ACP agent is running before forking session
-    ensure_agent_connected(state).await?;
+    let client = ensure_session_agent_connected(state, &state.agent_for(provider_id.clone(), cwd)).await?;
 
-    let manager = AgentManager::new(state.client.clone());
+    let manager = AgentManager::new(client).with_mcp_servers(state.mcp_servers());
     let response = manager.fork_session(session_id, cwd).await.map_err(|e: AcpError| e.to_string())?;
 
     // Register new session in the registry
@@ -1759,7 +4386,7 @@ async fn fork_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &str
     );
 
     // Load historical chat items from JSONL file
-    let chat_items = state.session_registry.load_chat_items(session_id);
+    let chat_items = state.load_chat_items(session_id);
     info!("Loaded {} historical chat items for forked session {}", chat_items.len(), session_id);
 
     // Create session state with historical chat items
@@ -1770,6 +4397,12 @@ async fn fork_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &str
         response.models.clone(),
         chat_items,
     );
+    if let Some(usage) = state.session_registry.session_usage(session_id) {
+        state.session_state_manager.set_usage(&response.session_id, usage);
+    }
+    if provider_id.is_some() {
+        state.set_session_provider(&response.session_id, provider_id);
+    }
 
     // Set as current active session and broadcast to all clients
     state.set_current_session(Some(response.session_id.clone())).await;
@@ -1778,68 +4411,636 @@ async fn fork_session_handler(state: &Arc<AppState>, session_id: &str, cwd: &str
     Ok(response)
 }
 
-async fn get_session_info_handler(state: &Arc<AppState>, session_id: &str) -> Result<SessionInfo, String> {
+/// Result of re-running an edited message
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EditMessageResponse {
+    /// The fork the edited message was sent in
+    session_id: SessionId,
+    message_id: String,
+    #[serde(flatten)]
+    prompt: PromptResponse,
+}
+
+/// Edit the latest user message of a session and re-run it.
+///
+/// The original session is left untouched: the agent forks it, the fork's
+/// history is cut at the edited message (broadcast as `message_edited`) and
+/// the new content is prompted there. The agent cannot drop turns from its own
+/// context, so only the latest user message can be edited; the replaced
+/// exchange stays visible to the agent in the fork.
+async fn edit_message_handler(
+    state: &Arc<AppState>,
+    client_state: &Arc<ClientState>,
+    session_id: &str,
+    message_id: &str,
+    content: &str,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<EditMessageResponse, RpcError> {
+    info!("WebSocket: Editing message {} in session {}", message_id, session_id);
+
+    if let Some(info) = state.session_registry.get_session_info(session_id) {
+        if matches!(info.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending) {
+            return Err("Cannot edit a message while a turn is in progress".into());
+        }
+    }
+
+    let session_state = state.session_state_manager.get_state(&session_id.to_string())
+        .ok_or_else(|| RpcError::session_not_found(session_id))?;
+    let idx = session_state.chat_items.iter()
+        .position(|item| item.id() == message_id)
+        .ok_or_else(|| format!("Message not found: {}", message_id))?;
+    let is_user_message = |item: &ChatItem| {
+        matches!(item, ChatItem::Message { message } if message.role == MessageRole::User)
+    };
+    if !is_user_message(&session_state.chat_items[idx]) {
+        return Err("Only user messages can be edited".into());
+    }
+    if session_state.chat_items[idx + 1..].iter().any(is_user_message) {
+        return Err("Only the latest user message can be edited".into());
+    }
+
+    let fork = fork_session_handler(state, session_id, &session_state.cwd).await?;
+    track_session(state, client_state, &fork.session_id);
+
+    // The fork starts from the live history up to the edited message
+    let mut history = session_state.chat_items;
+    history.truncate(idx + 1);
+    if let Some(sequenced) = state.session_state_manager.load_history(&fork.session_id, history) {
+        send_state_update(event_tx, &fork.session_id, sequenced);
+    }
+    if let Some(sequenced) = state.session_state_manager.edit_message(&fork.session_id, message_id, content.to_string()) {
+        send_state_update(event_tx, &fork.session_id, sequenced);
+    }
+
+    let blocks = vec![ContentBlock::Text { text: content.to_string() }];
+    let prompt = send_prompt_handler(state, &fork.session_id, blocks, Some(message_id.to_string()), event_tx).await?;
+
+    Ok(EditMessageResponse {
+        session_id: fork.session_id,
+        message_id: message_id.to_string(),
+        prompt,
+    })
+}
+
+/// Remove a message (or its whole turn) from the session's live state.
+///
+/// Only the in-memory state and its persisted snapshot change; the agent's
+/// session file is left as is, so the agent still has the exchange in its
+/// context and re-reading the file (e.g. after an external change) restores it.
+fn delete_message_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    message_id: &str,
+    whole_turn: bool,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<Vec<String>, RpcError> {
+    info!("WebSocket: Deleting message {} in session {} (whole turn: {})", message_id, session_id, whole_turn);
+    let session_id = session_id.to_string();
+
+    let session_state = state.session_state_manager.get_state(&session_id)
+        .ok_or_else(|| RpcError::session_not_found(&session_id))?;
+    let idx = session_state.chat_items.iter()
+        .position(|item| item.id() == message_id)
+        .ok_or_else(|| format!("Message not found: {}", message_id))?;
+
+    // The running turn is still being streamed into
+    let running = state.session_registry.get_session_info(&session_id)
+        .is_some_and(|info| matches!(info.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending));
+    if running {
+        let last = session_state.chat_items.len() - 1;
+        let touches_current_turn = if whole_turn {
+            session_state.turn_range(idx).end > last
+        } else {
+            idx >= session_state.turn_range(last).start
+        };
+        if touches_current_turn {
+            return Err("Cannot delete a message from a turn that is in progress".into());
+        }
+    }
+
+    let sequenced = state.session_state_manager.delete_message(&session_id, message_id, whole_turn)
+        .ok_or_else(|| format!("Message not found: {}", message_id))?;
+    let update = sequenced.update.clone();
+    send_state_update(event_tx, &session_id, sequenced);
+
+    match update {
+        SessionStateUpdate::ItemsDeleted { item_ids } => Ok(item_ids),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Prepend up to `count` history items preceding `before_index` in the session
+/// file (defaulting to the oldest loaded item) and broadcast them as an
+/// `items_prepended` delta
+fn load_more_history_handler(
+    state: &Arc<AppState>,
+    session_id: &str,
+    before_index: Option<usize>,
+    count: usize,
+    event_tx: &broadcast::Sender<String>,
+) -> Result<serde_json::Value, RpcError> {
+    info!("WebSocket: Loading more history for session {}", session_id);
+    let session_id = session_id.to_string();
+
+    let session_state = state.session_state_manager.get_state(&session_id)
+        .ok_or_else(|| RpcError::session_not_found(&session_id))?;
+    let history = state.session_registry.load_all_chat_items(&session_id);
+    let before = match (before_index, session_state.chat_items.first()) {
+        (Some(index), _) => index.min(history.len()),
+        (None, Some(first)) => history.iter()
+            .position(|item| item.id() == first.id())
+            .ok_or_else(|| RpcError::Internal("Loaded history does not match the session file".to_string()))?,
+        (None, None) => history.len(),
+    };
+    let start = before.saturating_sub(count);
+    let items = history[start..before].to_vec();
+
+    let loaded = match state.session_state_manager.prepend_history(&session_id, items) {
+        Some(sequenced) => {
+            let loaded = match &sequenced.update {
+                SessionStateUpdate::ItemsPrepended { items } => items.len(),
+                _ => 0,
+            };
+            send_state_update(event_tx, &session_id, sequenced);
+            loaded
+        }
+        None => 0,
+    };
+
+    Ok(serde_json::json!({
+        "loaded": loaded,
+        "historyStart": start,
+        "hasMore": start > 0,
+    }))
+}
+
+fn server_info(state: &Arc<AppState>) -> serde_json::Value {
+    let port = state.get_ws_port();
+    let cwd = std::env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "/".to_string());
+    let home = dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let tls = state.ws_tls.load(std::sync::atomic::Ordering::SeqCst);
+    let host = state.ws_host.read().clone();
+    // A server bound to loopback can't be reached over the LAN, and one bound
+    // to a single interface only on that address
+    let lan_addresses = match host.parse::<std::net::IpAddr>() {
+        Ok(ip) if ip.is_unspecified() => get_lan_addresses(port, tls),
+        _ if is_public_host(&host) => vec![format!("{}://{}:{}/ws", if tls { "wss" } else { "ws" }, host, port)],
+        _ => Vec::new(),
+    };
+    serde_json::json!({
+        "port": port,
+        "host": host,
+        "cwd": cwd,
+        "home": home,
+        "tls": tls,
+        "lanAddresses": lan_addresses,
+        "projectsDir": state.session_registry.projects_dir()
+    })
+}
+
+/// Runtime state for health checks: agent connection and session, terminal and client counts
+async fn server_status(server: &ServerState) -> serde_json::Value {
+    use crate::core::ModelConfig;
+
+    let state = &server.app_state;
+    let agent_connected = state.client.read().await.as_ref().is_some_and(|c| c.is_connected());
+    let provider_agents = state.provider_clients.read().values().cloned().collect::<Vec<_>>();
+    let mut provider_agents_connected = 0;
+    for client in provider_agents {
+        if client.read().await.as_ref().is_some_and(|c| c.is_connected()) {
+            provider_agents_connected += 1;
+        }
+    }
+
+    let active_sessions = state.session_registry.get_active_sessions();
+    let running_sessions = active_sessions.iter()
+        .filter(|s| matches!(s.status, crate::core::SessionStatus::Running | crate::core::SessionStatus::Pending))
+        .count();
+    let active_provider = ModelConfig::load().ok().map(|config| config.active_provider);
+
+    serde_json::json!({
+        "agentConnected": agent_connected,
+        "providerAgentsConnected": provider_agents_connected,
+        "activeSessions": active_sessions.len(),
+        "runningSessions": running_sessions,
+        "loadedSessions": state.session_state_manager.get_session_ids().len(),
+        "terminals": state.terminal_manager.list_terminals().len(),
+        "clients": server.clients.read().unwrap().len(),
+        "uptimeSecs": state.started_at.elapsed().as_secs(),
+        "activeProvider": active_provider,
+        "version": env!("CARGO_PKG_VERSION"),
+    })
+}
+
+async fn get_session_info_handler(state: &Arc<AppState>, session_id: &str) -> Result<SessionInfo, RpcError> {
     info!("WebSocket: Getting session info: {}", session_id);
     state.session_registry.get_session_info(session_id)
-        .ok_or_else(|| format!("Session not found: {}", session_id))
+        .ok_or_else(|| RpcError::session_not_found(session_id))
 }
 
 fn delete_session_handler(state: &Arc<AppState>, session_id: &str) -> Result<bool, String> {
     info!("WebSocket: Deleting session: {}", session_id);
     // Also remove from session state manager if present
     state.session_state_manager.remove_session(&session_id.to_string());
+    state.session_state_manager.delete_persisted(&session_id.to_string());
     state.session_registry.delete_session(session_id)
 }
 
+/// Delete several sessions and broadcast one `sessions/updated` afterwards.
+/// Active sessions are skipped unless `force` is set, which stops them first.
+async fn delete_sessions_handler(
+    state: &Arc<AppState>,
+    session_ids: &[String],
+    force: bool,
+    cwd: Option<&str>,
+    event_tx: &broadcast::Sender<String>,
+) -> serde_json::Value {
+    info!("WebSocket: Deleting {} sessions (force: {})", session_ids.len(), force);
+    let mut deleted = 0;
+    let mut errors = Vec::new();
+    for session_id in session_ids {
+        let active = state.session_registry.get_status(session_id).is_some();
+        if active {
+            if !force {
+                errors.push(serde_json::json!({ "sessionId": session_id, "error": "Session is active" }));
+                continue;
+            }
+            stop_session(state, session_id).await;
+        }
+        match delete_session_handler(state, session_id) {
+            Ok(true) => deleted += 1,
+            // A stopped session that never wrote a file is gone all the same
+            Ok(false) if active => deleted += 1,
+            Ok(false) => errors.push(serde_json::json!({ "sessionId": session_id, "error": "Session not found" })),
+            Err(e) => errors.push(serde_json::json!({ "sessionId": session_id, "error": e })),
+        }
+    }
+
+    broadcast_sessions_update(state, event_tx, cwd);
+    serde_json::json!({ "deleted": deleted, "failed": errors.len(), "errors": errors })
+}
+
+/// Files one client may watch at once
+const MAX_WATCHED_FILES: usize = 64;
+
+/// Watch a file for this client; changes arrive as `file/changed` on its own socket
+fn watch_file_handler(
+    state: &Arc<AppState>,
+    client_state: &Arc<ClientState>,
+    path: &str,
+    include_content: bool,
+) -> Result<(), RpcError> {
+    let resolved = check_path(state, path)?;
+    let mut watches = client_state.file_watches.lock().unwrap();
+    if watches.contains_key(path) {
+        return Ok(());
+    }
+    if watches.len() >= MAX_WATCHED_FILES {
+        return Err(format!("Too many watched files (limit {})", MAX_WATCHED_FILES).into());
+    }
+
+    let outbox = client_state.outbox.clone();
+    let watch = FileWatch::new(path.to_string(), resolved, include_content, move |change| {
+        let msg = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "file/changed".to_string(),
+            params: serde_json::to_value(&change).unwrap_or_default(),
+        };
+        if let Ok(json) = serde_json::to_string(&msg) {
+            // A full outbox means the client is far behind; it will re-read on its next change
+            let _ = outbox.try_send(json);
+        }
+    })
+    .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+
+    debug!("WebSocket: client {} watching {}", client_state.client_id, path);
+    watches.insert(path.to_string(), watch);
+    Ok(())
+}
+
 // File handlers
-use crate::commands::file::{DirEntry, FileInfo, BinaryFileContent};
+use crate::commands::file::{DirEntry, FileInfo, FileTree, FileWriteDiff, BinaryFileContent, PartialFileContent};
+use crate::commands::git::GitStatus;
+
+/// Resolve a path inside the sandbox, reporting a miss as `PathNotAllowed`
+fn check_path(state: &Arc<AppState>, path: &str) -> Result<std::path::PathBuf, RpcError> {
+    state.check_path(path).map_err(RpcError::PathNotAllowed)
+}
+
+/// Directory listing is not sandboxed: the project picker browses the filesystem
+/// to choose a project. Only names and sizes are exposed, never file contents.
+async fn list_directory_handler(path: &str, show_hidden: bool, include_ignored: bool) -> Result<Vec<DirEntry>, String> {
+    crate::commands::file::list_directory_impl(path, show_hidden, include_ignored).await
+}
+
+/// Unlike list_directory, tree listings are for the project explorer and stay
+/// inside the sandbox
+async fn list_directory_tree_handler(
+    state: &Arc<AppState>,
+    path: &str,
+    max_depth: Option<usize>,
+    show_hidden: bool,
+    include_ignored: bool,
+) -> Result<FileTree, RpcError> {
+    let resolved = check_path(state, path)?;
+    Ok(crate::commands::file::list_directory_tree_impl(&resolved.to_string_lossy(), max_depth, show_hidden, include_ignored).await?)
+}
+
+/// Read a text file; `max_bytes` overrides the configured `files.maxTextBytes`
+async fn read_file_handler(
+    state: &Arc<AppState>,
+    path: &str,
+    max_bytes: Option<u64>,
+    truncate: bool,
+) -> Result<PartialFileContent, RpcError> {
+    check_path(state, path)?;
+    let max_bytes = max_bytes.unwrap_or_else(|| crate::core::ConfigManager::new().config().files.max_text_bytes);
+    Ok(crate::commands::file::read_file_partial_impl(path, max_bytes, truncate).await?)
+}
+
+/// Read a file as base64; `max_bytes` overrides the configured `files.maxBinaryBytes`
+async fn read_file_binary_handler(
+    state: &Arc<AppState>,
+    path: &str,
+    max_bytes: Option<u64>,
+    truncate: bool,
+) -> Result<BinaryFileContent, RpcError> {
+    check_path(state, path)?;
+    let max_bytes = max_bytes.unwrap_or_else(|| crate::core::ConfigManager::new().config().files.max_binary_bytes);
+    Ok(crate::commands::file::read_file_binary_impl(path, max_bytes, truncate).await?)
+}
+
+/// Default block size for streamed binary reads (before base64)
+const DEFAULT_FILE_CHUNK_SIZE: usize = 256 * 1024;
+const MIN_FILE_CHUNK_SIZE: usize = 16 * 1024;
+const MAX_FILE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Stream a binary file to the requesting client as `file/chunk` notifications
+/// (offset, totalSize, base64 data), followed by one `file/stream_complete`.
+/// Chunks may arrive before the response; clients can pass their own streamId
+/// to correlate them. Returns the file size.
+async fn read_file_binary_stream_handler(
+    state: &Arc<AppState>,
+    client_state: &Arc<ClientState>,
+    path: &str,
+    stream_id: String,
+    chunk_size: usize,
+) -> Result<u64, RpcError> {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+    use tokio::io::AsyncReadExt;
+
+    let resolved = check_path(state, path)?;
+    if !resolved.is_file() {
+        return Err(format!("Path is not a file: {}", path).into());
+    }
+    let mut file = tokio::fs::File::open(&resolved).await
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let total_size = file.metadata().await
+        .map_err(|e| format!("Failed to read metadata: {}", e))?
+        .len();
+
+    let cancelled = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    {
+        let mut streams = client_state.file_streams.lock().unwrap();
+        if streams.contains_key(&stream_id) {
+            return Err(format!("Stream already exists: {}", stream_id).into());
+        }
+        streams.insert(stream_id.clone(), cancelled.clone());
+    }
+    info!("WebSocket: Streaming {} ({} bytes) as {}", path, total_size, stream_id);
+
+    let client_state = client_state.clone();
+    let path = path.to_string();
+    tokio::spawn(async move {
+        let notify = |method: &str, params: serde_json::Value| {
+            serde_json::to_string(&JsonRpcNotification {
+                jsonrpc: "2.0".to_string(),
+                method: method.to_string(),
+                params,
+            })
+            .unwrap_or_default()
+        };
+
+        let mut buffer = vec![0u8; chunk_size];
+        let mut offset: u64 = 0;
+        let mut error = None;
+        loop {
+            if cancelled.load(std::sync::atomic::Ordering::SeqCst) {
+                break;
+            }
+            let read = match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    error = Some(format!("Failed to read file: {}", e));
+                    break;
+                }
+            };
+            let chunk = notify("file/chunk", serde_json::json!({
+                "streamId": stream_id,
+                "path": path,
+                "offset": offset,
+                "totalSize": total_size,
+                "data": BASE64.encode(&buffer[..read]),
+            }));
+            // Bounded channel: waits for the socket to drain instead of buffering the file
+            if client_state.outbox.send(chunk).await.is_err() {
+                break;
+            }
+            offset += read as u64;
+        }
+
+        client_state.file_streams.lock().unwrap().remove(&stream_id);
+        let was_cancelled = cancelled.load(std::sync::atomic::Ordering::SeqCst);
+        debug!("File stream {} finished at {} bytes (cancelled: {})", stream_id, offset, was_cancelled);
+        let _ = client_state.outbox.send(notify("file/stream_complete", serde_json::json!({
+            "streamId": stream_id,
+            "path": path,
+            "totalSize": total_size,
+            "bytesSent": offset,
+            "cancelled": was_cancelled,
+            "error": error,
+        }))).await;
+    });
 
-async fn list_directory_handler(path: &str, show_hidden: bool) -> Result<Vec<DirEntry>, String> {
-    crate::commands::file::list_directory_impl(path, show_hidden).await
+    Ok(total_size)
 }
 
-async fn read_file_handler(path: &str) -> Result<String, String> {
-    crate::commands::file::read_file_impl(path).await
+/// Search file contents in the background, sending each hit to the requesting
+/// client as a `search/match` notification and finishing with `search/complete`
+fn search_files_handler(
+    state: &Arc<AppState>,
+    client_state: &Arc<ClientState>,
+    root: &str,
+    query: &str,
+    options: crate::commands::file::SearchOptions,
+    search_id: String,
+) -> Result<(), RpcError> {
+    let root = check_path(state, root)?;
+    info!("WebSocket: Searching {:?} for {:?} as {}", root, query, search_id);
+
+    let outbox = client_state.outbox.clone();
+    let query = query.to_string();
+    tokio::task::spawn_blocking(move || {
+        let notify = |method: &str, params: serde_json::Value| {
+            serde_json::to_string(&JsonRpcNotification {
+                jsonrpc: "2.0".to_string(),
+                method: method.to_string(),
+                params,
+            })
+            .unwrap_or_default()
+        };
+
+        let result = crate::commands::file::search_files_impl(&root.to_string_lossy(), &query, &options, |m| {
+            let mut params = serde_json::to_value(&m).unwrap_or_default();
+            params["searchId"] = serde_json::Value::String(search_id.clone());
+            // Stop searching once the client is gone
+            outbox.blocking_send(notify("search/match", params)).is_ok()
+        });
+
+        let params = match result {
+            Ok(summary) => {
+                let mut params = serde_json::to_value(&summary).unwrap_or_default();
+                params["searchId"] = serde_json::Value::String(search_id.clone());
+                params
+            }
+            Err(e) => serde_json::json!({ "searchId": search_id, "error": e }),
+        };
+        let _ = outbox.blocking_send(notify("search/complete", params));
+    });
+
+    Ok(())
+}
+
+async fn get_file_info_handler(state: &Arc<AppState>, path: &str) -> Result<FileInfo, RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::get_file_info_impl(path).await?)
+}
+
+async fn git_status_handler(state: &Arc<AppState>, cwd: &str) -> Result<GitStatus, RpcError> {
+    check_path(state, cwd)?;
+    Ok(crate::commands::git::git_status_impl(cwd).await?)
+}
+
+async fn git_diff_handler(state: &Arc<AppState>, cwd: &str, path: Option<&str>, staged: bool) -> Result<String, RpcError> {
+    check_path(state, cwd)?;
+    if let Some(path) = path {
+        // Relative paths are relative to the repository directory
+        check_path(state, &std::path::Path::new(cwd).join(path).to_string_lossy())?;
+    }
+    Ok(crate::commands::git::git_diff_impl(cwd, path, staged).await?)
 }
 
-async fn read_file_binary_handler(path: &str) -> Result<BinaryFileContent, String> {
-    crate::commands::file::read_file_binary_impl(path).await
+async fn read_file_at_revision_handler(state: &Arc<AppState>, path: &str, revision: &str) -> Result<String, RpcError> {
+    let resolved = check_path(state, path)?;
+    crate::commands::git::read_file_at_revision_impl(&resolved.to_string_lossy(), revision)
+        .await
+        .map_err(|e| if e.starts_with("Invalid revision") { RpcError::InvalidParams(e) } else { RpcError::Internal(e) })
+}
+
+/// Open a sandboxed path or an allowed URL in the OS default app (desktop only)
+fn open_path_handler(state: &Arc<AppState>, path: &str) -> Result<(), RpcError> {
+    let target = match crate::core::parse_open_target(path).map_err(RpcError::InvalidParams)? {
+        crate::core::OpenTarget::Path(path) => crate::core::OpenTarget::Path(check_path(state, &path.to_string_lossy())?),
+        url => url,
+    };
+    Ok(state.open_external(&target)?)
+}
+
+async fn write_file_handler(state: &Arc<AppState>, path: &str, content: &str) -> Result<(), RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::write_file_impl(path, content).await?)
+}
+
+async fn write_file_with_diff_handler(state: &Arc<AppState>, path: &str, content: &str) -> Result<FileWriteDiff, RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::write_file_with_diff_impl(path, content).await?)
 }
 
-async fn get_file_info_handler(path: &str) -> Result<FileInfo, String> {
-    crate::commands::file::get_file_info_impl(path).await
+async fn write_file_binary_handler(state: &Arc<AppState>, path: &str, content: &str) -> Result<(), RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::write_file_binary_impl(path, content).await?)
 }
 
-async fn write_file_handler(path: &str, content: &str) -> Result<(), String> {
-    crate::commands::file::write_file_impl(path, content).await
+async fn create_file_handler(state: &Arc<AppState>, path: &str) -> Result<(), RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::create_file_impl(path).await?)
 }
 
-async fn write_file_binary_handler(path: &str, content: &str) -> Result<(), String> {
-    crate::commands::file::write_file_binary_impl(path, content).await
+async fn create_directory_handler(state: &Arc<AppState>, path: &str) -> Result<(), RpcError> {
+    check_path(state, path)?;
+    Ok(crate::commands::file::create_directory_impl(path).await?)
 }
 
-async fn create_file_handler(path: &str) -> Result<(), String> {
-    crate::commands::file::create_file_impl(path).await
+async fn delete_path_handler(state: &Arc<AppState>, path: &str, to_trash: bool) -> Result<bool, RpcError> {
+    let resolved = check_path(state, path)?;
+    // Never delete a project root itself
+    if state.path_sandbox.is_enabled() && state.path_sandbox.roots().contains(&resolved) {
+        return Err(format!("Refusing to delete project directory: {}", path).into());
+    }
+    Ok(crate::commands::file::delete_path_impl(path, to_trash).await?)
+}
+
+async fn preview_delete_handler(state: &Arc<AppState>, path: &str) -> Result<crate::commands::file::DeletePreview, RpcError> {
+    let resolved = check_path(state, path)?;
+    // Same refusal as delete_path, so the preview never promises a delete that can't happen
+    if state.path_sandbox.is_enabled() && state.path_sandbox.roots().contains(&resolved) {
+        return Err(format!("Refusing to delete project directory: {}", path).into());
+    }
+    let path = path.to_string();
+    // Counting a large tree takes a while; keep it off the async workers
+    tokio::task::spawn_blocking(move || crate::commands::file::preview_delete_impl(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(RpcError::from)
 }
 
-async fn create_directory_handler(path: &str) -> Result<(), String> {
-    crate::commands::file::create_directory_impl(path).await
+fn preview_rename_handler(state: &Arc<AppState>, from: &str, to: &str) -> Result<crate::commands::file::RenamePreview, RpcError> {
+    check_path(state, from)?;
+    check_path(state, to)?;
+    Ok(crate::commands::file::preview_rename_impl(from, to)?)
 }
 
-async fn delete_path_handler(path: &str) -> Result<(), String> {
-    crate::commands::file::delete_path_impl(path).await
+async fn copy_path_handler(
+    state: &Arc<AppState>,
+    from: &str,
+    to: &str,
+    overwrite: bool,
+    include_ignored: bool,
+) -> Result<(), RpcError> {
+    check_path(state, from)?;
+    check_path(state, to)?;
+    Ok(crate::commands::file::copy_path_impl(from, to, overwrite, include_ignored).await?)
 }
 
-async fn rename_path_handler(from: &str, to: &str) -> Result<(), String> {
-    crate::commands::file::rename_path_impl(from, to).await
+async fn rename_path_handler(state: &Arc<AppState>, from: &str, to: &str) -> Result<(), RpcError> {
+    check_path(state, from)?;
+    check_path(state, to)?;
+    Ok(crate::commands::file::rename_path_impl(from, to).await?)
 }
 
 // Terminal handlers
 use crate::core::terminal::TerminalInfo;
+use crate::core::CommandOutput;
 
-async fn create_terminal_handler(state: &Arc<AppState>, cwd: Option<&str>, cols: u16, rows: u16) -> Result<String, String> {
+/// Recent output included per terminal by `list_terminals` with `includeOutput`
+const LIST_TERMINALS_OUTPUT_BYTES: usize = 64 * 1024;
+
+async fn create_terminal_handler(
+    state: &Arc<AppState>,
+    cwd: Option<&str>,
+    cols: u16,
+    rows: u16,
+    shell: Option<&str>,
+    env: &HashMap<String, String>,
+) -> Result<String, String> {
     let cwd = cwd.map(|s| s.to_string()).unwrap_or_else(|| std::env::var("HOME").unwrap_or_else(|_| "/".to_string()));
-    state.terminal_manager.create_terminal(cwd, cols, rows)
+    state.terminal_manager.create_terminal(cwd, cols, rows, shell, env)
 }
 
 async fn write_terminal_handler(state: &Arc<AppState>, terminal_id: &str, data: &str) -> Result<(), String> {
@@ -1854,6 +5055,23 @@ async fn kill_terminal_handler(state: &Arc<AppState>, terminal_id: &str) -> Resu
     state.terminal_manager.kill_terminal(terminal_id)
 }
 
+async fn run_command_handler(
+    state: &Arc<AppState>,
+    cwd: &str,
+    program: &str,
+    args: &[String],
+    timeout_ms: Option<u64>,
+) -> Result<CommandOutput, RpcError> {
+    use crate::core::command_runner::{run_command, DEFAULT_COMMAND_TIMEOUT, MAX_COMMAND_TIMEOUT};
+
+    check_path(state, cwd)?;
+    let timeout = timeout_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(DEFAULT_COMMAND_TIMEOUT)
+        .min(MAX_COMMAND_TIMEOUT);
+    Ok(run_command(cwd, program, args, timeout).await?)
+}
+
 async fn list_terminals_handler(state: &Arc<AppState>) -> Result<Vec<TerminalInfo>, String> {
     Ok(state.terminal_manager.list_terminals())
 }
@@ -1900,124 +5118,6 @@ fn toggle_marketplace_handler(name: &str, enabled: bool) -> Result<MarketplaceRe
     PluginManager::toggle_marketplace(name, enabled)
 }
 
-// ===== Recent Projects Persistence =====
-
-const MAX_RECENT_PROJECTS: usize = 20;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct RecentProject {
-    path: String,
-    name: String,
-    #[serde(rename = "lastOpened")]
-    last_opened: u64,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct RecentProjectsConfig {
-    projects: Vec<RecentProject>,
-}
-
-/// Get the config directory path (~/.config/aerowork/)
-fn get_config_dir() -> Result<std::path::PathBuf, String> {
-    let home = dirs::home_dir().ok_or("Could not find home directory")?;
-    let config_dir = home.join(".config").join("aerowork");
-
-    // Create directory if it doesn't exist
-    if !config_dir.exists() {
-        std::fs::create_dir_all(&config_dir)
-            .map_err(|e| format!("Failed to create config directory: {}", e))?;
-    }
-
-    Ok(config_dir)
-}
-
-/// Get the recent projects config file path
-fn get_recent_projects_path() -> Result<std::path::PathBuf, String> {
-    Ok(get_config_dir()?.join("recent-projects.json"))
-}
-
-/// Load recent projects from config file
-fn load_recent_projects() -> Result<Vec<RecentProject>, String> {
-    let config_path = get_recent_projects_path()?;
-
-    if !config_path.exists() {
-        return Ok(Vec::new());
-    }
-
-    let content = std::fs::read_to_string(&config_path)
-        .map_err(|e| format!("Failed to read recent projects: {}", e))?;
-
-    let config: RecentProjectsConfig = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse recent projects: {}", e))?;
-
-    Ok(config.projects)
-}
-
-/// Save recent projects to config file
-fn save_recent_projects(projects: &[RecentProject]) -> Result<(), String> {
-    let config_path = get_recent_projects_path()?;
-    let config = RecentProjectsConfig {
-        projects: projects.to_vec(),
-    };
-
-    let content = serde_json::to_string_pretty(&config)
-        .map_err(|e| format!("Failed to serialize recent projects: {}", e))?;
-
-    std::fs::write(&config_path, content)
-        .map_err(|e| format!("Failed to write recent projects: {}", e))?;
-
-    Ok(())
-}
-
-/// Add a project to recent projects list
-fn add_recent_project(path: &str, name: Option<&str>) -> Result<(), String> {
-    let mut projects = load_recent_projects().unwrap_or_default();
-
-    // Remove existing entry with same path
-    projects.retain(|p| p.path != path);
-
-    // Create new entry
-    let project_name = name
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| {
-            std::path::Path::new(path)
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_else(|| path.to_string())
-        });
-
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
-
-    // Insert at the beginning
-    projects.insert(0, RecentProject {
-        path: path.to_string(),
-        name: project_name,
-        last_opened: now,
-    });
-
-    // Limit to max projects
-    if projects.len() > MAX_RECENT_PROJECTS {
-        projects.truncate(MAX_RECENT_PROJECTS);
-    }
-
-    save_recent_projects(&projects)
-}
-
-/// Remove a project from recent projects list
-fn remove_recent_project(path: &str) -> Result<(), String> {
-    let mut projects = load_recent_projects().unwrap_or_default();
-    projects.retain(|p| p.path != path);
-    save_recent_projects(&projects)
-}
-
-/// Clear all recent projects
-fn clear_recent_projects() -> Result<(), String> {
-    save_recent_projects(&[])
-}
-
 // ===== Model Config Handlers =====
 use crate::core::model_config::ModelConfig;
 
@@ -2044,10 +5144,79 @@ fn set_active_provider_handler(provider: &str) -> Result<(), String> {
     config.sync_to_claude_settings()
 }
 
+/// Timeout for provider reachability checks
+const PROVIDER_TEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Result of a provider reachability check
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderTestResult {
+    success: bool,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Check that a provider's endpoint is reachable with its saved credentials
+async fn test_provider_handler(provider_id: &str) -> Result<ProviderTestResult, String> {
+    let probe = ModelConfig::load()?.provider_probe(provider_id)?;
+    info!("WebSocket: Testing provider {} at {}", provider_id, probe.url);
+
+    let client = reqwest::Client::builder()
+        .timeout(PROVIDER_TEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut request = client.get(&probe.url);
+    for (name, value) in &probe.headers {
+        request = request.header(name, value);
+    }
+
+    let started = std::time::Instant::now();
+    let response = request.send().await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            let error = if e.is_timeout() {
+                format!("Timed out after {}s", PROVIDER_TEST_TIMEOUT.as_secs())
+            } else {
+                format!("Connection failed: {}", e)
+            };
+            return Ok(ProviderTestResult { success: false, latency_ms, status: None, error: Some(error) });
+        }
+    };
+
+    let status = response.status();
+    // Some compatible endpoints don't implement the models list; reaching
+    // them without an auth error is enough
+    let success = match status.as_u16() {
+        401 | 403 => !probe.has_credentials,
+        404 | 405 => true,
+        _ => status.is_success(),
+    };
+    let error = if success {
+        None
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        let detail: String = body.trim().chars().take(200).collect();
+        Some(if detail.is_empty() {
+            format!("HTTP {}", status)
+        } else {
+            format!("HTTP {}: {}", status, detail)
+        })
+    };
+
+    Ok(ProviderTestResult { success, latency_ms, status: Some(status.as_u16()), error })
+}
+
 /// Get LAN IP addresses and construct WebSocket URLs
 /// Only returns addresses that other devices can connect to (excludes localhost)
-fn get_lan_addresses(port: u16) -> Vec<String> {
+fn get_lan_addresses(port: u16, tls: bool) -> Vec<String> {
     let mut addresses = Vec::new();
+    let scheme = if tls { "wss" } else { "ws" };
 
     // Get network interfaces
     if let Ok(interfaces) = get_if_addrs::get_if_addrs() {
@@ -2064,10 +5233,141 @@ fn get_lan_addresses(port: u16) -> Vec<String> {
                 if ip.octets()[0] == 169 && ip.octets()[1] == 254 {
                     continue;
                 }
-                addresses.push(format!("ws://{}:{}/ws", ip, port));
+                addresses.push(format!("{}://{}:{}/ws", scheme, ip, port));
             }
         }
     }
 
     addresses
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_client() -> ClientState {
+        let (outbox, _) = mpsc::channel(1);
+        ClientState::new("client".to_string(), outbox)
+    }
+
+    fn subscribe_all(client: &ClientState, cwd: Option<&str>) {
+        *client.all_sessions.lock().unwrap() = Some(AllSessionsSubscription {
+            cwd: cwd.map(String::from),
+            last_activity: HashMap::new(),
+        });
+    }
+
+    fn state_update(session_id: &str, kind: &str) -> serde_json::Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "session/state_update",
+            "params": { "sessionId": session_id, "update": { "updateType": kind }, "seq": 1 },
+        })
+    }
+
+    fn project_a(_: &str) -> Option<String> {
+        Some("/a".to_string())
+    }
+
+    #[test]
+    fn test_activity_cwd_filter() {
+        let client = test_client();
+        subscribe_all(&client, Some("/a"));
+
+        let activity = client.activity_notification(&state_update("s1", "message_added"), project_a).unwrap();
+        let activity: serde_json::Value = serde_json::from_str(&activity).unwrap();
+        assert_eq!(activity["method"], "session/activity");
+        assert_eq!(activity["params"]["kind"], "message_added");
+        assert_eq!(activity["params"]["cwd"], "/a");
+
+        let other_project = |_: &str| Some("/b".to_string());
+        assert!(client.activity_notification(&state_update("s2", "message_added"), other_project).is_none());
+    }
+
+    #[test]
+    fn test_activity_throttle() {
+        let client = test_client();
+        subscribe_all(&client, None);
+
+        assert!(client.activity_notification(&state_update("s1", "message_chunk"), project_a).is_some());
+        // Throttled before the session's project is looked up
+        let unreachable = |_: &str| -> Option<String> { panic!("cwd looked up for a throttled update") };
+        assert!(client.activity_notification(&state_update("s1", "message_chunk"), unreachable).is_none());
+        // Other sessions and unthrottled kinds still get through
+        assert!(client.activity_notification(&state_update("s2", "message_chunk"), project_a).is_some());
+        assert!(client.activity_notification(&state_update("s1", "message_added"), project_a).is_some());
+    }
+
+    #[test]
+    fn test_activity_ignores_other_notifications() {
+        let client = test_client();
+        subscribe_all(&client, None);
+        let update = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": "s1" },
+        });
+        assert!(client.activity_notification(&update, project_a).is_none());
+    }
+
+    #[test]
+    fn test_activity_requires_subscription() {
+        let client = test_client();
+        assert!(client.activity_notification(&state_update("s1", "message_added"), project_a).is_none());
+
+        subscribe_all(&client, None);
+        assert!(client.activity_notification(&state_update("s1", "message_added"), project_a).is_some());
+        // unsubscribe_all
+        client.all_sessions.lock().unwrap().take();
+        assert!(client.activity_notification(&state_update("s1", "message_added"), project_a).is_none());
+    }
+
+    #[test]
+    fn test_encode_terminal_output() {
+        let output = |base64: bool| {
+            let mut params = serde_json::json!({ "terminalId": "t1", "data": "hi" });
+            if base64 {
+                params["dataBase64"] = serde_json::json!("aGk=");
+            }
+            serde_json::json!({ "jsonrpc": "2.0", "method": "terminal/output", "params": params })
+        };
+        let params = |client: &ClientState, base64: bool| {
+            let encoded: serde_json::Value = serde_json::from_str(&client.encode_terminal_output(output(base64))).unwrap();
+            encoded["params"].clone()
+        };
+
+        let client = test_client();
+        assert_eq!(params(&client, true), serde_json::json!({ "terminalId": "t1", "data": "hi" }));
+        client.set_terminal_raw("t1", true);
+        assert_eq!(params(&client, true), serde_json::json!({ "terminalId": "t1", "dataBase64": "aGk=" }));
+        // Sent before any client read the terminal raw
+        assert_eq!(params(&client, false), serde_json::json!({ "terminalId": "t1", "dataBase64": "aGk=" }));
+    }
+
+    async fn recv_json(rx: &mut broadcast::Receiver<String>) -> serde_json::Value {
+        let json = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_agent_update_broadcasts_state_delta() {
+        let state = Arc::new(AppState::new());
+        let (event_tx, mut event_rx) = broadcast::channel(16);
+        let id = "test".to_string();
+        state.session_state_manager.create_session(id.clone(), "/".to_string(), None, None);
+        WebSocketServer::start_event_forwarding(state.clone(), event_tx).await;
+
+        let update = crate::acp::SessionUpdate::CurrentModeUpdate { current_mode_id: "plan".to_string() };
+        state.notification_tx.send(crate::acp::SessionNotification { session_id: id.clone(), update }).await.unwrap();
+
+        // The raw ACP update, then the structured delta with the same seq
+        let raw = recv_json(&mut event_rx).await;
+        assert_eq!(raw["method"], "session/update");
+        let delta = recv_json(&mut event_rx).await;
+        assert_eq!(delta["method"], "session/state_update");
+        assert_eq!(delta["params"]["sessionId"], "test");
+        assert_eq!(delta["params"]["update"], serde_json::json!({ "updateType": "current_mode_updated", "modeId": "plan" }));
+        assert_eq!(delta["params"]["seq"], raw["params"]["seq"]);
+        assert_eq!(delta["params"]["seq"], state.session_state_manager.get_state(&id).unwrap().seq);
+    }
+}
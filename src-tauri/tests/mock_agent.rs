@@ -0,0 +1,390 @@
+//! End-to-end test: WebSocket server + scripted mock agent
+//!
+//! Run with: cargo test --features mock-agent --test mock_agent
+
+use std::sync::Arc;
+
+use aero_work_lib::core::{AgentCommand, AppState, BusyPromptPolicy, SessionRegistry};
+use aero_work_lib::server::WebSocketServer;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A WebSocket client that keeps the notifications it sees while waiting for responses
+struct TestClient {
+    socket: Socket,
+    next_id: u64,
+    notifications: Vec<Value>,
+}
+
+impl TestClient {
+    async fn connect(port: u16) -> Self {
+        let (socket, _) = connect_async(format!("ws://127.0.0.1:{}/ws", port)).await.unwrap();
+        Self { socket, next_id: 1, notifications: Vec::new() }
+    }
+
+    async fn next_message(&mut self) -> Value {
+        loop {
+            let message = tokio::time::timeout(std::time::Duration::from_secs(10), self.socket.next())
+                .await
+                .expect("timed out waiting for the server")
+                .unwrap()
+                .unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Send a request without waiting for its response
+    async fn send(&mut self, method: &str, params: Value) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.socket.send(Message::Text(request.to_string())).await.unwrap();
+        id
+    }
+
+    async fn wait_response(&mut self, id: u64) -> Value {
+        if let Some(index) = self.notifications.iter().position(|n| n["id"] == id) {
+            let message = self.notifications.remove(index);
+            assert!(message["error"].is_null(), "request {} failed: {}", id, message["error"]);
+            return message["result"].clone();
+        }
+        loop {
+            let message = self.next_message().await;
+            if message["id"] == id {
+                assert!(message["error"].is_null(), "request {} failed: {}", id, message["error"]);
+                return message["result"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.send(method, params).await;
+        self.wait_response(id).await
+    }
+
+    /// Send a request that must fail and return its error
+    async fn call_error(&mut self, method: &str, params: Value) -> Value {
+        let id = self.send(method, params).await;
+        loop {
+            let message = self.next_message().await;
+            if message["id"] == id {
+                assert!(message["result"].is_null(), "request {} succeeded: {}", id, message["result"]);
+                return message["error"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    async fn wait_notification(&mut self, method: &str) -> Value {
+        if let Some(index) = self.notifications.iter().position(|n| n["method"] == method) {
+            return self.notifications.remove(index);
+        }
+        loop {
+            let message = self.next_message().await;
+            if message["method"] == method {
+                return message;
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    /// Read messages until the session's assistant deltas add up to `expected`.
+    /// Deltas are forwarded separately from responses and can trail them.
+    async fn wait_assistant_text(&mut self, session_id: &str, expected: &str) {
+        while self.assistant_text(session_id) != expected {
+            let message = self.next_message().await;
+            self.notifications.push(message);
+        }
+    }
+
+    /// Assistant text from the state deltas seen so far for a session
+    fn assistant_text(&self, session_id: &str) -> String {
+        self.notifications
+            .iter()
+            .filter(|n| n["method"] == "session/state_update" && n["params"]["sessionId"] == session_id)
+            .map(|n| &n["params"]["update"])
+            .filter_map(|update| match update["updateType"].as_str() {
+                Some("message_chunk") => update["content"].as_str(),
+                Some("message_added") if update["message"]["role"] == "assistant" => {
+                    update["message"]["content"].as_str()
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Wait for a session's `session/state_update` of the given type and return its update
+    async fn wait_state_update(&mut self, session_id: &str, update_type: &str) -> Value {
+        let matches = |n: &Value| {
+            n["method"] == "session/state_update"
+                && n["params"]["sessionId"] == session_id
+                && n["params"]["update"]["updateType"] == update_type
+        };
+        if let Some(index) = self.notifications.iter().position(matches) {
+            return self.notifications.remove(index)["params"]["update"].clone();
+        }
+        loop {
+            let message = self.next_message().await;
+            if matches(&message) {
+                return message["params"]["update"].clone();
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn tool_call_updates(&self, session_id: &str) -> Vec<Value> {
+        self.notifications
+            .iter()
+            .filter(|n| n["method"] == "session/state_update" && n["params"]["sessionId"] == session_id)
+            .map(|n| &n["params"]["update"])
+            .filter(|update| matches!(update["updateType"].as_str(), Some("tool_call_added" | "tool_call_updated")))
+            .map(|update| update["toolCall"].clone())
+            .collect()
+    }
+}
+
+/// The returned directory is the project root; it is removed when dropped
+async fn start_server() -> (u16, tempfile::TempDir) {
+    start_server_with(AppState::new()).await
+}
+
+/// `start_server` with a state the test prepared
+async fn start_server_with(state: AppState) -> (u16, tempfile::TempDir) {
+    let state = Arc::new(state);
+    state.set_agent_command(Some(AgentCommand {
+        command: env!("CARGO_BIN_EXE_aero-mock-agent").to_string(),
+        args: Vec::new(),
+        env: Vec::new(),
+    }));
+    let cwd = tempfile::tempdir().unwrap();
+    // Stands in for the project the user picked in the desktop app
+    state.allow_project_root(cwd.path().to_str().unwrap());
+
+    let port = WebSocketServer::new(state).with_auth_token(None).spawn(39527).await.unwrap();
+    (port, cwd)
+}
+
+async fn open_session(client: &mut TestClient, cwd: &std::path::Path) -> String {
+    let session = client.call("create_session", json!({ "cwd": cwd })).await;
+    let session_id = session["sessionId"].as_str().unwrap().to_string();
+    client.call("subscribe_session", json!({ "sessionId": session_id, "autoResume": false })).await;
+    session_id
+}
+
+#[tokio::test]
+async fn test_prompt_produces_message_deltas() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut client = TestClient::connect(port).await;
+    let session_id = open_session(&mut client, cwd).await;
+
+    let response = client.call("send_prompt", json!({ "sessionId": session_id, "content": "hi" })).await;
+    assert_eq!(response["stopReason"], "end_turn");
+    client.wait_assistant_text(&session_id, "Mock reply: hi").await;
+
+    let state = client.call("get_session_state", json!({ "sessionId": session_id })).await;
+    let last = state["chatItems"].as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["message"]["role"], "assistant");
+    assert_eq!(last["message"]["content"], "Mock reply: hi");
+}
+
+#[tokio::test]
+async fn test_prompt_permission_flow() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut client = TestClient::connect(port).await;
+    let session_id = open_session(&mut client, cwd).await;
+
+    let prompt_id = client.send("send_prompt", json!({ "sessionId": session_id, "content": "use a tool" })).await;
+    let request = client.wait_notification("permission/request").await;
+    assert_eq!(request["params"]["sessionId"], session_id);
+    assert_eq!(request["params"]["toolCall"]["toolCallId"], "mock-tool-1");
+
+    client
+        .call("respond_permission", json!({
+            "requestId": request["params"]["requestId"],
+            "sessionId": session_id,
+            "outcome": { "outcome": "selected", "optionId": "allow" },
+        }))
+        .await;
+    let response = client.wait_response(prompt_id).await;
+    assert_eq!(response["stopReason"], "end_turn");
+    client.wait_assistant_text(&session_id, "Mock reply: use a tool").await;
+
+    let state = client.call("get_session_state", json!({ "sessionId": session_id })).await;
+    let tool_call = state["chatItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find_map(|item| item.get("toolCall"))
+        .cloned()
+        .unwrap();
+    assert_eq!(tool_call["status"], "completed");
+    assert!(client.tool_call_updates(&session_id).iter().any(|t| t["toolCallId"] == "mock-tool-1"));
+}
+
+/// A session whose first prompt stays running until its permission request
+/// is answered. Returns the session, the prompt's request id and the permission request.
+async fn open_busy_session(client: &mut TestClient, cwd: &std::path::Path) -> (String, u64, Value) {
+    let session_id = open_session(client, cwd).await;
+    let first = client.send("send_prompt", json!({ "sessionId": session_id, "content": "use a tool" })).await;
+    let request = client.wait_notification("permission/request").await;
+    (session_id, first, request)
+}
+
+#[tokio::test]
+async fn test_concurrent_prompts_are_queued() {
+    let (port, tmp) = start_server_with(AppState::new().with_busy_prompts(BusyPromptPolicy::Queue)).await;
+    let mut client = TestClient::connect(port).await;
+    let (session_id, first, request) = open_busy_session(&mut client, tmp.path()).await;
+
+    let second = client.send("send_prompt", json!({ "sessionId": session_id, "content": "hi" })).await;
+    let queued = client.wait_notification("session/prompt_queue").await;
+    assert_eq!(queued["params"]["sessionId"], session_id);
+    assert_eq!(queued["params"]["queued"], 1);
+
+    respond_allow(&mut client, &session_id, &request).await;
+    assert_eq!(client.wait_response(first).await["stopReason"], "end_turn");
+    assert_eq!(client.wait_response(second).await["stopReason"], "end_turn");
+    // The queued prompt ran after the first one, not interleaved with it
+    client.wait_assistant_text(&session_id, "Mock reply: use a toolMock reply: hi").await;
+}
+
+#[tokio::test]
+async fn test_concurrent_prompts_are_rejected() {
+    let (port, tmp) = start_server_with(AppState::new().with_busy_prompts(BusyPromptPolicy::Reject)).await;
+    let mut client = TestClient::connect(port).await;
+    let (session_id, first, request) = open_busy_session(&mut client, tmp.path()).await;
+
+    let error = client.call_error("send_prompt", json!({ "sessionId": session_id, "content": "hi" })).await;
+    assert_eq!(error["code"], 1006);
+    respond_allow(&mut client, &session_id, &request).await;
+    assert_eq!(client.wait_response(first).await["stopReason"], "end_turn");
+}
+
+async fn respond_allow(client: &mut TestClient, session_id: &str, request: &Value) {
+    client
+        .call("respond_permission", json!({
+            "requestId": request["params"]["requestId"],
+            "sessionId": session_id,
+            "outcome": { "outcome": "selected", "optionId": "allow" },
+        }))
+        .await;
+}
+
+#[tokio::test]
+async fn test_create_session_outside_sandbox() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut client = TestClient::connect(port).await;
+
+    // createIfMissing must not create directories outside the sandbox
+    let outside = tempfile::tempdir().unwrap();
+    let error = client
+        .call_error("create_session", json!({ "cwd": outside.path().join("new"), "createIfMissing": true }))
+        .await;
+    assert_eq!(error["code"], 1003);
+    assert!(!outside.path().join("new").exists());
+
+    let inside = cwd.join("new");
+    let session = client.call("create_session", json!({ "cwd": inside, "createIfMissing": true, "lazy": true })).await;
+    assert!(session["sessionId"].is_string());
+    assert!(inside.is_dir());
+}
+
+#[tokio::test]
+async fn test_concurrent_subscribes_to_same_session() {
+    // A saved session for the agent to resume
+    let projects_tmp = tempfile::tempdir().unwrap();
+    let projects = projects_tmp.path();
+    let mut state = AppState::new();
+    state.session_registry = Arc::new(SessionRegistry::with_projects_dir(projects.to_path_buf()));
+    let (port, tmp) = start_server_with(state).await;
+    let cwd = tmp.path();
+    let session_id = uuid::Uuid::new_v4().to_string();
+    std::fs::create_dir_all(projects.join("saved")).unwrap();
+    let line = json!({
+        "sessionId": session_id,
+        "cwd": cwd,
+        "timestamp": "2025-01-01T00:00:00Z",
+        "type": "user",
+        "message": { "role": "user", "content": "earlier" },
+    });
+    std::fs::write(projects.join("saved").join(format!("{}.jsonl", session_id)), format!("{}\n", line)).unwrap();
+
+    // Both auto-resume the same session; neither may supersede the other
+    let mut client = TestClient::connect(port).await;
+    let first = client.send("subscribe_session", json!({ "sessionId": session_id, "autoResume": true })).await;
+    let second = client.send("subscribe_session", json!({ "sessionId": session_id, "autoResume": true })).await;
+    let first = client.wait_response(first).await;
+    let second = client.wait_response(second).await;
+    assert_eq!(first["id"], second["id"]);
+
+    let state = client.call("get_session_state", json!({ "sessionId": first["id"] })).await;
+    assert_eq!(state["id"], first["id"]);
+}
+
+#[tokio::test]
+async fn test_placeholder_subscribers_follow_started_session() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut first = TestClient::connect(port).await;
+    let mut second = TestClient::connect(port).await;
+
+    let session = first.call("create_session", json!({ "cwd": cwd, "lazy": true })).await;
+    let placeholder = session["sessionId"].as_str().unwrap().to_string();
+    for client in [&mut first, &mut second] {
+        client.call("subscribe_session", json!({ "sessionId": placeholder, "autoResume": false })).await;
+    }
+
+    // The other client keeps receiving the session's updates under its real ID
+    first.call("send_prompt", json!({ "sessionId": placeholder, "content": "hi" })).await;
+    let started = second.wait_notification("session/started").await;
+    assert_eq!(started["params"]["placeholderId"], placeholder);
+    let real_id = started["params"]["sessionId"].as_str().unwrap().to_string();
+    assert_ne!(real_id, placeholder);
+    second.wait_assistant_text(&real_id, "Mock reply: hi").await;
+}
+
+#[tokio::test]
+async fn test_set_session_model_sends_delta() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut client = TestClient::connect(port).await;
+    let session_id = open_session(&mut client, cwd).await;
+
+    client.call("set_session_model", json!({ "sessionId": session_id, "modelId": "mock-fast" })).await;
+    let update = client.wait_state_update(&session_id, "model_updated").await;
+    assert_eq!(update["modelId"], "mock-fast");
+
+    let error = client.call_error("set_session_model", json!({ "sessionId": session_id, "modelId": "gpt" })).await;
+    assert!(error["message"].as_str().unwrap().contains("mock-default, mock-fast"));
+}
+
+#[tokio::test]
+async fn test_set_session_mode_sends_delta_and_rollback() {
+    let (port, tmp) = start_server().await;
+    let cwd = tmp.path();
+    let mut client = TestClient::connect(port).await;
+    let session_id = open_session(&mut client, cwd).await;
+
+    client.call("set_session_mode", json!({ "sessionId": session_id, "modeId": "plan" })).await;
+    let update = client.wait_state_update(&session_id, "current_mode_updated").await;
+    assert_eq!(update["modeId"], "plan");
+
+    // The agent rejects the mode: it is shown at once, then rolled back
+    client.call_error("set_session_mode", json!({ "sessionId": session_id, "modeId": "locked" })).await;
+    let update = client.wait_state_update(&session_id, "current_mode_updated").await;
+    assert_eq!(update["modeId"], "locked");
+    let update = client.wait_state_update(&session_id, "current_mode_updated").await;
+    assert_eq!(update["modeId"], "plan");
+    let modes = client.call("get_session_modes", json!({ "sessionId": session_id })).await;
+    assert_eq!(modes["currentModeId"], "plan");
+}
@@ -14,10 +14,8 @@ import { useState, useEffect, useCallback, useRef } from "react";
 import type {
   SessionId,
   SessionState,
-  SessionUpdate,
   SessionStateUpdate,
   Message,
-  ToolCall,
 } from "@/types/acp";
 import { getTransport } from "@/services/transport";
 import type { WebSocketTransport } from "@/services/transport/websocket";
@@ -145,150 +143,24 @@ export function useSessionData(sessionId: SessionId | null): UseSessionDataResul
           };
         }
 
-        case "full_state": {
-          return update.state;
-        }
-
-        case "dangerous_mode_updated": {
-          return { ...prev, dangerousMode: update.dangerousMode, updatedAt: Date.now() };
-        }
-
-        case "noop":
-        default:
-          return prev;
-      }
-    });
-  }, []);
-
-  /**
-   * Apply incremental update to local state
-   */
-  const applyUpdate = useCallback((update: SessionUpdate) => {
-    setState((prev) => {
-      if (!prev) return prev;
-
-      switch (update.sessionUpdate) {
-        case "user_message_chunk":
-        case "agent_message_chunk": {
-          if (update.content.type !== "text") return prev;
-
-          const role = update.sessionUpdate === "user_message_chunk" ? "user" : "assistant";
-          const text = update.content.text;
-
-          // Only append if the LAST item is a message with same role (preserve ordering)
-          const newChatItems = [...prev.chatItems];
-          const lastItem = newChatItems[newChatItems.length - 1];
-
-          if (lastItem && lastItem.type === "message" && lastItem.message.role === role) {
-            // Append to existing message
-            newChatItems[newChatItems.length - 1] = {
-              type: "message",
-              message: {
-                ...lastItem.message,
-                content: lastItem.message.content + text,
-              },
-            };
-            return { ...prev, chatItems: newChatItems, updatedAt: Date.now() };
-          }
-
-          // Create new message (last item is not a message with same role)
-          const newMessage: Message = {
-            id: generateUUID(),
-            role,
-            content: text,
-            timestamp: Date.now(),
-          };
+        case "model_updated": {
+          if (!prev.models) return prev;
           return {
             ...prev,
-            chatItems: [...prev.chatItems, { type: "message", message: newMessage }],
+            models: { ...prev.models, currentModelId: update.modelId },
             updatedAt: Date.now(),
           };
         }
 
-        case "tool_call": {
-          // Check if tool call already exists
-          const existingIndex = prev.chatItems.findIndex(
-            (item) => item.type === "tool_call" && item.toolCall.toolCallId === update.toolCallId
-          );
-
-          if (existingIndex >= 0) {
-            // Update existing tool call (merge fields)
-            const newChatItems = [...prev.chatItems];
-            const existing = newChatItems[existingIndex] as { type: "tool_call"; toolCall: ToolCall };
-            newChatItems[existingIndex] = {
-              type: "tool_call",
-              toolCall: {
-                ...existing.toolCall,
-                title: update.title ?? existing.toolCall.title,
-                kind: update.kind ?? existing.toolCall.kind,
-                status: update.status ?? existing.toolCall.status,
-                rawInput: update.rawInput ?? existing.toolCall.rawInput,
-                rawOutput: update.rawOutput ?? existing.toolCall.rawOutput,
-                content: update.content ?? existing.toolCall.content,
-                locations: update.locations ?? existing.toolCall.locations,
-              },
-            };
-            return { ...prev, chatItems: newChatItems, updatedAt: Date.now() };
-          }
-
-          // New tool call - add it
-          // Note: session/state_update may also send tool_call_added, applyStateUpdate handles dedup
-          const newToolCall: ToolCall = {
-            toolCallId: update.toolCallId,
-            title: update.title ?? "",
-            kind: update.kind,
-            status: update.status,
-            rawInput: update.rawInput,
-            rawOutput: update.rawOutput,
-            content: update.content,
-            locations: update.locations,
-          };
-          return {
-            ...prev,
-            chatItems: [...prev.chatItems, { type: "tool_call", toolCall: newToolCall }],
-            updatedAt: Date.now(),
-          };
-        }
-
-        case "tool_call_update": {
-          const newChatItems = prev.chatItems.map((item) => {
-            if (item.type === "tool_call" && item.toolCall.toolCallId === update.toolCallId) {
-              return {
-                type: "tool_call" as const,
-                toolCall: {
-                  ...item.toolCall,
-                  title: update.title ?? item.toolCall.title,
-                  kind: update.kind ?? item.toolCall.kind,
-                  status: update.status ?? item.toolCall.status,
-                  rawInput: update.rawInput ?? item.toolCall.rawInput,
-                  rawOutput: update.rawOutput ?? item.toolCall.rawOutput,
-                  content: update.content ?? item.toolCall.content,
-                  locations: update.locations ?? item.toolCall.locations,
-                },
-              };
-            }
-            return item;
-          });
-          return { ...prev, chatItems: newChatItems, updatedAt: Date.now() };
-        }
-
-        case "plan": {
-          return { ...prev, plan: { entries: update.entries }, updatedAt: Date.now() };
-        }
-
-        case "available_commands_update": {
-          return { ...prev, availableCommands: update.availableCommands, updatedAt: Date.now() };
+        case "full_state": {
+          return update.state;
         }
 
-        case "current_mode_update": {
-          if (!prev.modes) return prev;
-          return {
-            ...prev,
-            modes: { ...prev.modes, currentModeId: update.currentModeId },
-            updatedAt: Date.now(),
-          };
+        case "dangerous_mode_updated": {
+          return { ...prev, dangerousMode: update.dangerousMode, updatedAt: Date.now() };
         }
 
+        case "noop":
         default:
           return prev;
       }
@@ -420,14 +292,9 @@ export function useSessionData(sessionId: SessionId | null): UseSessionDataResul
     // Setup update listeners using public transport API
     const transport = getTransport() as WebSocketTransport;
 
-    // Listen for ACP session updates (real-time updates from agent during prompts)
-    const handleUpdate = (update: SessionUpdate) => {
-      if (currentSessionRef.current === sessionId) {
-        applyUpdate(update);
-      }
-    };
-
-    // Listen for backend state updates (cross-client syncing)
+    // Listen for backend state updates. Every change to the session (agent
+    // output, mode and command changes, other clients' edits) arrives here;
+    // the raw ACP session/update notifications duplicate part of it.
     const handleStateUpdate = (update: SessionStateUpdate) => {
       if (currentSessionRef.current === sessionId) {
         applyStateUpdate(update);
@@ -435,18 +302,29 @@ export function useSessionData(sessionId: SessionId | null): UseSessionDataResul
     };
 
     // Use public API methods - they return unsubscribe functions
-    const unsubscribeSessionUpdate = transport.onSessionUpdate(sessionId, handleUpdate);
-    const unsubscribeStateUpdate = transport.onSessionStateUpdate(
+    let unsubscribeStateUpdate = transport.onSessionStateUpdate(
       sessionId,
       handleStateUpdate as (update: unknown) => void
     );
 
+    // A lazily created session gets its real ID when its first prompt starts
+    // it; from then on its updates arrive under that ID
+    const unsubscribeStarted = transport.onSessionStarted(({ placeholderId, sessionId: realId }) => {
+      if (placeholderId !== sessionId) return;
+      unsubscribeStateUpdate();
+      unsubscribeStateUpdate = transport.onSessionStateUpdate(
+        realId,
+        handleStateUpdate as (update: unknown) => void
+      );
+      setState((prev) => (prev ? { ...prev, id: realId } : prev));
+    });
+
     // Cleanup using returned unsubscribe functions
     return () => {
-      unsubscribeSessionUpdate();
       unsubscribeStateUpdate();
+      unsubscribeStarted();
     };
-  }, [sessionId, isConnected, fetchSessionState, applyUpdate, applyStateUpdate]);
+  }, [sessionId, isConnected, fetchSessionState, applyStateUpdate]);
 
   // Listen for reconnection events
   useEffect(() => {
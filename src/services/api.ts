@@ -19,12 +19,15 @@ import { WebSocketTransport } from "./transport/websocket";
 const DEFAULT_LOCAL_WS_URL = "ws://127.0.0.1:9527/ws";
 import { useAgentStore } from "@/stores/agentStore";
 import { useSettingsStore, type PermissionRule } from "@/stores/settingsStore";
-import { useFileStore } from "@/stores/fileStore";
+import { useFileStore, type RecentProject } from "@/stores/fileStore";
 import { useSessionStore } from "@/stores/sessionStore";
 import { useTerminalStore } from "@/stores/terminalStore";
 import type {
+  AvailableCommand,
   SessionId,
   SessionInfo,
+  SessionMode,
+  SessionStatus,
   ListSessionsResponse,
   PermissionRequest,
   PermissionOutcome,
@@ -444,6 +447,32 @@ class AgentAPI {
     return result.deleted;
   }
 
+  /**
+   * Get a session's status (stopped if it isn't loaded)
+   */
+  async getSessionStatus(sessionId: SessionId): Promise<SessionStatus> {
+    const transport = getTransport();
+    const result = await transport.request<{ sessionId: SessionId; status: SessionStatus }>("get_session_status", { sessionId });
+    return result.status;
+  }
+
+  /**
+   * Get the slash commands the agent currently offers for a session
+   */
+  async getAvailableCommands(sessionId: SessionId): Promise<AvailableCommand[]> {
+    const transport = getTransport();
+    const result = await transport.request<{ sessionId: SessionId; commands: AvailableCommand[] }>("get_available_commands", { sessionId });
+    return result.commands;
+  }
+
+  /**
+   * Pin or unpin a session (pinned sessions are listed first)
+   */
+  async pinSession(sessionId: SessionId, pinned: boolean): Promise<void> {
+    const transport = getTransport();
+    await transport.request("pin_session", { sessionId, pinned });
+  }
+
   /**
    * Send a prompt to a session
    * Note: User message is added optimistically by the UI hook,
@@ -549,6 +578,40 @@ class AgentAPI {
     await transport.setSessionMode(sessionId, modeId);
   }
 
+  /**
+   * Get the modes a session offers and the one it is in
+   */
+  async getSessionModes(sessionId: SessionId): Promise<{ currentModeId: string | null; availableModes: SessionMode[] }> {
+    const transport = getTransport();
+    return transport.request<{ sessionId: SessionId; currentModeId: string | null; availableModes: SessionMode[] }>(
+      "get_session_modes",
+      { sessionId }
+    );
+  }
+
+  /**
+   * Switch the session's model (one of `models.availableModels`)
+   */
+  async setSessionModel(sessionId: SessionId, modelId: string): Promise<void> {
+    const transport = getTransport();
+    await transport.request("set_session_model", { sessionId, modelId });
+  }
+
+  /**
+   * Let the server's file commands use a project the user picked in the desktop app.
+   * Only the desktop app can widen the sandbox (over Tauri IPC); WebSocket clients
+   * are limited to the server's configured project roots.
+   */
+  private async allowProjectRoot(path: string): Promise<void> {
+    if (!isDesktopApp()) return;
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      await invoke("add_project_root", { path });
+    } catch (e) {
+      console.warn("Failed to allow project root:", e);
+    }
+  }
+
   /**
    * Add a project to recent projects (syncs with server)
    */
@@ -556,6 +619,7 @@ class AgentAPI {
     const transport = getTransport();
     const fileStore = useFileStore.getState();
 
+    await this.allowProjectRoot(path);
     try {
       const response = await transport.request<{ projects: Array<{ path: string; name: string; lastOpened: number }> }>(
         "add_recent_project",
@@ -606,6 +670,18 @@ class AgentAPI {
     }
   }
 
+  /**
+   * Pin or unpin a recent project; pinned projects stay at the top
+   */
+  async pinRecentProject(path: string, pinned: boolean): Promise<void> {
+    const transport = getTransport();
+    const response = await transport.request<{ projects: RecentProject[] }>(
+      "pin_recent_project",
+      { path, pinned }
+    );
+    useFileStore.getState().setRecentProjects(response.projects);
+  }
+
   /**
    * Set current working directory for this client (for session filtering)
    * This tells the server which project this client is working on,
@@ -614,6 +690,9 @@ class AgentAPI {
   async setCurrentCwd(cwd: string | null): Promise<void> {
     const transport = getTransport();
 
+    if (cwd) {
+      await this.allowProjectRoot(cwd);
+    }
     try {
       await transport.request("set_current_cwd", { cwd });
     } catch (e) {
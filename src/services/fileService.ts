@@ -11,6 +11,12 @@ export interface FileEntry {
   isHidden: boolean;
   size?: number;
   modified?: number;
+  /** The entry itself is a symlink */
+  isSymlink?: boolean;
+  /** Coarse type from the extension, or the first bytes when it is unknown */
+  kind?: "directory" | "symlink" | "text" | "image" | "binary";
+  /** Matched the ignore rules but was listed because includeIgnored was set */
+  forceIncluded?: boolean;
 }
 
 export interface FileContent {
@@ -32,6 +38,7 @@ export interface BinaryFileContent {
   content: string; // base64 encoded
   size: number;
   modified?: number;
+  truncated?: boolean;
 }
 
 function getWsTransport(): WebSocketTransport {
@@ -73,6 +80,11 @@ export async function renamePath(oldPath: string, newPath: string): Promise<void
   await getWsTransport().send<void>("rename_path", { from: oldPath, to: newPath });
 }
 
+/** Open a project file or an http(s)/mailto link in the OS default app (desktop only) */
+export async function openPath(path: string): Promise<void> {
+  await getWsTransport().send<void>("open_path", { path });
+}
+
 export async function readFileBinary(path: string): Promise<BinaryFileContent> {
   return getWsTransport().send<BinaryFileContent>("read_file_binary", { path });
 }
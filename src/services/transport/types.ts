@@ -69,4 +69,6 @@ export interface ServerInfo {
   cwd: string;
   home: string;
   lanAddresses?: string[];
+  /** Directory the server reads saved agent sessions from */
+  projectsDir?: string;
 }
@@ -30,10 +30,37 @@ interface JsonRpcRequest {
 interface JsonRpcResponse {
   jsonrpc: "2.0";
   result?: unknown;
-  error?: { code: number; message: string };
+  error?: { code: number; message: string; data?: unknown };
   id: number;
 }
 
+/** Server error codes (see src-tauri/src/server/error.rs) */
+export const RPC_ERROR_CODES = {
+  METHOD_NOT_FOUND: -32601,
+  INVALID_PARAMS: -32602,
+  INTERNAL_ERROR: -32603,
+  TOO_MANY_REQUESTS: -32001,
+  SESSION_NOT_FOUND: 1001,
+  AGENT_DISCONNECTED: 1002,
+  PATH_NOT_ALLOWED: 1003,
+  AUTH_REQUIRED: 1004,
+  SUPERSEDED: 1005,
+  SESSION_BUSY: 1006,
+  READ_ONLY: 1007,
+} as const;
+
+/** A failed request, with the server's error code and optional data */
+export class RpcRequestError extends Error {
+  constructor(
+    message: string,
+    public readonly code: number,
+    public readonly data?: unknown
+  ) {
+    super(message);
+    this.name = "RpcRequestError";
+  }
+}
+
 interface JsonRpcNotification {
   jsonrpc: "2.0";
   method: string;
@@ -280,7 +307,9 @@ export class WebSocketTransport implements Transport {
         if (pending) {
           this.pendingRequests.delete(response.id);
           if (response.error) {
-            pending.reject(new Error(response.error.message));
+            pending.reject(
+              new RpcRequestError(response.error.message, response.error.code, response.error.data)
+            );
           } else {
             pending.resolve(response.result);
           }
@@ -371,6 +400,16 @@ export class WebSocketTransport implements Transport {
         }
         break;
       }
+      case "session/started": {
+        // A lazily created placeholder session got its real ID
+        const handlers = this.eventHandlers.get("session-started");
+        if (handlers) {
+          for (const handler of handlers) {
+            handler(params);
+          }
+        }
+        break;
+      }
       case "sessions/updated": {
         // Session list was updated (e.g., session stopped, status changed)
         const { sessions } = params as { sessions: SessionInfo[] };
@@ -586,6 +625,22 @@ export class WebSocketTransport implements Transport {
     await this.send("unsubscribe_session", { sessionId });
   }
 
+  /**
+   * Receive `session/activity` summaries from every session (optionally one project's)
+   * Returns the current session list
+   */
+  async subscribeAll(cwd?: string): Promise<SessionInfo[]> {
+    const result = await this.send<{ cwd: string | null; sessions: SessionInfo[] }>("subscribe_all", { cwd });
+    return result.sessions;
+  }
+
+  /**
+   * Stop receiving `session/activity` summaries
+   */
+  async unsubscribeAll(): Promise<void> {
+    await this.send("unsubscribe_all");
+  }
+
   /**
    * Get session state without subscribing (one-time fetch)
    * @param sessionId - Session ID to get state for
@@ -646,6 +701,26 @@ export class WebSocketTransport implements Transport {
     };
   }
 
+  /**
+   * Subscribe to `session/started`, sent when a placeholder session from a lazy
+   * `create_session` is started and gets its real ID
+   * @returns Unsubscribe function
+   */
+  onSessionStarted(handler: (started: { placeholderId: string; sessionId: string }) => void): () => void {
+    const eventKey = "session-started";
+    if (!this.eventHandlers.has(eventKey)) {
+      this.eventHandlers.set(eventKey, new Set());
+    }
+    this.eventHandlers.get(eventKey)!.add(handler as (data: unknown) => void);
+
+    return () => {
+      this.eventHandlers.get(eventKey)?.delete(handler as (data: unknown) => void);
+      if (this.eventHandlers.get(eventKey)?.size === 0) {
+        this.eventHandlers.delete(eventKey);
+      }
+    };
+  }
+
   /**
    * Subscribe to all session state update notifications
    * @param handler - Callback receiving { sessionId, update }
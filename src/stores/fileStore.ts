@@ -6,6 +6,10 @@ export interface RecentProject {
   path: string;
   name: string;
   lastOpened: number;
+  /** Kept at the top of the list and never pruned */
+  pinned?: boolean;
+  /** False when the directory is gone (only pinned projects are kept then) */
+  exists?: boolean;
 }
 
 export interface FileEntry {
@@ -15,6 +19,12 @@ export interface FileEntry {
   isHidden: boolean;
   size?: number;
   modified?: number;
+  /** The entry itself is a symlink */
+  isSymlink?: boolean;
+  /** Coarse type from the extension, or the first bytes when it is unknown */
+  kind?: "directory" | "symlink" | "text" | "image" | "binary";
+  /** Matched the ignore rules but was listed because includeIgnored was set */
+  forceIncluded?: boolean;
 }
 
 export type FileType = "text" | "image" | "pdf" | "binary";
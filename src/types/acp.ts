@@ -305,12 +305,16 @@ export interface SessionInfo {
   lastAssistantMessage?: string;
   /** Whether the session has any agent response (text or tool call) */
   hasAgentResponse: boolean;
+  /** Pinned sessions are listed first */
+  pinned?: boolean;
 }
 
 export interface ListSessionsResponse {
   sessions: SessionInfo[];
   hasMore: boolean;
   total: number;
+  /** Pass as `cursor` to fetch the next page */
+  nextCursor?: string;
 }
 
 // === Backend-driven Session State Types ===
@@ -347,6 +351,7 @@ export type SessionStateUpdate =
   | { updateType: "plan_updated"; plan: Plan }
   | { updateType: "available_commands_updated"; commands: AvailableCommand[] }
   | { updateType: "current_mode_updated"; modeId: SessionModeId }
+  | { updateType: "model_updated"; modelId: string }
   | { updateType: "full_state"; state: SessionState }
   | { updateType: "dangerous_mode_updated"; dangerousMode: boolean }
   | { updateType: "noop" };
@@ -4,7 +4,7 @@
  * These types match the Rust backend model_config.rs structures
  */
 
-export type ProviderType = 'default' | 'anthropic' | 'bedrock' | 'bigmodel' | 'minimax' | 'moonshot' | 'ollama' | 'openrouter' | 'custom';
+export type ProviderType = 'default' | 'anthropic' | 'bedrock' | 'bigmodel' | 'minimax' | 'moonshot' | 'ollama' | 'openrouter' | 'azure' | 'custom';
 
 /**
  * Main model configuration structure
@@ -27,6 +27,7 @@ export interface Providers {
   moonshot: MoonshotProvider;
   ollama: OllamaProvider;
   openrouter: OpenRouterProvider;
+  azure: AzureProvider;
 }
 
 /**
@@ -117,6 +118,20 @@ export interface OpenRouterProvider {
   model: string;
 }
 
+/**
+ * Azure (Microsoft Foundry) provider configuration, addressed by deployment name
+ */
+export interface AzureProvider {
+  type: 'azure';
+  enabled: boolean;
+  endpoint: string;
+  apiVersion: string;
+  opusDeployment: string;
+  sonnetDeployment: string;
+  haikuDeployment: string;
+  apiKey: string;
+}
+
 /**
  * Custom provider configuration
  */
@@ -194,6 +209,7 @@ export const PROVIDER_NAMES: Record<string, string> = {
   moonshot: 'Moonshot AI / Kimi',
   ollama: 'Ollama',
   openrouter: 'OpenRouter',
+  azure: 'Azure',
 };
 
 /**
@@ -259,6 +275,16 @@ export function createDefaultModelConfig(): ModelProviderConfig {
         authToken: '',
         model: '',
       },
+      azure: {
+        type: 'azure',
+        enabled: true,
+        endpoint: '',
+        apiVersion: '',
+        opusDeployment: 'claude-opus-4-5',
+        sonnetDeployment: 'claude-sonnet-4-5',
+        haikuDeployment: 'claude-haiku-4-5',
+        apiKey: '',
+      },
     },
     customProviders: [],
   };